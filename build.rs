@@ -0,0 +1,24 @@
+// The C API's smoke test is written in C, against the header, to catch what
+// a Rust test can't: a header that disagrees with the library. Only the capi
+// tests link it in.
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    #[cfg(feature = "capi")]
+    {
+        println!("cargo:rerun-if-changed=include/chip8.h");
+        println!("cargo:rerun-if-changed=tests/capi_smoke.c");
+
+        cc::Build::new()
+            .file("tests/capi_smoke.c")
+            .include("include")
+            .warnings_into_errors(true)
+            .cargo_metadata(false)
+            .compile("capi_smoke");
+
+        println!(
+            "cargo:rustc-link-search=native={}",
+            std::env::var("OUT_DIR").unwrap()
+        );
+    }
+}
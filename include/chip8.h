@@ -0,0 +1,70 @@
+#ifndef CHIP8_H
+#define CHIP8_H
+
+/* Generated by cbindgen from src/capi.rs, do not edit. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#define CHIP8_DISPLAY_MAX_WIDTH 128
+
+#define CHIP8_DISPLAY_MAX_HEIGHT 64
+
+#define CHIP8_MAX_ROM_SIZE 3584
+
+#define CHIP8_OK 0
+
+#define CHIP8_ERR_NULL_POINTER -1
+
+#define CHIP8_ERR_ROM_TOO_LARGE -2
+
+#define CHIP8_ERR_INVALID_KEY -3
+
+#define CHIP8_ERR_FAULT -4
+
+#define CHIP8_ERR_PANIC -5
+
+typedef struct Chip8 Chip8;
+
+Chip8 *chip8_new(void);
+
+// # Safety
+//
+// `chip8` must come from `chip8_new` and `rom` must point to `len` readable bytes.
+int chip8_load_rom(Chip8 *chip8, const uint8_t *rom, size_t len);
+
+// # Safety
+//
+// `chip8` must come from `chip8_new`.
+int chip8_run_frame(Chip8 *chip8);
+
+// # Safety
+//
+// `chip8` must come from `chip8_new`.
+int chip8_key(Chip8 *chip8, int key, bool down);
+
+// # Safety
+//
+// `chip8` must come from `chip8_new`, `width` and `height` must be writable.
+int chip8_display_size(const Chip8 *chip8, size_t *width, size_t *height);
+
+// Writes width * height bytes for the current display size.
+//
+// # Safety
+//
+// `chip8` must come from `chip8_new` and `out` must point to at least
+// `CHIP8_DISPLAY_MAX_WIDTH * CHIP8_DISPLAY_MAX_HEIGHT` writable bytes.
+int chip8_display(const Chip8 *chip8, uint8_t *out);
+
+// # Safety
+//
+// `chip8` must come from `chip8_new` or be null.
+bool chip8_sound_active(const Chip8 *chip8);
+
+// # Safety
+//
+// `chip8` must come from `chip8_new` or be null, and must not be used afterwards.
+void chip8_free(Chip8 *chip8);
+
+#endif /* CHIP8_H */
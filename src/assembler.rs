@@ -0,0 +1,652 @@
+use std::collections::HashMap;
+
+use crate::cpu::{MAX_ROM_SIZE, PROGRAM_START};
+
+// A subset of Octo: labels (`: name`), `:byte`, `:call`, `:const`, the
+// register and `i` assignments, `if ... then`, `if ... begin ... else ...
+// end`, `loop ... while ... again`, `sprite` and the rest of the plain
+// instructions. Bare numbers are emitted as bytes, bare names call a label.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    let tokens: Vec<Token> = source
+        .lines()
+        .enumerate()
+        .flat_map(|(i, line)| {
+            let code = line.split('#').next().unwrap();
+
+            code.split_whitespace()
+                .map(move |text| Token { text, line: i + 1 })
+        })
+        .collect();
+
+    let mut assembler = Assembler {
+        tokens: tokens.iter(),
+        output: Vec::new(),
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+        line: 0,
+    };
+
+    // Execution starts at `main`, wherever it is.
+    let starts_with_main = matches!(
+        tokens.as_slice(),
+        [Token { text: ":", .. }, Token { text: "main", .. }, ..]
+            | [Token { text: ":main", .. }, ..]
+    );
+    let has_main = tokens
+        .windows(2)
+        .any(|pair| pair[0].text == ":" && pair[1].text == "main")
+        || tokens.iter().any(|token| token.text == ":main");
+
+    if has_main && !starts_with_main {
+        assembler.emit_jump(0x1000, "main");
+    }
+
+    assembler
+        .run()
+        .map_err(|err| format!("line {}: {}", assembler.line, err))?;
+
+    Ok(assembler.output)
+}
+
+#[derive(Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+struct Fixup<'a> {
+    offset: usize,
+    label: &'a str,
+    line: usize,
+}
+
+enum Block {
+    Loop { start: u16, breaks: Vec<usize> },
+    If { skip_jump: usize },
+    Else { end_jump: usize },
+}
+
+// The two skips a condition turns into: the one taken when it holds and
+// the one taken when it doesn't.
+struct Condition {
+    skip_if_true: u16,
+    skip_if_false: u16,
+}
+
+struct Assembler<'a, 't> {
+    tokens: std::slice::Iter<'t, Token<'a>>,
+    output: Vec<u8>,
+    labels: HashMap<&'a str, u16>,
+    constants: HashMap<&'a str, u16>,
+    fixups: Vec<Fixup<'a>>,
+    blocks: Vec<Block>,
+    line: usize,
+}
+
+impl<'a, 't> Assembler<'a, 't> {
+    fn run(&mut self) -> Result<(), String> {
+        while let Some(token) = self.tokens.next() {
+            self.line = token.line;
+            self.statement(token.text)?;
+
+            if self.output.len() > MAX_ROM_SIZE {
+                return Err(format!("program is larger than {} bytes", MAX_ROM_SIZE));
+            }
+        }
+
+        if !self.blocks.is_empty() {
+            return Err("missing `again` or `end` at the end of the file".to_string());
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            self.line = fixup.line;
+
+            let address = *self
+                .labels
+                .get(fixup.label)
+                .ok_or_else(|| format!("undefined label `{}`", fixup.label))?;
+
+            self.patch(fixup.offset, address);
+        }
+
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<&'a str, String> {
+        let token = self
+            .tokens
+            .next()
+            .ok_or_else(|| "unexpected end of file".to_string())?;
+
+        self.line = token.line;
+
+        Ok(token.text)
+    }
+
+    fn expect(&mut self, wanted: &str) -> Result<(), String> {
+        match self.next()? {
+            token if token == wanted => Ok(()),
+            token => Err(format!("expected `{}`, got `{}`", wanted, token)),
+        }
+    }
+
+    fn address(&self) -> u16 {
+        PROGRAM_START + self.output.len() as u16
+    }
+
+    fn emit(&mut self, opcode: u16) {
+        self.output.extend_from_slice(&opcode.to_be_bytes());
+    }
+
+    fn emit_jump(&mut self, opcode: u16, label: &'a str) {
+        self.fixups.push(Fixup {
+            offset: self.output.len(),
+            label,
+            line: self.line,
+        });
+        self.emit(opcode);
+    }
+
+    fn patch(&mut self, offset: usize, address: u16) {
+        self.output[offset] |= (address >> 8) as u8;
+        self.output[offset + 1] = address as u8;
+    }
+
+    fn statement(&mut self, token: &'a str) -> Result<(), String> {
+        match token {
+            ":" => {
+                let name = self.next()?;
+
+                self.define_label(name)
+            }
+            ":byte" => {
+                let value = self.next()?;
+                let byte = self.byte(value)?;
+
+                self.output.push(byte);
+                Ok(())
+            }
+            ":call" => {
+                let label = self.next()?;
+
+                self.address_operand(0x2000, label)
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value = self.next()?;
+                let value = self.number(value)?;
+
+                self.constants.insert(name, value);
+                Ok(())
+            }
+            _ if token.starts_with(':') => self.define_label(&token[1..]),
+            "clear" => {
+                self.emit(0x00E0);
+                Ok(())
+            }
+            "return" | ";" => {
+                self.emit(0x00EE);
+                Ok(())
+            }
+            "jump" => {
+                let target = self.next()?;
+
+                self.address_operand(0x1000, target)
+            }
+            "jump0" => {
+                let target = self.next()?;
+
+                self.address_operand(0xB000, target)
+            }
+            "loop" => {
+                self.blocks.push(Block::Loop {
+                    start: self.address(),
+                    breaks: Vec::new(),
+                });
+                Ok(())
+            }
+            "while" => {
+                let condition = self.condition()?;
+
+                self.emit(condition.skip_if_true);
+
+                let offset = self.output.len();
+
+                self.emit(0x1000);
+
+                match self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|block| matches!(block, Block::Loop { .. }))
+                {
+                    Some(Block::Loop { breaks, .. }) => breaks.push(offset),
+                    _ => return Err("`while` outside of a loop".to_string()),
+                }
+
+                Ok(())
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, breaks }) => {
+                    self.emit(0x1000 | start);
+
+                    let end = self.address();
+
+                    for offset in breaks {
+                        self.patch(offset, end);
+                    }
+
+                    Ok(())
+                }
+                _ => Err("`again` without a matching `loop`".to_string()),
+            },
+            "if" => {
+                let condition = self.condition()?;
+
+                match self.next()? {
+                    "then" => {
+                        self.emit(condition.skip_if_false);
+                        Ok(())
+                    }
+                    "begin" => {
+                        self.emit(condition.skip_if_true);
+                        self.blocks.push(Block::If {
+                            skip_jump: self.output.len(),
+                        });
+                        self.emit(0x1000);
+                        Ok(())
+                    }
+                    other => Err(format!("expected `then` or `begin`, got `{}`", other)),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If { skip_jump }) => {
+                    let end_jump = self.output.len();
+
+                    self.emit(0x1000);
+                    self.patch(skip_jump, self.address());
+                    self.blocks.push(Block::Else { end_jump });
+                    Ok(())
+                }
+                _ => Err("`else` without a matching `if ... begin`".to_string()),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { skip_jump: offset }) | Some(Block::Else { end_jump: offset }) => {
+                    self.patch(offset, self.address());
+                    Ok(())
+                }
+                _ => Err("`end` without a matching `if ... begin`".to_string()),
+            },
+            "sprite" => {
+                let x = self.next_register()?;
+                let y = self.next_register()?;
+                let height = self.next()?;
+                let height = self.number(height)?;
+
+                if height > 0xF {
+                    return Err(format!("sprite height {} is larger than 15", height));
+                }
+
+                self.emit(0xD000 | x << 8 | y << 4 | height);
+                Ok(())
+            }
+            "bcd" => self.register_instruction(0xF033),
+            "save" => self.register_instruction(0xF055),
+            "load" => self.register_instruction(0xF065),
+            // The debug print extension, see CPU::set_debug_opcodes.
+            "dbg" => self.register_instruction(0xF001),
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+
+                let opcode = if token == "delay" { 0xF015 } else { 0xF018 };
+
+                self.register_instruction(opcode)
+            }
+            "i" => self.pointer_statement(),
+            _ if register(token).is_some() => self.register_statement(register(token).unwrap()),
+            _ if self.number(token).is_ok() => {
+                let byte = self.byte(token)?;
+
+                self.output.push(byte);
+                Ok(())
+            }
+            _ if is_name(token) => self.address_operand(0x2000, token),
+            _ => Err(format!("unexpected `{}`", token)),
+        }
+    }
+
+    fn define_label(&mut self, name: &'a str) -> Result<(), String> {
+        if !is_name(name) {
+            return Err(format!("invalid label name `{}`", name));
+        }
+
+        if self.labels.insert(name, self.address()).is_some() {
+            return Err(format!("label `{}` is defined twice", name));
+        }
+
+        Ok(())
+    }
+
+    // A label, resolved once the whole file is read, or a number.
+    fn address_operand(&mut self, opcode: u16, target: &'a str) -> Result<(), String> {
+        match self.number(target) {
+            Ok(address) if address <= 0xFFF => {
+                self.emit(opcode | address);
+                Ok(())
+            }
+            Ok(address) => Err(format!("address 0x{:X} is out of range", address)),
+            Err(_) if is_name(target) => {
+                self.emit_jump(opcode, target);
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn register_instruction(&mut self, opcode: u16) -> Result<(), String> {
+        let x = self.next_register()?;
+
+        self.emit(opcode | x << 8);
+        Ok(())
+    }
+
+    fn pointer_statement(&mut self) -> Result<(), String> {
+        match self.next()? {
+            ":=" => match self.next()? {
+                "hex" => self.register_instruction(0xF029),
+                target => self.address_operand(0xA000, target),
+            },
+            "+=" => self.register_instruction(0xF01E),
+            other => Err(format!("expected `:=` or `+=` after `i`, got `{}`", other)),
+        }
+    }
+
+    fn register_statement(&mut self, x: u16) -> Result<(), String> {
+        let operator = self.next()?;
+        let operand = self.next()?;
+        let y = register(operand);
+
+        let opcode = match (operator, y) {
+            (":=", Some(y)) => 0x8000 | y << 4,
+            (":=", None) => match operand {
+                "random" => {
+                    let mask = self.next()?;
+
+                    0xC000 | self.byte(mask)? as u16
+                }
+                "delay" => 0xF007,
+                "key" => 0xF00A,
+                _ => 0x6000 | self.byte(operand)? as u16,
+            },
+            ("+=", Some(y)) => 0x8004 | y << 4,
+            ("+=", None) => 0x7000 | self.byte(operand)? as u16,
+            ("|=", Some(y)) => 0x8001 | y << 4,
+            ("&=", Some(y)) => 0x8002 | y << 4,
+            ("^=", Some(y)) => 0x8003 | y << 4,
+            ("-=", Some(y)) => 0x8005 | y << 4,
+            (">>=", Some(y)) => 0x8006 | y << 4,
+            ("=-", Some(y)) => 0x8007 | y << 4,
+            ("<<=", Some(y)) => 0x800E | y << 4,
+            ("|=" | "&=" | "^=" | "-=" | ">>=" | "=-" | "<<=", None) => {
+                return Err(format!(
+                    "`{}` needs a register, got `{}`",
+                    operator, operand
+                ))
+            }
+            _ => return Err(format!("unknown operator `{}`", operator)),
+        };
+
+        self.emit(opcode | x << 8);
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let x = self.next_register()? << 8;
+        let operator = self.next()?;
+
+        let (skip_if_true, skip_if_false) = match operator {
+            "key" => (0xE09E | x, 0xE0A1 | x),
+            "-key" => (0xE0A1 | x, 0xE09E | x),
+            "==" | "!=" => {
+                let operand = self.next()?;
+                let (equal, not_equal) = match register(operand) {
+                    Some(y) => (0x5000 | x | y << 4, 0x9000 | x | y << 4),
+                    None => {
+                        let value = self.byte(operand)? as u16;
+
+                        (0x3000 | x | value, 0x4000 | x | value)
+                    }
+                };
+
+                if operator == "==" {
+                    (equal, not_equal)
+                } else {
+                    (not_equal, equal)
+                }
+            }
+            other => return Err(format!("unknown comparison `{}`", other)),
+        };
+
+        Ok(Condition {
+            skip_if_true,
+            skip_if_false,
+        })
+    }
+
+    fn next_register(&mut self) -> Result<u16, String> {
+        let token = self.next()?;
+
+        register(token).ok_or_else(|| format!("expected a register, got `{}`", token))
+    }
+
+    fn number(&self, token: &str) -> Result<u16, String> {
+        if let Some(value) = self.constants.get(token) {
+            return Ok(*value);
+        }
+
+        let (negative, digits) = match token.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, token),
+        };
+
+        let value = if let Some(hex) = digits.strip_prefix("0x") {
+            u16::from_str_radix(hex, 16)
+        } else if let Some(binary) = digits.strip_prefix("0b") {
+            u16::from_str_radix(binary, 2)
+        } else {
+            digits.parse()
+        }
+        .map_err(|_| format!("expected a number, got `{}`", token))?;
+
+        match (negative, value) {
+            (false, value) => Ok(value),
+            (true, value @ 1..=128) => Ok(0x100 - value),
+            (true, 0) => Ok(0),
+            (true, _) => Err(format!("{} doesn't fit in a byte", token)),
+        }
+    }
+
+    fn byte(&self, token: &str) -> Result<u8, String> {
+        let value = self.number(token)?;
+
+        u8::try_from(value).map_err(|_| format!("{} doesn't fit in a byte", token))
+    }
+}
+
+fn register(token: &str) -> Option<u16> {
+    let digit = token
+        .strip_prefix('v')
+        .or_else(|| token.strip_prefix('V'))?;
+
+    match digit.len() {
+        1 => u16::from_str_radix(digit, 16).ok(),
+        _ => None,
+    }
+}
+
+fn is_name(token: &str) -> bool {
+    token
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+
+    fn opcodes(source: &str) -> Vec<u16> {
+        assemble(source)
+            .unwrap()
+            .chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn register_statements() {
+        assert_eq!(
+            opcodes(
+                "v0 := 5  v1 := v0  v2 += 3  v3 += v1
+                 v4 |= v5  v4 &= v5  v4 ^= v5  v4 -= v5  v4 >>= v5  v4 =- v5  v4 <<= v5
+                 v6 := random 0xF0  v7 := delay  v8 := key  VA := -1  vb := 0b101"
+            ),
+            [
+                0x6005, 0x8100, 0x7203, 0x8314, 0x8451, 0x8452, 0x8453, 0x8455, 0x8456, 0x8457,
+                0x845E, 0xC6F0, 0xF707, 0xF80A, 0x6AFF, 0x6B05,
+            ]
+        );
+    }
+
+    #[test]
+    fn pointer_timer_and_plain_statements() {
+        assert_eq!(
+            opcodes(
+                "i := 0x300  i := hex v3  i += v2  delay := v1  buzzer := v2
+                 bcd v3  save v4  load v5  clear  return  ;  dbg v6
+                 jump 0x2F0  jump0 0x300  :call 0x400  sprite v1 v2 15"
+            ),
+            [
+                0xA300, 0xF329, 0xF21E, 0xF115, 0xF218, 0xF333, 0xF455, 0xF565, 0x00E0, 0x00EE,
+                0x00EE, 0xF601, 0x12F0, 0xB300, 0x2400, 0xD12F,
+            ]
+        );
+    }
+
+    #[test]
+    fn bytes_constants_and_labels() {
+        // Data comes first, so a jump to main goes in front of it.
+        assert_eq!(
+            assemble(
+                ":const HEIGHT 2
+                 : ball 0x60 :byte 0xF0
+                 : main
+                   i := ball
+                   sprite v0 v1 HEIGHT
+                   draw-again
+                   jump main
+                 : draw-again  # called by name
+                   :call main ;"
+            ),
+            Ok(vec![
+                0x12, 0x04, 0x60, 0xF0, 0xA2, 0x02, 0xD0, 0x12, 0x22, 0x0C, 0x12, 0x04, 0x22, 0x04,
+                0x00, 0xEE,
+            ])
+        );
+        // Already first, nothing to jump over.
+        assert_eq!(opcodes(":main v0 := 1 jump main"), [0x6001, 0x1200]);
+    }
+
+    #[test]
+    fn if_then_and_if_begin_else_end() {
+        assert_eq!(
+            opcodes("if v0 == 10 then v1 := 1  if v0 != v2 then v1 := 2"),
+            [0x400A, 0x6101, 0x5020, 0x6102]
+        );
+        assert_eq!(
+            opcodes("if v0 key begin v1 := 1 else v1 := 2 end"),
+            [0xE09E, 0x1208, 0x6101, 0x120A, 0x6102]
+        );
+        assert_eq!(
+            opcodes("if v0 -key begin v1 := 1 end"),
+            [0xE0A1, 0x1206, 0x6101]
+        );
+    }
+
+    #[test]
+    fn loops_jump_back_and_while_breaks_out() {
+        assert_eq!(
+            opcodes("loop v0 += 1 if v0 == 10 then v1 := 1 while v0 != 20 again v2 := 2"),
+            [0x7001, 0x400A, 0x6101, 0x4014, 0x120C, 0x1200, 0x6202]
+        );
+        // A `while` in an `if` block still breaks out of the loop around it.
+        assert_eq!(
+            opcodes("loop if v0 key begin while v1 == 0 end again"),
+            [0xE09E, 0x1208, 0x3100, 0x120A, 0x1200]
+        );
+    }
+
+    #[test]
+    fn errors_come_with_their_line() {
+        for (source, error) in [
+            ("v0 := 256", "line 1: 256 doesn't fit in a byte"),
+            ("v0 := -129", "line 1: -129 doesn't fit in a byte"),
+            ("\njump nowhere", "line 2: undefined label `nowhere`"),
+            (
+                "loop\nv0 := 1",
+                "line 2: missing `again` or `end` at the end of the file",
+            ),
+            ("again", "line 1: `again` without a matching `loop`"),
+            ("else", "line 1: `else` without a matching `if ... begin`"),
+            ("v0 := 1 while v0 == 1", "line 1: `while` outside of a loop"),
+            (
+                "sprite v0 v1 16",
+                "line 1: sprite height 16 is larger than 15",
+            ),
+            (": a\n: a", "line 2: label `a` is defined twice"),
+            (": 3d", "line 1: invalid label name `3d`"),
+            ("if v0 < 3 then", "line 1: unknown comparison `<`"),
+            (
+                "if v0 == 3 v1 := 1",
+                "line 1: expected `then` or `begin`, got `v1`",
+            ),
+            ("v0 ~= v1", "line 1: unknown operator `~=`"),
+            ("v0 |= 3", "line 1: `|=` needs a register, got `3`"),
+            ("i := 0x1000", "line 1: address 0x1000 is out of range"),
+            (
+                "i -= v0",
+                "line 1: expected `:=` or `+=` after `i`, got `-=`",
+            ),
+            ("delay v0", "line 1: expected `:=`, got `v0`"),
+            ("sprite v0 vz 1", "line 1: expected a register, got `vz`"),
+            ("bcd", "line 1: unexpected end of file"),
+            ("@", "line 1: unexpected `@`"),
+        ] {
+            assert_eq!(assemble(source), Err(error.to_string()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn the_bundled_example_assembles_and_draws_its_ball() {
+        let rom = assemble(include_str!("../roms/bounce.8o")).unwrap();
+        let mut cpu = CPU::new();
+
+        cpu.load_rom(rom).unwrap();
+
+        for _ in 0..10 {
+            cpu.run_frame().unwrap();
+        }
+
+        // 0x60 0xF0 0xF0 0x60.
+        assert_eq!(
+            cpu.display().iter().filter(|pixel| **pixel == 1).count(),
+            12
+        );
+    }
+}
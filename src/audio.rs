@@ -0,0 +1,125 @@
+use rodio::{
+    cpal::{
+        self,
+        traits::{DeviceTrait, HostTrait},
+    },
+    OutputStream, OutputStreamHandle,
+};
+
+// The stream has to stay alive for as long as the handle is used.
+pub struct Output {
+    _stream: OutputStream,
+    pub handle: OutputStreamHandle,
+    pub name: String,
+}
+
+pub fn device_names() -> Vec<String> {
+    cpal::default_host()
+        .output_devices()
+        .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+        .unwrap_or_default()
+}
+
+// Case-insensitive substring match, the first device listed wins.
+pub fn find_device(names: &[String], wanted: &str) -> Option<usize> {
+    let wanted = wanted.to_lowercase();
+
+    names
+        .iter()
+        .position(|name| name.to_lowercase().contains(&wanted))
+}
+
+// Falls back to the default output when `wanted` isn't found, and to no sound
+// at all when there is no output, neither is worth refusing to run over.
+pub fn open(wanted: Option<&str>) -> Option<Output> {
+    let host = cpal::default_host();
+
+    if let Some(wanted) = wanted {
+        let devices: Vec<_> = host
+            .output_devices()
+            .map(|devices| devices.collect())
+            .unwrap_or_default();
+        let names: Vec<String> = devices
+            .iter()
+            .map(|device| device.name().unwrap_or_default())
+            .collect();
+
+        match find_device(&names, wanted) {
+            Some(index) => match OutputStream::try_from_device(&devices[index]) {
+                Ok((stream, handle)) => {
+                    return Some(Output {
+                        _stream: stream,
+                        handle,
+                        name: names[index].clone(),
+                    })
+                }
+                Err(err) => eprintln!(
+                    "warning: could not open audio device {}: {}, using the default",
+                    names[index], err
+                ),
+            },
+            None => eprintln!(
+                "warning: no audio device matches \"{}\", using the default",
+                wanted
+            ),
+        }
+    }
+
+    match OutputStream::try_default() {
+        Ok((stream, handle)) => {
+            let name = host
+                .default_output_device()
+                .and_then(|device| device.name().ok())
+                .unwrap_or_else(|| "default".to_string());
+
+            Some(Output {
+                _stream: stream,
+                handle,
+                name,
+            })
+        }
+        Err(err) => {
+            eprintln!("warning: no audio output ({}), running without sound", err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn devices() -> Vec<String> {
+        [
+            "default",
+            "HDA Intel PCH, ALC892 Analog",
+            "HDA NVidia, HDMI 0",
+            "HDA NVidia, HDMI 1",
+            "Logitech USB Headset",
+            "Loopback, Loopback PCM",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+
+    #[test]
+    fn devices_match_by_case_insensitive_substring() {
+        let names = devices();
+
+        assert_eq!(find_device(&names, "usb headset"), Some(4));
+        assert_eq!(find_device(&names, "ALC892"), Some(1));
+        assert_eq!(find_device(&names, "loopback pcm"), Some(5));
+    }
+
+    #[test]
+    fn the_first_listed_device_wins() {
+        assert_eq!(find_device(&devices(), "HDMI"), Some(2));
+        assert_eq!(find_device(&devices(), "hda"), Some(1));
+    }
+
+    #[test]
+    fn a_missing_device_is_not_found() {
+        assert_eq!(find_device(&devices(), "bluetooth"), None);
+        assert_eq!(find_device(&[], "default"), None);
+    }
+}
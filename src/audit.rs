@@ -0,0 +1,177 @@
+use std::{collections::VecDeque, fmt};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessSource {
+    StoreRegisters,
+    LoadRegisters,
+    StoreBcd,
+    SpriteFetch,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryAccess {
+    pub pc: u16,
+    pub opcode: u16,
+    pub address: u16,
+    pub old: u8,
+    pub new: Option<u8>,
+    pub source: AccessSource,
+}
+
+pub struct MemoryAudit {
+    records: VecDeque<MemoryAccess>,
+    capacity: usize,
+}
+
+impl MemoryAudit {
+    pub fn new(capacity: usize) -> Self {
+        MemoryAudit {
+            records: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn record(&mut self, access: MemoryAccess) {
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+
+        self.records.push_back(access);
+    }
+
+    // One record per address, with the addresses as the CPU wrapped them.
+    pub(crate) fn record_range(
+        &mut self,
+        pc: u16,
+        opcode: u16,
+        source: AccessSource,
+        addresses: &[u16],
+        old: &[u8],
+        new: Option<&[u8]>,
+    ) {
+        for (i, (address, old)) in addresses.iter().zip(old).enumerate() {
+            self.record(MemoryAccess {
+                pc,
+                opcode,
+                address: *address,
+                old: *old,
+                new: new.map(|new| new[i]),
+                source,
+            });
+        }
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &MemoryAccess> {
+        self.records.iter()
+    }
+}
+
+impl fmt::Display for AccessSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            AccessSource::StoreRegisters => "FX55",
+            AccessSource::LoadRegisters => "FX65",
+            AccessSource::StoreBcd => "FX33",
+            AccessSource::SpriteFetch => "DXYN",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for MemoryAccess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "0x{:04X} {:04X} {} 0x{:03X} ",
+            self.pc, self.opcode, self.source, self.address
+        )?;
+
+        match self.new {
+            Some(new) => write!(f, "write 0x{:02X} -> 0x{:02X}", self.old, new),
+            None => write!(f, "read 0x{:02X}", self.old),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::CPU;
+
+    fn audited(program: &[u16], capacity: usize, instructions: usize) -> CPU {
+        let mut cpu = CPU::new();
+        let rom = program.iter().flat_map(|opcode| opcode.to_be_bytes());
+
+        cpu.load_rom(rom.collect()).unwrap();
+        cpu.enable_memory_audit(capacity);
+
+        for _ in 0..instructions {
+            cpu.cycle().unwrap();
+        }
+
+        cpu
+    }
+
+    fn lines(cpu: &CPU) -> Vec<String> {
+        let audit = cpu.memory_audit().unwrap();
+
+        audit.records().map(|access| access.to_string()).collect()
+    }
+
+    #[test]
+    fn each_memory_opcode_records_what_it_touched() {
+        // BCD of 123 at 0x300, loads two bytes back, stores V0 and V1 = 7
+        // over them and draws a one row sprite from there.
+        let cpu = audited(
+            &[0xA300, 0x607B, 0xF033, 0xF165, 0x6107, 0xF155, 0xD001],
+            100,
+            7,
+        );
+
+        assert_eq!(
+            lines(&cpu),
+            [
+                "0x0204 F033 FX33 0x300 write 0x00 -> 0x01",
+                "0x0204 F033 FX33 0x301 write 0x00 -> 0x02",
+                "0x0204 F033 FX33 0x302 write 0x00 -> 0x03",
+                "0x0206 F165 FX65 0x300 read 0x01",
+                "0x0206 F165 FX65 0x301 read 0x02",
+                "0x020A F155 FX55 0x300 write 0x01 -> 0x01",
+                "0x020A F155 FX55 0x301 write 0x02 -> 0x07",
+                "0x020C D001 DXYN 0x300 read 0x01",
+            ]
+        );
+    }
+
+    #[test]
+    fn only_the_latest_records_are_kept() {
+        let cpu = audited(&[0xA300, 0x607B, 0xF033], 2, 3);
+
+        assert_eq!(
+            lines(&cpu),
+            [
+                "0x0204 F033 FX33 0x301 write 0x00 -> 0x02",
+                "0x0204 F033 FX33 0x302 write 0x00 -> 0x03",
+            ]
+        );
+    }
+
+    #[test]
+    fn addresses_wrap_like_the_accesses_do() {
+        // FX1E never masks I, 256 times 0xF0 from 0xFFF leaves it at 0xFFFF.
+        let mut program = vec![0xAFFF, 0x62F0];
+
+        program.extend([0xF21E; 256]);
+        program.push(0xF155);
+
+        let cpu = audited(&program, 100, program.len());
+        let audit = cpu.memory_audit().unwrap();
+        let addresses: Vec<u16> = audit.records().map(|access| access.address).collect();
+
+        assert_eq!(addresses, [0xFFF, 0x000]);
+    }
+}
@@ -0,0 +1,278 @@
+use std::fmt;
+
+use crate::{
+    disassembler::{disassemble, MNEMONICS},
+    parse::{parse_byte, parse_number},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OpcodePattern {
+    Masked { value: u16, mask: u16 },
+    Mnemonic(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegisterCondition {
+    pub register: u8,
+    pub equal: bool,
+    pub value: u8,
+}
+
+// Stops before an instruction matching `pattern` runs, `DRW if V3 == 0x1F`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpcodeBreakpoint {
+    pub pattern: OpcodePattern,
+    pub condition: Option<RegisterCondition>,
+    text: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuEvent {
+    KeyRead,
+    SoundStart,
+    ClearScreen,
+    DebugOp,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BreakReason {
+    // Stopped before the instruction at this address.
+    Address(u16),
+    // Stopped before the instruction matching the breakpoint at this index.
+    Opcode(usize),
+    // Stopped after the instruction at this address raised the event.
+    Event(CpuEvent, u16),
+    // Stopped after the instruction at `pc` wrote to the watched `address`.
+    Watch { address: u16, pc: u16 },
+    // Stopped once the cycles asked for with `run_for` ran.
+    RunFor,
+}
+
+impl OpcodePattern {
+    // `0xF00A/0xF0FF`, a nibble pattern like `Dxyn` or `FX0A`, or a mnemonic like `RND`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+
+        if let Some((value, mask)) = text.split_once('/') {
+            let value = parse_number(value).ok_or_else(|| format!("invalid opcode `{}`", value))?;
+            let mask = parse_number(mask).ok_or_else(|| format!("invalid mask `{}`", mask))?;
+
+            return Ok(OpcodePattern::Masked {
+                value: value & mask,
+                mask,
+            });
+        }
+
+        let mnemonic = text.to_ascii_uppercase();
+
+        if MNEMONICS.contains(&mnemonic.as_str()) {
+            return Ok(OpcodePattern::Mnemonic(mnemonic));
+        }
+
+        if text.len() == 4 {
+            let mut value = 0;
+            let mut mask = 0;
+
+            for c in text.chars() {
+                value <<= 4;
+                mask <<= 4;
+
+                match c.to_ascii_lowercase() {
+                    'x' | 'y' | 'n' | 'k' => {}
+                    c => match c.to_digit(16) {
+                        Some(digit) => {
+                            value |= digit as u16;
+                            mask |= 0xF;
+                        }
+                        None => return Err(format!("invalid opcode pattern `{}`", text)),
+                    },
+                }
+            }
+
+            return Ok(OpcodePattern::Masked { value, mask });
+        }
+
+        Err(format!("unknown instruction `{}`", text))
+    }
+
+    pub fn matches(&self, opcode: u16) -> bool {
+        match self {
+            OpcodePattern::Masked { value, mask } => opcode & mask == *value,
+            OpcodePattern::Mnemonic(mnemonic) => {
+                disassemble(opcode).split(' ').next() == Some(mnemonic.as_str())
+            }
+        }
+    }
+}
+
+impl RegisterCondition {
+    // `V3 == 0x1F` or `VA != 0`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (register, value, equal) = if let Some((register, value)) = text.split_once("==") {
+            (register, value, true)
+        } else if let Some((register, value)) = text.split_once("!=") {
+            (register, value, false)
+        } else {
+            return Err(format!(
+                "expected `VX == VALUE` or `VX != VALUE`, got `{}`",
+                text
+            ));
+        };
+
+        let register = register.trim();
+        let register = register
+            .strip_prefix('V')
+            .or_else(|| register.strip_prefix('v'))
+            .and_then(|index| u8::from_str_radix(index, 16).ok())
+            .filter(|index| *index < 0x10)
+            .ok_or_else(|| format!("invalid register `{}`", register))?;
+        let value = parse_byte(value).ok_or_else(|| format!("invalid value `{}`", value.trim()))?;
+
+        Ok(RegisterCondition {
+            register,
+            equal,
+            value,
+        })
+    }
+
+    pub fn holds(&self, registers: &[u8; 0x10]) -> bool {
+        (registers[self.register as usize] == self.value) == self.equal
+    }
+}
+
+impl CpuEvent {
+    // `key`, `sound`, `cls` or `dbg`, as given to --break-on.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text.trim() {
+            "key" => Ok(CpuEvent::KeyRead),
+            "sound" => Ok(CpuEvent::SoundStart),
+            "cls" => Ok(CpuEvent::ClearScreen),
+            "dbg" => Ok(CpuEvent::DebugOp),
+            other => Err(format!(
+                "unknown event `{}` (expected key, sound, cls or dbg)",
+                other
+            )),
+        }
+    }
+}
+
+impl OpcodeBreakpoint {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (pattern, condition) = match text.split_once(" if ") {
+            Some((pattern, condition)) => (pattern, Some(RegisterCondition::parse(condition)?)),
+            None => (text, None),
+        };
+
+        Ok(OpcodeBreakpoint {
+            pattern: OpcodePattern::parse(pattern)?,
+            condition,
+            text: text.trim().to_string(),
+        })
+    }
+
+    pub fn matches(&self, opcode: u16, registers: &[u8; 0x10]) -> bool {
+        self.pattern.matches(opcode)
+            && self
+                .condition
+                .is_none_or(|condition| condition.holds(registers))
+    }
+}
+
+impl fmt::Display for OpcodeBreakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl fmt::Display for CpuEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            CpuEvent::KeyRead => "first keypad read",
+            CpuEvent::SoundStart => "sound start",
+            CpuEvent::ClearScreen => "screen clear",
+            CpuEvent::DebugOp => "debug opcode",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn masked(value: u16, mask: u16) -> OpcodePattern {
+        OpcodePattern::Masked { value, mask }
+    }
+
+    #[test]
+    fn patterns_parse_from_nibbles_masks_and_mnemonics() {
+        assert_eq!(OpcodePattern::parse("Dxyn"), Ok(masked(0xD000, 0xF000)));
+        assert_eq!(OpcodePattern::parse("FX0A"), Ok(masked(0xF00A, 0xF0FF)));
+        assert_eq!(
+            OpcodePattern::parse("0xF00A/0xF0FF"),
+            Ok(masked(0xF00A, 0xF0FF))
+        );
+        // The value is masked, bits the mask drops don't matter.
+        assert_eq!(
+            OpcodePattern::parse("0xF30A/0xF0FF"),
+            Ok(masked(0xF00A, 0xF0FF))
+        );
+        assert_eq!(
+            OpcodePattern::parse(" rnd "),
+            Ok(OpcodePattern::Mnemonic("RND".to_string()))
+        );
+
+        for (text, error) in [
+            ("DxyG", "invalid opcode pattern `DxyG`"),
+            ("Dxy", "unknown instruction `Dxy`"),
+            ("0xF00A/zz", "invalid mask `zz`"),
+            ("zz/0xF0FF", "invalid opcode `zz`"),
+        ] {
+            assert_eq!(OpcodePattern::parse(text), Err(error.to_string()));
+        }
+    }
+
+    #[test]
+    fn patterns_hit_and_miss_decoded_opcodes() {
+        let draw = OpcodePattern::parse("Dxyn").unwrap();
+        let key_wait = OpcodePattern::parse("0xF00A/0xF0FF").unwrap();
+        let load = OpcodePattern::parse("LD").unwrap();
+
+        assert!(draw.matches(0xD01F) && draw.matches(0xDFF0));
+        assert!(!draw.matches(0x600C));
+        assert!(key_wait.matches(0xF30A));
+        assert!(!key_wait.matches(0xF30B) && !key_wait.matches(0xE30A));
+        assert!(load.matches(0x600C) && load.matches(0xA22A) && load.matches(0xF329));
+        assert!(!load.matches(0xD01F));
+    }
+
+    #[test]
+    fn register_conditions_narrow_a_breakpoint_down() {
+        let breakpoint = OpcodeBreakpoint::parse("DRW if V3 == 0x1F").unwrap();
+        let other = OpcodeBreakpoint::parse("Dxyn if va != 0").unwrap();
+        let mut registers = [0; 0x10];
+
+        assert!(!breakpoint.matches(0xD01F, &registers));
+        assert!(!other.matches(0xD01F, &registers));
+
+        registers[3] = 0x1F;
+        registers[0xA] = 1;
+
+        assert!(breakpoint.matches(0xD01F, &registers));
+        assert!(!breakpoint.matches(0x601F, &registers));
+        assert!(other.matches(0xD01F, &registers));
+        assert_eq!(breakpoint.to_string(), "DRW if V3 == 0x1F");
+
+        for (text, error) in [
+            ("DRW if VG == 1", "invalid register `VG`"),
+            ("DRW if V3 == 0x100", "invalid value `0x100`"),
+            (
+                "DRW if V3",
+                "expected `VX == VALUE` or `VX != VALUE`, got `V3`",
+            ),
+        ] {
+            assert_eq!(OpcodeBreakpoint::parse(text), Err(error.to_string()));
+        }
+    }
+}
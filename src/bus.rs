@@ -1,22 +1,163 @@
+use std::time::Instant;
+
+pub const BUS_CAPACITY: usize = 32;
+
+pub type Signal = (u8, u8);
+
+// A fixed ring of signals, so a stalled CPU can't make the queue grow.
 pub struct Bus {
-    signals: Vec<(u8, u8)>,
+    signals: [Signal; BUS_CAPACITY],
+    sent_at: [Option<Instant>; BUS_CAPACITY],
+    head: usize,
+    len: usize,
+    dropped: u64,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Bus {
     pub fn new() -> Self {
         Bus {
-            signals: Vec::new(),
+            signals: [(0, 0); BUS_CAPACITY],
+            sent_at: [None; BUS_CAPACITY],
+            head: 0,
+            len: 0,
+            dropped: 0,
         }
     }
 
+    // When full the oldest signal is dropped, the newest state matters most.
     pub fn send(&mut self, d1: u8, d2: u8) {
-        self.signals.push((d1, d2));
+        if self.is_full() {
+            self.dropped += 1;
+            self.head = (self.head + 1) % BUS_CAPACITY;
+            self.len -= 1;
+        }
+
+        let tail = (self.head + self.len) % BUS_CAPACITY;
+
+        self.signals[tail] = (d1, d2);
+        self.sent_at[tail] = Some(Instant::now());
+        self.len += 1;
     }
 
-    pub fn read(&mut self) -> (u8, u8) {
-        match self.signals.pop() {
-            Some(signal) => signal,
-            None => (0x0, 0x0),
+    // Signals come out in the order they were sent, so a press followed by a
+    // release leaves the key released.
+    pub fn try_read(&mut self) -> Option<Signal> {
+        self.try_read_timed().map(|(signal, _)| signal)
+    }
+
+    // Also returns when the signal was sent.
+    pub fn try_read_timed(&mut self) -> Option<(Signal, Instant)> {
+        if self.len == 0 {
+            return None;
         }
+
+        let signal = self.signals[self.head];
+        let sent_at = self.sent_at[self.head].unwrap();
+
+        self.head = (self.head + 1) % BUS_CAPACITY;
+        self.len -= 1;
+
+        Some((signal, sent_at))
+    }
+
+    pub fn read(&mut self) -> Signal {
+        self.try_read().unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == BUS_CAPACITY
+    }
+
+    // Signals lost to a full bus so far, for the front end to report.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_full_bus_drops_the_oldest_signals_and_counts_them() {
+        let mut bus = Bus::new();
+
+        for i in 0..BUS_CAPACITY + 3 {
+            bus.send(i as u8, 1);
+        }
+
+        assert!(bus.is_full());
+        assert_eq!(bus.dropped(), 3);
+        assert_eq!(bus.read(), (3, 1));
+
+        while bus.try_read().is_some() {}
+
+        assert!(bus.is_empty());
+        assert_eq!(bus.read(), (0, 0));
+        assert_eq!(bus.dropped(), 3);
+    }
+
+    #[test]
+    fn signals_come_out_in_order_as_the_ring_wraps_around() {
+        let mut bus = Bus::new();
+        let mut next = 0u32;
+        let mut expected = 0u32;
+
+        // Three quarters full, then half read, again and again, so the head
+        // and the tail go round the ring several times.
+        for _ in 0..10 {
+            while bus.len() < BUS_CAPACITY * 3 / 4 {
+                bus.send(next as u8, (next >> 8) as u8);
+                next += 1;
+            }
+
+            for _ in 0..BUS_CAPACITY / 2 {
+                assert_eq!(bus.read(), (expected as u8, (expected >> 8) as u8));
+                expected += 1;
+            }
+        }
+
+        assert!(next as usize > 4 * BUS_CAPACITY);
+        assert_eq!(bus.len(), (next - expected) as usize);
+
+        while let Some(signal) = bus.try_read() {
+            assert_eq!(signal, (expected as u8, (expected >> 8) as u8));
+            expected += 1;
+        }
+
+        assert_eq!(expected, next);
+        assert_eq!(bus.dropped(), 0);
+    }
+
+    #[test]
+    fn a_press_then_release_reads_back_in_that_order() {
+        let mut bus = Bus::new();
+
+        bus.send(0x5, 1);
+        bus.send(0x5, 0);
+        bus.send(0xA, 1);
+
+        let (first, first_sent) = bus.try_read_timed().unwrap();
+        let (second, second_sent) = bus.try_read_timed().unwrap();
+
+        assert_eq!((first, second), ((0x5, 1), (0x5, 0)));
+        assert!(first_sent <= second_sent);
+        assert_eq!(bus.len(), 1);
+        assert_eq!(bus.try_read(), Some((0xA, 1)));
+        assert_eq!(bus.try_read(), None);
     }
 }
@@ -1,43 +1,218 @@
-use std::time::{Duration, SystemTime};
+use std::{
+    fs::File,
+    io::BufReader,
+    path::Path,
+    time::{Duration, SystemTime},
+};
 
-use rodio::{source::SineWave, OutputStreamHandle, Sink, Source};
+use chip8_emu::cpu::SoundEvent;
+use rodio::{buffer::SamplesBuffer, source::SineWave, Decoder, OutputStreamHandle, Sink, Source};
 
 const MIN_PLAYBACK_DURATION: f32 = 0.25; // 250ms
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BeepMode {
+    // Repeats the sample for as long as the sound timer runs.
+    Loop,
+    // Plays the whole sample once every time the ROM starts a beep.
+    Once,
+}
+
+// A sample decoded up front, so a bad file shows up before the game starts.
+pub struct BeepSound {
+    channels: u16,
+    sample_rate: u32,
+    samples: Vec<f32>,
+    mode: BeepMode,
+}
+
+impl BeepSound {
+    pub fn load(path: &str, mode: BeepMode) -> Result<Self, String> {
+        let file = File::open(path).map_err(|err| err.to_string())?;
+        let reader = BufReader::new(file);
+
+        // Probing every format would hand a broken WAV to the MP3 decoder.
+        let extension = Path::new(path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+
+        let decoder = match extension.as_deref() {
+            Some("wav") => Decoder::new_wav(reader),
+            Some("flac") => Decoder::new_flac(reader),
+            Some("ogg") => Decoder::new_vorbis(reader),
+            _ => return Err("not a WAV, FLAC or Ogg Vorbis file".to_string()),
+        }
+        .map_err(|err| err.to_string())?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        let samples: Vec<f32> = decoder.convert_samples().collect();
+
+        if samples.is_empty() {
+            return Err("no samples in the file".to_string());
+        }
+
+        Ok(BeepSound {
+            channels,
+            sample_rate,
+            samples,
+            mode,
+        })
+    }
+
+    // None, and so the synthesized tone, when the file can't be used.
+    pub fn load_or_tone(path: &str, mode: BeepMode) -> Option<Self> {
+        Self::load(path, mode)
+            .map_err(|err| eprintln!("warning: could not load {}: {}, using the tone", path, err))
+            .ok()
+    }
+
+    fn source(&self) -> SamplesBuffer<f32> {
+        SamplesBuffer::new(self.channels, self.sample_rate, self.samples.clone())
+    }
+}
+
+// Without an output there is no sink and the buzzer stays silent.
 pub struct Buzzer {
-    sink: Sink,
+    output: Option<OutputStreamHandle>,
+    sink: Option<Sink>,
+    sound: Option<BeepSound>,
     last_started_at: SystemTime,
+    sounding: bool,
+    retrigger: bool,
 }
 
 impl Buzzer {
-    pub fn new(output: &OutputStreamHandle) -> Self {
-        let sink = Sink::try_new(output).unwrap();
+    // The tone plays when there's no sound, and so does a looping one: both
+    // sit paused in the sink until a beep starts.
+    pub fn new(output: Option<&OutputStreamHandle>, sound: Option<BeepSound>) -> Self {
+        let sink = output.and_then(|output| Sink::try_new(output).ok());
+
+        if let Some(sink) = &sink {
+            match &sound {
+                Some(sound) if sound.mode == BeepMode::Loop => {
+                    sink.append(sound.source().repeat_infinite())
+                }
+                Some(_) => {}
+                None => sink.append(
+                    SineWave::new(440.0)
+                        .take_duration(Duration::from_secs_f32(10.0))
+                        .repeat_infinite(),
+                ),
+            }
 
-        sink.append(
-            SineWave::new(440.0)
-                .take_duration(Duration::from_secs_f32(10.0))
-                .repeat_infinite(),
-        );
-        sink.pause();
+            sink.pause();
+        }
 
         Buzzer {
+            output: output.cloned(),
             sink,
+            sound,
             last_started_at: SystemTime::now(),
+            sounding: false,
+            retrigger: false,
         }
     }
 
-    pub fn update(&mut self, is_buzzing: bool) {
-        if is_buzzing && self.sink.is_paused() {
-            self.sink.play();
+    pub fn handle(&mut self, event: &SoundEvent) {
+        self.sounding = matches!(event, SoundEvent::Started { .. });
+        self.retrigger |= self.sounding;
+    }
+
+    // Muting keeps track of the events, the beep comes back on unmute.
+    pub fn update(&mut self, muted: bool) {
+        match &self.sound {
+            Some(sound) if sound.mode == BeepMode::Once => self.update_once(muted),
+            _ => self.update_looping(muted),
+        }
+    }
+
+    fn update_looping(&mut self, muted: bool) {
+        let is_buzzing = self.sounding && !muted;
+        let Some(sink) = &self.sink else {
+            return;
+        };
+
+        if is_buzzing && sink.is_paused() {
+            sink.play();
             self.last_started_at = SystemTime::now();
             return;
         }
 
         if !is_buzzing
-            && !self.sink.is_paused()
+            && !sink.is_paused()
             && self.last_started_at.elapsed().unwrap().as_secs_f32() >= MIN_PLAYBACK_DURATION
         {
-            self.sink.pause();
+            sink.pause();
         }
     }
+
+    // A new beep cuts the previous one short, the sample always starts over.
+    fn update_once(&mut self, muted: bool) {
+        if muted {
+            if let Some(sink) = &self.sink {
+                sink.pause();
+            }
+
+            self.retrigger = false;
+            return;
+        }
+
+        if std::mem::take(&mut self.retrigger) {
+            let sound = self.sound.as_ref().unwrap();
+
+            self.sink = self
+                .output
+                .as_ref()
+                .and_then(|output| Sink::try_new(output).ok());
+
+            if let Some(sink) = &self.sink {
+                sink.append(sound.source());
+            }
+        } else if let Some(sink) = &self.sink {
+            sink.play();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[test]
+    fn a_wav_is_decoded_with_its_format() {
+        let mono = BeepSound::load(&fixture("beep.wav"), BeepMode::Loop).unwrap();
+
+        assert_eq!((mono.channels, mono.sample_rate), (1, 8000));
+        assert_eq!(mono.samples.len(), 400);
+        assert_eq!(mono.mode, BeepMode::Loop);
+
+        // Samples are interleaved, two per frame.
+        let stereo = BeepSound::load(&fixture("stereo.wav"), BeepMode::Once).unwrap();
+
+        assert_eq!((stereo.channels, stereo.sample_rate), (2, 11025));
+        assert_eq!(stereo.samples.len(), 220);
+        assert_eq!(stereo.mode, BeepMode::Once);
+    }
+
+    #[test]
+    fn a_file_without_samples_is_refused() {
+        let err = BeepSound::load(&fixture("silent.wav"), BeepMode::Loop).err();
+
+        assert_eq!(err.as_deref(), Some("no samples in the file"));
+    }
+
+    #[test]
+    fn a_bad_file_falls_back_to_the_tone() {
+        assert!(BeepSound::load(&fixture("corrupt.wav"), BeepMode::Loop).is_err());
+        assert!(BeepSound::load(&fixture("missing.wav"), BeepMode::Loop).is_err());
+        assert!(BeepSound::load(&fixture("beep.mp3"), BeepMode::Loop).is_err());
+
+        assert!(BeepSound::load_or_tone(&fixture("corrupt.wav"), BeepMode::Loop).is_none());
+        assert!(BeepSound::load_or_tone(&fixture("missing.wav"), BeepMode::Loop).is_none());
+        assert!(BeepSound::load_or_tone(&fixture("beep.wav"), BeepMode::Loop).is_some());
+    }
 }
@@ -0,0 +1,261 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use crate::cpu::{decompose_opcode, PROGRAM_START};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Subroutine {
+    // Bytes of code reachable from the entry without following calls.
+    pub size: usize,
+    pub calls: BTreeSet<u16>,
+    // BNNN jumps, their target depends on V0 at runtime.
+    pub unresolved: Vec<u16>,
+}
+
+// The entry point and every 2NNN target found walking from it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CallGraph {
+    pub subroutines: BTreeMap<u16, Subroutine>,
+}
+
+pub fn build(rom: &[u8]) -> CallGraph {
+    let mut graph = CallGraph::default();
+    let mut pending = vec![PROGRAM_START];
+
+    while let Some(entry) = pending.pop() {
+        if graph.subroutines.contains_key(&entry) {
+            continue;
+        }
+
+        let subroutine = walk(rom, entry);
+
+        pending.extend(subroutine.calls.iter().copied());
+        graph.subroutines.insert(entry, subroutine);
+    }
+
+    graph
+}
+
+fn walk(rom: &[u8], entry: u16) -> Subroutine {
+    let mut subroutine = Subroutine::default();
+    let mut visited = BTreeSet::new();
+    let mut pending = vec![entry];
+    let end = PROGRAM_START as usize + rom.len();
+
+    while let Some(address) = pending.pop() {
+        if (address as usize) < PROGRAM_START as usize
+            || address as usize + 1 >= end
+            || !visited.insert(address)
+        {
+            continue;
+        }
+
+        let offset = (address - PROGRAM_START) as usize;
+        let opcode = (rom[offset] as u16) << 8 | rom[offset + 1] as u16;
+        let target = opcode & 0x0FFF;
+        let next = address + 2;
+
+        match decompose_opcode(opcode) {
+            (0, 0, 0xE, 0xE) | (0, 0, 0, 0) => {}
+            (1, _, _, _) => pending.push(target),
+            (2, _, _, _) => {
+                subroutine.calls.insert(target);
+                pending.push(next);
+            }
+            (0xB, _, _, _) => subroutine.unresolved.push(address),
+            (3, _, _, _) | (4, _, _, _) | (5, _, _, 0) | (9, _, _, 0) => {
+                pending.extend([next, next + 2])
+            }
+            (0xE, _, 9, 0xE) | (0xE, _, 0xA, 1) => pending.extend([next, next + 2]),
+            _ => pending.push(next),
+        }
+    }
+
+    subroutine.size = visited.len() * 2;
+    subroutine
+}
+
+pub fn name(entry: u16) -> String {
+    if entry == PROGRAM_START {
+        "main".to_string()
+    } else {
+        format!("sub_{:03X}", entry)
+    }
+}
+
+// Names for routine entries from a symbol file, one `ADDRESS NAME` per line
+// with the address in hex. `#` starts a comment. Entries without one keep
+// the names of `name`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Symbols {
+    names: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut names = BTreeMap::new();
+
+        for (i, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let (address, symbol) = line
+                .split_once(char::is_whitespace)
+                .map(|(address, symbol)| (address, symbol.trim()))
+                .ok_or_else(|| format!("line {}: expected `ADDRESS NAME`", i + 1))?;
+            let digits = address.trim_start_matches("0x");
+            let address = u16::from_str_radix(digits, 16)
+                .ok()
+                .filter(|address| *address <= 0xFFF)
+                .ok_or_else(|| format!("line {}: invalid address {}", i + 1, address))?;
+
+            names.insert(address, symbol.to_string());
+        }
+
+        Ok(Symbols { names })
+    }
+
+    pub fn name(&self, entry: u16) -> String {
+        match self.names.get(&entry) {
+            Some(symbol) => symbol.clone(),
+            None => name(entry),
+        }
+    }
+}
+
+impl CallGraph {
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph callgraph {\n    node [shape=box];\n");
+
+        for (entry, subroutine) in &self.subroutines {
+            dot.push_str(&format!(
+                "    {} [label=\"{}\\n0x{:03X}, {} bytes\"];\n",
+                name(*entry),
+                name(*entry),
+                entry,
+                subroutine.size
+            ));
+
+            for callee in &subroutine.calls {
+                dot.push_str(&format!("    {} -> {};\n", name(*entry), name(*callee)));
+            }
+
+            for address in &subroutine.unresolved {
+                dot.push_str(&format!(
+                    "    jump_{:03X} [label=\"BNNN at 0x{:03X}\", style=dashed];\n    {} -> jump_{:03X} [style=dashed];\n",
+                    address,
+                    address,
+                    name(*entry),
+                    address
+                ));
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+// One line per subroutine: `sub_2F0 0x2F0, 12 bytes -> sub_320; unresolved BNNN at 0x2F6`.
+impl fmt::Display for CallGraph {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (entry, subroutine) in &self.subroutines {
+            write!(
+                f,
+                "{} 0x{:03X}, {} bytes",
+                name(*entry),
+                entry,
+                subroutine.size
+            )?;
+
+            if !subroutine.calls.is_empty() {
+                let calls: Vec<String> = subroutine
+                    .calls
+                    .iter()
+                    .map(|callee| name(*callee))
+                    .collect();
+
+                write!(f, " -> {}", calls.join(", "))?;
+            }
+
+            for address in &subroutine.unresolved {
+                write!(f, "; unresolved BNNN at 0x{:03X}", address)?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(program: &[u16]) -> Vec<u8> {
+        program
+            .iter()
+            .flat_map(|opcode| opcode.to_be_bytes())
+            .collect()
+    }
+
+    // main calls two subroutines, the first one a third behind a skip, the
+    // second one jumps through V0.
+    const PROGRAM: [u16; 9] = [
+        0x2206, 0x220C, 0x1204, // main
+        0x3001, 0x2210, 0x00EE, // sub_206
+        0xB300, // sub_20C
+        0x0000, // never reached
+        0x00EE, // sub_210
+    ];
+
+    #[test]
+    fn the_graph_follows_calls_jumps_and_skips() {
+        let graph = build(&rom(&PROGRAM));
+
+        assert_eq!(
+            graph.to_string(),
+            "main 0x200, 6 bytes -> sub_206, sub_20C\n\
+             sub_206 0x206, 6 bytes -> sub_210\n\
+             sub_20C 0x20C, 2 bytes; unresolved BNNN at 0x20C\n\
+             sub_210 0x210, 2 bytes\n"
+        );
+        assert_eq!(graph.subroutines[&0x20C].unresolved, [0x20C]);
+    }
+
+    #[test]
+    fn the_dot_output_has_a_node_per_subroutine_and_an_edge_per_call() {
+        let dot = build(&rom(&PROGRAM)).to_dot();
+
+        assert!(dot.starts_with("digraph callgraph {\n"));
+        assert!(dot.contains("    sub_206 [label=\"sub_206\\n0x206, 6 bytes\"];\n"));
+        assert!(dot.contains("    main -> sub_206;\n    main -> sub_20C;\n"));
+        assert!(dot.contains("    sub_206 -> sub_210;\n"));
+        assert!(dot.contains("    sub_20C -> jump_20C [style=dashed];\n"));
+        assert!(dot.ends_with("}\n"));
+        assert_eq!(dot.matches(" -> ").count(), 4);
+    }
+
+    #[test]
+    fn the_walk_stops_at_the_end_of_the_rom_and_on_recursion() {
+        // Calls itself, then falls off the end.
+        let graph = build(&rom(&[0x2202, 0x2202, 0x6001]));
+
+        assert_eq!(
+            graph.to_string(),
+            "main 0x200, 6 bytes -> sub_202\n\
+             sub_202 0x202, 4 bytes -> sub_202\n"
+        );
+        // Calls and jumps outside the ROM lead nowhere.
+        assert_eq!(
+            build(&rom(&[0x2100, 0x1F00])).to_string(),
+            "sub_100 0x100, 0 bytes\nmain 0x200, 4 bytes -> sub_100\n"
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    os::raw::c_int,
+    panic::{catch_unwind, AssertUnwindSafe},
+    slice,
+};
+
+use crate::{cpu::KEY_COUNT, emulator::Emulator};
+
+// include/chip8.h is generated from this file by cbindgen (see cbindgen.toml),
+// so the sizes are spelled out here rather than taken from the other modules.
+pub const CHIP8_DISPLAY_MAX_WIDTH: usize = 128;
+pub const CHIP8_DISPLAY_MAX_HEIGHT: usize = 64;
+pub const CHIP8_MAX_ROM_SIZE: usize = 3584;
+
+pub const CHIP8_OK: c_int = 0;
+pub const CHIP8_ERR_NULL_POINTER: c_int = -1;
+pub const CHIP8_ERR_ROM_TOO_LARGE: c_int = -2;
+pub const CHIP8_ERR_INVALID_KEY: c_int = -3;
+// The CPU faulted. It stays stopped, every later frame gets this too.
+pub const CHIP8_ERR_FAULT: c_int = -4;
+// A bug in the library. Unwinding into C would abort the host instead.
+pub const CHIP8_ERR_PANIC: c_int = -5;
+
+pub struct Chip8 {
+    emulator: Emulator,
+}
+
+// Every exported function runs in here, so a panic never crosses into C.
+fn guard<T>(on_panic: T, f: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(on_panic)
+}
+
+#[no_mangle]
+pub extern "C" fn chip8_new() -> *mut Chip8 {
+    guard(std::ptr::null_mut(), || {
+        Box::into_raw(Box::new(Chip8 {
+            emulator: Emulator::new(Default::default()),
+        }))
+    })
+}
+
+/// # Safety
+///
+/// `chip8` must come from `chip8_new` and `rom` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(chip8: *mut Chip8, rom: *const u8, len: usize) -> c_int {
+    if chip8.is_null() || rom.is_null() {
+        return CHIP8_ERR_NULL_POINTER;
+    }
+
+    if len > CHIP8_MAX_ROM_SIZE {
+        return CHIP8_ERR_ROM_TOO_LARGE;
+    }
+
+    let chip8 = &mut *chip8;
+    let rom = slice::from_raw_parts(rom, len);
+
+    // Starts over on a fresh CPU, which also clears a fault.
+    guard(CHIP8_ERR_PANIC, || match chip8.emulator.load_rom(rom) {
+        Ok(()) => {
+            chip8.emulator.reset();
+            CHIP8_OK
+        }
+        Err(_) => CHIP8_ERR_ROM_TOO_LARGE,
+    })
+}
+
+/// # Safety
+///
+/// `chip8` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frame(chip8: *mut Chip8) -> c_int {
+    if chip8.is_null() {
+        return CHIP8_ERR_NULL_POINTER;
+    }
+
+    let chip8 = &mut *chip8;
+
+    guard(CHIP8_ERR_PANIC, || match chip8.emulator.run_frame() {
+        Ok(_) => CHIP8_OK,
+        Err(_) => CHIP8_ERR_FAULT,
+    })
+}
+
+/// # Safety
+///
+/// `chip8` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_key(chip8: *mut Chip8, key: c_int, down: bool) -> c_int {
+    if chip8.is_null() {
+        return CHIP8_ERR_NULL_POINTER;
+    }
+
+    if !(0..KEY_COUNT as c_int).contains(&key) {
+        return CHIP8_ERR_INVALID_KEY;
+    }
+
+    let chip8 = &mut *chip8;
+
+    guard(CHIP8_ERR_PANIC, || {
+        if down {
+            chip8.emulator.press_key(key as u8);
+        } else {
+            chip8.emulator.release_key(key as u8);
+        }
+
+        CHIP8_OK
+    })
+}
+
+/// # Safety
+///
+/// `chip8` must come from `chip8_new`, `width` and `height` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_display_size(
+    chip8: *const Chip8,
+    width: *mut usize,
+    height: *mut usize,
+) -> c_int {
+    if chip8.is_null() || width.is_null() || height.is_null() {
+        return CHIP8_ERR_NULL_POINTER;
+    }
+
+    guard(CHIP8_ERR_PANIC, || {
+        let mode = (*chip8).emulator.display_mode();
+
+        *width = mode.width;
+        *height = mode.height;
+
+        CHIP8_OK
+    })
+}
+
+/// Writes width * height bytes for the current display size.
+///
+/// # Safety
+///
+/// `chip8` must come from `chip8_new` and `out` must point to at least
+/// `CHIP8_DISPLAY_MAX_WIDTH * CHIP8_DISPLAY_MAX_HEIGHT` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_display(chip8: *const Chip8, out: *mut u8) -> c_int {
+    if chip8.is_null() || out.is_null() {
+        return CHIP8_ERR_NULL_POINTER;
+    }
+
+    guard(CHIP8_ERR_PANIC, || {
+        let display = (*chip8).emulator.display();
+
+        slice::from_raw_parts_mut(out, display.len()).copy_from_slice(display);
+
+        CHIP8_OK
+    })
+}
+
+/// # Safety
+///
+/// `chip8` must come from `chip8_new` or be null.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_sound_active(chip8: *const Chip8) -> bool {
+    !chip8.is_null() && guard(false, || (*chip8).emulator.sound_active())
+}
+
+/// # Safety
+///
+/// `chip8` must come from `chip8_new` or be null, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(chip8: *mut Chip8) {
+    if !chip8.is_null() {
+        guard((), || drop(Box::from_raw(chip8)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cpu::MAX_ROM_SIZE, display::DisplayMode};
+
+    const HEADER: &str = include_str!("../include/chip8.h");
+
+    #[link(name = "capi_smoke", kind = "static")]
+    extern "C" {
+        fn chip8_capi_smoke() -> c_int;
+    }
+
+    #[test]
+    fn the_c_smoke_test_passes() {
+        assert_eq!(unsafe { chip8_capi_smoke() }, 0);
+    }
+
+    #[test]
+    fn a_panic_becomes_an_error_code() {
+        assert_eq!(guard(CHIP8_ERR_PANIC, || panic!("bug")), CHIP8_ERR_PANIC);
+        assert_eq!(guard(CHIP8_ERR_PANIC, || CHIP8_ERR_FAULT), CHIP8_ERR_FAULT);
+        assert!(guard(std::ptr::null_mut::<Chip8>(), || panic!("bug")).is_null());
+    }
+
+    // CI regenerates the header with cbindgen, this catches a stale one
+    // without it.
+    #[test]
+    fn the_header_declares_every_function_and_constant() {
+        let source = include_str!("capi.rs");
+        let functions = source
+            .lines()
+            .filter_map(|line| line.split_once("extern \"C\" fn "))
+            .filter_map(|(_, rest)| rest.split_once('('))
+            .map(|(name, _)| name);
+        let constants = source
+            .lines()
+            .filter_map(|line| line.strip_prefix("pub const "))
+            .filter_map(|line| line.split_once(": "))
+            .filter_map(|(name, rest)| Some((name, rest.split_once(" = ")?.1)))
+            .map(|(name, value)| (name, value.trim_end_matches(';')));
+
+        for name in functions {
+            assert!(
+                HEADER.contains(&format!("{}(", name)),
+                "{} is missing from chip8.h",
+                name
+            );
+        }
+
+        for (name, value) in constants {
+            assert!(
+                HEADER.contains(&format!("#define {} {}\n", name, value)),
+                "{} is missing from chip8.h or has another value",
+                name
+            );
+        }
+
+        // And nothing is declared that the library doesn't export.
+        let declarations = HEADER
+            .lines()
+            .filter(|line| !line.starts_with("//") && line.contains("chip8_"));
+
+        for line in declarations {
+            let name = line
+                .split('(')
+                .next()
+                .unwrap()
+                .rsplit([' ', '*'])
+                .next()
+                .unwrap();
+
+            assert!(
+                source.contains(&format!("extern \"C\" fn {}(", name)),
+                "chip8.h declares {}, which the library doesn't export",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn the_exported_sizes_match_the_library() {
+        assert_eq!(CHIP8_DISPLAY_MAX_WIDTH, DisplayMode::HIRES.width);
+        assert_eq!(CHIP8_DISPLAY_MAX_HEIGHT, DisplayMode::HIRES.height);
+        assert_eq!(CHIP8_MAX_ROM_SIZE, MAX_ROM_SIZE);
+    }
+}
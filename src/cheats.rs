@@ -0,0 +1,201 @@
+use crate::{
+    cpu::MEMORY_SIZE,
+    emulator::Emulator,
+    parse::{parse_byte, parse_number},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheatKind {
+    Freeze,
+    Patch,
+}
+
+#[derive(Clone, Debug)]
+pub struct Cheat {
+    pub name: String,
+    pub kind: CheatKind,
+    pub address: u16,
+    pub value: u8,
+    pub enabled: bool,
+    // What a patch wrote over, put back when the patch is turned off.
+    original: Option<u8>,
+}
+
+#[derive(Default)]
+pub struct Cheats {
+    entries: Vec<Cheat>,
+}
+
+impl Cheats {
+    // One cheat per line: `<freeze|patch> <address>=<value> <name>`, `#` starts a comment.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut entries = Vec::new();
+
+        for (i, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let cheat = Self::parse_line(line).map_err(|err| format!("line {}: {}", i + 1, err))?;
+
+            entries.push(cheat);
+        }
+
+        Ok(Cheats { entries })
+    }
+
+    fn parse_line(line: &str) -> Result<Cheat, String> {
+        let mut parts = line.splitn(3, char::is_whitespace);
+
+        let kind = match parts.next() {
+            Some("freeze") => CheatKind::Freeze,
+            Some("patch") => CheatKind::Patch,
+            Some(other) => return Err(format!("unknown cheat type `{}`", other)),
+            None => unreachable!(),
+        };
+
+        let assignment = parts.next().ok_or("missing `address=value`")?;
+        let (address, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| format!("expected `address=value`, got `{}`", assignment))?;
+
+        let address = parse_number(address)
+            .filter(|address| (*address as usize) < MEMORY_SIZE)
+            .ok_or_else(|| format!("invalid address `{}`", address))?;
+        let value = parse_byte(value).ok_or_else(|| format!("invalid value `{}`", value))?;
+
+        let name = parts.next().unwrap_or("").trim();
+        let name = if name.is_empty() {
+            format!("{:?} 0x{:03X}", kind, address)
+        } else {
+            name.to_string()
+        };
+
+        Ok(Cheat {
+            name,
+            kind,
+            address,
+            value,
+            enabled: true,
+            original: None,
+        })
+    }
+
+    pub fn entries(&self) -> &[Cheat] {
+        &self.entries
+    }
+
+    // Called again after a reset, the ROM's bytes are back under the patches.
+    pub fn apply_patches(&mut self, emulator: &mut Emulator) {
+        for cheat in &mut self.entries {
+            if cheat.enabled && cheat.kind == CheatKind::Patch {
+                cheat.patch(emulator);
+            }
+        }
+    }
+
+    // A patch turned off gives the byte it replaced back, a freeze just lets go.
+    pub fn toggle(&mut self, index: usize, emulator: &mut Emulator) -> Option<&Cheat> {
+        let cheat = self.entries.get_mut(index)?;
+
+        cheat.enabled = !cheat.enabled;
+
+        match (cheat.enabled, cheat.kind, cheat.original.take()) {
+            (true, CheatKind::Patch, _) => cheat.patch(emulator),
+            (true, CheatKind::Freeze, _) => emulator.write_memory(cheat.address, cheat.value),
+            (false, _, Some(original)) => emulator.write_memory(cheat.address, original),
+            (false, _, None) => {}
+        }
+
+        Some(cheat)
+    }
+
+    // Runs after the CPU so the frozen values win over whatever the ROM wrote.
+    pub fn apply(&self, emulator: &mut Emulator) {
+        for cheat in &self.entries {
+            if cheat.enabled && cheat.kind == CheatKind::Freeze {
+                emulator.write_memory(cheat.address, cheat.value);
+            }
+        }
+    }
+}
+
+impl Cheat {
+    fn patch(&mut self, emulator: &mut Emulator) {
+        self.original = Some(emulator.read_memory(self.address));
+        emulator.write_memory(self.address, self.value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::EmulatorOptions;
+
+    fn emulator(rom: &[u8]) -> Emulator {
+        let mut emulator = Emulator::new(EmulatorOptions::default());
+
+        emulator.load_rom(rom).unwrap();
+        emulator
+    }
+
+    #[test]
+    fn a_patch_turned_off_puts_the_rom_back() {
+        let mut emulator = emulator(&[0x60, 0x03, 0x12, 0x02]);
+        let mut cheats = Cheats::parse("patch 0x201=0x09 lives").unwrap();
+
+        cheats.apply_patches(&mut emulator);
+
+        assert_eq!(emulator.read_memory(0x201), 0x09);
+
+        let cheat = cheats.toggle(0, &mut emulator).unwrap();
+
+        assert!(!cheat.enabled);
+        assert_eq!(emulator.read_memory(0x201), 0x03);
+
+        cheats.toggle(0, &mut emulator);
+
+        assert_eq!(emulator.read_memory(0x201), 0x09);
+
+        cheats.toggle(0, &mut emulator);
+
+        assert_eq!(emulator.read_memory(0x201), 0x03);
+    }
+
+    #[test]
+    fn a_frozen_address_survives_fx55() {
+        // Stores V0 to V2 over 0x300 to 0x302 every frame.
+        let rom = [
+            0xA3, 0x00, 0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0xF2, 0x55, 0x12, 0x08,
+        ];
+        let mut emulator = emulator(&rom);
+        let cheats = Cheats::parse("freeze 0x301=0x63 energy").unwrap();
+
+        for _ in 0..3 {
+            emulator.run_frame().unwrap();
+            cheats.apply(&mut emulator);
+
+            assert_eq!(emulator.read_memory(0x300), 0x01);
+            assert_eq!(emulator.read_memory(0x301), 0x63);
+            assert_eq!(emulator.read_memory(0x302), 0x03);
+        }
+    }
+
+    #[test]
+    fn bad_lines_name_their_line_number() {
+        let cheats = Cheats::parse("# lives\nfreeze 0x1F0=3\n\npatch 0x200=0xFF jump").unwrap();
+
+        assert_eq!(cheats.entries().len(), 2);
+        assert_eq!(cheats.entries()[0].name, "Freeze 0x1F0");
+        assert_eq!(cheats.entries()[1].kind, CheatKind::Patch);
+
+        assert_eq!(
+            Cheats::parse("freeze 0x1F0=3\npoke 0x200=1").err(),
+            Some("line 2: unknown cheat type `poke`".to_string())
+        );
+        assert!(Cheats::parse("freeze 0x1000=1").is_err());
+        assert!(Cheats::parse("freeze 0x200=0x100").is_err());
+    }
+}
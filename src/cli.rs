@@ -0,0 +1,538 @@
+use std::{path::PathBuf, time::Duration};
+
+use chip8_emu::{
+    breakpoint::{CpuEvent, OpcodeBreakpoint},
+    filter::Filter,
+    font::{self, Font},
+    key_profile::{self, KeyBinding},
+    poke::Poke,
+    quirks::{self, Quirks},
+    timing::{self, Timing},
+};
+
+use crate::{buzzer::BeepMode, keypad::host_key, replay};
+
+pub const USAGE: &str = "\
+usage: chip8-emu ROM|-|--stdin [OPTIONS]
+       chip8-emu smoke|thumbnails|patch|compare|info|trace-diff|callgraph ...
+
+ROM:
+  --entry NAME                the ROM to take from a .zip
+  --assemble                  assemble the ROM as Octo source
+  --rom-dir DIR --pick NAME   pick the ROM in DIR closest to NAME
+  --test-pattern              show the display and keypad test, no ROM
+  --disassemble               print the ROM's listing and quit
+  --poke ADDR[..END]=VALUE    change memory after loading, repeatable
+  --force                     let --poke change the ROM itself
+  --cheats FILE               freeze and patch cheats, toggled while paused
+  --write-sidecar             save the settings next to the ROM
+
+Speed and compatibility:
+  --speed CYCLES              instructions per frame
+  --remember-speed            save --speed for this ROM
+  --timing fixed|vip          how long instructions take
+  --quirks chip8|schip|xo-chip
+  --refresh 50|60             frames and timer ticks per second
+  --skip-unknown-opcodes      warn about unknown instructions and go on
+  --strict-smc                fault when the ROM writes over its code
+  --enable-debug-opcodes      print the registers on FX01
+
+Display:
+  --font NAME | --font-file FILE
+  --invert
+  --high-contrast
+  --filter none|scale2x|scale3x
+  --highlight-draws           flash the last sprite drawn
+  --show-polling              show the keys the ROM is testing
+  --replay-seconds N          length of the F11 replay GIF
+
+Input:
+  --map HOST=KEY              bind a host key to a keypad key, repeatable
+  --remember-keys             save --map for this ROM
+  --turbo KEY[:FRAMES]        auto-repeat a keypad key
+  --macros FILE               key sequences on one host key
+  --measure-input-latency
+
+Sound:
+  --audio-device NAME
+  --list-audio-devices
+  --beep-sound FILE
+  --beep-mode loop|once
+
+Debugging:
+  --start-paused
+  --debugger                  read debugger commands from stdin
+  --debug-script FILE         run debugger commands first
+  --debug-script-strict       stop on a bad line in the script
+  --break-op PATTERN          stop on matching opcodes, repeatable
+  --break-on key,sound,cls,dbg
+  --break-on-debug-op
+  --trace FILE                write every instruction to FILE
+  --audit-memory
+  --profile | --profile-folded FILE
+  --symbols FILE              name the profiled routines, `ADDRESS NAME` lines
+  --frame-stats
+
+Unattended runs:
+  --max-cycles N              stop with exit code 3 after N instructions
+  --timeout SECONDS           stop with exit code 3 after SECONDS
+  --headless --cycles N       run without a window and print the display
+  --dump FILE.png|FILE.pgm    write the headless display to FILE
+  --print-keys-on-exit
+  --dump-display-on-exit";
+
+pub struct Options {
+    pub rom: String,
+    pub entry: Option<String>,
+    pub cheats: Option<String>,
+    pub macros: Option<String>,
+    pub pokes: Vec<Poke>,
+    pub force: bool,
+    pub audit_memory: bool,
+    pub strict_smc: bool,
+    pub skip_unknown_opcodes: bool,
+    pub test_pattern: bool,
+    pub speed: Option<u32>,
+    pub remember_speed: bool,
+    pub timing: Option<Timing>,
+    pub quirks: Option<Quirks>,
+    pub refresh_rate: Option<u32>,
+    pub print_keys_on_exit: bool,
+    pub font: Option<Font>,
+    pub font_file: Option<String>,
+    pub invert: bool,
+    pub high_contrast: bool,
+    pub filter: Filter,
+    pub dump_display: bool,
+    pub highlight_draws: bool,
+    pub turbo: Option<(u8, u32)>,
+    pub key_bindings: Vec<KeyBinding>,
+    pub remember_keys: bool,
+    pub max_cycles: Option<u64>,
+    pub timeout: Option<Duration>,
+    pub break_ops: Vec<OpcodeBreakpoint>,
+    pub break_events: Vec<CpuEvent>,
+    pub debug_script: Option<String>,
+    pub debug_script_strict: bool,
+    pub debugger: bool,
+    pub start_paused: bool,
+    pub frame_stats: bool,
+    pub audio_device: Option<String>,
+    pub list_audio_devices: bool,
+    pub beep_sound: Option<String>,
+    pub beep_mode: BeepMode,
+    pub measure_input_latency: bool,
+    pub write_sidecar: bool,
+    pub show_polling: bool,
+    pub replay_seconds: u32,
+    pub rom_dir: Option<String>,
+    pub pick: Option<String>,
+    pub assemble: bool,
+    pub debug_opcodes: bool,
+    pub profile: bool,
+    pub profile_folded: Option<String>,
+    pub symbols: Option<String>,
+    pub trace: Option<String>,
+    pub disassemble: bool,
+    pub headless: bool,
+    pub cycles: Option<u64>,
+    pub dump: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            rom: String::new(),
+            entry: None,
+            cheats: None,
+            macros: None,
+            pokes: Vec::new(),
+            force: false,
+            audit_memory: false,
+            strict_smc: false,
+            skip_unknown_opcodes: false,
+            test_pattern: false,
+            speed: None,
+            remember_speed: false,
+            timing: None,
+            quirks: None,
+            refresh_rate: None,
+            print_keys_on_exit: false,
+            font: None,
+            font_file: None,
+            invert: false,
+            high_contrast: false,
+            filter: Filter::None,
+            dump_display: false,
+            highlight_draws: false,
+            turbo: None,
+            key_bindings: Vec::new(),
+            remember_keys: false,
+            max_cycles: None,
+            timeout: None,
+            break_ops: Vec::new(),
+            break_events: Vec::new(),
+            debug_script: None,
+            debug_script_strict: false,
+            debugger: false,
+            start_paused: false,
+            frame_stats: false,
+            audio_device: None,
+            list_audio_devices: false,
+            beep_sound: None,
+            beep_mode: BeepMode::Loop,
+            measure_input_latency: false,
+            write_sidecar: false,
+            show_polling: false,
+            replay_seconds: replay::DEFAULT_SECONDS,
+            rom_dir: None,
+            pick: None,
+            assemble: false,
+            debug_opcodes: false,
+            profile: false,
+            profile_folded: None,
+            symbols: None,
+            trace: None,
+            disassemble: false,
+            headless: false,
+            cycles: None,
+            dump: None,
+        }
+    }
+}
+
+// The arguments after the program name. Nothing is read from disk here, the
+// files the options name are opened by the caller.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+
+        match arg.as_str() {
+            "--cheats" => options.cheats = Some(value()?),
+            "--macros" => options.macros = Some(value()?),
+            "--poke" => {
+                let poke =
+                    Poke::parse(&value()?).map_err(|err| format!("Invalid --poke: {}", err))?;
+
+                options.pokes.push(poke);
+            }
+            "--force" => options.force = true,
+            "--audit-memory" => options.audit_memory = true,
+            "--strict-smc" => options.strict_smc = true,
+            "--skip-unknown-opcodes" => options.skip_unknown_opcodes = true,
+            "--print-keys-on-exit" => options.print_keys_on_exit = true,
+            "--dump-display-on-exit" => options.dump_display = true,
+            "--invert" => options.invert = true,
+            "--highlight-draws" => options.highlight_draws = true,
+            "--map" => options.key_bindings.push(parse_key_binding(&value()?)?),
+            "--remember-keys" => options.remember_keys = true,
+            "--max-cycles" => options.max_cycles = Some(parse_limit("--max-cycles", &value()?)?),
+            "--timeout" => {
+                let seconds = parse_limit("--timeout", &value()?)?;
+
+                options.timeout = Some(Duration::from_secs(seconds));
+            }
+            "--break-op" => {
+                let breakpoint = OpcodeBreakpoint::parse(&value()?)
+                    .map_err(|err| format!("Invalid --break-op: {}", err))?;
+
+                options.break_ops.push(breakpoint);
+            }
+            "--break-on" => {
+                for name in value()?.split(',') {
+                    let event = CpuEvent::parse(name)
+                        .map_err(|err| format!("Invalid --break-on: {}", err))?;
+
+                    options.break_events.push(event);
+                }
+            }
+            "--debug-script" => options.debug_script = Some(value()?),
+            "--debug-script-strict" => options.debug_script_strict = true,
+            "--debugger" => options.debugger = true,
+            "--start-paused" => options.start_paused = true,
+            "--frame-stats" => options.frame_stats = true,
+            "--measure-input-latency" => options.measure_input_latency = true,
+            "--write-sidecar" => options.write_sidecar = true,
+            "--show-polling" => options.show_polling = true,
+            "--replay-seconds" => {
+                let seconds = parse_limit("--replay-seconds", &value()?)?;
+
+                options.replay_seconds = seconds.try_into().unwrap_or(u32::MAX);
+            }
+            "--rom-dir" => options.rom_dir = Some(value()?),
+            "--pick" => options.pick = Some(value()?),
+            "--assemble" => options.assemble = true,
+            "--enable-debug-opcodes" => options.debug_opcodes = true,
+            "--profile" => options.profile = true,
+            "--profile-folded" => options.profile_folded = Some(value()?),
+            "--symbols" => options.symbols = Some(value()?),
+            "--trace" => options.trace = Some(value()?),
+            "--disassemble" => options.disassemble = true,
+            "--headless" => options.headless = true,
+            "--cycles" => options.cycles = Some(parse_limit("--cycles", &value()?)?),
+            "--dump" => options.dump = Some(PathBuf::from(value()?)),
+            "--break-on-debug-op" => {
+                options.debug_opcodes = true;
+                options.break_events.push(CpuEvent::DebugOp);
+            }
+            "--audio-device" => options.audio_device = Some(value()?),
+            "--list-audio-devices" => options.list_audio_devices = true,
+            "--beep-sound" => options.beep_sound = Some(value()?),
+            "--beep-mode" => options.beep_mode = parse_beep_mode(&value()?)?,
+            "--turbo" => options.turbo = Some(parse_turbo(&value()?)?),
+            "--filter" => options.filter = parse_filter(&value()?)?,
+            "--high-contrast" => options.high_contrast = true,
+            "--font" => options.font = Some(builtin_font(&value()?)?),
+            "--font-file" => options.font_file = Some(value()?),
+            "--test-pattern" => options.test_pattern = true,
+            "--speed" => options.speed = Some(parse_speed(&value()?)?),
+            "--remember-speed" => options.remember_speed = true,
+            "--refresh" => options.refresh_rate = Some(parse_refresh_rate(&value()?)?),
+            "--timing" => options.timing = Some(parse_timing(&value()?)?),
+            "--quirks" => options.quirks = Some(parse_quirks(&value()?)?),
+            "--stdin" => options.rom = "-".to_string(),
+            "--entry" => options.entry = Some(value()?),
+            _ if arg == "-" || !arg.starts_with('-') => options.rom = arg,
+            _ => return Err(format!("Unknown option {}", arg)),
+        }
+    }
+
+    // The test pattern, --pick and the device list don't need one.
+    let needs_rom = !options.test_pattern && options.pick.is_none() && !options.list_audio_devices;
+
+    if options.rom.is_empty() && needs_rom {
+        return Err("No ROM given".to_string());
+    }
+
+    // There is nothing to type a command into before the first instruction otherwise.
+    if options.debugger {
+        options.start_paused = true;
+    }
+
+    if options.debugger && options.rom == "-" {
+        return Err(
+            "--debugger reads its commands from stdin, it can't read the ROM from there too"
+                .to_string(),
+        );
+    }
+
+    if options.rom_dir.is_some() && options.pick.is_none() {
+        return Err("--rom-dir needs --pick NAME".to_string());
+    }
+
+    if options.write_sidecar && (options.rom == "-" || options.test_pattern) {
+        return Err("--write-sidecar needs a ROM file to put the settings next to".to_string());
+    }
+
+    if options.headless && options.cycles.is_none() {
+        return Err("--headless needs --cycles N".to_string());
+    }
+
+    Ok(options)
+}
+
+pub fn parse_limit(flag: &str, text: &str) -> Result<u64, String> {
+    match text.parse() {
+        Ok(limit) if limit > 0 => Ok(limit),
+        _ => Err(format!("Invalid {}: {}", flag, text)),
+    }
+}
+
+fn parse_speed(text: &str) -> Result<u32, String> {
+    match text.parse() {
+        Ok(speed) if speed > 0 => Ok(speed),
+        _ => Err(format!("Invalid --speed: {}", text)),
+    }
+}
+
+fn parse_timing(text: &str) -> Result<Timing, String> {
+    match text {
+        "fixed" => Ok(Timing::Fixed),
+        "vip" => Ok(Timing::Vip),
+        _ => Err(format!(
+            "Invalid --timing: {} (expected fixed or vip)",
+            text
+        )),
+    }
+}
+
+fn parse_quirks(text: &str) -> Result<Quirks, String> {
+    Quirks::preset(text).ok_or_else(|| {
+        format!(
+            "Invalid --quirks: {} (expected {})",
+            text,
+            quirks::PRESET_NAMES.join(", ")
+        )
+    })
+}
+
+fn parse_beep_mode(text: &str) -> Result<BeepMode, String> {
+    match text {
+        "loop" => Ok(BeepMode::Loop),
+        "once" => Ok(BeepMode::Once),
+        _ => Err(format!(
+            "Invalid --beep-mode: {} (expected loop or once)",
+            text
+        )),
+    }
+}
+
+fn parse_refresh_rate(text: &str) -> Result<u32, String> {
+    match text.parse() {
+        Ok(hz) if timing::is_valid_refresh_rate(hz) => Ok(hz),
+        _ => Err(format!("Invalid --refresh: {} (expected 50 or 60)", text)),
+    }
+}
+
+fn parse_filter(text: &str) -> Result<Filter, String> {
+    Filter::parse(text).ok_or_else(|| {
+        format!(
+            "Invalid --filter: {} (expected none, scale2x or scale3x)",
+            text
+        )
+    })
+}
+
+// `KEY[:FRAMES]`, the keypad key in hex and how many frames each press and
+// release lasts.
+fn parse_turbo(text: &str) -> Result<(u8, u32), String> {
+    let (key, rate) = text.split_once(':').unwrap_or((text, "2"));
+
+    match (u8::from_str_radix(key, 16), rate.parse()) {
+        (Ok(key), Ok(rate)) if key < 0x10 && rate > 0 => Ok((key, rate)),
+        _ => Err(format!("Invalid --turbo: {} (expected KEY[:FRAMES])", text)),
+    }
+}
+
+fn parse_key_binding(text: &str) -> Result<KeyBinding, String> {
+    key_profile::parse_binding(text)
+        .and_then(|binding| match host_key(&binding.0) {
+            Some(_) => Ok(binding),
+            None => Err(format!("unknown host key `{}`", binding.0)),
+        })
+        .map_err(|err| format!("Invalid --map: {}", err))
+}
+
+fn builtin_font(name: &str) -> Result<Font, String> {
+    font::by_name(name).copied().ok_or_else(|| {
+        let names: Vec<&str> = font::FONTS.iter().map(|(name, _)| *name).collect();
+
+        format!("Unknown font {} (available: {})", name, names.join(", "))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_line(line: &str) -> Result<Options, String> {
+        parse(line.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn a_rom_is_required() {
+        assert_eq!(parse_line("").err().unwrap(), "No ROM given");
+        assert_eq!(parse_line("--speed 20").err().unwrap(), "No ROM given");
+
+        for line in [
+            "--test-pattern",
+            "--rom-dir roms --pick brix",
+            "--list-audio-devices",
+        ] {
+            assert!(parse_line(line).is_ok(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn the_defaults() {
+        let options = parse_line("game.ch8").unwrap();
+
+        assert_eq!(options.rom, "game.ch8");
+        assert_eq!(options.replay_seconds, replay::DEFAULT_SECONDS);
+        assert!(options.pokes.is_empty());
+        assert!(!options.headless);
+    }
+
+    #[test]
+    fn flags_and_values_land_in_their_fields() {
+        let options = parse_line(
+            "game.ch8 --speed 20 --timing vip --quirks schip --refresh 50 --poke 0x300=7 \
+             --map Q=4 --map W=5 --turbo 5:3 --break-on key,cls --max-cycles 100 \
+             --timeout 2 --filter scale2x --font-file big.bin --beep-mode once",
+        )
+        .unwrap();
+
+        assert_eq!(options.rom, "game.ch8");
+        assert_eq!(options.speed, Some(20));
+        assert_eq!(options.timing, Some(Timing::Vip));
+        assert_eq!(options.quirks, Quirks::preset("schip"));
+        assert_eq!(options.refresh_rate, Some(50));
+        assert_eq!(options.pokes.len(), 1);
+        assert_eq!(options.pokes[0].value, 7);
+        assert_eq!(
+            options.key_bindings,
+            [("Q".to_string(), 4), ("W".to_string(), 5)]
+        );
+        assert_eq!(options.turbo, Some((5, 3)));
+        assert_eq!(
+            options.break_events,
+            [CpuEvent::KeyRead, CpuEvent::ClearScreen]
+        );
+        assert_eq!(options.max_cycles, Some(100));
+        assert_eq!(options.timeout, Some(Duration::from_secs(2)));
+        assert_eq!(options.filter, Filter::parse("scale2x").unwrap());
+        assert_eq!(options.font_file.as_deref(), Some("big.bin"));
+        assert_eq!(options.beep_mode, BeepMode::Once);
+    }
+
+    #[test]
+    fn the_last_rom_and_stdin_win() {
+        assert_eq!(parse_line("a.ch8 b.ch8").unwrap().rom, "b.ch8");
+        assert_eq!(parse_line("a.ch8 --stdin").unwrap().rom, "-");
+        assert_eq!(parse_line("-").unwrap().rom, "-");
+    }
+
+    #[test]
+    fn some_flags_imply_others() {
+        let options = parse_line("game.ch8 --debugger").unwrap();
+
+        assert!(options.start_paused);
+
+        let options = parse_line("game.ch8 --break-on-debug-op").unwrap();
+
+        assert!(options.debug_opcodes);
+        assert_eq!(options.break_events, [CpuEvent::DebugOp]);
+
+        assert_eq!(
+            parse_line("game.ch8 --turbo a").unwrap().turbo,
+            Some((0xA, 2))
+        );
+    }
+
+    #[test]
+    fn bad_arguments_are_errors() {
+        for (line, error) in [
+            ("--bogus", "Unknown option --bogus"),
+            ("--speed", "--speed needs a value"),
+            ("--speed 0", "Invalid --speed: 0"),
+            ("--max-cycles lots", "Invalid --max-cycles: lots"),
+            ("--refresh 75", "Invalid --refresh: 75 (expected 50 or 60)"),
+            ("--turbo 10", "Invalid --turbo: 10 (expected KEY[:FRAMES])"),
+            ("game.ch8 --rom-dir roms", "--rom-dir needs --pick NAME"),
+            ("game.ch8 --headless", "--headless needs --cycles N"),
+            (
+                "--stdin --write-sidecar",
+                "--write-sidecar needs a ROM file to put the settings next to",
+            ),
+        ] {
+            assert_eq!(parse_line(line).err().as_deref(), Some(error), "{}", line);
+        }
+
+        assert!(parse_line("--map nokey=4").is_err());
+        assert!(parse_line("--font nofont").is_err());
+        assert!(parse_line("- --debugger").is_err());
+    }
+}
@@ -0,0 +1,84 @@
+use std::time::{Duration, Instant};
+
+pub const MAX_FRAMES_PER_TICK: u32 = 4;
+
+// Fixed-timestep pacing for a front end: however often it wakes up, frames
+// come out at one per `frame_duration` of elapsed time on average.
+pub struct Clock {
+    last_frame: Instant,
+}
+
+impl Clock {
+    pub fn new(now: Instant) -> Self {
+        Clock { last_frame: now }
+    }
+
+    // Drops whatever was due, for resuming after a pause.
+    pub fn restart(&mut self, now: Instant) {
+        self.last_frame = now;
+    }
+
+    // The number of frames to run at `now`. After a stall longer than
+    // MAX_FRAMES_PER_TICK frames the missed ones are dropped instead of
+    // being caught up on.
+    pub fn tick(&mut self, now: Instant, frame_duration: Duration) -> u32 {
+        let elapsed = now.saturating_duration_since(self.last_frame);
+        let due = elapsed.as_nanos() / frame_duration.as_nanos().max(1);
+
+        if due > MAX_FRAMES_PER_TICK as u128 {
+            self.last_frame = now;
+            return MAX_FRAMES_PER_TICK;
+        }
+
+        self.last_frame += frame_duration * due as u32;
+        due as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn frames_come_out_as_time_passes() {
+        let start = Instant::now();
+        let mut clock = Clock::new(start);
+
+        assert_eq!(clock.tick(start, FRAME), 0);
+        assert_eq!(clock.tick(start + Duration::from_millis(9), FRAME), 0);
+        assert_eq!(clock.tick(start + Duration::from_millis(10), FRAME), 1);
+        // The leftover 5ms carries over to the next tick.
+        assert_eq!(clock.tick(start + Duration::from_millis(25), FRAME), 1);
+        assert_eq!(clock.tick(start + Duration::from_millis(30), FRAME), 1);
+        assert_eq!(clock.tick(start + Duration::from_millis(60), FRAME), 3);
+        // Time going backwards runs nothing.
+        assert_eq!(clock.tick(start, FRAME), 0);
+    }
+
+    #[test]
+    fn a_stall_runs_at_most_max_frames_per_tick() {
+        let start = Instant::now();
+        let mut clock = Clock::new(start);
+        let stalled = start + FRAME * 100 + Duration::from_millis(5);
+
+        assert_eq!(clock.tick(start + FRAME * 4, FRAME), MAX_FRAMES_PER_TICK);
+        assert_eq!(clock.tick(stalled, FRAME), MAX_FRAMES_PER_TICK);
+        // The missed frames were dropped, the leftover with them.
+        assert_eq!(clock.tick(stalled + Duration::from_millis(9), FRAME), 0);
+        assert_eq!(clock.tick(stalled + FRAME, FRAME), 1);
+    }
+
+    #[test]
+    fn restart_drops_what_was_due() {
+        let start = Instant::now();
+        let mut clock = Clock::new(start);
+        let resumed = start + FRAME * 3;
+
+        clock.restart(resumed);
+
+        assert_eq!(clock.tick(resumed, FRAME), 0);
+        assert_eq!(clock.tick(resumed + FRAME, FRAME), 1);
+    }
+}
@@ -0,0 +1,369 @@
+use std::{process, time::Instant};
+
+use chip8_emu::{
+    clock::Clock,
+    display::DisplayMode,
+    emulator::{Emulator, EmulatorOptions},
+    quirks::Quirks,
+    timing::Timing,
+};
+use pixels::{Pixels, SurfaceTexture};
+use winit::{
+    dpi::PhysicalSize,
+    event::{Event, VirtualKeyCode},
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+use winit_input_helper::WinitInputHelper;
+
+use crate::{config::Config, keypad::Keypad, palette::Palette};
+
+const SCALE: f64 = 10.0;
+// Columns of the lo-res grid left between the two screens.
+const GAP: usize = 2;
+const GAP_COLOR: [u8; 4] = [0x20, 0x20, 0x20, 0xff];
+const PAUSE_KEY: VirtualKeyCode = VirtualKeyCode::P;
+const STEP_KEY: VirtualKeyCode = VirtualKeyCode::N;
+const DIFF_KEY: VirtualKeyCode = VirtualKeyCode::H;
+
+pub struct Side {
+    pub label: String,
+    pub options: EmulatorOptions,
+}
+
+// `vip`, `fixed`, a quirks preset, or a comma list of `timing=fixed|vip`,
+// `quirks=NAME` and `speed=N`.
+pub fn parse_side(spec: &str) -> Result<Side, String> {
+    let mut options = EmulatorOptions::default();
+
+    for setting in spec.split(',') {
+        match setting.split_once('=') {
+            None if setting == "fixed" => options.timing = Timing::Fixed,
+            None if setting == "vip" => options.timing = Timing::Vip,
+            Some(("timing", "fixed")) => options.timing = Timing::Fixed,
+            Some(("timing", "vip")) => options.timing = Timing::Vip,
+            None if Quirks::preset(setting).is_some() => {
+                options.quirks = Quirks::preset(setting).unwrap()
+            }
+            Some(("quirks", name)) => {
+                options.quirks =
+                    Quirks::preset(name).ok_or_else(|| format!("unknown quirks `{}`", name))?
+            }
+            Some(("speed", speed)) => {
+                options.cycles_per_frame = speed
+                    .parse()
+                    .ok()
+                    .filter(|speed| *speed > 0)
+                    .ok_or_else(|| format!("invalid speed `{}`", speed))?
+            }
+            _ => return Err(format!("unknown setting `{}`", setting)),
+        }
+    }
+
+    Ok(Side {
+        label: spec.to_string(),
+        options,
+    })
+}
+
+// Both screens at the size of the larger mode, lo-res drawn 2x2 next to
+// hi-res. Pixels where the two disagree get the highlight mixed in.
+pub fn compose(
+    frame: &mut [u8],
+    left: (&[u8], DisplayMode),
+    right: (&[u8], DisplayMode),
+    palette: Palette,
+    highlight: bool,
+) {
+    let canvas = canvas(left.1, right.1);
+    let width = frame_width(canvas);
+    let gap = canvas.width..width - canvas.width;
+    let pixel = |(display, mode): (&[u8], DisplayMode), x: usize, y: usize| {
+        let factor = canvas.width / mode.width;
+
+        display[mode.index(x / factor, y / factor)]
+    };
+
+    for (i, color) in frame.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (i % width, i / width);
+
+        if gap.contains(&x) {
+            color.copy_from_slice(&GAP_COLOR);
+            continue;
+        }
+
+        let column = if x < canvas.width { x } else { x - gap.end };
+        let shown = if x < canvas.width { left } else { right };
+        let value = pixel(shown, column, y);
+        let mut rgba = palette.color(value);
+
+        if highlight && pixel(left, column, y) != pixel(right, column, y) {
+            rgba = Palette::highlight(rgba, 0.8);
+        }
+
+        color.copy_from_slice(&rgba);
+    }
+}
+
+pub fn canvas(left: DisplayMode, right: DisplayMode) -> DisplayMode {
+    if left == DisplayMode::HIRES || right == DisplayMode::HIRES {
+        DisplayMode::HIRES
+    } else {
+        DisplayMode::LORES
+    }
+}
+
+pub fn frame_width(canvas: DisplayMode) -> usize {
+    canvas.width * 2 + GAP * canvas.width / DisplayMode::LORES.width
+}
+
+fn build(side: &Side, rom: &[u8], seed: u64) -> Emulator {
+    let mut emulator = Emulator::new(side.options);
+
+    emulator.load_rom(rom).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    emulator.set_frame_timers(true);
+    emulator.seed_random(seed);
+    emulator
+}
+
+// Both sides run a frame on every tick of the left one's clock, with the
+// timers following the frames and the same seed, so only their settings
+// tell them apart.
+pub fn run(title: &str, rom: &[u8], left: Side, right: Side, seed: u64, config: &Config) -> ! {
+    let mut emulators = [build(&left, rom, seed), build(&right, rom, seed)];
+    let mut keypad = Keypad::with_host_keys(&config.host_keys);
+    let palette = config.palette;
+    let mut input = WinitInputHelper::new();
+    let mut paused = false;
+    let mut highlight = false;
+    let mut clock = Clock::new(Instant::now());
+    let frame_duration = emulators[0].frame_duration();
+
+    println!("Left: {}, right: {}", left.label, right.label);
+
+    let event_loop = EventLoop::new();
+    let lores = DisplayMode::LORES;
+    let size = PhysicalSize::new(
+        frame_width(lores) as f64 * SCALE,
+        lores.height as f64 * SCALE,
+    );
+    let window = WindowBuilder::new()
+        .with_title(format!(
+            "chip-8 compare - {} - {} | {}",
+            title, left.label, right.label
+        ))
+        .with_inner_size(size)
+        .with_resizable(false)
+        .build(&event_loop)
+        .unwrap();
+    let mut pixels = {
+        let window_size = window.inner_size();
+        let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
+
+        Pixels::new(
+            frame_width(lores) as u32,
+            lores.height as u32,
+            surface_texture,
+        )
+        .unwrap()
+    };
+    let mut buffer_canvas = lores;
+
+    event_loop.run(move |event, _, control_flow| {
+        if let Event::RedrawRequested(_) = event {
+            let modes = emulators.each_ref().map(|emulator| emulator.display_mode());
+            let canvas = canvas(modes[0], modes[1]);
+
+            if canvas != buffer_canvas {
+                pixels
+                    .resize_buffer(frame_width(canvas) as u32, canvas.height as u32)
+                    .unwrap();
+                buffer_canvas = canvas;
+            }
+
+            compose(
+                pixels.get_frame_mut(),
+                (emulators[0].display(), modes[0]),
+                (emulators[1].display(), modes[1]),
+                palette,
+                highlight,
+            );
+            pixels.render().unwrap();
+        }
+
+        if !input.update(&event) {
+            return;
+        }
+
+        if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+            *control_flow = ControlFlow::Exit;
+            return;
+        }
+
+        if input.key_pressed(PAUSE_KEY) {
+            paused = !paused;
+            clock.restart(Instant::now());
+        }
+
+        if input.key_pressed(DIFF_KEY) {
+            highlight = !highlight;
+            window.request_redraw();
+        }
+
+        let step = paused && input.key_pressed(STEP_KEY);
+
+        let changes = keypad.read(&input);
+
+        for emulator in &mut emulators {
+            for (key, pressed) in &changes {
+                if *pressed {
+                    emulator.press_key(*key);
+                } else {
+                    emulator.release_key(*key);
+                }
+            }
+        }
+
+        let frames = match (paused, step) {
+            (false, _) => clock.tick(Instant::now(), frame_duration),
+            (true, step) => step as u32,
+        };
+
+        for _ in 0..frames {
+            // A side that faulted freezes on its last frame, the other one
+            // goes on.
+            for (i, emulator) in emulators.iter_mut().enumerate() {
+                if emulator.fault().is_some() {
+                    continue;
+                }
+
+                if let Err(error) = emulator.run_frame() {
+                    let side = [&left.label, &right.label][i];
+
+                    eprintln!("{} stopped: {}", side, error);
+                }
+            }
+
+            window.request_redraw();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+    const BLACK: [u8; 4] = [0x00, 0x00, 0x00, 0xff];
+    // 80% of the highlight mixed into each.
+    const WHITE_HIGHLIGHTED: [u8; 4] = [255, 211, 89, 0xff];
+    const BLACK_HIGHLIGHTED: [u8; 4] = [204, 160, 38, 0xff];
+
+    fn display(mode: DisplayMode, lit: &[(usize, usize)]) -> Vec<u8> {
+        let mut display = vec![0; mode.width * mode.height];
+
+        for &(x, y) in lit {
+            display[mode.index(x, y)] = 1;
+        }
+
+        display
+    }
+
+    fn composed(
+        left: (&[u8], DisplayMode),
+        right: (&[u8], DisplayMode),
+        highlight: bool,
+    ) -> (Vec<u8>, usize) {
+        let canvas = canvas(left.1, right.1);
+        let width = frame_width(canvas);
+        let mut frame = vec![0; width * canvas.height * 4];
+
+        compose(&mut frame, left, right, Palette::HIGH_CONTRAST, highlight);
+        (frame, width)
+    }
+
+    fn pixel(frame: &[u8], width: usize, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * width + x) * 4;
+
+        frame[i..i + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn only_the_pixels_that_differ_are_highlighted() {
+        let lores = DisplayMode::LORES;
+        let left = display(lores, &[(0, 0), (3, 1)]);
+        let right = display(lores, &[(0, 0), (10, 5)]);
+        let (frame, width) = composed((&left, lores), (&right, lores), true);
+
+        assert_eq!(width, 130);
+
+        for y in 0..lores.height {
+            for x in 0..width {
+                let expected = match (x, y) {
+                    (64 | 65, _) => GAP_COLOR,
+                    // Lit on both sides.
+                    (0, 0) | (66, 0) => WHITE,
+                    // Lit on the left only, so dark on the right.
+                    (3, 1) => WHITE_HIGHLIGHTED,
+                    (69, 1) => BLACK_HIGHLIGHTED,
+                    // And the other way round.
+                    (10, 5) => BLACK_HIGHLIGHTED,
+                    (76, 5) => WHITE_HIGHLIGHTED,
+                    _ => BLACK,
+                };
+
+                assert_eq!(pixel(&frame, width, x, y), expected, "at {}, {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn without_the_highlight_each_side_is_drawn_as_is() {
+        let lores = DisplayMode::LORES;
+        let left = display(lores, &[(3, 1)]);
+        let right = display(lores, &[(10, 5)]);
+        let (frame, width) = composed((&left, lores), (&right, lores), false);
+
+        for y in 0..lores.height {
+            for x in 0..width {
+                let expected = match (x, y) {
+                    (64 | 65, _) => GAP_COLOR,
+                    (3, 1) | (76, 5) => WHITE,
+                    _ => BLACK,
+                };
+
+                assert_eq!(pixel(&frame, width, x, y), expected, "at {}, {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn a_lores_side_is_doubled_against_a_hires_one() {
+        let (lores, hires) = (DisplayMode::LORES, DisplayMode::HIRES);
+        // One lo-res pixel covers 2..4 on both axes, the hi-res side lights
+        // only the first of those four.
+        let left = display(lores, &[(1, 1)]);
+        let right = display(hires, &[(2, 2)]);
+        let (frame, width) = composed((&left, lores), (&right, hires), true);
+
+        assert_eq!(width, 260);
+
+        for y in 0..hires.height {
+            for x in 0..width {
+                let expected = match (x, y) {
+                    (128..=131, _) => GAP_COLOR,
+                    (2, 2) | (134, 2) => WHITE,
+                    (2..=3, 2..=3) => WHITE_HIGHLIGHTED,
+                    (134..=135, 2..=3) => BLACK_HIGHLIGHTED,
+                    _ => BLACK,
+                };
+
+                assert_eq!(pixel(&frame, width, x, y), expected, "at {}, {}", x, y);
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::{env, path::PathBuf};
+
+use chip8_emu::flat_toml::{self, Value};
+use winit::event::VirtualKeyCode;
+
+use crate::{
+    keypad::{self, HostKeys, HOST_KEYS},
+    palette::{self, Palette},
+};
+
+const FILE_NAME: &str = "chip8.toml";
+
+// Colors and keys for every ROM, from `chip8.toml` next to the executable:
+//
+//     [display]
+//     on-color = "#5E48E8"
+//     off-color = "#48B2E8"
+//
+//     [keypad]
+//     5 = "Up"
+//
+// What the file leaves out keeps its built-in default.
+pub struct Config {
+    pub palette: Palette,
+    pub host_keys: HostKeys,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            palette: Palette::DEFAULT,
+            host_keys: keypad::default_host_keys(),
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        let exe = env::current_exe().ok()?;
+
+        Some(exe.parent()?.join(FILE_NAME))
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut config = Config::default();
+        let mut assigned = [false; 0x10];
+
+        flat_toml::parse(source, |section, field, value| {
+            match (section, field, value) {
+                (Some("display"), "on-color", Value::String(color)) => {
+                    config.palette =
+                        Palette::new(palette::parse_color(&color)?, config.palette.color(0))
+                }
+                (Some("display"), "off-color", Value::String(color)) => {
+                    config.palette =
+                        Palette::new(config.palette.color(1), palette::parse_color(&color)?)
+                }
+                (Some("display"), "on-color" | "off-color", _) => {
+                    return Err("expected a color like \"#5E48E8\"".to_string())
+                }
+                (Some("keypad"), key, value) => {
+                    let key = parse_keypad_key(key)?;
+                    let host = match value {
+                        Value::String(name) => parse_host_key(&name)?,
+                        _ => return Err("expected a key name".to_string()),
+                    };
+
+                    if assigned[key] {
+                        return Err(format!("keypad key {:X} is assigned twice", key));
+                    }
+
+                    assigned[key] = true;
+                    config.host_keys[key] = host;
+                }
+                _ => return Err("unknown field".to_string()),
+            }
+
+            Ok(())
+        })?;
+
+        // Also catches a key taken from another keypad key's default.
+        for (i, host) in config.host_keys.iter().enumerate() {
+            if let Some(j) = config.host_keys[i + 1..]
+                .iter()
+                .position(|other| other == host)
+            {
+                return Err(format!(
+                    "`{}` drives both keypad keys {:X} and {:X}",
+                    host_key_name(*host),
+                    i,
+                    i + 1 + j
+                ));
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_keypad_key(text: &str) -> Result<usize, String> {
+    match u8::from_str_radix(text, 16) {
+        Ok(key) if text.len() == 1 => Ok(key as usize),
+        _ => Err("expected a keypad key from 0 to F".to_string()),
+    }
+}
+
+fn parse_host_key(name: &str) -> Result<VirtualKeyCode, String> {
+    keypad::host_key(name).ok_or_else(|| {
+        let names: Vec<&str> = HOST_KEYS.iter().map(|(name, _)| *name).collect();
+
+        format!(
+            "unknown key `{}`, expected one of {}",
+            name,
+            names.join(", ")
+        )
+    })
+}
+
+fn host_key_name(key: VirtualKeyCode) -> &'static str {
+    HOST_KEYS
+        .iter()
+        .find(|(_, host)| *host == key)
+        .map_or("?", |(name, _)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> Option<String> {
+        Config::parse(source).err()
+    }
+
+    #[test]
+    fn a_valid_file_sets_colors_and_keys() {
+        let config = Config::parse(
+            "# Arrows for the direction keys\n\
+             [display]\n\
+             on-color = \"#FFFFFF\"\n\
+             off-color = \"#000000\"\n\
+             \n\
+             [keypad]\n\
+             5 = \"Up\"\n\
+             8 = \"Down\"\n",
+        )
+        .unwrap();
+        let mut host_keys = keypad::default_host_keys();
+
+        host_keys[5] = VirtualKeyCode::Up;
+        host_keys[8] = VirtualKeyCode::Down;
+
+        assert!(config.palette == Palette::new([0xFF; 4], [0, 0, 0, 0xFF]));
+        assert_eq!(config.host_keys, host_keys);
+    }
+
+    #[test]
+    fn an_empty_file_keeps_the_defaults() {
+        let config = Config::parse("").unwrap();
+
+        assert!(config.palette == Palette::DEFAULT);
+        assert_eq!(config.host_keys, keypad::default_host_keys());
+    }
+
+    #[test]
+    fn malformed_colors_are_rejected() {
+        for color in ["\"5E48E8\"", "\"#5E48E\"", "\"#5E48EG\"", "5"] {
+            let source = format!("[display]\non-color = {}", color);
+
+            assert!(error(&source).unwrap().starts_with("line 2: on-color: "));
+        }
+    }
+
+    #[test]
+    fn duplicate_keys_are_rejected() {
+        assert_eq!(
+            error("[keypad]\n5 = \"Up\"\n5 = \"Down\"").as_deref(),
+            Some("line 3: 5: keypad key 5 is assigned twice")
+        );
+        // W drives keypad key 5 by default.
+        assert_eq!(
+            error("[keypad]\n4 = \"W\"").as_deref(),
+            Some("`W` drives both keypad keys 4 and 5")
+        );
+        assert_eq!(
+            error("[keypad]\n4 = \"Up\"\n6 = \"Up\"").as_deref(),
+            Some("`Up` drives both keypad keys 4 and 6")
+        );
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        assert!(error("[keypad]\n5 = \"Upp\"")
+            .unwrap()
+            .contains("unknown key `Upp`, expected one of 0, 1,"));
+        assert!(error("[keypad]\nG = \"Up\"").is_some());
+        assert!(error("[sound]\nvolume = 3").is_some());
+    }
+}
@@ -1,395 +1,2788 @@
-use rand::Rng;
-use std::time::SystemTime;
-
-use crate::bus::Bus;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt,
+    ops::Range,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    audit::{AccessSource, MemoryAudit},
+    breakpoint::{BreakReason, CpuEvent, OpcodeBreakpoint},
+    bus::Bus,
+    display::{self, DisplayMode, SpriteDraw, TextStyle, MAX_PIXELS},
+    font::{
+        Font, BIG_FONT, BIG_FONT_ADDRESS, BIG_FONT_SIZE, BIG_GLYPH_HEIGHT, FONTSET, FONT_ADDRESS,
+        FONT_SIZE, GLYPH_HEIGHT,
+    },
+    latency::InputLatency,
+    memory_bus::{MappedRange, MemoryBus},
+    profiler::Profiler,
+    quirks::Quirks,
+    savestate::{StateReader, StateWriter},
+    snapshot::CpuSnapshot,
+    timing::{self, Timing, DEFAULT_REFRESH_RATE},
+    trace::TraceLine,
+};
 
 const FREQUENCY: f32 = 500.0;
-const WIDTH: u32 = 64;
-const HEIGHT: u32 = 32;
 
+pub const DEFAULT_CYCLES_PER_FRAME: u32 = (FREQUENCY / 60.0) as u32;
+
+pub const MEMORY_SIZE: usize = 0x1000;
+pub const PROGRAM_START: u16 = 0x200;
+pub const MAX_ROM_SIZE: usize = MEMORY_SIZE - PROGRAM_START as usize;
+// Addresses wrap around the 4 KB address space instead of running off it.
+const ADDRESS_MASK: u16 = 0xFFF;
+pub const KEY_COUNT: usize = 0x10;
+pub const HISTORY_SIZE: usize = 64;
+// Subroutine levels, as on most interpreters after the VIP's 12.
+pub const STACK_SIZE: usize = 16;
+// No FX0A waiting, in a save state.
+const NO_KEY: u8 = 0xFF;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnknownOpcodePolicy {
+    Error,
+    WarnAndSkip,
+}
+
+// Why the CPU stopped. A fault comes back from cycle and run_frame and
+// sticks: the CPU runs nothing more until it is reset or a state is
+// loaded, and the PC stays on the failing instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuError {
+    // `pc` is the address of the instruction.
+    UnknownOpcode { opcode: u16, pc: u16 },
+    // A 0000, usually the PC running off the end of the ROM.
+    EndOfCode,
+    StackUnderflow,
+    StackOverflow,
+    CodeWrite { address: u16 },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::UnknownOpcode { opcode, .. } => {
+                write!(f, "Unknown instruction {:04X}", opcode)
+            }
+            CpuError::EndOfCode => write!(f, "Ran into a 0000 instruction"),
+            CpuError::StackUnderflow => write!(f, "Return with an empty stack"),
+            CpuError::StackOverflow => {
+                write!(f, "Stack overflow, more than {} nested calls", STACK_SIZE)
+            }
+            CpuError::CodeWrite { address } => {
+                write!(f, "Wrote into the ROM code at 0x{:03X}", address)
+            }
+        }
+    }
+}
+
+// What the CPU has to tell the user without stopping, kept until the front
+// end takes it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuMessage {
+    // Once per address.
+    SkippedOpcode { pc: u16, opcode: u16 },
+    CodeWrite { pc: u16, address: u16 },
+    // An FX01 with the debug opcodes on.
+    Debug { pc: u16, register: u8, value: u8 },
+}
+
+impl fmt::Display for CpuMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuMessage::SkippedOpcode { pc, opcode } => {
+                write!(
+                    f,
+                    "skipping unknown instruction {:04X} at 0x{:03X}",
+                    opcode, pc
+                )
+            }
+            CpuMessage::CodeWrite { pc, address } => write!(
+                f,
+                "instruction at 0x{:03X} wrote into the ROM code at 0x{:03X}",
+                pc, address
+            ),
+            CpuMessage::Debug {
+                pc,
+                register,
+                value,
+            } => write!(f, "DBG pc=0x{:03X} V{:X}=0x{:02X}", pc, register, value),
+        }
+    }
+}
+
+pub fn decompose_opcode(opcode: u16) -> (u8, u8, u8, u8) {
+    let op_byte1 = (opcode >> 12) as u8;
+    let op_byte2 = ((opcode >> 8) & 0x000F) as u8;
+    let op_byte3 = ((opcode >> 4) & 0x000F) as u8;
+    let op_byte4 = (opcode & 0x000F) as u8;
+
+    (op_byte1, op_byte2, op_byte3, op_byte4)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SoundEvent {
+    // FX18 set a non-zero sound timer, again while sounding extends the beep.
+    Started { frames: u8 },
+    Stopped,
+}
+
+pub type Tracer = Box<dyn FnMut(&TraceLine)>;
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct CPU {
     buses: [Bus; 0x2],
-    key_registers: [u8; 0x9],
+    key_registers: [u8; KEY_COUNT],
     registers: [u8; 0x10],
-    memory: [u8; 0x1000],
+    memory: [u8; MEMORY_SIZE],
+    mapped: Vec<MappedRange>,
     pc: u16,
+    opcode: u16,
+    cycles_per_frame: u32,
+    timing: Timing,
+    quirks: Quirks,
+    machine_cycles: i32,
+    refresh_rate: u32,
     pointer: u16,
-    pub display: [u8; WIDTH as usize * HEIGHT as usize],
+    display: [u8; MAX_PIXELS],
+    display_mode: DisplayMode,
+    last_draw: Option<SpriteDraw>,
+    display_changed: bool,
+    // One bit per display row touched since the last `take_dirty_rows`.
+    dirty_rows: u64,
     stack: Vec<u16>,
+    // SUPER-CHIP kept these in the HP 48's RPL user flags. XO-CHIP has 16.
+    flags: [u8; 0x10],
+    waiting_for_key: Option<u8>,
+    // A DXYN with the display wait quirk ended the frame.
+    vblank_wait: bool,
+    // The keys pressed since FX0A started, one of which it waits to see
+    // released.
+    key_wait_presses: u16,
+    queried_keys: u16,
+    recent_polls: [u32; 0x10],
+    waited_for_key: bool,
     delay_timer: u8,
     delay_timer_timestamp: SystemTime,
-    pub sound_timer: u8,
+    sound_timer: u8,
     sound_timer_timestamp: SystemTime,
+    clock_paused_at: Option<SystemTime>,
+    frame_timers: bool,
+    rng: StdRng,
+    seed: Option<u64>,
+    audit: Option<MemoryAudit>,
+    code: Range<u16>,
+    code_writes: HashSet<u16>,
+    strict_code_writes: bool,
+    unknown_opcode_policy: UnknownOpcodePolicy,
+    debug_opcodes: bool,
+    unknown_opcodes: HashSet<u16>,
+    skipped_opcodes: u32,
+    cycles: u64,
+    max_cycles: Option<u64>,
+    address_breakpoints: Vec<u16>,
+    opcode_breakpoints: Vec<OpcodeBreakpoint>,
+    watchpoints: Vec<u16>,
+    stop_at_cycle: Option<u64>,
+    break_reason: Option<BreakReason>,
+    fault: Option<CpuError>,
+    resume_past_break: bool,
+    break_events: Vec<CpuEvent>,
+    event: Option<CpuEvent>,
+    sound_events: Vec<SoundEvent>,
+    messages: Vec<CpuMessage>,
+    history: VecDeque<TraceLine>,
+    tracer: Option<Tracer>,
+    input_latency: Option<InputLatency>,
+    profiler: Option<Profiler>,
+    pressed_at: [Option<Instant>; KEY_COUNT],
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CPU {
     pub fn new() -> CPU {
-        CPU {
+        let mut cpu = CPU {
             buses: [Bus::new(), Bus::new()],
-            key_registers: [0; 0x9],
+            key_registers: [0; KEY_COUNT],
             registers: [0; 0x10],
-            memory: [0; 0x1000],
-            display: [0; WIDTH as usize * HEIGHT as usize],
-            stack: Vec::with_capacity(16),
-            pc: 0x200,
+            memory: [0; MEMORY_SIZE],
+            mapped: Vec::new(),
+            display: [0; MAX_PIXELS],
+            display_mode: DisplayMode::LORES,
+            last_draw: None,
+            display_changed: false,
+            dirty_rows: u64::MAX,
+            stack: Vec::with_capacity(STACK_SIZE),
+            flags: [0; 0x10],
+            waiting_for_key: None,
+            vblank_wait: false,
+            key_wait_presses: 0,
+            queried_keys: 0,
+            recent_polls: [0; 0x10],
+            waited_for_key: false,
+            pc: PROGRAM_START,
+            opcode: 0,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            timing: Timing::Fixed,
+            quirks: Quirks::default(),
+            machine_cycles: 0,
+            refresh_rate: DEFAULT_REFRESH_RATE,
             pointer: 0,
             delay_timer: 0,
             delay_timer_timestamp: SystemTime::now(),
             sound_timer: 0,
             sound_timer_timestamp: SystemTime::now(),
-        }
+            clock_paused_at: None,
+            frame_timers: false,
+            rng: StdRng::from_entropy(),
+            seed: None,
+            audit: None,
+            code: PROGRAM_START..PROGRAM_START,
+            code_writes: HashSet::new(),
+            strict_code_writes: false,
+            unknown_opcode_policy: UnknownOpcodePolicy::Error,
+            debug_opcodes: false,
+            unknown_opcodes: HashSet::new(),
+            skipped_opcodes: 0,
+            cycles: 0,
+            max_cycles: None,
+            address_breakpoints: Vec::new(),
+            opcode_breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            stop_at_cycle: None,
+            break_reason: None,
+            fault: None,
+            resume_past_break: false,
+            break_events: Vec::new(),
+            event: None,
+            sound_events: Vec::new(),
+            messages: Vec::new(),
+            history: VecDeque::with_capacity(HISTORY_SIZE),
+            tracer: None,
+            input_latency: None,
+            profiler: None,
+            pressed_at: [None; KEY_COUNT],
+        };
+
+        cpu.load_font(&FONTSET);
+        cpu.memory[BIG_FONT_ADDRESS..BIG_FONT_ADDRESS + BIG_FONT_SIZE].copy_from_slice(&BIG_FONT);
+        cpu
     }
 
-    pub fn get_keypad_bus(&mut self) -> &mut Bus {
-        &mut self.buses[0x0]
+    // Back to power-on with no ROM, but with the same font, settings,
+    // breakpoints, mapped devices and tracer. The reports start over, and a
+    // seeded random sequence starts from its seed again.
+    pub fn reset(&mut self) {
+        let font = self.font();
+        let sounding = self.sound_timer > 0;
+
+        self.buses = [Bus::new(), Bus::new()];
+        self.key_registers = [0; KEY_COUNT];
+        self.registers = [0; 0x10];
+        self.memory = [0; MEMORY_SIZE];
+        self.load_font(&font);
+        self.memory[BIG_FONT_ADDRESS..BIG_FONT_ADDRESS + BIG_FONT_SIZE].copy_from_slice(&BIG_FONT);
+        self.pc = PROGRAM_START;
+        self.opcode = 0;
+        self.machine_cycles = 0;
+        self.pointer = 0;
+        self.display = [0; MAX_PIXELS];
+        self.display_mode = DisplayMode::LORES;
+        self.last_draw = None;
+        self.display_changed = true;
+        self.dirty_rows = u64::MAX;
+        self.stack.clear();
+        self.flags = [0; 0x10];
+        self.waiting_for_key = None;
+        self.vblank_wait = false;
+        self.key_wait_presses = 0;
+        self.queried_keys = 0;
+        self.recent_polls = [0; 0x10];
+        self.waited_for_key = false;
+        self.delay_timer = 0;
+        self.delay_timer_timestamp = SystemTime::now();
+        self.sound_timer = 0;
+        self.sound_timer_timestamp = SystemTime::now();
+
+        if let Some(seed) = self.seed {
+            self.rng = StdRng::seed_from_u64(seed);
+        }
+
+        self.audit = self
+            .audit
+            .as_ref()
+            .map(|audit| MemoryAudit::new(audit.capacity()));
+        self.code = PROGRAM_START..PROGRAM_START;
+        self.code_writes.clear();
+        self.unknown_opcodes.clear();
+        self.skipped_opcodes = 0;
+        self.cycles = 0;
+        self.stop_at_cycle = None;
+        self.break_reason = None;
+        self.fault = None;
+        self.resume_past_break = false;
+        self.event = None;
+        self.sound_events.clear();
+
+        // Or a front end driven by the events keeps beeping.
+        if sounding {
+            self.sound_events.push(SoundEvent::Stopped);
+        }
+
+        self.messages.clear();
+        self.history.clear();
+        self.input_latency = self.input_latency.as_ref().map(|_| InputLatency::new());
+        self.profiler = self.profiler.as_ref().map(|_| Profiler::new());
+        self.pressed_at = [None; KEY_COUNT];
     }
 
-    pub fn get_sound_timer(&self) -> u8 {
-        self.sound_timer
+    pub fn load_font(&mut self, font: &Font) {
+        self.memory[FONT_ADDRESS..FONT_ADDRESS + FONT_SIZE].copy_from_slice(font);
     }
 
-    fn update_sound_timer(&mut self) {
-        if self.sound_timer > 0
-            && self.sound_timer_timestamp.elapsed().unwrap().as_millis()
-                >= ((1.0 / (FREQUENCY as f32 * 0.12)) * 1000.0) as u128
-        {
-            self.sound_timer_timestamp = SystemTime::now();
-            self.sound_timer = self.sound_timer.saturating_sub(1);
-        }
+    pub fn font(&self) -> Font {
+        self.memory[FONT_ADDRESS..FONT_ADDRESS + FONT_SIZE]
+            .try_into()
+            .unwrap()
     }
 
-    fn update_delay_timer(&mut self) {
-        if self.delay_timer_timestamp.elapsed().unwrap().as_millis()
-            >= ((1.0 / (FREQUENCY as f32 * 0.12)) * 1000.0) as u128
-        {
-            self.delay_timer_timestamp = SystemTime::now();
-            self.delay_timer = self.delay_timer.saturating_sub(1);
-        }
+    pub fn set_cycles_per_frame(&mut self, cycles_per_frame: u32) {
+        self.cycles_per_frame = cycles_per_frame;
     }
 
-    fn read_memory_opcode(&self) -> u16 {
-        let p = self.pc;
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.machine_cycles = 0;
+    }
 
-        let op_byte1 = self.memory[p as usize] as u16;
-        let op_byte2 = self.memory[p as usize + 1] as u16;
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
 
-        op_byte1 << 8 | op_byte2
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
     }
 
-    fn decompose_opcode(&self, opcode: u16) -> (u8, u8, u8, u8) {
-        let op_byte1 = (opcode >> 12) as u8;
-        let op_byte2 = ((opcode >> 8) & 0x000F) as u8;
-        let op_byte3 = ((opcode >> 4) & 0x000F) as u8;
-        let op_byte4 = (opcode & 0x000F) as u8;
+    pub fn set_refresh_rate(&mut self, refresh_rate: u32) {
+        assert!(timing::is_valid_refresh_rate(refresh_rate));
 
-        (op_byte1, op_byte2, op_byte3, op_byte4)
+        self.refresh_rate = refresh_rate;
     }
 
-    pub fn cycle(&mut self) {
-        let memory_opcode = self.read_memory_opcode();
+    pub fn refresh_rate(&self) -> u32 {
+        self.refresh_rate
+    }
 
-        self.pc += 2;
+    pub fn frame_duration(&self) -> Duration {
+        timing::frame_duration(self.refresh_rate)
+    }
 
-        match self.decompose_opcode(memory_opcode) {
-            (0, 0, 0xE, 0) => self.clear_display(),
-            (0, 0, 0xE, 0xE) => self.return_from_subroutine(),
-            (1, n1, n2, n3) => self.goto(n1, n2, n3),
-            (2, n1, n2, n3) => self.call_subroutine(n1, n2, n3),
-            (3, x, n1, n2) => self.skip_if_equal(x, n1, n2),
-            (4, x, n1, n2) => self.skip_if_not_equal(x, n1, n2),
-            (5, x, y, 0) => self.skip_if_equal_registers(x, y),
-            (6, x, b1, b2) => self.set_register(x, b1 << 4 | b2),
-            (7, x, b1, b2) => self.add_to_register(x, b1 << 4 | b2),
-            (8, x, y, 0) => self.set_register_x_to_register_y(x, y),
-            (8, x, y, 1) => self.set_register_x_or_register_y(x, y),
-            (8, x, y, 2) => self.set_register_x_and_register_y(x, y),
-            (8, x, y, 3) => self.set_register_x_xor_register_y(x, y),
-            (8, x, y, 4) => self.add_register_y_to_register_x(x, y),
-            (8, x, y, 5) => self.sub_register_y_to_register_x(x, y),
-            (8, x, _, 6) => self.store_shift_register_x_least(x),
-            (8, x, y, 7) => self.diff_register_y_and_register_x(x, y),
-            (8, x, _, 0xE) => self.store_shift_register_x_most(x),
-            (9, x, y, 0) => self.comp_register_x_register_y_skip(x, y),
-            (0xA, n1, n2, n3) => self.set_pointer_address(n1, n2, n3),
-            (0xB, n1, n2, n3) => self.jump_to_address_plus_v0(n1, n2, n3),
-            (0xC, x, n1, n2) => self.set_register_x_rand_and_value(x, n1, n2),
-            (0xD, x, y, n) => self.draw_sprite(x, y, n),
-            (0xE, x, 9, 0xE) => self.skip_if_key_pressed(x),
-            (0xE, x, 0xA, 1) => self.skip_if_key_not_pressed(x),
-            (0xF, x, 0, 7) => self.set_register_x_to_delay_timer(x),
-            (0xF, x, 0, 0xA) => self.wait_for_key_press(x),
-            (0xF, x, 1, 5) => self.set_delay_timer_to_register_x(x),
-            (0xF, x, 1, 8) => self.set_sound_timer_to_register_x(x),
-            (0xF, x, 1, 0xE) => self.add_register_x_to_pointer(x),
-            (0xF, x, 2, 9) => self.set_pointer_to_sprite(x),
-            (0xF, x, 3, 3) => self.store_bcd_in_memory(x),
-            (0xF, x, 5, 5) => self.store_registers_in_memory(x),
-            (0xF, x, 6, 5) => self.fills_memory_from_registers(x),
-            (0, 0, 0, 0) => panic!("Done"),
-            _ => todo!("Unknown instruction {:04X}", memory_opcode),
-        }
+    // The timers tick once per run_frame instead of following the wall
+    // clock, so a run doesn't depend on how fast the host is.
+    pub fn set_frame_timers(&mut self, enabled: bool) {
+        self.frame_timers = enabled;
+    }
 
-        self.update_delay_timer();
-        self.update_sound_timer();
-        self.read_keypad_bus();
+    // CXNN draws from a fixed sequence from now on.
+    pub fn seed_random(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.seed = Some(seed);
     }
 
-    fn read_keypad_bus(&mut self) {
-        match self.buses[0].read() {
-            (0x0, 0x0) => (),
-            (key, value) => self.key_registers[key as usize] = value,
-        };
+    // Once the budget is spent the CPU stops for good, for unattended runs.
+    pub fn set_max_cycles(&mut self, max_cycles: Option<u64>) {
+        self.max_cycles = max_cycles;
     }
 
-    fn store_bcd_in_memory(&mut self, register_x: u8) {
-        let reg_value = self.registers[register_x as usize];
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
 
-        let mut bcd: [u8; 3] = [0, 0, reg_value % 10];
+    pub fn watchdog_expired(&self) -> bool {
+        self.max_cycles.is_some_and(|max| self.cycles >= max)
+    }
 
-        for (div, i) in [100, 10].iter().zip([0, 1]) {
-            bcd[i] = ((reg_value - reg_value % div) / div) % div;
+    pub fn add_breakpoint(&mut self, address: u16) {
+        if !self.address_breakpoints.contains(&address) {
+            self.address_breakpoints.push(address);
         }
-
-        self.memory[self.pointer as usize..self.pointer as usize + 3].copy_from_slice(&bcd);
     }
 
-    fn skip_if_key_pressed(&mut self, register_x: u8) {
-        let expected_key = self.registers[register_x as usize];
+    pub fn breakpoints(&self) -> &[u16] {
+        &self.address_breakpoints
+    }
 
-        if self.key_registers[expected_key as usize] == 0x1 {
-            self.pc += 2;
+    pub fn add_watchpoint(&mut self, address: u16) {
+        if !self.watchpoints.contains(&address) {
+            self.watchpoints.push(address);
         }
     }
 
-    fn skip_if_key_not_pressed(&mut self, register_x: u8) {
-        let expected_key = self.registers[register_x as usize];
+    pub fn watchpoints(&self) -> &[u16] {
+        &self.watchpoints
+    }
 
-        if self.key_registers[expected_key as usize] == 0x0 {
-            self.pc += 2;
-        }
+    // Stops again once that many more cycles ran.
+    pub fn run_for(&mut self, cycles: u64) {
+        self.stop_at_cycle = Some(self.cycles + cycles);
     }
 
-    fn wait_for_key_press(&mut self, register_x: u8) {
-        for key in 0..0x9 {
-            if self.key_registers[key] == 0x1 {
-                self.registers[register_x as usize] = self.key_registers[key];
-                return;
-            }
-        }
+    pub fn add_opcode_breakpoint(&mut self, breakpoint: OpcodeBreakpoint) {
+        self.opcode_breakpoints.push(breakpoint);
+    }
 
-        self.pc -= 2;
+    pub fn opcode_breakpoints(&self) -> &[OpcodeBreakpoint] {
+        &self.opcode_breakpoints
     }
 
-    fn skip_if_equal_registers(&mut self, register_x: u8, register_y: u8) {
-        if self.registers[register_x as usize] == self.registers[register_y as usize] {
-            self.pc += 2;
+    pub fn add_break_event(&mut self, event: CpuEvent) {
+        if !self.break_events.contains(&event) {
+            self.break_events.push(event);
         }
     }
 
-    fn fills_memory_from_registers(&mut self, max_register: u8) {
-        self.registers[0..max_register as usize + 1].copy_from_slice(
-            &self.memory[self.pointer as usize..self.pointer as usize + max_register as usize + 1],
-        );
+    // Set while stopped before the instruction at `pc()`.
+    pub fn break_reason(&self) -> Option<BreakReason> {
+        self.break_reason
     }
 
-    fn store_registers_in_memory(&mut self, max_register: u8) {
-        self.memory[self.pointer as usize..self.pointer as usize + max_register as usize + 1]
-            .copy_from_slice(&self.registers[0..max_register as usize + 1]);
+    // Runs the instruction the CPU stopped on without breaking on it again.
+    pub fn continue_from_break(&mut self) {
+        if let Some(BreakReason::Address(_) | BreakReason::Opcode(_)) = self.break_reason.take() {
+            self.resume_past_break = true;
+        }
     }
 
-    fn add_register_x_to_pointer(&mut self, register: u8) {
-        self.pointer += self.registers[register as usize] as u16;
-    }
+    // Runs the next instruction even if a breakpoint sits on it.
+    pub fn step(&mut self) -> Result<(), CpuError> {
+        self.break_reason = None;
+        self.resume_past_break = true;
 
-    fn set_register_x_to_delay_timer(&mut self, register: u8) {
-        self.registers[register as usize] = self.delay_timer;
+        let result = self.cycle();
+
+        self.resume_past_break = false;
+        result
     }
 
-    fn set_delay_timer_to_register_x(&mut self, register: u8) {
-        self.delay_timer = self.registers[register as usize];
+    pub fn fault(&self) -> Option<CpuError> {
+        self.fault
     }
 
-    fn set_sound_timer_to_register_x(&mut self, register: u8) {
-        self.sound_timer = self.registers[register as usize];
+    pub fn pc(&self) -> u16 {
+        self.pc
     }
 
-    fn set_pointer_to_sprite(&mut self, register: u8) {
-        self.pointer = (self.registers[register as usize] * 5) as u16;
+    pub fn registers(&self) -> &[u8; 0x10] {
+        &self.registers
     }
 
-    fn set_register(&mut self, register: u8, value: u8) {
-        self.registers[register as usize] = value;
+    pub fn pointer(&self) -> u16 {
+        self.pointer
     }
 
-    fn add_to_register(&mut self, register: u8, value: u8) {
-        let (value, _) = self.registers[register as usize].overflowing_add(value);
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
 
-        self.registers[register as usize] = value;
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
     }
 
-    fn set_register_x_to_register_y(&mut self, register_x: u8, register_y: u8) {
-        self.registers[register_x as usize] = self.registers[register_y as usize];
+    pub fn snapshot(&self) -> CpuSnapshot {
+        CpuSnapshot::capture(self)
     }
 
-    fn set_register_x_or_register_y(&mut self, register_x: u8, register_y: u8) {
-        self.registers[register_x as usize] |= self.registers[register_y as usize];
+    // The machine as the ROM sees it. Breakpoints, the audit and the random
+    // sequence are the host's business and stay out.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+
+        state.bytes(&self.registers);
+        state.bytes(&self.memory);
+        state.u16(self.pc);
+        state.u16(self.pointer);
+        state.u8((self.display_mode == DisplayMode::HIRES) as u8);
+        state.bits(&self.display);
+        state.u8(self.stack.len() as u8);
+
+        for address in &self.stack {
+            state.u16(*address);
+        }
+
+        state.u8(self.delay_timer);
+        state.u8(self.sound_timer);
+        state.bytes(&self.key_registers);
+        state.bytes(&self.flags);
+        state.u8(self.waiting_for_key.unwrap_or(NO_KEY));
+        state.u16(self.key_wait_presses);
+        state.u64(self.cycles);
+        state.finish()
     }
 
-    fn set_register_x_and_register_y(&mut self, register_x: u8, register_y: u8) {
-        self.registers[register_x as usize] &= self.registers[register_y as usize];
+    // Nothing changes unless the whole state reads back.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let mut state = StateReader::new(bytes)?;
+        let registers = state.bytes()?;
+        let memory = state.bytes()?;
+        let pc = state.u16()?;
+        let pointer = state.u16()?;
+        let display_mode = match state.u8()? {
+            0 => DisplayMode::LORES,
+            1 => DisplayMode::HIRES,
+            mode => return Err(format!("invalid display mode {}", mode)),
+        };
+        let mut display = [0; MAX_PIXELS];
+
+        state.bits(&mut display)?;
+
+        let depth = state.u8()? as usize;
+
+        if depth > STACK_SIZE {
+            return Err(format!("invalid stack depth {}", depth));
+        }
+
+        let stack = (0..depth)
+            .map(|_| state.u16())
+            .collect::<Result<Vec<_>, _>>()?;
+        let delay_timer = state.u8()?;
+        let sound_timer = state.u8()?;
+        let key_registers = state.bytes()?;
+        let flags = state.bytes()?;
+        let waiting_for_key = match state.u8()? {
+            NO_KEY => None,
+            register => Some(register & 0xF),
+        };
+        let key_wait_presses = state.u16()?;
+        let cycles = state.u64()?;
+
+        state.finish()?;
+
+        self.registers = registers;
+        self.memory = memory;
+        self.pc = pc & ADDRESS_MASK;
+        self.pointer = pointer;
+        self.display_mode = display_mode;
+        self.display = display;
+        self.stack = stack;
+        self.delay_timer = delay_timer;
+        self.delay_timer_timestamp = SystemTime::now();
+
+        // The beep follows the loaded timer, as if FX18 had just run.
+        if sound_timer > 0 {
+            self.sound_events.push(SoundEvent::Started {
+                frames: sound_timer,
+            });
+        } else if self.sound_timer > 0 {
+            self.sound_events.push(SoundEvent::Stopped);
+        }
+
+        self.sound_timer = sound_timer;
+        self.sound_timer_timestamp = SystemTime::now();
+        self.key_registers = key_registers;
+        self.flags = flags;
+        self.waiting_for_key = waiting_for_key;
+        self.key_wait_presses = key_wait_presses;
+        self.cycles = cycles;
+        self.last_draw = None;
+        self.display_changed = true;
+        self.dirty_rows = u64::MAX;
+        self.break_reason = None;
+        self.fault = None;
+        Ok(())
     }
 
-    fn set_register_x_xor_register_y(&mut self, register_x: u8, register_y: u8) {
-        self.registers[register_x as usize] ^= self.registers[register_y as usize];
+    // Faulted, stopped at a break or out of cycles: nothing runs until the
+    // front end does something about it.
+    pub fn halted(&self) -> bool {
+        self.watchdog_expired() || self.break_reason.is_some() || self.fault.is_some()
     }
 
-    fn add_register_y_to_register_x(&mut self, register_x: u8, register_y: u8) {
-        let (v, overflow) = self.registers[register_x as usize]
-            .overflowing_add(self.registers[register_y as usize]);
+    pub fn run_frame(&mut self) -> Result<(), CpuError> {
+        self.vblank_wait = false;
 
-        if overflow {
-            self.registers[0xF] = 1;
-        } else {
-            self.registers[0xF] = 0;
+        for frames in &mut self.recent_polls {
+            *frames = frames.saturating_sub(1);
         }
 
-        self.registers[register_x as usize] = v;
+        for mapped in &mut self.mapped {
+            mapped.device.frame();
+        }
+
+        match self.timing {
+            Timing::Fixed => self.run_fixed_frame()?,
+            Timing::Vip => self.run_vip_frame()?,
+        }
+
+        if self.frame_timers && !self.halted() {
+            self.delay_timer = self.delay_timer.saturating_sub(1);
+
+            if self.sound_timer == 1 {
+                self.sound_events.push(SoundEvent::Stopped);
+            }
+
+            self.sound_timer = self.sound_timer.saturating_sub(1);
+        }
+
+        Ok(())
     }
 
-    fn sub_register_y_to_register_x(&mut self, register_x: u8, register_y: u8) {
-        let (v, overflow) = self.registers[register_x as usize]
-            .overflowing_sub(self.registers[register_y as usize]);
+    fn run_fixed_frame(&mut self) -> Result<(), CpuError> {
+        for _ in 0..self.cycles_per_frame {
+            self.cycle()?;
 
-        if overflow {
-            self.registers[0xF] = 1;
-        } else {
-            self.registers[0xF] = 0;
+            // Nothing left to execute this frame until a key arrives.
+            if self.is_waiting_for_key() || self.vblank_wait || self.halted() {
+                break;
+            }
         }
 
-        self.registers[register_x as usize] = v;
+        Ok(())
     }
 
-    fn store_shift_register_x_least(&mut self, register_x: u8) {
-        let least_significant_bit = self.registers[register_x as usize] & 0x0F;
+    // Whatever the last instruction overspent is taken from the next frame.
+    fn run_vip_frame(&mut self) -> Result<(), CpuError> {
+        self.machine_cycles += timing::vip_cycles_per_frame(self.refresh_rate);
+
+        while self.machine_cycles > 0 && !self.halted() {
+            if self.is_waiting_for_key() {
+                self.cycle()?;
+                self.machine_cycles = 0;
+                break;
+            }
+
+            let cost = timing::vip_cycles(self.read_memory_opcode(), &self.registers);
 
-        self.registers[0xF] = least_significant_bit;
-        self.registers[register_x as usize] >>= 1;
+            self.cycle()?;
+            self.machine_cycles -= cost as i32;
+
+            if self.vblank_wait {
+                self.machine_cycles = 0;
+                break;
+            }
+        }
+
+        Ok(())
     }
 
-    fn store_shift_register_x_most(&mut self, register_x: u8) {
-        let most_significant_bit = (self.registers[register_x as usize] >> 7) & 1;
+    // Plain RAM, whatever is mapped over it. The debugger, cheats and pokes
+    // work on this, only the ROM's data accesses reach the mapped devices.
+    pub fn read_memory(&self, address: u16) -> u8 {
+        self.memory[(address & ADDRESS_MASK) as usize]
+    }
 
-        self.registers[0xF] = most_significant_bit;
-        self.registers[register_x as usize] <<= 1;
+    pub fn write_memory(&mut self, address: u16, value: u8) {
+        self.memory[(address & ADDRESS_MASK) as usize] = value;
     }
 
-    fn diff_register_y_and_register_x(&mut self, register_x: u8, register_y: u8) {
-        let register_x_value = self.registers[register_x as usize];
-        self.registers[register_x as usize] =
-            self.registers[register_y as usize] - register_x_value;
+    // Later mappings don't override earlier ones where they overlap.
+    pub fn map_memory(&mut self, range: Range<u16>, device: Box<dyn MemoryBus>) {
+        self.mapped.push(MappedRange { range, device });
     }
 
-    fn comp_register_x_register_y_skip(&mut self, register_x: u8, register_y: u8) {
-        if self.registers[register_x as usize] != self.registers[register_y as usize] {
-            self.pc += 2;
+    // Gets every instruction about to run, with the registers before it.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    fn data_bus(&mut self, address: u16) -> &mut dyn MemoryBus {
+        match self
+            .mapped
+            .iter()
+            .position(|mapped| mapped.range.contains(&address))
+        {
+            Some(i) => self.mapped[i].device.as_mut(),
+            None => &mut self.memory,
         }
     }
 
-    fn set_pointer_address(&mut self, n1: u8, n2: u8, n3: u8) {
-        self.pointer = ((n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16) as u16;
+    fn read_data(&mut self, address: u16) -> u8 {
+        let address = address & ADDRESS_MASK;
+
+        self.data_bus(address).read(address)
     }
 
-    fn jump_to_address_plus_v0(&mut self, n1: u8, n2: u8, n3: u8) {
-        let address = ((n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16) as u16;
+    fn write_data(&mut self, address: u16, value: u8) {
+        let address = address & ADDRESS_MASK;
 
-        self.pc = address + self.registers[0] as u16;
+        self.data_bus(address).write(address, value);
     }
 
-    fn set_register_x_rand_and_value(&mut self, register_x: u8, n1: u8, n2: u8) {
-        let random_number: u8 = rand::thread_rng().gen();
+    pub fn enable_memory_audit(&mut self, capacity: usize) {
+        self.audit = Some(MemoryAudit::new(capacity));
+    }
 
-        self.registers[register_x as usize] = random_number & (n1 << 4 | n2);
+    pub fn memory_audit(&self) -> Option<&MemoryAudit> {
+        self.audit.as_ref()
     }
 
-    fn goto(&mut self, n1: u8, n2: u8, n3: u8) {
-        let address = ((n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16) as u16;
+    pub fn enable_input_latency(&mut self) {
+        self.input_latency = Some(InputLatency::new());
+    }
 
-        self.pc = address;
+    pub fn input_latency(&self) -> Option<&InputLatency> {
+        self.input_latency.as_ref()
     }
 
-    fn call_subroutine(&mut self, n1: u8, n2: u8, n3: u8) {
-        let address = ((n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16) as u16;
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Profiler::new());
+    }
 
-        self.stack.push(self.pc);
-        self.pc = address;
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
     }
 
-    fn return_from_subroutine(&mut self) {
-        self.pc = self.stack.pop().unwrap();
+    // Self-modifying ROMs exist, but a write into the loaded code is far more
+    // often a bad I about to derail the game.
+    pub fn set_strict_code_writes(&mut self, strict: bool) {
+        self.strict_code_writes = strict;
     }
 
-    fn skip_if_equal(&mut self, register_x: u8, n1: u8, n2: u8) {
-        let value = n1 << 4 | n2;
+    pub fn set_unknown_opcode_policy(&mut self, policy: UnknownOpcodePolicy) {
+        self.unknown_opcode_policy = policy;
+    }
 
-        if self.registers[register_x as usize] == value {
-            self.pc += 2;
-        }
+    pub fn skipped_opcodes(&self) -> u32 {
+        self.skipped_opcodes
     }
 
-    fn skip_if_not_equal(&mut self, register_x: u8, n1: u8, n2: u8) {
-        let value = n1 << 4 | n2;
+    // FX01 isn't a CHIP-8 instruction, ROMs under development can use it
+    // as a printf when the frontend turns it on.
+    pub fn set_debug_opcodes(&mut self, enabled: bool) {
+        self.debug_opcodes = enabled;
+    }
 
-        if self.registers[register_x as usize] != value {
-            self.pc += 2;
+    fn debug_print(&mut self, register_x: u8) {
+        self.messages.push(CpuMessage::Debug {
+            pc: self.instruction_pc(),
+            register: register_x,
+            value: self.registers[register_x as usize],
+        });
+        self.event = Some(CpuEvent::DebugOp);
+    }
+
+    fn unknown_opcode(&mut self, opcode: u16) -> Result<(), CpuError> {
+        let pc = self.instruction_pc();
+
+        if self.unknown_opcode_policy == UnknownOpcodePolicy::Error {
+            return Err(CpuError::UnknownOpcode { opcode, pc });
+        }
+
+        // The PC is already past the opcode, skipping it is just carrying on.
+        if self.unknown_opcodes.insert(pc) {
+            self.messages.push(CpuMessage::SkippedOpcode { pc, opcode });
         }
+
+        self.skipped_opcodes += 1;
+        Ok(())
     }
 
-    fn clear_display(&mut self) {
-        self.display = [0; WIDTH as usize * HEIGHT as usize];
+    // The `len` bytes from I on, wrapping past 0xFFF. Stores, loads and the
+    // checks before them all go through here.
+    fn pointer_addresses(&self, len: usize) -> Vec<u16> {
+        (0..len)
+            .map(|offset| self.pointer.wrapping_add(offset as u16) & ADDRESS_MASK)
+            .collect()
     }
 
-    fn draw_sprite(&mut self, register_x: u8, register_y: u8, n1: u8) {
-        let x = self.registers[register_x as usize] as usize;
-        let y = self.registers[register_y as usize] as usize;
-        let height = n1 as usize;
+    fn check_code_write(&mut self, len: usize) -> Result<(), CpuError> {
+        let pc = self.instruction_pc();
 
-        self.registers[0xF] = 0;
+        for address in self.pointer_addresses(len) {
+            if !self.code.contains(&address) || !self.code_writes.insert(address) {
+                continue;
+            }
 
-        for y_line in 0..height {
-            let pixel = self.memory[self.pointer as usize + y_line];
+            if self.strict_code_writes {
+                return Err(CpuError::CodeWrite { address });
+            }
 
-            for x_line in 0..8 {
-                if (pixel & (0x80 >> x_line)) != 0 {
-                    let index = (x + x_line + ((y + y_line) * WIDTH as usize))
-                        % (WIDTH as usize * HEIGHT as usize);
+            self.messages.push(CpuMessage::CodeWrite { pc, address });
+        }
 
-                    if self.display[index] == 1 {
-                        self.registers[0xF] = 1;
-                    }
+        Ok(())
+    }
 
-                    self.display[index] ^= 1;
-                }
-            }
+    // Instructions that touch memory all leave the PC right after themselves.
+    fn check_watchpoints(&mut self, len: usize) {
+        let written = self.pointer_addresses(len);
+
+        if let Some(address) = self
+            .watchpoints
+            .iter()
+            .find(|address| written.contains(address))
+        {
+            self.break_reason = Some(BreakReason::Watch {
+                address: *address,
+                pc: self.instruction_pc(),
+            });
         }
     }
 
-    #[allow(dead_code)]
-    pub fn pretty_print_memory(&self) {
-        for (i, byte) in self.memory.iter().enumerate() {
-            if i % 16 == 0 {
-                println!("");
-                print!("0x{:04X} ", i);
-            }
+    fn audit_range(&mut self, source: AccessSource, len: usize, new: Option<&[u8]>) {
+        let pc = self.instruction_pc();
 
-            print!("{:02X} ", byte);
+        if self.audit.is_none() {
+            return;
         }
 
-        println!("");
+        let addresses = self.pointer_addresses(len);
+        let old: Vec<u8> = addresses
+            .iter()
+            .map(|address| self.memory[*address as usize])
+            .collect();
+
+        if let Some(audit) = &mut self.audit {
+            audit.record_range(pc, self.opcode, source, &addresses, &old, new);
+        }
+    }
+
+    pub fn display(&self) -> &[u8] {
+        &self.display[..self.display_mode.pixel_count()]
+    }
+
+    pub fn display_mut(&mut self) -> &mut [u8] {
+        self.display_changed = true;
+        self.dirty_rows = u64::MAX;
+        &mut self.display[..self.display_mode.pixel_count()]
+    }
+
+    pub fn display_text(&self, style: TextStyle) -> String {
+        display::to_text(self.display(), self.display_mode, style)
+    }
+
+    // The last DXYN since the previous call, if any.
+    pub fn take_last_draw(&mut self) -> Option<SpriteDraw> {
+        self.last_draw.take()
+    }
+
+    pub fn display_mode(&self) -> DisplayMode {
+        self.display_mode
+    }
+
+    // Whether anything touched the display since the last call.
+    pub fn take_display_changed(&mut self) -> bool {
+        std::mem::take(&mut self.display_changed)
+    }
+
+    // Rows of the current mode whose pixels changed since the last call, bit
+    // 0 being the top row. Unlike `take_display_changed`, a sprite that didn't
+    // flip any pixel leaves the mask alone.
+    pub fn take_dirty_rows(&mut self) -> u64 {
+        std::mem::take(&mut self.dirty_rows)
+    }
+
+    pub fn set_display_mode(&mut self, mode: DisplayMode) {
+        self.display_mode = mode;
+        self.clear_display();
+    }
+
+    pub fn get_keypad_bus(&mut self) -> &mut Bus {
+        &mut self.buses[0x0]
+    }
+
+    pub fn keypad_bus(&self) -> &Bus {
+        &self.buses[0x0]
+    }
+
+    pub fn get_sound_timer(&self) -> u8 {
+        self.sound_timer
     }
 
-    pub fn load_rom(&mut self, rom: Vec<u8>) {
-        for (i, byte) in rom.iter().enumerate() {
-            self.memory[i + 0x200] = *byte;
+    // Wall time spent paused must not count against the timers on resume.
+    pub fn pause_clock(&mut self) {
+        if self.clock_paused_at.is_none() {
+            self.clock_paused_at = Some(SystemTime::now());
         }
     }
+
+    pub fn resume_clock(&mut self) {
+        if let Some(paused_at) = self.clock_paused_at.take() {
+            let paused_for = paused_at.elapsed().unwrap_or_default();
+
+            self.delay_timer_timestamp += paused_for;
+            self.sound_timer_timestamp += paused_for;
+        }
+    }
+
+    fn update_timers(&mut self) {
+        if self.frame_timers {
+            return;
+        }
+
+        let period = self.frame_duration();
+
+        Self::tick_timer(
+            &mut self.delay_timer,
+            &mut self.delay_timer_timestamp,
+            period,
+        );
+
+        let sounding = self.sound_timer > 0;
+
+        Self::tick_timer(
+            &mut self.sound_timer,
+            &mut self.sound_timer_timestamp,
+            period,
+        );
+
+        if sounding && self.sound_timer == 0 {
+            self.sound_events.push(SoundEvent::Stopped);
+        }
+    }
+
+    // Start and stop events since the last call, oldest first.
+    pub fn take_sound_events(&mut self) -> Vec<SoundEvent> {
+        std::mem::take(&mut self.sound_events)
+    }
+
+    // Warnings and the like since the last call, oldest first.
+    pub fn take_messages(&mut self) -> Vec<CpuMessage> {
+        std::mem::take(&mut self.messages)
+    }
+
+    // Advance by whole periods so the timers don't drift with the cycle rate.
+    fn tick_timer(timer: &mut u8, timestamp: &mut SystemTime, period: Duration) {
+        if *timer == 0 {
+            *timestamp = SystemTime::now();
+            return;
+        }
+
+        while *timer > 0 && timestamp.elapsed().unwrap_or_default() >= period {
+            *timestamp += period;
+            *timer -= 1;
+        }
+    }
+
+    fn read_memory_opcode(&self) -> u16 {
+        let op_byte1 = self.read_memory(self.pc) as u16;
+        let op_byte2 = self.read_memory(self.pc.wrapping_add(1)) as u16;
+
+        op_byte1 << 8 | op_byte2
+    }
+
+    pub fn cycle(&mut self) -> Result<(), CpuError> {
+        if let Some(error) = self.fault {
+            return Err(error);
+        }
+
+        if self.stop_at_cycle == Some(self.cycles) {
+            self.stop_at_cycle = None;
+            self.break_reason = Some(BreakReason::RunFor);
+        }
+
+        if self.halted() || (self.waiting_for_key.is_none() && self.check_breakpoints()) {
+            return Ok(());
+        }
+
+        self.cycles += 1;
+        // Before the instruction, so a key pressed ahead of an FX0A counts as
+        // already held.
+        self.read_keypad_bus();
+
+        if self.waiting_for_key.is_none() {
+            let address = self.pc;
+
+            self.record_history();
+
+            if let Some(tracer) = &mut self.tracer {
+                tracer(self.history.back().unwrap());
+            }
+
+            if let Some(profiler) = &mut self.profiler {
+                profiler.instruction();
+            }
+
+            if let Err(error) = self.execute() {
+                self.pc = address;
+                self.fault = Some(error);
+                return Err(error);
+            }
+
+            if let Some(event) = self.event.take() {
+                if self.break_events.contains(&event) && self.break_reason.is_none() {
+                    self.break_reason = Some(BreakReason::Event(event, address));
+                }
+            }
+        }
+
+        self.update_timers();
+        Ok(())
+    }
+
+    // The last HISTORY_SIZE instructions, with the state they ran in.
+    fn record_history(&mut self) {
+        if self.history.len() == HISTORY_SIZE {
+            self.history.pop_front();
+        }
+
+        self.history.push_back(TraceLine {
+            pc: self.pc,
+            opcode: self.read_memory_opcode(),
+            registers: self.registers,
+            pointer: self.pointer,
+        });
+    }
+
+    pub fn history(&self) -> impl Iterator<Item = &TraceLine> {
+        self.history.iter()
+    }
+
+    fn check_breakpoints(&mut self) -> bool {
+        if std::mem::take(&mut self.resume_past_break) {
+            return false;
+        }
+
+        let opcode = self.read_memory_opcode();
+
+        self.break_reason = if self.address_breakpoints.contains(&self.pc) {
+            Some(BreakReason::Address(self.pc))
+        } else {
+            self.opcode_breakpoints
+                .iter()
+                .position(|breakpoint| breakpoint.matches(opcode, &self.registers))
+                .map(BreakReason::Opcode)
+        };
+
+        self.break_reason.is_some()
+    }
+
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.waiting_for_key.is_some()
+    }
+
+    // Keys the ROM tested with EX9E/EXA1 so far, in key order.
+    pub fn queried_keys(&self) -> Vec<u8> {
+        (0..0x10)
+            .filter(|key| self.queried_keys & (1 << key) != 0)
+            .collect()
+    }
+
+    // Keys tested by EX9E or EXA1 in the last second of emulated time, one
+    // bit per key.
+    pub fn recently_polled_keys(&self) -> u16 {
+        self.recent_polls
+            .iter()
+            .enumerate()
+            .filter(|(_, frames)| **frames > 0)
+            .fold(0, |keys, (key, _)| keys | 1 << key)
+    }
+
+    pub fn waited_for_key(&self) -> bool {
+        self.waited_for_key
+    }
+
+    fn execute(&mut self) -> Result<(), CpuError> {
+        let memory_opcode = self.read_memory_opcode();
+
+        self.opcode = memory_opcode;
+        self.advance_pc();
+
+        match decompose_opcode(memory_opcode) {
+            (0, 0, 0xE, 0) => self.clear_display(),
+            (0, 0, 0xE, 0xE) => self.return_from_subroutine()?,
+            (0, 0, 0xC, n) => self.scroll(0, n as isize),
+            (0, 0, 0xF, 0xB) => self.scroll(4, 0),
+            (0, 0, 0xF, 0xC) => self.scroll(-4, 0),
+            (0, 0, 0xF, 0xE) => self.set_display_mode(DisplayMode::LORES),
+            (0, 0, 0xF, 0xF) => self.set_display_mode(DisplayMode::HIRES),
+            (1, n1, n2, n3) => self.goto(n1, n2, n3),
+            (2, n1, n2, n3) => self.call_subroutine(n1, n2, n3)?,
+            (3, x, n1, n2) => self.skip_if_equal(x, n1, n2),
+            (4, x, n1, n2) => self.skip_if_not_equal(x, n1, n2),
+            (5, x, y, 0) => self.skip_if_equal_registers(x, y),
+            (6, x, b1, b2) => self.set_register(x, b1 << 4 | b2),
+            (7, x, b1, b2) => self.add_to_register(x, b1 << 4 | b2),
+            (8, x, y, 0) => self.set_register_x_to_register_y(x, y),
+            (8, x, y, 1) => self.set_register_x_or_register_y(x, y),
+            (8, x, y, 2) => self.set_register_x_and_register_y(x, y),
+            (8, x, y, 3) => self.set_register_x_xor_register_y(x, y),
+            (8, x, y, 4) => self.add_register_y_to_register_x(x, y),
+            (8, x, y, 5) => self.sub_register_y_to_register_x(x, y),
+            (8, x, y, 6) => self.store_shift_register_x_least(x, y),
+            (8, x, y, 7) => self.diff_register_y_and_register_x(x, y),
+            (8, x, y, 0xE) => self.store_shift_register_x_most(x, y),
+            (9, x, y, 0) => self.comp_register_x_register_y_skip(x, y),
+            (0xA, n1, n2, n3) => self.set_pointer_address(n1, n2, n3),
+            (0xB, n1, n2, n3) => self.jump_with_offset(n1, n2, n3),
+            (0xC, x, n1, n2) => self.set_register_x_rand_and_value(x, n1, n2),
+            (0xD, x, y, n) => self.draw_sprite(x, y, n),
+            (0xE, x, 9, 0xE) => self.skip_if_key_pressed(x),
+            (0xE, x, 0xA, 1) => self.skip_if_key_not_pressed(x),
+            (0xF, x, 0, 7) => self.set_register_x_to_delay_timer(x),
+            (0xF, x, 0, 0xA) => self.wait_for_key_press(x),
+            (0xF, x, 1, 5) => self.set_delay_timer_to_register_x(x),
+            (0xF, x, 1, 8) => self.set_sound_timer_to_register_x(x),
+            (0xF, x, 1, 0xE) => self.add_register_x_to_pointer(x),
+            (0xF, x, 2, 9) => self.set_pointer_to_sprite(x),
+            (0xF, x, 3, 0) => self.set_pointer_to_big_sprite(x),
+            (0xF, x, 3, 3) => self.store_bcd_in_memory(x)?,
+            (0xF, x, 5, 5) => self.store_registers_in_memory(x)?,
+            (0xF, x, 6, 5) => self.fills_memory_from_registers(x),
+            (0xF, x, 7, 5) => self.store_registers_in_flags(x),
+            (0xF, x, 8, 5) => self.load_registers_from_flags(x),
+            (0xF, x, 0, 1) if self.debug_opcodes => self.debug_print(x),
+            (0, 0, 0, 0) => return Err(CpuError::EndOfCode),
+            _ => self.unknown_opcode(memory_opcode)?,
+        }
+
+        Ok(())
+    }
+
+    // Running off 0xFFF carries on at 0x000, like every other address.
+    fn advance_pc(&mut self) {
+        self.pc = (self.pc + 2) & ADDRESS_MASK;
+    }
+
+    // Where the instruction being executed started, for the ones that don't
+    // jump.
+    fn instruction_pc(&self) -> u16 {
+        self.pc.wrapping_sub(2) & ADDRESS_MASK
+    }
+
+    fn read_keypad_bus(&mut self) {
+        let mut released = 0;
+
+        while let Some(((key, value), sent_at)) = self.buses[0].try_read_timed() {
+            // Anyone can send on the bus, there are only 16 keys.
+            if key as usize >= KEY_COUNT {
+                continue;
+            }
+
+            let was_down = self.key_registers[key as usize] == 0x1;
+
+            self.key_registers[key as usize] = value;
+
+            // A press released before the ROM looked at it isn't counted.
+            if self.input_latency.is_some() {
+                self.pressed_at[key as usize] = (value == 0x1).then_some(sent_at);
+            }
+
+            // Every change goes through here, so a tap within a single cycle
+            // still ends the wait.
+            if self.waiting_for_key.is_some() {
+                released |= self.track_key_wait(key, was_down, value == 0x1);
+            }
+        }
+
+        if released != 0 {
+            self.end_key_wait(released.trailing_zeros() as u8);
+        }
+    }
+
+    fn store_bcd_in_memory(&mut self, register_x: u8) -> Result<(), CpuError> {
+        let reg_value = self.registers[register_x as usize];
+
+        let mut bcd: [u8; 3] = [0, 0, reg_value % 10];
+
+        for (div, i) in [100, 10].iter().zip([0, 1]) {
+            bcd[i] = ((reg_value - reg_value % div) / div) % div;
+        }
+
+        self.audit_range(AccessSource::StoreBcd, 3, Some(&bcd));
+        self.check_code_write(3)?;
+        self.check_watchpoints(3);
+        for (address, digit) in self.pointer_addresses(3).into_iter().zip(bcd) {
+            self.write_data(address, digit);
+        }
+
+        Ok(())
+    }
+
+    fn record_key_read(&mut self) {
+        if self.queried_keys == 0 && !self.waited_for_key {
+            self.event = Some(CpuEvent::KeyRead);
+        }
+    }
+
+    // The first time the ROM sees a key down after it was pressed.
+    fn observe_key(&mut self, key: u8) {
+        let pressed_at = self
+            .pressed_at
+            .get_mut(key as usize)
+            .filter(|_| self.key_registers.get(key as usize) == Some(&0x1))
+            .and_then(Option::take);
+
+        if let (Some(latency), Some(pressed_at)) = (&mut self.input_latency, pressed_at) {
+            latency.record(pressed_at.elapsed());
+        }
+    }
+
+    fn skip_if_key_pressed(&mut self, register_x: u8) {
+        let expected_key = self.registers[register_x as usize] & 0xF;
+
+        self.record_key_read();
+        self.observe_key(expected_key);
+
+        self.queried_keys |= 1 << expected_key;
+        self.recent_polls[expected_key as usize] = self.refresh_rate;
+
+        if self.key_registers[expected_key as usize] == 0x1 {
+            self.advance_pc();
+        }
+    }
+
+    fn skip_if_key_not_pressed(&mut self, register_x: u8) {
+        let expected_key = self.registers[register_x as usize] & 0xF;
+
+        self.record_key_read();
+        self.observe_key(expected_key);
+
+        self.queried_keys |= 1 << expected_key;
+        self.recent_polls[expected_key as usize] = self.refresh_rate;
+
+        if self.key_registers[expected_key as usize] == 0x0 {
+            self.advance_pc();
+        }
+    }
+
+    // FX0A accepts any key, so it can't narrow down which ones the ROM wants
+    // and leaves the queried and polled keys alone.
+    fn wait_for_key_press(&mut self, register_x: u8) {
+        self.record_key_read();
+        self.waiting_for_key = Some(register_x);
+        self.key_wait_presses = 0;
+        self.waited_for_key = true;
+    }
+
+    // Like on the VIP, FX0A takes a key pressed after it started and only
+    // lets go once that key is released. Keys already held don't count. With
+    // several keys down the first one released wins, and when several are
+    // released between two instructions the lowest key does, whatever order
+    // they came over the bus in. Every quirk profile does the same. Returns
+    // the key's bit if this released it.
+    fn track_key_wait(&mut self, key: u8, was_down: bool, down: bool) -> u16 {
+        let bit = 1 << key;
+
+        if down && !was_down {
+            if self.key_wait_presses == 0 {
+                self.observe_key(key);
+            }
+
+            self.key_wait_presses |= bit;
+        } else if !down && self.key_wait_presses & bit != 0 {
+            self.key_wait_presses &= !bit;
+            return bit;
+        }
+
+        0
+    }
+
+    fn end_key_wait(&mut self, key: u8) {
+        if let Some(register_x) = self.waiting_for_key.take() {
+            self.registers[register_x as usize] = key;
+        }
+
+        self.key_wait_presses = 0;
+    }
+
+    fn skip_if_equal_registers(&mut self, register_x: u8, register_y: u8) {
+        if self.registers[register_x as usize] == self.registers[register_y as usize] {
+            self.advance_pc();
+        }
+    }
+
+    fn fills_memory_from_registers(&mut self, max_register: u8) {
+        self.audit_range(AccessSource::LoadRegisters, max_register as usize + 1, None);
+
+        for (i, address) in self
+            .pointer_addresses(max_register as usize + 1)
+            .into_iter()
+            .enumerate()
+        {
+            self.registers[i] = self.read_data(address);
+        }
+
+        self.increment_pointer_after_load_store(max_register);
+    }
+
+    fn store_registers_in_memory(&mut self, max_register: u8) -> Result<(), CpuError> {
+        self.check_code_write(max_register as usize + 1)?;
+        self.check_watchpoints(max_register as usize + 1);
+
+        if self.audit.is_some() {
+            let registers = self.registers;
+
+            self.audit_range(
+                AccessSource::StoreRegisters,
+                max_register as usize + 1,
+                Some(&registers[0..max_register as usize + 1]),
+            );
+        }
+
+        for (i, address) in self
+            .pointer_addresses(max_register as usize + 1)
+            .into_iter()
+            .enumerate()
+        {
+            self.write_data(address, self.registers[i]);
+        }
+
+        self.increment_pointer_after_load_store(max_register);
+        Ok(())
+    }
+
+    fn increment_pointer_after_load_store(&mut self, max_register: u8) {
+        if self.quirks.load_store_increments_i {
+            self.pointer = self.pointer.wrapping_add(max_register as u16 + 1) & ADDRESS_MASK;
+        }
+    }
+
+    fn add_register_x_to_pointer(&mut self, register: u8) {
+        // VF flags I going past 0xFFF, as on the Amiga interpreter.
+        let pointer = self.pointer as u32 + self.registers[register as usize] as u32;
+
+        self.registers[0xF] = (pointer > ADDRESS_MASK as u32) as u8;
+        self.pointer = pointer as u16;
+    }
+
+    fn set_register_x_to_delay_timer(&mut self, register: u8) {
+        self.registers[register as usize] = self.delay_timer;
+    }
+
+    fn set_delay_timer_to_register_x(&mut self, register: u8) {
+        self.delay_timer = self.registers[register as usize];
+    }
+
+    fn set_sound_timer_to_register_x(&mut self, register: u8) {
+        let value = self.registers[register as usize];
+
+        if self.sound_timer == 0 && value != 0 {
+            self.event = Some(CpuEvent::SoundStart);
+        }
+
+        if value != 0 {
+            self.sound_events
+                .push(SoundEvent::Started { frames: value });
+        } else if self.sound_timer != 0 {
+            self.sound_events.push(SoundEvent::Stopped);
+        }
+
+        self.sound_timer = value;
+    }
+
+    fn set_pointer_to_sprite(&mut self, register: u8) {
+        // Only the low nibble picks the glyph, as on the VIP.
+        let digit = (self.registers[register as usize] & 0x0F) as usize;
+
+        self.pointer = (FONT_ADDRESS + digit * GLYPH_HEIGHT) as u16;
+    }
+
+    fn set_pointer_to_big_sprite(&mut self, register: u8) {
+        let digit = (self.registers[register as usize] & 0x0F) as usize;
+
+        self.pointer = (BIG_FONT_ADDRESS + digit * BIG_GLYPH_HEIGHT) as u16;
+    }
+
+    fn store_registers_in_flags(&mut self, max_register: u8) {
+        let count = max_register as usize + 1;
+
+        self.flags[..count].copy_from_slice(&self.registers[..count]);
+    }
+
+    fn load_registers_from_flags(&mut self, max_register: u8) {
+        let count = max_register as usize + 1;
+
+        self.registers[..count].copy_from_slice(&self.flags[..count]);
+    }
+
+    fn set_register(&mut self, register: u8, value: u8) {
+        self.registers[register as usize] = value;
+    }
+
+    fn add_to_register(&mut self, register: u8, value: u8) {
+        let (value, _) = self.registers[register as usize].overflowing_add(value);
+
+        self.registers[register as usize] = value;
+    }
+
+    fn set_register_x_to_register_y(&mut self, register_x: u8, register_y: u8) {
+        self.registers[register_x as usize] = self.registers[register_y as usize];
+    }
+
+    fn set_register_x_or_register_y(&mut self, register_x: u8, register_y: u8) {
+        self.registers[register_x as usize] |= self.registers[register_y as usize];
+        self.reset_flag_after_logic();
+    }
+
+    fn set_register_x_and_register_y(&mut self, register_x: u8, register_y: u8) {
+        self.registers[register_x as usize] &= self.registers[register_y as usize];
+        self.reset_flag_after_logic();
+    }
+
+    fn set_register_x_xor_register_y(&mut self, register_x: u8, register_y: u8) {
+        self.registers[register_x as usize] ^= self.registers[register_y as usize];
+        self.reset_flag_after_logic();
+    }
+
+    fn reset_flag_after_logic(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.registers[0xF] = 0;
+        }
+    }
+
+    // VF is written last, like the other flag setters, so it holds the carry
+    // when X is F.
+    fn add_register_y_to_register_x(&mut self, register_x: u8, register_y: u8) {
+        let (v, overflow) = self.registers[register_x as usize]
+            .overflowing_add(self.registers[register_y as usize]);
+
+        self.registers[register_x as usize] = v;
+        self.registers[0xF] = overflow as u8;
+    }
+
+    // VF is 1 when there is no borrow, equal operands included.
+    fn sub_register_y_to_register_x(&mut self, register_x: u8, register_y: u8) {
+        let (v, borrow) = self.registers[register_x as usize]
+            .overflowing_sub(self.registers[register_y as usize]);
+
+        self.registers[register_x as usize] = v;
+        self.registers[0xF] = !borrow as u8;
+    }
+
+    // VF gets the bit shifted out, after the shift so it still holds the
+    // flag when X is F.
+    fn store_shift_register_x_least(&mut self, register_x: u8, register_y: u8) {
+        let value = self.shift_operand(register_x, register_y);
+
+        self.registers[register_x as usize] = value >> 1;
+        self.registers[0xF] = value & 0x01;
+    }
+
+    fn store_shift_register_x_most(&mut self, register_x: u8, register_y: u8) {
+        let value = self.shift_operand(register_x, register_y);
+
+        self.registers[register_x as usize] = value << 1;
+        self.registers[0xF] = (value >> 7) & 1;
+    }
+
+    fn shift_operand(&self, register_x: u8, register_y: u8) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.registers[register_y as usize]
+        } else {
+            self.registers[register_x as usize]
+        }
+    }
+
+    fn diff_register_y_and_register_x(&mut self, register_x: u8, register_y: u8) {
+        let (v, borrow) = self.registers[register_y as usize]
+            .overflowing_sub(self.registers[register_x as usize]);
+
+        self.registers[register_x as usize] = v;
+        self.registers[0xF] = !borrow as u8;
+    }
+
+    fn comp_register_x_register_y_skip(&mut self, register_x: u8, register_y: u8) {
+        if self.registers[register_x as usize] != self.registers[register_y as usize] {
+            self.advance_pc();
+        }
+    }
+
+    fn set_pointer_address(&mut self, n1: u8, n2: u8, n3: u8) {
+        self.pointer = (n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16;
+    }
+
+    // BNNN on the VIP, BXNN on SCHIP.
+    fn jump_with_offset(&mut self, n1: u8, n2: u8, n3: u8) {
+        let address = (n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16;
+        let offset = if self.quirks.jump_uses_vx { n1 } else { 0 };
+
+        self.pc = (address + self.registers[offset as usize] as u16) & ADDRESS_MASK;
+    }
+
+    fn set_register_x_rand_and_value(&mut self, register_x: u8, n1: u8, n2: u8) {
+        let random_number: u8 = self.rng.gen();
+
+        self.registers[register_x as usize] = random_number & (n1 << 4 | n2);
+    }
+
+    fn goto(&mut self, n1: u8, n2: u8, n3: u8) {
+        let address = (n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16;
+
+        self.pc = address;
+    }
+
+    fn call_subroutine(&mut self, n1: u8, n2: u8, n3: u8) -> Result<(), CpuError> {
+        let address = (n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16;
+
+        if self.stack.len() == STACK_SIZE {
+            return Err(CpuError::StackOverflow);
+        }
+
+        self.stack.push(self.pc);
+        self.pc = address;
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.call(address);
+        }
+
+        Ok(())
+    }
+
+    fn return_from_subroutine(&mut self) -> Result<(), CpuError> {
+        self.pc = self.stack.pop().ok_or(CpuError::StackUnderflow)?;
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.ret();
+        }
+
+        Ok(())
+    }
+
+    fn skip_if_equal(&mut self, register_x: u8, n1: u8, n2: u8) {
+        let value = n1 << 4 | n2;
+
+        if self.registers[register_x as usize] == value {
+            self.advance_pc();
+        }
+    }
+
+    fn skip_if_not_equal(&mut self, register_x: u8, n1: u8, n2: u8) {
+        let value = n1 << 4 | n2;
+
+        if self.registers[register_x as usize] != value {
+            self.advance_pc();
+        }
+    }
+
+    fn clear_display(&mut self) {
+        self.display = [0; MAX_PIXELS];
+        self.display_changed = true;
+        self.dirty_rows = u64::MAX;
+        self.event = Some(CpuEvent::ClearScreen);
+    }
+
+    // Pixels scrolled past an edge are lost, blank ones come in from the
+    // other side. The distances are in pixels of the current mode.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let mode = self.display_mode;
+        let before = self.display;
+
+        for y in 0..mode.height {
+            for x in 0..mode.width {
+                let (from_x, from_y) = (x as isize - dx, y as isize - dy);
+                let inside = (0..mode.width as isize).contains(&from_x)
+                    && (0..mode.height as isize).contains(&from_y);
+
+                self.display[mode.index(x, y)] = if inside {
+                    before[mode.index(from_x as usize, from_y as usize)]
+                } else {
+                    0
+                };
+            }
+        }
+
+        self.display_changed = true;
+        self.dirty_rows = u64::MAX;
+    }
+
+    // The origin wraps around the screen, the pixels past the right and
+    // bottom edges are clipped unless sprites wrap too. DXY0 draws 16x16,
+    // two bytes a row.
+    fn draw_sprite(&mut self, register_x: u8, register_y: u8, n1: u8) {
+        let mode = self.display_mode;
+        let x = self.registers[register_x as usize] as usize % mode.width;
+        let y = self.registers[register_y as usize] as usize % mode.height;
+        let (sprite_width, rows) = if n1 == 0 { (16, 16) } else { (8, n1 as usize) };
+        let row_bytes = sprite_width / 8;
+        let (width, height) = if self.quirks.sprite_wrapping {
+            (sprite_width, rows)
+        } else {
+            (sprite_width.min(mode.width - x), rows.min(mode.height - y))
+        };
+
+        self.registers[0xF] = 0;
+        self.display_changed = true;
+        self.audit_range(AccessSource::SpriteFetch, height * row_bytes, None);
+
+        for y_line in 0..height {
+            let address = self.pointer.wrapping_add((y_line * row_bytes) as u16);
+            let pixel = if row_bytes == 2 {
+                (self.read_data(address) as u16) << 8
+                    | self.read_data(address.wrapping_add(1)) as u16
+            } else {
+                (self.read_data(address) as u16) << 8
+            };
+
+            for x_line in 0..width {
+                if (pixel & (0x8000 >> x_line)) != 0 {
+                    let (column, row) = ((x + x_line) % mode.width, (y + y_line) % mode.height);
+                    let index = mode.index(column, row);
+
+                    if self.display[index] == 1 {
+                        self.registers[0xF] = 1;
+                    }
+
+                    self.display[index] ^= 1;
+                    self.dirty_rows |= 1 << row;
+                }
+            }
+        }
+
+        if self.quirks.display_wait {
+            self.vblank_wait = true;
+        }
+
+        // A wrapped sprite is outlined up to the edges only.
+        self.last_draw = Some(SpriteDraw {
+            x,
+            y,
+            width: width.min(mode.width - x),
+            height: height.min(mode.height - y),
+            collision: self.registers[0xF] == 1,
+        });
+    }
+
+    #[allow(dead_code)]
+    pub fn pretty_print_memory(&self) {
+        for (i, byte) in self.memory.iter().enumerate() {
+            if i % 16 == 0 {
+                println!();
+                print!("0x{:04X} ", i);
+            }
+
+            print!("{:02X} ", byte);
+        }
+
+        println!();
+    }
+
+    pub fn load_rom(&mut self, rom: Vec<u8>) -> Result<(), String> {
+        if rom.len() > MAX_ROM_SIZE {
+            return Err(format!(
+                "ROM is {} bytes, larger than the {} bytes available",
+                rom.len(),
+                MAX_ROM_SIZE
+            ));
+        }
+
+        let start = PROGRAM_START as usize;
+
+        self.memory[start..start + rom.len()].copy_from_slice(&rom);
+        self.code = PROGRAM_START..PROGRAM_START + rom.len() as u16;
+        self.code_writes.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::StateChange;
+
+    // The opcodes go in from 0x200 as they would in a ROM file.
+    fn load(program: &[u16]) -> CPU {
+        let mut cpu = CPU::new();
+        let rom = program.iter().flat_map(|opcode| opcode.to_be_bytes());
+
+        cpu.load_rom(rom.collect()).unwrap();
+        cpu
+    }
+
+    fn run(cpu: &mut CPU, instructions: usize) {
+        for _ in 0..instructions {
+            cpu.cycle().unwrap();
+        }
+    }
+
+    #[test]
+    fn the_watchdog_stops_the_cpu_mid_frame_for_good() {
+        let mut cpu = load(&[0x7001, 0x1200]);
+
+        cpu.set_cycles_per_frame(100);
+        cpu.set_max_cycles(Some(25));
+        cpu.run_frame().unwrap();
+
+        assert_eq!(cpu.cycles(), 25);
+        assert_eq!(cpu.registers()[0], 13);
+        assert!(cpu.watchdog_expired() && cpu.halted());
+
+        cpu.run_frame().unwrap();
+
+        assert_eq!(cpu.cycles(), 25);
+
+        // Raising the budget lets it run on.
+        cpu.set_max_cycles(Some(30));
+        cpu.run_frame().unwrap();
+
+        assert_eq!(cpu.cycles(), 30);
+    }
+
+    #[test]
+    fn opcode_breakpoints_stop_on_the_matching_draw_of_a_rom() {
+        let mut cpu = CPU::new();
+
+        cpu.load_rom(include_bytes!("../roms/ibm_logo.ch8").to_vec())
+            .unwrap();
+        cpu.add_opcode_breakpoint(OpcodeBreakpoint::parse("FX0A").unwrap());
+        cpu.add_opcode_breakpoint(OpcodeBreakpoint::parse("Dxyn if V0 == 0x15").unwrap());
+
+        for _ in 0..10 {
+            cpu.run_frame().unwrap();
+        }
+
+        // The second letter, drawn after V0 went from 0x0C to 0x15.
+        assert_eq!(cpu.break_reason(), Some(BreakReason::Opcode(1)));
+        assert_eq!(cpu.pc(), 0x20E);
+        assert_eq!(cpu.registers()[0], 0x15);
+
+        cpu.continue_from_break();
+
+        for _ in 0..10 {
+            cpu.run_frame().unwrap();
+        }
+
+        // The logo ends on a jump to itself, no other instruction matches.
+        assert_eq!(cpu.break_reason(), None);
+        assert_eq!(cpu.pc(), cpu.history().last().unwrap().pc);
+    }
+
+    #[test]
+    fn each_break_event_stops_after_its_instruction() {
+        let mut cpu = load(&[0x00E0, 0x6005, 0xF018, 0xF018, 0xE09E, 0xE09E, 0x120C]);
+
+        cpu.set_cycles_per_frame(100);
+
+        for event in [
+            CpuEvent::ClearScreen,
+            CpuEvent::SoundStart,
+            CpuEvent::KeyRead,
+        ] {
+            cpu.add_break_event(event);
+        }
+
+        let mut reasons = Vec::new();
+
+        for _ in 0..10 {
+            cpu.run_frame().unwrap();
+
+            if let Some(reason) = cpu.break_reason() {
+                reasons.push((reason, cpu.pc()));
+                cpu.continue_from_break();
+            }
+        }
+
+        // Only the sound going from silent to on, and only the first read.
+        assert_eq!(
+            reasons,
+            [
+                (BreakReason::Event(CpuEvent::ClearScreen, 0x200), 0x202),
+                (BreakReason::Event(CpuEvent::SoundStart, 0x204), 0x206),
+                (BreakReason::Event(CpuEvent::KeyRead, 0x208), 0x20A),
+            ]
+        );
+        assert_eq!(CpuEvent::parse(" sound "), Ok(CpuEvent::SoundStart));
+        assert!(CpuEvent::parse("draw").is_err());
+    }
+
+    #[test]
+    fn a_fault_sticks_until_a_state_is_loaded() {
+        let mut cpu = load(&[0x00EE, 0x6001]);
+        let state = cpu.save_state();
+
+        assert_eq!(cpu.cycle(), Err(CpuError::StackUnderflow));
+        assert_eq!(cpu.pc(), 0x200);
+        assert_eq!(cpu.cycle(), Err(CpuError::StackUnderflow));
+        assert_eq!(cpu.run_frame(), Err(CpuError::StackUnderflow));
+        assert_eq!(cpu.pc(), 0x200);
+        assert_eq!(cpu.cycles(), 1);
+
+        cpu.load_state(&state).unwrap();
+
+        assert_eq!(cpu.fault(), None);
+    }
+
+    #[test]
+    fn unknown_opcodes_and_0000_fault() {
+        assert_eq!(
+            load(&[0x5AB1]).cycle(),
+            Err(CpuError::UnknownOpcode {
+                opcode: 0x5AB1,
+                pc: 0x200
+            })
+        );
+        assert_eq!(load(&[0x0000]).cycle(), Err(CpuError::EndOfCode));
+
+        let mut cpu = load(&[0x5AB1, 0x6001]);
+
+        cpu.set_unknown_opcode_policy(UnknownOpcodePolicy::WarnAndSkip);
+        run(&mut cpu, 2);
+
+        assert_eq!(cpu.registers()[0], 1);
+        assert_eq!(cpu.skipped_opcodes(), 1);
+        assert_eq!(
+            cpu.take_messages(),
+            [CpuMessage::SkippedOpcode {
+                pc: 0x200,
+                opcode: 0x5AB1
+            }]
+        );
+    }
+
+    #[test]
+    fn fx01_prints_only_with_the_debug_opcodes_on() {
+        let mut cpu = load(&[0x631F, 0xF301]);
+
+        cpu.set_debug_opcodes(true);
+        cpu.add_break_event(CpuEvent::DebugOp);
+        run(&mut cpu, 2);
+
+        let messages = cpu.take_messages();
+
+        assert_eq!(
+            messages,
+            [CpuMessage::Debug {
+                pc: 0x202,
+                register: 3,
+                value: 0x1F
+            }]
+        );
+        assert_eq!(messages[0].to_string(), "DBG pc=0x202 V3=0x1F");
+        assert_eq!(
+            cpu.break_reason(),
+            Some(BreakReason::Event(CpuEvent::DebugOp, 0x202))
+        );
+
+        let mut cpu = load(&[0x631F, 0xF301]);
+
+        cpu.cycle().unwrap();
+
+        assert_eq!(
+            cpu.cycle(),
+            Err(CpuError::UnknownOpcode {
+                opcode: 0xF301,
+                pc: 0x202
+            })
+        );
+        assert_eq!(cpu.take_messages(), []);
+    }
+
+    #[test]
+    fn the_stack_overflows_past_its_size() {
+        // 0x200 calls itself.
+        let mut cpu = load(&[0x2200]);
+
+        run(&mut cpu, STACK_SIZE);
+
+        assert_eq!(cpu.cycle(), Err(CpuError::StackOverflow));
+    }
+
+    // Runs `8X1N` with VX = x and V1 = y, returning VX and VF.
+    fn alu(n: u16, register_x: u16, x: u8, y: u8) -> (u8, u8) {
+        let mut cpu = load(&[
+            0x6000 | register_x << 8 | x as u16,
+            0x6100 | y as u16,
+            0x8010 | register_x << 8 | n,
+        ]);
+
+        run(&mut cpu, 3);
+
+        let registers = cpu.registers();
+
+        (registers[register_x as usize], registers[0xF])
+    }
+
+    // Everything the last instruction of `program` changed.
+    fn changes(program: &[u16]) -> Vec<StateChange> {
+        let mut cpu = load(program);
+
+        run(&mut cpu, program.len() - 1);
+
+        let before = cpu.snapshot();
+
+        run(&mut cpu, 1);
+        before.diff(&cpu.snapshot())
+    }
+
+    #[test]
+    fn add_sets_the_carry_after_the_sum() {
+        let register = |index, old, new| StateChange::Register { index, old, new };
+        let pc = StateChange::Pc {
+            old: 0x204,
+            new: 0x206,
+        };
+
+        assert_eq!(
+            changes(&[0x62FF, 0x6101, 0x8214]),
+            [register(2, 0xFF, 0x00), register(0xF, 0, 1), pc.clone()]
+        );
+        assert_eq!(
+            changes(&[0x6280, 0x6180, 0x8214]),
+            [register(2, 0x80, 0x00), register(0xF, 0, 1), pc.clone()]
+        );
+        // No carry, VF was 0 already.
+        assert_eq!(
+            changes(&[0x6280, 0x6101, 0x8214]),
+            [register(2, 0x80, 0x81), pc.clone()]
+        );
+        // 6FFF 6101 8F14, the flag overwrites the sum.
+        assert_eq!(
+            changes(&[0x6FFF, 0x6101, 0x8F14]),
+            [register(0xF, 0xFF, 0x01), pc.clone()]
+        );
+        assert_eq!(
+            changes(&[0x6F01, 0x6101, 0x8F14]),
+            [register(0xF, 0x01, 0x00), pc]
+        );
+    }
+
+    #[test]
+    fn subtractions_set_vf_when_there_is_no_borrow() {
+        // 8XY5 is VX - VY, 8XY7 is VY - VX.
+        assert_eq!(alu(5, 2, 0x80, 0x01), (0x7F, 1));
+        assert_eq!(alu(5, 2, 0x01, 0x80), (0x81, 0));
+        assert_eq!(alu(5, 2, 0xFF, 0xFF), (0x00, 1));
+        assert_eq!(alu(5, 2, 0x00, 0xFF), (0x01, 0));
+        assert_eq!(alu(7, 2, 0x01, 0x80), (0x7F, 1));
+        assert_eq!(alu(7, 2, 0x80, 0x01), (0x81, 0));
+        assert_eq!(alu(7, 2, 0xFF, 0xFF), (0x00, 1));
+        assert_eq!(alu(7, 2, 0xFF, 0x00), (0x01, 0));
+        // VF ends up as the flag, not the difference.
+        assert_eq!(alu(5, 0xF, 0x80, 0x01), (1, 1));
+        assert_eq!(alu(7, 0xF, 0x80, 0x01), (0, 0));
+    }
+
+    #[test]
+    fn bcd_and_register_stores_wrap_past_0xfff() {
+        let mut cpu = load(&[0xAFFE, 0x60EA, 0xF033]);
+
+        run(&mut cpu, 3);
+
+        let digits = [0xFFE, 0xFFF, 0x000].map(|address| cpu.read_memory(address));
+
+        assert_eq!(digits, [2, 3, 4]);
+
+        let mut cpu = load(&[
+            0xAFFE, 0x6001, 0x6102, 0x6203, 0x6304, 0xF355, 0x6000, 0x6300, 0xF365,
+        ]);
+
+        run(&mut cpu, 9);
+
+        let stored = [0xFFE, 0xFFF, 0x000, 0x001].map(|address| cpu.read_memory(address));
+
+        assert_eq!(stored, [1, 2, 3, 4]);
+        assert_eq!(cpu.registers()[..4], [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn watchpoints_catch_stores_that_wrap_past_0xfff() {
+        let mut cpu = load(&[0xAFFE, 0xF255, 0x6001]);
+
+        cpu.add_watchpoint(0x000);
+        run(&mut cpu, 3);
+
+        assert_eq!(
+            cpu.break_reason(),
+            Some(BreakReason::Watch {
+                address: 0x000,
+                pc: 0x202
+            })
+        );
+        assert_eq!(cpu.pc(), 0x204);
+        assert_eq!(cpu.registers()[0], 0);
+    }
+
+    #[test]
+    fn writes_into_the_code_warn_once_or_fault_when_strict() {
+        // FX55 turns the 6005 right after it into a 6012.
+        let program = [0xA208, 0x6060, 0x6112, 0xF155, 0x6005];
+        let mut cpu = load(&program);
+
+        run(&mut cpu, 5);
+
+        assert_eq!(cpu.registers()[0], 0x12);
+        assert_eq!(
+            cpu.take_messages(),
+            [0x208, 0x209].map(|address| CpuMessage::CodeWrite { pc: 0x206, address })
+        );
+
+        let mut cpu = load(&program);
+
+        cpu.set_strict_code_writes(true);
+        run(&mut cpu, 3);
+
+        assert_eq!(cpu.cycle(), Err(CpuError::CodeWrite { address: 0x208 }));
+        assert_eq!(cpu.read_memory(0x209), 0x05);
+    }
+
+    #[test]
+    fn code_write_checks_wrap_past_0xfff_like_the_store() {
+        let mut rom = vec![0; MAX_ROM_SIZE];
+
+        // I = 0xFFF, then FX55 writes 0xFFF and 0x000.
+        rom[..4].copy_from_slice(&[0xAF, 0xFF, 0xF1, 0x55]);
+
+        let mut cpu = CPU::new();
+
+        cpu.load_rom(rom).unwrap();
+        run(&mut cpu, 2);
+
+        assert_eq!(
+            cpu.take_messages(),
+            [CpuMessage::CodeWrite {
+                pc: 0x202,
+                address: 0xFFF
+            }]
+        );
+    }
+
+    #[test]
+    fn the_pc_wraps_past_0xfff() {
+        let mut cpu = load(&[0x1FFE]);
+
+        cpu.write_memory(0xFFE, 0x60);
+        cpu.write_memory(0xFFF, 0x05);
+        run(&mut cpu, 2);
+
+        assert_eq!(cpu.pc(), 0x000);
+        assert_eq!(cpu.history().last().unwrap().pc, 0xFFE);
+
+        cpu.add_breakpoint(0x000);
+        cpu.cycle().unwrap();
+
+        assert_eq!(cpu.break_reason(), Some(BreakReason::Address(0x000)));
+    }
+
+    // Presses 7 and then 2 while FX0A waits, then releases them in the given
+    // batches with a cycle after each. Returns V5.
+    fn release_held_keys(batches: &[&[u8]]) -> u8 {
+        let mut cpu = load(&[0xF50A, 0x1202]);
+
+        cpu.cycle().unwrap();
+        cpu.get_keypad_bus().send(7, 1);
+        cpu.get_keypad_bus().send(2, 1);
+        run(&mut cpu, 2);
+
+        for batch in batches {
+            assert!(cpu.is_waiting_for_key());
+
+            for key in *batch {
+                cpu.get_keypad_bus().send(*key, 0);
+            }
+
+            cpu.cycle().unwrap();
+        }
+
+        assert!(!cpu.is_waiting_for_key());
+        cpu.registers()[5]
+    }
+
+    #[test]
+    fn fx0a_takes_the_first_key_released_and_the_lowest_of_a_tie() {
+        assert_eq!(release_held_keys(&[&[7, 2]]), 2);
+        assert_eq!(release_held_keys(&[&[2, 7]]), 2);
+        assert_eq!(release_held_keys(&[&[7]]), 7);
+        assert_eq!(release_held_keys(&[&[2]]), 2);
+    }
+
+    #[test]
+    fn frame_timers_tick_once_per_frame_at_any_speed() {
+        for (cycles_per_frame, timing) in [
+            (1, Timing::Fixed),
+            (10, Timing::Fixed),
+            (200, Timing::Fixed),
+            (0, Timing::Vip),
+        ] {
+            // Sets the delay timer to FF, then spins.
+            let mut cpu = load(&[0x60FF, 0xF015, 0x1204]);
+
+            cpu.set_frame_timers(true);
+            cpu.set_cycles_per_frame(cycles_per_frame);
+            cpu.set_timing(timing);
+            cpu.run_frame().unwrap();
+            cpu.run_frame().unwrap();
+
+            let (delay, cycles) = (cpu.delay_timer(), cpu.cycles());
+
+            for _ in 0..30 {
+                cpu.run_frame().unwrap();
+            }
+
+            assert_eq!(delay - cpu.delay_timer(), 30);
+            assert!(cpu.cycles() > cycles);
+        }
+    }
+
+    #[test]
+    fn time_spent_paused_does_not_run_the_timers_down() {
+        let pause = Duration::from_millis(100);
+        // Sets the delay timer to FF, then spins.
+        let mut paused = load(&[0x60FF, 0xF015, 0x1204]);
+        let mut running = load(&[0x60FF, 0xF015, 0x1204]);
+
+        run(&mut paused, 2);
+        run(&mut running, 2);
+        paused.pause_clock();
+        std::thread::sleep(pause);
+        paused.resume_clock();
+        run(&mut paused, 1);
+        run(&mut running, 1);
+
+        // 100ms is six ticks at 60 Hz, a tick of slack for a slow machine.
+        assert!(paused.delay_timer() >= 0xFE, "{:X}", paused.delay_timer());
+        assert!(running.delay_timer() <= 0xFA, "{:X}", running.delay_timer());
+    }
+
+    #[test]
+    fn vip_frames_carry_the_overspent_cycles_over() {
+        // A jump to itself costs 52 machine cycles out of 3668 a frame.
+        let mut cpu = load(&[0x1200]);
+
+        cpu.set_timing(Timing::Vip);
+        cpu.run_frame().unwrap();
+
+        assert_eq!(cpu.cycles(), 71);
+
+        for _ in 0..9 {
+            cpu.run_frame().unwrap();
+        }
+
+        // 10 frames of 3668 are 705.4 jumps, the last one overspends.
+        assert_eq!(cpu.cycles(), 706);
+    }
+
+    #[test]
+    fn a_delay_of_50_lasts_one_second_at_50_hz() {
+        for refresh_rate in [50, 60] {
+            // Sets the delay timer to 50, then spins.
+            let mut cpu = load(&[0x6032, 0xF015, 0x1204]);
+            let mut frames = 1;
+
+            cpu.set_frame_timers(true);
+            cpu.set_refresh_rate(refresh_rate);
+            cpu.run_frame().unwrap();
+
+            while cpu.delay_timer() > 0 {
+                cpu.run_frame().unwrap();
+                frames += 1;
+            }
+
+            // The frame that set the timer already took one off.
+            assert_eq!(frames, 50);
+
+            if refresh_rate == 50 {
+                assert_eq!(cpu.frame_duration() * frames, Duration::from_secs(1));
+            }
+        }
+    }
+
+    #[test]
+    fn load_rom_rejects_what_does_not_fit() {
+        let mut cpu = CPU::new();
+
+        assert!(cpu.load_rom(vec![0; MAX_ROM_SIZE + 1]).is_err());
+        assert!(cpu.load_rom(vec![0xAA; MAX_ROM_SIZE]).is_ok());
+        assert_eq!(cpu.read_memory(0xFFF), 0xAA);
+    }
+
+    // The 8 pixels from (x, y) rightwards as one byte, the way sprites store
+    // them.
+    fn display_byte(cpu: &CPU, x: usize, y: usize) -> u8 {
+        let mode = cpu.display_mode();
+
+        (0..8).fold(0, |byte, i| byte << 1 | cpu.display()[mode.index(x + i, y)])
+    }
+
+    #[test]
+    fn fx29_points_dxyn_at_each_glyph() {
+        for digit in 0..0x10 {
+            // FX29 ignores the high nibble.
+            let mut cpu = load(&[0x6130 | digit, 0xF129, 0xD005]);
+
+            run(&mut cpu, 3);
+
+            let glyph = &FONTSET[digit as usize * GLYPH_HEIGHT..][..GLYPH_HEIGHT];
+            let drawn: Vec<u8> = (0..GLYPH_HEIGHT)
+                .map(|y| display_byte(&cpu, 0, y))
+                .collect();
+
+            assert_eq!(drawn, glyph, "digit {:X}", digit);
+            assert_eq!(
+                cpu.pointer(),
+                (FONT_ADDRESS + digit as usize * GLYPH_HEIGHT) as u16
+            );
+        }
+    }
+
+    #[test]
+    fn all_sixteen_keys_reach_the_cpu_through_the_bus() {
+        for key in 0..0x10 {
+            // V0 has junk in the high nibble, which EX9E and EXA1 ignore.
+            let mut cpu = load(&[0x60F0 | key, 0xE09E, 0x6101, 0xE0A1, 0x6201]);
+
+            cpu.get_keypad_bus().send(key as u8, 1);
+            run(&mut cpu, 4);
+
+            assert_eq!(cpu.registers()[1..3], [0, 1], "key {:X}", key);
+
+            let mut cpu = load(&[0x60F0 | key, 0xE0A1, 0x6101, 0xE09E, 0x6201]);
+
+            cpu.get_keypad_bus().send(key as u8, 1);
+            cpu.get_keypad_bus().send(key as u8, 0);
+            run(&mut cpu, 4);
+
+            assert_eq!(cpu.registers()[1..3], [0, 1], "key {:X}", key);
+        }
+    }
+
+    // Counts V3 up to 100 before testing key 5 with `test`, sets V1 when
+    // that didn't skip and V2 after.
+    fn test_key_after_a_wait(test: u16, press: &[(u8, u8)]) -> [u8; 2] {
+        let mut cpu = load(&[0x6005, 0x7301, 0x3364, 0x1202, test, 0x6101, 0x6201]);
+
+        for (key, state) in press {
+            cpu.get_keypad_bus().send(*key, *state);
+        }
+
+        while cpu.pc() != 0x20E {
+            cpu.cycle().unwrap();
+        }
+
+        assert!(cpu.cycles() > 300);
+        [cpu.registers()[1], cpu.registers()[2]]
+    }
+
+    #[test]
+    fn ex9e_and_exa1_see_a_key_held_long_after_its_press() {
+        assert_eq!(test_key_after_a_wait(0xE09E, &[(5, 1)]), [0, 1]);
+        assert_eq!(test_key_after_a_wait(0xE0A1, &[(5, 1)]), [1, 1]);
+        // Other keys and a press that was let go don't count.
+        assert_eq!(test_key_after_a_wait(0xE09E, &[(4, 1), (6, 1)]), [1, 1]);
+        assert_eq!(test_key_after_a_wait(0xE09E, &[(5, 1), (5, 0)]), [1, 1]);
+        assert_eq!(test_key_after_a_wait(0xE0A1, &[(5, 1), (5, 0)]), [0, 1]);
+    }
+
+    #[test]
+    fn input_latency_counts_each_press_the_rom_sees_once() {
+        // Looks at key 5 over and over, pressed or not.
+        let mut cpu = load(&[0x6005, 0xE09E, 0x1202, 0x1202]);
+
+        cpu.enable_input_latency();
+        run(&mut cpu, 1);
+
+        cpu.get_keypad_bus().send(5, 1);
+        std::thread::sleep(Duration::from_millis(5));
+        run(&mut cpu, 6);
+
+        let latency = cpu.input_latency().unwrap();
+
+        assert_eq!(latency.samples(), 1);
+        assert!(latency.max() >= Duration::from_millis(5));
+
+        // Released before the ROM looked, and a key it doesn't look at.
+        cpu.get_keypad_bus().send(5, 0);
+        run(&mut cpu, 2);
+        cpu.get_keypad_bus().send(5, 1);
+        cpu.get_keypad_bus().send(5, 0);
+        cpu.get_keypad_bus().send(6, 1);
+        run(&mut cpu, 6);
+
+        assert_eq!(cpu.input_latency().unwrap().samples(), 1);
+
+        cpu.get_keypad_bus().send(5, 1);
+        run(&mut cpu, 2);
+
+        assert_eq!(cpu.input_latency().unwrap().samples(), 2);
+    }
+
+    #[test]
+    fn the_last_signal_for_a_key_in_a_cycle_wins() {
+        // A tap inside one cycle leaves the key up, a release and a new
+        // press leave it down.
+        assert_eq!(test_key_after_a_wait(0xE09E, &[(5, 1), (5, 0)]), [1, 1]);
+        assert_eq!(
+            test_key_after_a_wait(0xE09E, &[(5, 1), (5, 0), (5, 1)]),
+            [0, 1]
+        );
+        assert_eq!(test_key_after_a_wait(0xE09E, &[(5, 0), (5, 1)]), [0, 1]);
+        // Signals for other keys in between don't reorder them.
+        assert_eq!(
+            test_key_after_a_wait(0xE09E, &[(5, 1), (6, 1), (6, 0), (5, 0)]),
+            [1, 1]
+        );
+        assert_eq!(
+            test_key_after_a_wait(0xE09E, &[(5, 1), (6, 1), (5, 0), (6, 0), (5, 1)]),
+            [0, 1]
+        );
+    }
+
+    #[test]
+    fn a_press_and_release_between_cycles_still_reaches_the_rom() {
+        // The press isn't lost behind its release.
+        let mut cpu = load(&[0xF50A, 0x6001]);
+
+        cpu.cycle().unwrap();
+        cpu.get_keypad_bus().send(9, 1);
+        cpu.get_keypad_bus().send(9, 0);
+        cpu.cycle().unwrap();
+
+        assert!(!cpu.is_waiting_for_key());
+        assert_eq!(cpu.registers()[5], 9);
+    }
+
+    #[test]
+    fn shifts_set_vf_to_the_bit_shifted_out() {
+        // In place by default, VY is ignored.
+        assert_eq!(alu(6, 2, 0x01, 0xFF), (0x00, 1));
+        assert_eq!(alu(6, 2, 0x80, 0xFF), (0x40, 0));
+        assert_eq!(alu(6, 2, 0xFF, 0x00), (0x7F, 1));
+        assert_eq!(alu(0xE, 2, 0x01, 0xFF), (0x02, 0));
+        assert_eq!(alu(0xE, 2, 0x80, 0x00), (0x00, 1));
+        assert_eq!(alu(0xE, 2, 0xFF, 0x00), (0xFE, 1));
+        // Shifting VF itself leaves only the flag.
+        assert_eq!(alu(6, 0xF, 0x80, 0x00), (0, 0));
+        assert_eq!(alu(0xE, 0xF, 0x80, 0x00), (1, 1));
+    }
+
+    #[test]
+    fn fx0a_stalls_until_a_fresh_key_is_released() {
+        let mut cpu = load(&[0xF50A, 0x6001]);
+
+        // Held before the wait started, so it doesn't count.
+        cpu.get_keypad_bus().send(0xA, 1);
+        run(&mut cpu, 3);
+        cpu.get_keypad_bus().send(0xA, 0);
+        run(&mut cpu, 2);
+
+        assert!(cpu.is_waiting_for_key());
+
+        cpu.get_keypad_bus().send(0xC, 1);
+        run(&mut cpu, 3);
+
+        assert!(cpu.is_waiting_for_key());
+        assert_eq!(cpu.pc(), 0x202);
+        assert_eq!(cpu.registers()[..2], [0, 0]);
+
+        cpu.get_keypad_bus().send(0xC, 0);
+        cpu.cycle().unwrap();
+
+        assert!(!cpu.is_waiting_for_key());
+        assert_eq!(cpu.registers()[5], 0xC);
+        assert_eq!(cpu.registers()[0], 1);
+    }
+
+    fn lit_pixels(cpu: &CPU) -> Vec<(usize, usize)> {
+        let mode = cpu.display_mode();
+
+        (0..mode.height)
+            .flat_map(|y| (0..mode.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| cpu.display()[mode.index(x, y)] == 1)
+            .collect()
+    }
+
+    // Draws an 8x4 block with VX = x and VY = y, twice if `again`.
+    fn draw_block(quirks: Quirks, x: u8, y: u8, again: bool) -> CPU {
+        let draw = if again { 0xD014 } else { 0x120A };
+        let mut cpu = load(&[
+            0xA20C,
+            0x6000 | x as u16,
+            0x6100 | y as u16,
+            0xD014,
+            draw,
+            0x120A,
+            0xFFFF,
+            0xFFFF,
+        ]);
+
+        cpu.set_quirks(quirks);
+        run(&mut cpu, 5);
+        cpu
+    }
+
+    #[test]
+    fn sprites_are_clipped_at_the_right_and_bottom_edges() {
+        let clipped = vec![(62, 30), (63, 30), (62, 31), (63, 31)];
+
+        assert_eq!(
+            lit_pixels(&draw_block(Quirks::default(), 62, 30, false)),
+            clipped
+        );
+        // The origin itself wraps.
+        assert_eq!(
+            lit_pixels(&draw_block(Quirks::default(), 126, 62, false)),
+            clipped
+        );
+
+        let cpu = draw_block(Quirks::default(), 62, 30, true);
+
+        assert_eq!(lit_pixels(&cpu), []);
+        assert_eq!(cpu.registers()[0xF], 1);
+        assert_eq!(
+            draw_block(Quirks::default(), 62, 30, false).registers()[0xF],
+            0
+        );
+    }
+
+    #[test]
+    fn the_last_draw_outlines_the_sprite_up_to_the_edges() {
+        let box_at = |quirks: Quirks, x: u8, y: u8, again: bool| {
+            draw_block(quirks, x, y, again).take_last_draw().unwrap()
+        };
+        let outline = |x, y, width, height, collision| SpriteDraw {
+            x,
+            y,
+            width,
+            height,
+            collision,
+        };
+
+        assert_eq!(
+            box_at(Quirks::default(), 10, 4, false),
+            outline(10, 4, 8, 4, false)
+        );
+        assert_eq!(
+            box_at(Quirks::default(), 10, 4, true),
+            outline(10, 4, 8, 4, true)
+        );
+        // Clipped, and the origin wraps before that.
+        assert_eq!(
+            box_at(Quirks::default(), 126, 62, false),
+            outline(62, 30, 2, 2, false)
+        );
+        // A wrapping sprite is only outlined up to the edges.
+        assert_eq!(
+            box_at(Quirks::XOCHIP, 60, 29, false),
+            outline(60, 29, 4, 3, false)
+        );
+
+        let mut cpu = draw_block(Quirks::default(), 10, 4, false);
+
+        assert!(cpu.take_last_draw().is_some());
+        assert_eq!(cpu.take_last_draw(), None);
+    }
+
+    #[test]
+    fn a_sprite_draw_contains_only_its_own_pixels() {
+        let draw = SpriteDraw {
+            x: 10,
+            y: 4,
+            width: 8,
+            height: 4,
+            collision: false,
+        };
+
+        assert!(draw.contains(10, 4));
+        assert!(draw.contains(17, 7));
+        assert!(!draw.contains(18, 7));
+        assert!(!draw.contains(17, 8));
+        assert!(!draw.contains(9, 4));
+    }
+
+    // Runs the whole program once with the quirk off and once with it on.
+    fn with_quirk(quirk: fn(&mut Quirks) -> &mut bool, program: &[u16]) -> [CPU; 2] {
+        [false, true].map(|enabled| {
+            let mut quirks = Quirks::default();
+            let mut cpu = load(program);
+
+            *quirk(&mut quirks) = enabled;
+            cpu.set_quirks(quirks);
+            run(&mut cpu, program.len());
+            cpu
+        })
+    }
+
+    #[test]
+    fn each_quirk_changes_its_instruction() {
+        let [off, on] = with_quirk(|q| &mut q.shift_uses_vy, &[0x6004, 0x6103, 0x8016]);
+
+        assert_eq!((off.registers()[0], off.registers()[0xF]), (0x02, 0));
+        assert_eq!((on.registers()[0], on.registers()[0xF]), (0x01, 1));
+
+        let [off, on] = with_quirk(|q| &mut q.load_store_increments_i, &[0xA300, 0xF255]);
+
+        assert_eq!(off.pointer(), 0x300);
+        assert_eq!(on.pointer(), 0x303);
+
+        let [off, on] = with_quirk(|q| &mut q.jump_uses_vx, &[0x6004, 0x6210, 0xB220]);
+
+        assert_eq!(off.pc(), 0x224);
+        assert_eq!(on.pc(), 0x230);
+
+        for logic in [0x8011, 0x8012, 0x8013] {
+            let [off, on] =
+                with_quirk(|q| &mut q.logic_resets_vf, &[0x6F05, 0x6003, 0x6105, logic]);
+
+            assert_eq!(off.registers()[0xF], 5);
+            assert_eq!(on.registers()[0xF], 0);
+        }
+
+        let wrapping = Quirks {
+            sprite_wrapping: true,
+            ..Quirks::default()
+        };
+        let lit = lit_pixels(&draw_block(wrapping, 62, 30, false));
+
+        assert_eq!(lit.len(), 32);
+        assert!(lit.contains(&(0, 0)) && lit.contains(&(5, 1)) && lit.contains(&(63, 31)));
+    }
+
+    #[test]
+    fn display_wait_draws_one_sprite_a_frame() {
+        for display_wait in [false, true] {
+            // Three one-pixel sprites side by side, then spins.
+            let mut cpu = load(&[0xA300, 0xD011, 0x7001, 0xD011, 0x7001, 0xD011, 0x120C]);
+            let mut drawn = Vec::new();
+
+            cpu.write_memory(0x300, 0x80);
+            cpu.set_quirks(Quirks {
+                display_wait,
+                ..Quirks::default()
+            });
+
+            for _ in 0..3 {
+                cpu.run_frame().unwrap();
+                drawn.push(lit_pixels(&cpu).len());
+            }
+
+            let expected = if display_wait { [1, 2, 3] } else { [3, 3, 3] };
+
+            assert_eq!(drawn, expected);
+        }
+    }
+
+    // Puts a 16x16 block at 0x300 for DXY0.
+    fn load_with_big_block(program: &[u16]) -> CPU {
+        let mut cpu = load(program);
+
+        for address in 0x300..0x320 {
+            cpu.write_memory(address, 0xFF);
+        }
+        cpu
+    }
+
+    #[test]
+    fn mode_switches_clear_the_screen() {
+        let mut cpu = load(&[
+            0x00E0, 0x6064, 0x6132, 0xA300, 0xD011, 0x00FF, 0xD011, 0x00FE,
+        ]);
+
+        cpu.write_memory(0x300, 0x80);
+        run(&mut cpu, 5);
+
+        // 100 and 50 wrap in lo-res, but not in hi-res.
+        assert_eq!(lit_pixels(&cpu), [(36, 18)]);
+
+        run(&mut cpu, 2);
+
+        assert_eq!(cpu.display_mode(), DisplayMode::HIRES);
+        assert_eq!(lit_pixels(&cpu), [(100, 50)]);
+
+        cpu.cycle().unwrap();
+
+        assert_eq!(cpu.display_mode(), DisplayMode::LORES);
+        assert_eq!(lit_pixels(&cpu), []);
+    }
+
+    #[test]
+    fn dxy0_draws_16x16_and_reports_collisions() {
+        let mut cpu = load_with_big_block(&[
+            0x00FF, 0xA300, 0x6008, 0x6104, 0xD010, 0x6017, 0x6113, 0xD010,
+        ]);
+
+        run(&mut cpu, 5);
+
+        let lit = lit_pixels(&cpu);
+
+        assert_eq!(lit.len(), 256);
+        assert_eq!((lit[0], lit[255]), ((8, 4), (23, 19)));
+        assert_eq!(cpu.registers()[0xF], 0);
+
+        // Overlaps the first block on its bottom right pixel only.
+        run(&mut cpu, 3);
+
+        assert_eq!(lit_pixels(&cpu).len(), 510);
+        assert_eq!(cpu.registers()[0xF], 1);
+    }
+
+    #[test]
+    fn scrolling_moves_the_picture_and_drops_what_falls_off() {
+        let row = |xs: Range<usize>, y| xs.map(|x| (x, y)).collect::<Vec<_>>();
+        let mut cpu = load_with_big_block(&[
+            0x00FF, 0xA300, 0x6000, 0x6100, 0xD011, 0x00C3, 0x00FB, 0x00FC, 0x00FC,
+        ]);
+
+        run(&mut cpu, 5);
+
+        assert_eq!(lit_pixels(&cpu), row(0..8, 0));
+
+        cpu.cycle().unwrap();
+
+        assert_eq!(lit_pixels(&cpu), row(0..8, 3));
+
+        cpu.cycle().unwrap();
+
+        assert_eq!(lit_pixels(&cpu), row(4..12, 3));
+
+        // Four of the pixels go past the left edge and are lost.
+        run(&mut cpu, 2);
+
+        assert_eq!(lit_pixels(&cpu), row(0..4, 3));
+    }
+
+    #[test]
+    fn a_loaded_state_runs_on_exactly_like_the_original() {
+        // Draws the glyphs one after the other across the screen, forever.
+        let mut cpu = load(&[
+            0x7001, 0xF029, 0x7103, 0x7205, 0xD125, 0x2210, 0x1200, 0x0000, 0x00EE,
+        ]);
+
+        cpu.set_frame_timers(true);
+        run(&mut cpu, 300);
+
+        let state = cpu.save_state();
+
+        run(&mut cpu, 300);
+
+        let (display, registers) = (cpu.display().to_vec(), *cpu.registers());
+
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.cycles(), 300);
+        run(&mut cpu, 300);
+
+        assert_eq!(cpu.display(), display);
+        assert_eq!(cpu.registers(), &registers);
+        assert_eq!(cpu.cycles(), 600);
+    }
+
+    #[test]
+    fn a_truncated_state_changes_nothing() {
+        let mut cpu = load(&[0x6007, 0x1202]);
+        let state = cpu.save_state();
+
+        run(&mut cpu, 1);
+
+        assert!(cpu.load_state(&state[..state.len() - 1]).is_err());
+        assert_eq!(cpu.registers()[0], 7);
+        assert_eq!(cpu.pc(), 0x202);
+    }
+
+    #[test]
+    fn a_sprite_marks_exactly_the_rows_it_flips() {
+        // The 0 glyph at y = 3, then an empty sprite from 0x300.
+        let mut cpu = load(&[0x6000, 0x6103, 0xF029, 0xD015, 0xA300, 0xD015]);
+
+        // A new CPU has everything to present.
+        assert_eq!(cpu.take_dirty_rows(), u64::MAX);
+
+        run(&mut cpu, 4);
+
+        assert_eq!(cpu.take_dirty_rows(), 0b11111 << 3);
+        assert_eq!(cpu.take_dirty_rows(), 0);
+
+        run(&mut cpu, 2);
+
+        assert_eq!(cpu.take_dirty_rows(), 0);
+    }
+
+    #[test]
+    fn a_sprite_past_the_bottom_marks_only_the_rows_it_reaches() {
+        let program = [0x6000, 0x611E, 0xF029, 0xD015];
+        let mut clipped = load(&program);
+        let mut wrapped = load(&program);
+
+        wrapped.set_quirks(Quirks::XOCHIP);
+
+        for cpu in [&mut clipped, &mut wrapped] {
+            cpu.take_dirty_rows();
+            run(cpu, 4);
+        }
+
+        assert_eq!(clipped.take_dirty_rows(), 0b11 << 30);
+        assert_eq!(wrapped.take_dirty_rows(), 0b11 << 30 | 0b111);
+    }
+
+    #[test]
+    fn hires_rows_use_the_whole_mask() {
+        let mut cpu = load(&[0x00FF, 0x6000, 0x6128, 0xF029, 0xD011]);
+
+        run(&mut cpu, 1);
+
+        // Switching modes clears the screen.
+        assert_eq!(cpu.take_dirty_rows(), u64::MAX);
+
+        run(&mut cpu, 4);
+
+        assert_eq!(cpu.take_dirty_rows(), 1 << 40);
+    }
+
+    #[test]
+    fn clears_scrolls_and_mode_switches_mark_every_row() {
+        for opcode in [0x00E0, 0x00C1, 0x00FB, 0x00FC, 0x00FE, 0x00FF] {
+            let mut cpu = load(&[opcode]);
+
+            cpu.take_dirty_rows();
+            run(&mut cpu, 1);
+
+            assert_eq!(cpu.take_dirty_rows(), u64::MAX, "{opcode:04X}");
+        }
+    }
+
+    #[test]
+    fn other_opcodes_leave_the_mask_alone() {
+        let mut cpu = load(&[0x6005, 0x7001, 0xA300, 0xF033, 0xF065, 0x8010, 0x1200]);
+
+        cpu.take_dirty_rows();
+        run(&mut cpu, 7);
+
+        assert_eq!(cpu.take_dirty_rows(), 0);
+    }
+
+    #[test]
+    fn a_reset_or_a_load_during_a_beep_stops_it() {
+        let mut cpu = load(&[0x6014, 0xF018, 0x1204]);
+        let silent = cpu.save_state();
+
+        run(&mut cpu, 2);
+
+        let beeping = cpu.save_state();
+
+        assert_eq!(
+            cpu.take_sound_events(),
+            [SoundEvent::Started { frames: 20 }]
+        );
+
+        cpu.reset();
+
+        assert_eq!(cpu.take_sound_events(), [SoundEvent::Stopped]);
+
+        // Silent to beeping and back.
+        cpu.load_state(&beeping).unwrap();
+
+        assert_eq!(
+            cpu.take_sound_events(),
+            [SoundEvent::Started { frames: 20 }]
+        );
+
+        cpu.load_state(&silent).unwrap();
+
+        assert_eq!(cpu.take_sound_events(), [SoundEvent::Stopped]);
+
+        // Nothing to stop.
+        cpu.reset();
+        cpu.load_state(&silent).unwrap();
+
+        assert_eq!(cpu.take_sound_events(), []);
+    }
+
+    #[test]
+    fn keys_past_f_on_the_bus_are_ignored() {
+        let mut cpu = load(&[0xF00A, 0x1202]);
+
+        run(&mut cpu, 1);
+        cpu.get_keypad_bus().send(0x20, 1);
+        cpu.get_keypad_bus().send(0xFF, 0);
+        cpu.get_keypad_bus().send(0x7, 1);
+        cpu.get_keypad_bus().send(0x7, 0);
+        run(&mut cpu, 1);
+
+        assert_eq!(cpu.registers()[0], 0x7);
+        assert!(!cpu.is_waiting_for_key());
+    }
 }
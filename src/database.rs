@@ -0,0 +1,178 @@
+use std::fmt;
+
+use crate::hash::{sha1, to_hex};
+
+pub struct RomInfo {
+    pub title: &'static str,
+    pub author: Option<&'static str>,
+    pub year: Option<u16>,
+    pub cycles_per_frame: Option<u32>,
+    pub refresh_rate: Option<u32>,
+    pub keys: &'static [(&'static str, u8)],
+}
+
+// Trimmed from the CHIP-8 community database, keyed by the SHA-1 of the ROM.
+const ROMS: &[(&str, RomInfo)] = &[
+    (
+        "fca71182a8838b686573e69b22aff945d79fe1d0",
+        RomInfo {
+            title: "Airplane",
+            author: None,
+            year: None,
+            cycles_per_frame: None,
+            refresh_rate: None,
+            keys: &[],
+        },
+    ),
+    (
+        "1ba58656810b67fd131eb9af3e3987863bf26c90",
+        RomInfo {
+            title: "IBM Logo",
+            author: None,
+            year: None,
+            cycles_per_frame: None,
+            refresh_rate: None,
+            keys: &[],
+        },
+    ),
+    (
+        "0ebc4b92c6059d6193565644fb00108161d03d23",
+        RomInfo {
+            title: "Keypad Test",
+            author: Some("Hap"),
+            year: Some(2006),
+            cycles_per_frame: None,
+            refresh_rate: None,
+            keys: &[],
+        },
+    ),
+    (
+        "507e7dc6783565071dfe4b72154af431d4466958",
+        RomInfo {
+            title: "Particle Demo",
+            author: Some("zeroZshadow"),
+            year: Some(2008),
+            cycles_per_frame: None,
+            refresh_rate: None,
+            keys: &[],
+        },
+    ),
+    (
+        "ea9af3c09b0d9e265fcd92bcc5d51a2939fdf27a",
+        RomInfo {
+            title: "15 Puzzle",
+            author: Some("Roger Ivie"),
+            year: None,
+            cycles_per_frame: None,
+            refresh_rate: None,
+            keys: &[],
+        },
+    ),
+    (
+        "443550abf646bc7f475ef0466f8e1232ec7474f3",
+        RomInfo {
+            title: "Shooting Stars",
+            author: Some("Philip Baltzer"),
+            year: Some(1978),
+            cycles_per_frame: None,
+            refresh_rate: None,
+            keys: &[],
+        },
+    ),
+    (
+        "0085dd8fce4f7ac2e39ba73cf67cc043f9ba4812",
+        RomInfo {
+            title: "Stars",
+            author: Some("Sergey Naydenov"),
+            year: Some(2010),
+            cycles_per_frame: None,
+            refresh_rate: None,
+            keys: &[],
+        },
+    ),
+];
+
+pub fn rom_hash(rom: &[u8]) -> String {
+    to_hex(&sha1(rom))
+}
+
+pub fn lookup(rom: &[u8]) -> Option<&'static RomInfo> {
+    let hash = rom_hash(rom);
+
+    ROMS.iter()
+        .find(|(entry_hash, _)| *entry_hash == hash)
+        .map(|(_, info)| info)
+}
+
+impl fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.title)?;
+
+        match (self.year, self.author) {
+            (Some(year), Some(author)) => write!(f, " ({}, {})", year, author),
+            (Some(year), None) => write!(f, " ({})", year),
+            (None, Some(author)) => write!(f, " ({})", author),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_hash_is_the_lowercase_sha1() {
+        assert_eq!(rom_hash(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(rom_hash(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        // Longer than one 64-byte block.
+        assert_eq!(
+            rom_hash(&[b'a'; 100]),
+            "7f9000257a4918d7072655ea468540cdcbd42e0c"
+        );
+    }
+
+    #[test]
+    fn the_bundled_roms_are_found_by_their_contents() {
+        let airplane = include_bytes!("../roms/airplane.ch8");
+
+        assert_eq!(rom_hash(airplane), ROMS[0].0);
+        assert_eq!(lookup(airplane).unwrap().title, "Airplane");
+        assert_eq!(
+            lookup(include_bytes!("../roms/ibm_logo.ch8"))
+                .unwrap()
+                .title,
+            "IBM Logo"
+        );
+
+        // One changed byte is another ROM.
+        let mut changed = airplane.to_vec();
+
+        changed[0] ^= 1;
+
+        assert!(lookup(&changed).is_none());
+    }
+
+    #[test]
+    fn titles_show_the_year_and_author_they_have() {
+        let info = |author, year| RomInfo {
+            title: "Pong",
+            author,
+            year,
+            cycles_per_frame: None,
+            refresh_rate: None,
+            keys: &[],
+        };
+
+        assert_eq!(
+            info(Some("Paul Vervalin"), Some(1990)).to_string(),
+            "Pong (1990, Paul Vervalin)"
+        );
+        assert_eq!(info(None, Some(1990)).to_string(), "Pong (1990)");
+        assert_eq!(
+            info(Some("Paul Vervalin"), None).to_string(),
+            "Pong (Paul Vervalin)"
+        );
+        assert_eq!(info(None, None).to_string(), "Pong");
+    }
+}
@@ -0,0 +1,420 @@
+use crate::{
+    breakpoint::{CpuEvent, OpcodeBreakpoint},
+    cpu::{CPU, MEMORY_SIZE},
+    disassembler::disassemble,
+    parse::parse_number,
+};
+
+const DEFAULT_DUMP_LENGTH: u16 = 0x40;
+const DEFAULT_EXAMINE_LENGTH: u16 = 0x10;
+const DEFAULT_LISTING_LENGTH: u16 = 8;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Break(u16),
+    BreakOp(OpcodeBreakpoint),
+    BreakOn(Vec<CpuEvent>),
+    Watch(u16),
+    RunFor(u64),
+    Dump { address: u16, length: u16 },
+    Step,
+    Continue,
+    Registers,
+    Disassemble { address: Option<u16>, count: u16 },
+    Quit,
+}
+
+impl Command {
+    // `None` for blank lines and `#` comments.
+    pub fn parse(line: &str) -> Result<Option<Command>, String> {
+        let line = line.split('#').next().unwrap().trim();
+        let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+
+        let command = match name {
+            "" => return Ok(None),
+            "break" | "b" => Command::Break(parse_address(rest)?),
+            "break-op" => Command::BreakOp(OpcodeBreakpoint::parse(rest)?),
+            "break-on" => Command::BreakOn(
+                rest.split(',')
+                    .map(CpuEvent::parse)
+                    .collect::<Result<_, _>>()?,
+            ),
+            "watch" => Command::Watch(parse_address(rest)?),
+            "run-for" => Command::RunFor(
+                rest.parse()
+                    .map_err(|_| format!("expected a cycle count, got `{}`", rest))?,
+            ),
+            "dump" => {
+                let mut parts = rest.split_whitespace();
+                let address = parse_address(parts.next().unwrap_or(""))?;
+                let length = match parts.next() {
+                    Some(length) => parse_number(length)
+                        .ok_or_else(|| format!("invalid length `{}`", length))?,
+                    None => DEFAULT_DUMP_LENGTH,
+                };
+
+                Command::Dump { address, length }
+            }
+            "s" | "step" => Command::Step,
+            "c" | "continue" => Command::Continue,
+            "regs" => Command::Registers,
+            "q" | "quit" => Command::Quit,
+            "disas" => {
+                let mut parts = rest.split_whitespace();
+                let address = parts.next().map(parse_address).transpose()?;
+                let count = match parts.next() {
+                    Some(count) => {
+                        parse_number(count).ok_or_else(|| format!("invalid count `{}`", count))?
+                    }
+                    None => DEFAULT_LISTING_LENGTH,
+                };
+
+                Command::Disassemble { address, count }
+            }
+            // `x ADDR`, or `x/LENGTH ADDR` like GDB.
+            _ if name == "x" || name.starts_with("x/") => {
+                let length = match name.strip_prefix("x/") {
+                    Some(length) => parse_number(length)
+                        .ok_or_else(|| format!("invalid length `{}`", length))?,
+                    None => DEFAULT_EXAMINE_LENGTH,
+                };
+
+                Command::Dump {
+                    address: parse_address(rest)?,
+                    length,
+                }
+            }
+            "trace" => return Err("tracing is started with --trace FILE".to_string()),
+            _ => return Err(format!("unknown command `{}`", name)),
+        };
+
+        Ok(Some(command))
+    }
+
+    // Returns what the command prints, if anything. Continuing and quitting are
+    // up to the frontend, the CPU side only lets go of the breakpoint.
+    pub fn execute(&self, cpu: &mut CPU) -> Option<String> {
+        match self {
+            Command::Step => {
+                let before = cpu.snapshot();
+
+                // The frontend reports a fault.
+                if cpu.step().is_err() {
+                    return None;
+                }
+
+                let mut output = String::new();
+
+                for change in before.diff(&cpu.snapshot()) {
+                    output.push_str(&format!("   {}\n", change));
+                }
+
+                output.push_str(&disassemble_memory(cpu, cpu.pc(), 1));
+
+                return Some(output);
+            }
+            Command::Continue => cpu.continue_from_break(),
+            Command::Registers => return Some(format_registers(cpu)),
+            Command::Disassemble { address, count } => {
+                return Some(disassemble_memory(cpu, address.unwrap_or(cpu.pc()), *count))
+            }
+            Command::Quit => {}
+            Command::Break(address) => cpu.add_breakpoint(*address),
+            Command::BreakOp(breakpoint) => cpu.add_opcode_breakpoint(breakpoint.clone()),
+            Command::BreakOn(events) => {
+                for event in events {
+                    cpu.add_break_event(*event);
+                }
+            }
+            Command::Watch(address) => cpu.add_watchpoint(*address),
+            Command::RunFor(cycles) => cpu.run_for(*cycles),
+            Command::Dump { address, length } => return Some(dump_memory(cpu, *address, *length)),
+        }
+
+        None
+    }
+}
+
+// One command per line. Bad lines come back as warnings, or fail the whole
+// script in strict mode.
+pub fn parse_script(source: &str, strict: bool) -> Result<(Vec<Command>, Vec<String>), String> {
+    let mut commands = Vec::new();
+    let mut warnings = Vec::new();
+
+    for (i, line) in source.lines().enumerate() {
+        match Command::parse(line) {
+            Ok(command) => commands.extend(command),
+            Err(err) if strict => return Err(format!("line {}: {}", i + 1, err)),
+            Err(err) => warnings.push(format!("skipping line {}: {}", i + 1, err)),
+        }
+    }
+
+    Ok((commands, warnings))
+}
+
+fn parse_address(text: &str) -> Result<u16, String> {
+    parse_number(text)
+        .filter(|address| (*address as usize) < MEMORY_SIZE)
+        .ok_or_else(|| format!("invalid address `{}`", text))
+}
+
+// Sixteen bytes per row like pretty_print_memory, stopping at the end of memory.
+pub fn dump_memory(cpu: &CPU, address: u16, length: u16) -> String {
+    let end = (address as usize + length as usize).min(MEMORY_SIZE);
+    let mut dump = String::new();
+
+    for row in (address as usize..end).step_by(16) {
+        let bytes: Vec<u8> = (row..(row + 16).min(end))
+            .map(|address| cpu.read_memory(address as u16))
+            .collect();
+
+        dump.push_str(&format_dump_row(row, &bytes, |_, text| text));
+        dump.push('\n');
+    }
+
+    dump
+}
+
+// `0x0200 12 34 ...`, with `style` free to wrap each byte's text, say in
+// terminal escapes.
+pub fn format_dump_row(
+    address: usize,
+    bytes: &[u8],
+    mut style: impl FnMut(usize, String) -> String,
+) -> String {
+    let mut row = format!("0x{:04X}", address);
+
+    for (i, byte) in bytes.iter().enumerate() {
+        row.push(' ');
+        row.push_str(&style(address + i, format!("{:02X}", byte)));
+    }
+
+    row
+}
+
+pub fn format_registers(cpu: &CPU) -> String {
+    let registers: Vec<String> = cpu
+        .registers()
+        .iter()
+        .enumerate()
+        .map(|(i, value)| format!("V{:X}={:02X}", i, value))
+        .collect();
+
+    format!(
+        "{}\nPC={:03X} I={:03X} DT={:02X} ST={:02X} SP={}\n",
+        registers.join(" "),
+        cpu.pc(),
+        cpu.pointer(),
+        cpu.delay_timer(),
+        cpu.get_sound_timer(),
+        cpu.stack().len()
+    )
+}
+
+// `count` instructions from `address`, the next one to run marked with `=>`.
+pub fn disassemble_memory(cpu: &CPU, address: u16, count: u16) -> String {
+    let mut listing = String::new();
+
+    for i in 0..count as usize {
+        let address = address as usize + 2 * i;
+
+        if address + 1 >= MEMORY_SIZE {
+            break;
+        }
+
+        let opcode = (cpu.read_memory(address as u16) as u16) << 8
+            | cpu.read_memory(address as u16 + 1) as u16;
+        let marker = if address == cpu.pc() as usize {
+            "=>"
+        } else {
+            "  "
+        };
+
+        listing.push_str(&format!(
+            "{} 0x{:03X}  {:04X}  {}\n",
+            marker,
+            address,
+            opcode,
+            disassemble(opcode)
+        ));
+    }
+
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_script_sets_its_breakpoints_before_the_first_frame() {
+        let script = "# Stop on the second instruction.\n\
+                      break 0x202\n\
+                      \n\
+                      break-op RND\n\
+                      watch 0x300\n\
+                      bogus\n";
+        let (commands, warnings) = parse_script(script, false).unwrap();
+        let mut cpu = CPU::new();
+
+        cpu.load_rom(vec![0x60, 0x01, 0x61, 0x02, 0x12, 0x04])
+            .unwrap();
+
+        for command in &commands {
+            assert_eq!(command.execute(&mut cpu), None);
+        }
+
+        assert_eq!(warnings, ["skipping line 6: unknown command `bogus`"]);
+        assert_eq!(cpu.breakpoints(), [0x202]);
+        assert_eq!(cpu.opcode_breakpoints()[0].to_string(), "RND");
+        assert_eq!(cpu.watchpoints(), [0x300]);
+
+        cpu.run_frame().unwrap();
+
+        assert_eq!(cpu.pc(), 0x202);
+        assert_eq!(cpu.registers()[..2], [1, 0]);
+        assert_eq!(
+            parse_script(script, true).err().as_deref(),
+            Some("line 6: unknown command `bogus`")
+        );
+    }
+
+    fn parse(line: &str) -> Command {
+        Command::parse(line).unwrap().unwrap()
+    }
+
+    #[test]
+    fn every_command_form_parses() {
+        let dump = |address, length| Command::Dump { address, length };
+        let listing = |address, count| Command::Disassemble { address, count };
+
+        for (line, command) in [
+            ("break 0x2A4", Command::Break(0x2A4)),
+            ("b 676", Command::Break(0x2A4)),
+            (
+                "break-op Dxyn if V3 == 1",
+                Command::BreakOp(OpcodeBreakpoint::parse("Dxyn if V3 == 1").unwrap()),
+            ),
+            (
+                "break-on key,sound, cls",
+                Command::BreakOn(vec![
+                    CpuEvent::KeyRead,
+                    CpuEvent::SoundStart,
+                    CpuEvent::ClearScreen,
+                ]),
+            ),
+            ("watch 0x300", Command::Watch(0x300)),
+            ("run-for 1000", Command::RunFor(1000)),
+            ("dump 0x300", dump(0x300, DEFAULT_DUMP_LENGTH)),
+            ("dump 0x300 0x20", dump(0x300, 0x20)),
+            ("x 0x300", dump(0x300, DEFAULT_EXAMINE_LENGTH)),
+            ("x/16 0x300", dump(0x300, 16)),
+            ("s", Command::Step),
+            ("step", Command::Step),
+            ("c", Command::Continue),
+            ("continue", Command::Continue),
+            ("regs", Command::Registers),
+            ("disas", listing(None, DEFAULT_LISTING_LENGTH)),
+            ("disas 0x200", listing(Some(0x200), DEFAULT_LISTING_LENGTH)),
+            ("disas 0x200 3", listing(Some(0x200), 3)),
+            ("q", Command::Quit),
+            ("  quit  # done", Command::Quit),
+        ] {
+            assert_eq!(parse(line), command, "{}", line);
+        }
+
+        assert_eq!(Command::parse("   # nothing"), Ok(None));
+        assert_eq!(Command::parse(""), Ok(None));
+    }
+
+    #[test]
+    fn bad_commands_say_what_is_wrong() {
+        for (line, error) in [
+            ("frobnicate", "unknown command `frobnicate`"),
+            ("break", "invalid address ``"),
+            ("break 0x1000", "invalid address `0x1000`"),
+            ("watch here", "invalid address `here`"),
+            ("run-for soon", "expected a cycle count, got `soon`"),
+            ("dump 0x300 lots", "invalid length `lots`"),
+            ("x/lots 0x300", "invalid length `lots`"),
+            ("disas 0x200 many", "invalid count `many`"),
+            (
+                "break-on draw",
+                "unknown event `draw` (expected key, sound, cls or dbg)",
+            ),
+            ("trace on", "tracing is started with --trace FILE"),
+        ] {
+            assert_eq!(Command::parse(line), Err(error.to_string()), "{}", line);
+        }
+    }
+
+    #[test]
+    fn commands_print_with_the_dump_and_disassembler_formatting() {
+        let mut cpu = CPU::new();
+
+        cpu.load_rom(vec![0x60, 0x2A, 0xA3, 0x00, 0x12, 0x04])
+            .unwrap();
+
+        assert_eq!(
+            parse("s").execute(&mut cpu).as_deref(),
+            Some("   V0: 0x00 -> 0x2A\n   PC: 0x200 -> 0x202\n=> 0x202  A300  LD I, #300\n")
+        );
+        assert_eq!(
+            parse("regs").execute(&mut cpu).as_deref(),
+            Some(
+                "V0=2A V1=00 V2=00 V3=00 V4=00 V5=00 V6=00 V7=00 V8=00 V9=00 VA=00 VB=00 VC=00 VD=00 VE=00 VF=00\n\
+                 PC=202 I=000 DT=00 ST=00 SP=0\n"
+            )
+        );
+        assert_eq!(
+            parse("disas 0x200 3").execute(&mut cpu).as_deref(),
+            Some(
+                "   0x200  602A  LD V0, #2A\n\
+                 => 0x202  A300  LD I, #300\n   \
+                 0x204  1204  JP #204\n"
+            )
+        );
+        assert_eq!(
+            parse("x/18 0x200").execute(&mut cpu).as_deref(),
+            Some(
+                "0x0200 60 2A A3 00 12 04 00 00 00 00 00 00 00 00 00 00\n\
+                 0x0210 00 00\n"
+            )
+        );
+        // Clipped at the end of memory.
+        assert_eq!(
+            parse("dump 0xFFE").execute(&mut cpu).as_deref(),
+            Some("0x0FFE 00 00\n")
+        );
+        assert_eq!(
+            parse("disas 0xFFE").execute(&mut cpu).as_deref(),
+            Some("   0xFFE  0000  .db 0x0000\n")
+        );
+    }
+
+    #[test]
+    fn breakpoint_commands_stop_the_cpu_and_continue_lets_go() {
+        let mut cpu = CPU::new();
+
+        cpu.load_rom(vec![0x60, 0x2A, 0xA3, 0x00, 0x12, 0x04])
+            .unwrap();
+
+        assert_eq!(parse("b 0x202").execute(&mut cpu), None);
+
+        cpu.run_frame().unwrap();
+
+        assert_eq!(cpu.pc(), 0x202);
+        assert!(cpu.halted());
+
+        assert_eq!(parse("c").execute(&mut cpu), None);
+        assert_eq!(parse("run-for 2").execute(&mut cpu), None);
+
+        cpu.run_frame().unwrap();
+
+        assert_eq!(cpu.pc(), 0x204);
+        assert_eq!(cpu.pointer(), 0x300);
+        assert_eq!(cpu.cycles(), 3);
+        assert_eq!(parse("q").execute(&mut cpu), None);
+    }
+}
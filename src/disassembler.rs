@@ -0,0 +1,135 @@
+use crate::cpu::{decompose_opcode, PROGRAM_START};
+
+pub const MNEMONICS: &[&str] = &[
+    "CLS", "RET", "LOW", "HIGH", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB",
+    "SHR", "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP", "SCD", "SCR", "SCL",
+];
+
+// Cowgod-style mnemonics for every instruction the CPU runs, `.db` for the rest.
+pub fn disassemble(opcode: u16) -> String {
+    let nnn = opcode & 0x0FFF;
+    let nn = opcode & 0x00FF;
+
+    match decompose_opcode(opcode) {
+        (0, 0, 0xE, 0) => "CLS".to_string(),
+        (0, 0, 0xE, 0xE) => "RET".to_string(),
+        (0, 0, 0xC, n) => format!("SCD {}", n),
+        (0, 0, 0xF, 0xB) => "SCR".to_string(),
+        (0, 0, 0xF, 0xC) => "SCL".to_string(),
+        (0, 0, 0xF, 0xE) => "LOW".to_string(),
+        (0, 0, 0xF, 0xF) => "HIGH".to_string(),
+        (1, _, _, _) => format!("JP #{:03X}", nnn),
+        (2, _, _, _) => format!("CALL #{:03X}", nnn),
+        (3, x, _, _) => format!("SE V{:X}, #{:02X}", x, nn),
+        (4, x, _, _) => format!("SNE V{:X}, #{:02X}", x, nn),
+        (5, x, y, 0) => format!("SE V{:X}, V{:X}", x, y),
+        (6, x, _, _) => format!("LD V{:X}, #{:02X}", x, nn),
+        (7, x, _, _) => format!("ADD V{:X}, #{:02X}", x, nn),
+        (8, x, y, 0) => format!("LD V{:X}, V{:X}", x, y),
+        (8, x, y, 1) => format!("OR V{:X}, V{:X}", x, y),
+        (8, x, y, 2) => format!("AND V{:X}, V{:X}", x, y),
+        (8, x, y, 3) => format!("XOR V{:X}, V{:X}", x, y),
+        (8, x, y, 4) => format!("ADD V{:X}, V{:X}", x, y),
+        (8, x, y, 5) => format!("SUB V{:X}, V{:X}", x, y),
+        (8, x, y, 6) => format!("SHR V{:X}, V{:X}", x, y),
+        (8, x, y, 7) => format!("SUBN V{:X}, V{:X}", x, y),
+        (8, x, y, 0xE) => format!("SHL V{:X}, V{:X}", x, y),
+        (9, x, y, 0) => format!("SNE V{:X}, V{:X}", x, y),
+        (0xA, _, _, _) => format!("LD I, #{:03X}", nnn),
+        (0xB, _, _, _) => format!("JP V0, #{:03X}", nnn),
+        (0xC, x, _, _) => format!("RND V{:X}, #{:02X}", x, nn),
+        (0xD, x, y, n) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        (0xE, x, 9, 0xE) => format!("SKP V{:X}", x),
+        (0xE, x, 0xA, 1) => format!("SKNP V{:X}", x),
+        (0xF, x, 0, 7) => format!("LD V{:X}, DT", x),
+        (0xF, x, 0, 0xA) => format!("LD V{:X}, K", x),
+        (0xF, x, 1, 5) => format!("LD DT, V{:X}", x),
+        (0xF, x, 1, 8) => format!("LD ST, V{:X}", x),
+        (0xF, x, 1, 0xE) => format!("ADD I, V{:X}", x),
+        (0xF, x, 2, 9) => format!("LD F, V{:X}", x),
+        (0xF, x, 3, 0) => format!("LD HF, V{:X}", x),
+        (0xF, x, 3, 3) => format!("LD B, V{:X}", x),
+        (0xF, x, 5, 5) => format!("LD [I], V{:X}", x),
+        (0xF, x, 6, 5) => format!("LD V{:X}, [I]", x),
+        (0xF, x, 7, 5) => format!("LD R, V{:X}", x),
+        (0xF, x, 8, 5) => format!("LD V{:X}, R", x),
+        _ => format!(".db 0x{:04X}", opcode),
+    }
+}
+
+// Every two bytes of the ROM from 0x200 on, as the CPU would decode them. An
+// odd last byte is listed on its own.
+pub fn listing(rom: &[u8]) -> String {
+    let mut listing = String::new();
+
+    for (i, pair) in rom.chunks(2).enumerate() {
+        let address = PROGRAM_START as usize + 2 * i;
+        let line = match *pair {
+            [high, low] => {
+                let opcode = (high as u16) << 8 | low as u16;
+
+                format!("0x{:03X}  {:04X}  {}", address, opcode, disassemble(opcode))
+            }
+            [byte] => format!("0x{:03X}  {:02X}    .db 0x{:02X}", address, byte, byte),
+            _ => unreachable!(),
+        };
+
+        listing.push_str(&line);
+        listing.push('\n');
+    }
+
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opcodes_disassemble_to_their_mnemonics() {
+        let table = [
+            (0x00E0, "CLS"),
+            (0x00EE, "RET"),
+            (0x00C4, "SCD 4"),
+            (0x00FB, "SCR"),
+            (0x00FF, "HIGH"),
+            (0x1ABC, "JP #ABC"),
+            (0x2200, "CALL #200"),
+            (0x3A0F, "SE VA, #0F"),
+            (0x5120, "SE V1, V2"),
+            (0x6FFF, "LD VF, #FF"),
+            (0x7301, "ADD V3, #01"),
+            (0x8124, "ADD V1, V2"),
+            (0x8AB7, "SUBN VA, VB"),
+            (0x812E, "SHL V1, V2"),
+            (0xA2F0, "LD I, #2F0"),
+            (0xB300, "JP V0, #300"),
+            (0xC5AA, "RND V5, #AA"),
+            (0xD125, "DRW V1, V2, 5"),
+            (0xD010, "DRW V0, V1, 0"),
+            (0xE59E, "SKP V5"),
+            (0xE6A1, "SKNP V6"),
+            (0xF30A, "LD V3, K"),
+            (0xF233, "LD B, V2"),
+            (0xF755, "LD [I], V7"),
+            (0xF865, "LD V8, [I]"),
+            (0x0000, ".db 0x0000"),
+            (0x5AB1, ".db 0x5AB1"),
+            (0x800F, ".db 0x800F"),
+            (0xE000, ".db 0xE000"),
+            (0xFFFF, ".db 0xFFFF"),
+        ];
+
+        for (opcode, text) in table {
+            assert_eq!(disassemble(opcode), text, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn a_listing_has_one_line_per_opcode() {
+        assert_eq!(
+            listing(&[0x00, 0xE0, 0x12, 0x00, 0xAB]),
+            "0x200  00E0  CLS\n0x202  1200  JP #200\n0x204  AB    .db 0xAB\n"
+        );
+    }
+}
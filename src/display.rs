@@ -0,0 +1,127 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DisplayMode {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl DisplayMode {
+    pub const LORES: DisplayMode = DisplayMode {
+        width: 64,
+        height: 32,
+    };
+
+    pub const HIRES: DisplayMode = DisplayMode {
+        width: 128,
+        height: 64,
+    };
+
+    pub const fn pixel_count(&self) -> usize {
+        self.width * self.height
+    }
+
+    pub fn index(&self, x: usize, y: usize) -> usize {
+        y * self.width + x
+    }
+}
+
+pub const MAX_PIXELS: usize = DisplayMode::HIRES.pixel_count();
+
+// The area a DXYN covered, its origin already wrapped onto the screen and
+// clipped at the right and bottom edges.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteDraw {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub collision: bool,
+}
+
+impl SpriteDraw {
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextStyle {
+    Ascii,
+    Blocks,
+}
+
+// One line per row, prefixed with its y coordinate.
+pub fn to_text(display: &[u8], mode: DisplayMode, style: TextStyle) -> String {
+    let (on, off) = match style {
+        TextStyle::Ascii => ('#', '.'),
+        TextStyle::Blocks => ('█', ' '),
+    };
+    let mut text = String::new();
+
+    for (y, row) in display[..mode.pixel_count()].chunks(mode.width).enumerate() {
+        text.push_str(&format!("{:2} ", y));
+        text.extend(row.iter().map(|pixel| if *pixel == 1 { on } else { off }));
+        text.push('\n');
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMALL: DisplayMode = DisplayMode {
+        width: 5,
+        height: 3,
+    };
+
+    #[rustfmt::skip]
+    const ARROW: [u8; 15] = [
+        0, 0, 1, 0, 0,
+        0, 1, 1, 1, 0,
+        1, 0, 1, 0, 1,
+    ];
+
+    #[test]
+    fn each_row_is_numbered_and_drawn_left_to_right() {
+        assert_eq!(
+            to_text(&ARROW, SMALL, TextStyle::Ascii),
+            " 0 ..#..\n 1 .###.\n 2 #.#.#\n"
+        );
+        assert_eq!(
+            to_text(&ARROW, SMALL, TextStyle::Blocks),
+            " 0   █  \n 1  ███ \n 2 █ █ █\n"
+        );
+    }
+
+    #[test]
+    fn only_the_pixels_of_the_mode_are_drawn() {
+        // The framebuffer is always big enough for hi-res.
+        let mut display = [1; MAX_PIXELS];
+
+        display[..15].copy_from_slice(&ARROW);
+
+        assert_eq!(
+            to_text(&display, SMALL, TextStyle::Ascii),
+            " 0 ..#..\n 1 .###.\n 2 #.#.#\n"
+        );
+    }
+
+    #[test]
+    fn row_numbers_line_up_past_nine() {
+        let tall = DisplayMode {
+            width: 2,
+            height: 11,
+        };
+        let mut display = vec![0; tall.pixel_count()];
+
+        display[tall.index(1, 10)] = 1;
+
+        let text = to_text(&display, tall, TextStyle::Ascii);
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines.len(), 11);
+        assert_eq!(lines[9], " 9 ..");
+        assert_eq!(lines[10], "10 .#");
+    }
+}
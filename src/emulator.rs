@@ -0,0 +1,765 @@
+use std::{ops::Range, time::Duration};
+
+use crate::{
+    audit::MemoryAudit,
+    breakpoint::{BreakReason, CpuEvent, OpcodeBreakpoint},
+    cpu::{
+        CpuError, CpuMessage, SoundEvent, Tracer, UnknownOpcodePolicy, CPU,
+        DEFAULT_CYCLES_PER_FRAME, KEY_COUNT,
+    },
+    debugger::{self, Command},
+    display::{DisplayMode, SpriteDraw, TextStyle},
+    font::{Font, FONTSET},
+    latency::InputLatency,
+    memory_bus::MemoryBus,
+    profiler::Profiler,
+    quirks::Quirks,
+    snapshot::CpuSnapshot,
+    timing::{Timing, DEFAULT_REFRESH_RATE},
+    trace::TraceLine,
+};
+
+#[derive(Clone, Copy)]
+pub struct EmulatorOptions {
+    pub cycles_per_frame: u32,
+    pub timing: Timing,
+    pub refresh_rate: u32,
+    pub font: Font,
+    pub quirks: Quirks,
+}
+
+impl Default for EmulatorOptions {
+    fn default() -> Self {
+        EmulatorOptions {
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            timing: Timing::Fixed,
+            refresh_rate: DEFAULT_REFRESH_RATE,
+            font: FONTSET,
+            quirks: Quirks::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameEvent {
+    pub frame_number: u64,
+    pub display_dirty: bool,
+    pub beep_active: bool,
+    // The CPU faulted, stopped at a break or ran out of cycles, so nothing
+    // ran this frame or it ended early.
+    pub halted: bool,
+}
+
+// Gets the emulator as it is at the end of the frame, to read the display.
+type FrameCallback = Box<dyn FnMut(&FrameEvent, &Emulator)>;
+type SoundCallback = Box<dyn FnMut(&SoundEvent)>;
+
+/// Everything a frontend needs: feed it a ROM and keys, run a frame per
+/// refresh and present the display, plus the debugging and reporting hooks.
+///
+/// ```
+/// use chip8_emu::emulator::{Emulator, EmulatorOptions};
+///
+/// let mut emulator = Emulator::new(EmulatorOptions::default());
+///
+/// // Draws the 0 glyph of the font at the top left, then loops.
+/// emulator
+///     .load_rom(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06])
+///     .unwrap();
+///
+/// let frame = emulator.run_frame().unwrap();
+/// let mode = emulator.display_mode();
+///
+/// assert!(frame.display_dirty);
+/// assert_eq!(emulator.display()[mode.index(0, 0)], 1);
+/// assert_eq!(emulator.display()[mode.index(1, 1)], 0);
+/// assert_eq!(emulator.display().iter().filter(|&&pixel| pixel == 1).count(), 14);
+/// ```
+pub struct Emulator {
+    cpu: CPU,
+    rom: Vec<u8>,
+    frame_number: u64,
+    frame_callbacks: Vec<FrameCallback>,
+    sound_events: Vec<SoundEvent>,
+    sound_callbacks: Vec<SoundCallback>,
+}
+
+impl Emulator {
+    /// An emulator with no ROM loaded yet.
+    pub fn new(options: EmulatorOptions) -> Self {
+        Emulator {
+            cpu: Self::build_cpu(&options),
+            rom: Vec::new(),
+            frame_number: 0,
+            frame_callbacks: Vec::new(),
+            sound_events: Vec::new(),
+            sound_callbacks: Vec::new(),
+        }
+    }
+
+    fn build_cpu(options: &EmulatorOptions) -> CPU {
+        let mut cpu = CPU::new();
+
+        cpu.set_cycles_per_frame(options.cycles_per_frame);
+        cpu.set_timing(options.timing);
+        cpu.set_refresh_rate(options.refresh_rate);
+        cpu.load_font(&options.font);
+        cpu.set_quirks(options.quirks);
+        cpu
+    }
+
+    /// Loads `rom` at 0x200, keeping a copy for `reset`. A ROM too big for
+    /// memory is refused with the reason.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), String> {
+        self.cpu.load_rom(rom.to_vec())?;
+        self.rom = rom.to_vec();
+
+        Ok(())
+    }
+
+    /// Starts the ROM over. Everything set up through the emulator stays:
+    /// the options, debugging and reporting settings, breakpoints,
+    /// memory-mapped devices and the tracer.
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        // It fit when it was loaded.
+        self.cpu.load_rom(self.rom.clone()).unwrap();
+        self.frame_number = 0;
+    }
+
+    /// Called at the end of every run_frame, even when the CPU is halted or
+    /// waiting for a key and nothing ran, before a fault is returned.
+    ///
+    /// ```
+    /// use std::{cell::Cell, rc::Rc};
+    ///
+    /// use chip8_emu::emulator::{Emulator, EmulatorOptions};
+    ///
+    /// let mut emulator = Emulator::new(EmulatorOptions::default());
+    /// let frames = Rc::new(Cell::new(0));
+    /// let counted = frames.clone();
+    ///
+    /// emulator.load_rom(&[0x12, 0x00]).unwrap();
+    /// emulator.on_frame(move |event, _| counted.set(event.frame_number));
+    ///
+    /// for _ in 0..3 {
+    ///     emulator.run_frame().unwrap();
+    /// }
+    ///
+    /// assert_eq!(frames.get(), 3);
+    /// ```
+    pub fn on_frame(&mut self, callback: impl FnMut(&FrameEvent, &Emulator) + 'static) {
+        self.frame_callbacks.push(Box::new(callback));
+    }
+
+    /// Called during run_frame for every sound start and stop, before on_frame.
+    pub fn on_sound(&mut self, callback: impl FnMut(&SoundEvent) + 'static) {
+        self.sound_callbacks.push(Box::new(callback));
+    }
+
+    /// The sound events of the last frame.
+    pub fn sound_events(&self) -> &[SoundEvent] {
+        &self.sound_events
+    }
+
+    /// Warnings and debug output from the CPU since the last call, for the
+    /// front end to show.
+    pub fn take_messages(&mut self) -> Vec<CpuMessage> {
+        self.cpu.take_messages()
+    }
+
+    /// Runs one frame of instructions and ticks the timers, then calls the
+    /// `on_sound` and `on_frame` callbacks.
+    pub fn run_frame(&mut self) -> Result<FrameEvent, CpuError> {
+        let result = self.cpu.run_frame();
+
+        self.frame_number += 1;
+        self.sound_events = self.cpu.take_sound_events();
+
+        for event in &self.sound_events {
+            for callback in &mut self.sound_callbacks {
+                callback(event);
+            }
+        }
+
+        let event = FrameEvent {
+            frame_number: self.frame_number,
+            display_dirty: self.cpu.take_display_changed(),
+            beep_active: self.sound_active(),
+            halted: self.cpu.halted(),
+        };
+        // Taken out for the call, a callback only gets to read the emulator.
+        let mut callbacks = std::mem::take(&mut self.frame_callbacks);
+
+        for callback in &mut callbacks {
+            callback(&event, self);
+        }
+
+        self.frame_callbacks = callbacks;
+        result.map(|()| event)
+    }
+
+    /// One instruction, even one a breakpoint sits on. The timers and the
+    /// frame callbacks are left alone, as for a debugger step.
+    pub fn step(&mut self) -> Result<(), CpuError> {
+        self.cpu.step()
+    }
+
+    /// Keys the CPU doesn't track are ignored.
+    ///
+    /// ```
+    /// use chip8_emu::emulator::{Emulator, EmulatorOptions};
+    ///
+    /// let mut emulator = Emulator::new(EmulatorOptions::default());
+    ///
+    /// // Waits for a key into V0, then loops.
+    /// emulator.load_rom(&[0xF0, 0x0A, 0x12, 0x02]).unwrap();
+    /// emulator.run_frame().unwrap();
+    /// assert!(emulator.is_waiting_for_key());
+    ///
+    /// emulator.press_key(0x7);
+    /// emulator.run_frame().unwrap();
+    /// emulator.release_key(0x7);
+    /// emulator.run_frame().unwrap();
+    ///
+    /// assert!(!emulator.is_waiting_for_key());
+    /// assert_eq!(emulator.snapshot().registers[0], 0x7);
+    /// ```
+    pub fn press_key(&mut self, key: u8) {
+        self.send_key(key, 0x1);
+    }
+
+    /// Lets go of a key pressed with `press_key`.
+    pub fn release_key(&mut self, key: u8) {
+        self.send_key(key, 0x0);
+    }
+
+    fn send_key(&mut self, key: u8, state: u8) {
+        if (key as usize) < KEY_COUNT {
+            self.cpu.get_keypad_bus().send(key, state);
+        }
+    }
+
+    /// Key presses and releases lost because the CPU didn't read them in
+    /// time. A reset starts the count over.
+    pub fn dropped_key_signals(&self) -> u64 {
+        self.cpu.keypad_bus().dropped()
+    }
+
+    /// One byte per pixel, 1 for lit, row by row at the width of
+    /// `display_mode`.
+    pub fn display(&self) -> &[u8] {
+        self.cpu.display()
+    }
+
+    /// The resolution the ROM is drawing at now.
+    pub fn display_mode(&self) -> DisplayMode {
+        self.cpu.display_mode()
+    }
+
+    /// Whether the sound timer is running, so the buzzer should sound.
+    pub fn sound_active(&self) -> bool {
+        self.cpu.get_sound_timer() > 0
+    }
+
+    /// What the ROM has drawn since the last call, for a front end that only
+    /// redraws what changed.
+    pub fn take_dirty_rows(&mut self) -> u64 {
+        self.cpu.take_dirty_rows()
+    }
+
+    /// The area the last DXYN covered, if one ran since the last call.
+    pub fn take_last_draw(&mut self) -> Option<SpriteDraw> {
+        self.cpu.take_last_draw()
+    }
+
+    /// The display as text, one line per row.
+    pub fn display_text(&self, style: TextStyle) -> String {
+        self.cpu.display_text(style)
+    }
+
+    /// For drawing on the screen without running the ROM, like a test pattern.
+    pub fn display_mut(&mut self) -> &mut [u8] {
+        self.cpu.display_mut()
+    }
+
+    /// The font the ROM draws its digits with.
+    pub fn font(&self) -> Font {
+        self.cpu.font()
+    }
+
+    /// Instructions run since the ROM was loaded or reset.
+    pub fn cycles(&self) -> u64 {
+        self.cpu.cycles()
+    }
+
+    /// Frames per second, 50 or 60.
+    pub fn refresh_rate(&self) -> u32 {
+        self.cpu.refresh_rate()
+    }
+
+    /// How long a frame lasts at the refresh rate.
+    pub fn frame_duration(&self) -> Duration {
+        self.cpu.frame_duration()
+    }
+
+    /// The wall clock timers stand still in between, for a paused front end.
+    pub fn pause_clock(&mut self) {
+        self.cpu.pause_clock();
+    }
+
+    /// Starts the wall clock timers again after `pause_clock`.
+    pub fn resume_clock(&mut self) {
+        self.cpu.resume_clock();
+    }
+
+    /// Ticks the timers once per frame instead of following the wall clock,
+    /// so runs can be repeated exactly.
+    pub fn set_frame_timers(&mut self, enabled: bool) {
+        self.cpu.set_frame_timers(enabled);
+    }
+
+    /// Makes CXNN give the same numbers on every run.
+    pub fn seed_random(&mut self, seed: u64) {
+        self.cpu.seed_random(seed);
+    }
+
+    /// Halts the CPU for good once it has run `max_cycles` instructions.
+    pub fn set_max_cycles(&mut self, max_cycles: Option<u64>) {
+        self.cpu.set_max_cycles(max_cycles);
+    }
+
+    /// Stops with BreakReason::RunFor once `cycles` more instructions ran.
+    pub fn run_for(&mut self, cycles: u64) {
+        self.cpu.run_for(cycles);
+    }
+
+    /// Whether `set_max_cycles` stopped the CPU.
+    pub fn watchdog_expired(&self) -> bool {
+        self.cpu.watchdog_expired()
+    }
+
+    /// Whether an FX0A is waiting for a key press.
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.cpu.is_waiting_for_key()
+    }
+
+    /// Faults on the first write into the ROM's own code instead of warning.
+    pub fn set_strict_code_writes(&mut self, strict: bool) {
+        self.cpu.set_strict_code_writes(strict);
+    }
+
+    /// Whether an unknown opcode faults or is skipped with a warning.
+    pub fn set_unknown_opcode_policy(&mut self, policy: UnknownOpcodePolicy) {
+        self.cpu.set_unknown_opcode_policy(policy);
+    }
+
+    /// Unknown opcodes skipped so far.
+    pub fn skipped_opcodes(&self) -> u32 {
+        self.cpu.skipped_opcodes()
+    }
+
+    /// Lets FX01 print a register, for ROMs under development.
+    pub fn set_debug_opcodes(&mut self, enabled: bool) {
+        self.cpu.set_debug_opcodes(enabled);
+    }
+
+    /// The error the CPU stopped on, until `reset` or `load_state`.
+    pub fn fault(&self) -> Option<CpuError> {
+        self.cpu.fault()
+    }
+
+    /// The whole machine, for `load_state` to bring back.
+    ///
+    /// ```
+    /// use chip8_emu::emulator::{Emulator, EmulatorOptions};
+    ///
+    /// let mut emulator = Emulator::new(EmulatorOptions::default());
+    ///
+    /// // Counts up in V0 forever.
+    /// emulator.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+    /// emulator.run_frame().unwrap();
+    ///
+    /// let state = emulator.save_state();
+    /// let counted = emulator.snapshot().registers[0];
+    ///
+    /// emulator.run_frame().unwrap();
+    /// assert_ne!(emulator.snapshot().registers[0], counted);
+    ///
+    /// emulator.load_state(&state).unwrap();
+    /// assert_eq!(emulator.snapshot().registers[0], counted);
+    /// ```
+    pub fn save_state(&self) -> Vec<u8> {
+        self.cpu.save_state()
+    }
+
+    /// Also brings the CPU back from a fault.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.cpu.load_state(bytes)
+    }
+
+    /// The device stays across resets.
+    pub fn map_memory(&mut self, range: Range<u16>, device: Box<dyn MemoryBus>) {
+        self.cpu.map_memory(range, device);
+    }
+
+    /// Plain RAM, whatever is mapped over it.
+    pub fn read_memory(&self, address: u16) -> u8 {
+        self.cpu.read_memory(address)
+    }
+
+    /// Writes plain RAM, for cheats and pokes.
+    pub fn write_memory(&mut self, address: u16, value: u8) {
+        self.cpu.write_memory(address, value);
+    }
+
+    /// The address of the next instruction.
+    pub fn pc(&self) -> u16 {
+        self.cpu.pc()
+    }
+
+    /// The registers, timers, stack and a hash of the display.
+    pub fn snapshot(&self) -> CpuSnapshot {
+        self.cpu.snapshot()
+    }
+
+    /// The last instructions run, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &TraceLine> {
+        self.cpu.history()
+    }
+
+    /// Gets every instruction about to run, with the registers before it.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.cpu.set_tracer(tracer);
+    }
+
+    /// Stops the CPU whenever `event` happens.
+    pub fn add_break_event(&mut self, event: CpuEvent) {
+        self.cpu.add_break_event(event);
+    }
+
+    /// Stops the CPU before an instruction that matches.
+    pub fn add_opcode_breakpoint(&mut self, breakpoint: OpcodeBreakpoint) {
+        self.cpu.add_opcode_breakpoint(breakpoint);
+    }
+
+    /// The breakpoints added so far.
+    pub fn opcode_breakpoints(&self) -> &[OpcodeBreakpoint] {
+        self.cpu.opcode_breakpoints()
+    }
+
+    /// Why the CPU is stopped at a break, if it is.
+    pub fn break_reason(&self) -> Option<BreakReason> {
+        self.cpu.break_reason()
+    }
+
+    /// Runs the instruction the CPU stopped on without breaking on it again.
+    pub fn continue_from_break(&mut self) {
+        self.cpu.continue_from_break();
+    }
+
+    /// Runs a debugger command, returning what it has to show.
+    pub fn debug(&mut self, command: &Command) -> Option<String> {
+        command.execute(&mut self.cpu)
+    }
+
+    /// `count` instructions from `address`, one per line.
+    pub fn disassemble(&self, address: u16, count: u16) -> String {
+        debugger::disassemble_memory(&self.cpu, address, count)
+    }
+
+    /// Records the last `capacity` memory accesses with what made them.
+    pub fn enable_memory_audit(&mut self, capacity: usize) {
+        self.cpu.enable_memory_audit(capacity);
+    }
+
+    /// The accesses recorded since `enable_memory_audit`.
+    pub fn memory_audit(&self) -> Option<&MemoryAudit> {
+        self.cpu.memory_audit()
+    }
+
+    /// Measures the time from a key press to the ROM seeing it.
+    pub fn enable_input_latency(&mut self) {
+        self.cpu.enable_input_latency();
+    }
+
+    /// The latencies measured since `enable_input_latency`.
+    pub fn input_latency(&self) -> Option<&InputLatency> {
+        self.cpu.input_latency()
+    }
+
+    /// Counts the instructions run in each subroutine.
+    pub fn enable_profiler(&mut self) {
+        self.cpu.enable_profiler();
+    }
+
+    /// The counts since `enable_profiler`.
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.cpu.profiler()
+    }
+
+    /// Keys the ROM tested with EX9E or EXA1 so far, in key order.
+    pub fn queried_keys(&self) -> Vec<u8> {
+        self.cpu.queried_keys()
+    }
+
+    /// Keys tested by EX9E or EXA1 in the last second of emulated time, one
+    /// bit per key.
+    pub fn recently_polled_keys(&self) -> u16 {
+        self.cpu.recently_polled_keys()
+    }
+
+    /// Whether the ROM ever waited for a key with FX0A.
+    pub fn waited_for_key(&self) -> bool {
+        self.cpu.waited_for_key()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    // Sets V0, the delay timer and a pixel, then counts up in V1.
+    const ROM: [u8; 12] = [
+        0x60, 0x2A, 0xF0, 0x15, 0xD0, 0x01, 0x71, 0x01, 0x12, 0x06, 0x00, 0x00,
+    ];
+
+    fn emulator() -> Emulator {
+        let mut emulator = Emulator::new(EmulatorOptions::default());
+
+        emulator.load_rom(&ROM).unwrap();
+        emulator
+    }
+
+    #[test]
+    fn reset_starts_the_rom_over() {
+        let mut emulator = emulator();
+
+        for _ in 0..3 {
+            emulator.run_frame().unwrap();
+        }
+
+        assert_ne!(emulator.snapshot().registers[1], 0);
+        assert!(emulator.display().contains(&1));
+
+        emulator.reset();
+
+        assert_eq!(emulator.pc(), 0x200);
+        assert_eq!(emulator.snapshot().registers, [0; 0x10]);
+        assert_eq!(emulator.snapshot().delay_timer, 0);
+        assert_eq!(emulator.cycles(), 0);
+        assert!(!emulator.display().contains(&1));
+        assert_eq!(emulator.frame_number, 0);
+        assert_eq!(emulator.read_memory(0x200), ROM[0]);
+
+        // And it runs the same way again.
+        emulator.run_frame().unwrap();
+
+        assert_eq!(emulator.snapshot().registers[0], 0x2A);
+    }
+
+    #[test]
+    fn step_runs_one_instruction_and_leaves_the_timers() {
+        let mut emulator = emulator();
+
+        emulator.set_frame_timers(true);
+        emulator.debug(&Command::Break(0x200));
+
+        emulator.step().unwrap();
+
+        assert_eq!(emulator.pc(), 0x202);
+        assert_eq!(emulator.snapshot().registers[0], 0x2A);
+
+        emulator.step().unwrap();
+        emulator.step().unwrap();
+
+        assert_eq!(emulator.pc(), 0x206);
+        assert_eq!(emulator.cycles(), 3);
+        assert_eq!(emulator.snapshot().delay_timer, 0x2A);
+        assert_eq!(emulator.frame_number, 0);
+    }
+
+    #[test]
+    fn on_frame_fires_every_frame_even_when_halted() {
+        let mut emulator = Emulator::new(EmulatorOptions::default());
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let recorded = Rc::clone(&events);
+
+        // Waits for a key, then returns with an empty stack.
+        emulator.load_rom(&[0xF0, 0x0A, 0x00, 0xEE]).unwrap();
+        emulator.on_frame(move |event, emulator| {
+            recorded
+                .borrow_mut()
+                .push((*event, emulator.fault().is_some()));
+        });
+
+        emulator.run_frame().unwrap();
+        emulator.run_frame().unwrap();
+        emulator.press_key(1);
+        emulator.release_key(1);
+
+        assert_eq!(emulator.run_frame(), Err(CpuError::StackUnderflow));
+        assert_eq!(emulator.run_frame(), Err(CpuError::StackUnderflow));
+
+        let events = events.borrow();
+        let numbers: Vec<u64> = events.iter().map(|(event, _)| event.frame_number).collect();
+        let halted: Vec<bool> = events.iter().map(|(event, _)| event.halted).collect();
+
+        assert_eq!(numbers, [1, 2, 3, 4]);
+        assert_eq!(halted, [false, false, true, true]);
+        assert!(events[2].1);
+        assert_eq!(emulator.frame_number, 4);
+    }
+
+    #[test]
+    fn every_callback_fires_once_per_frame_across_a_run() {
+        let mut emulator = Emulator::new(EmulatorOptions::default());
+        let counts = Rc::new(RefCell::new([0u32; 4]));
+
+        // Beeps for three frames and draws once, then loops.
+        emulator
+            .load_rom(&[0x60, 0x03, 0xF0, 0x18, 0xD0, 0x11, 0x12, 0x06])
+            .unwrap();
+        emulator.set_frame_timers(true);
+
+        for callback in 0..2 {
+            let counts = Rc::clone(&counts);
+
+            emulator.on_frame(move |event, _| {
+                let mut counts = counts.borrow_mut();
+
+                counts[callback] += 1;
+
+                if callback == 0 {
+                    counts[2] += event.display_dirty as u32;
+                    counts[3] += event.beep_active as u32;
+                }
+            });
+        }
+
+        let sounds = Rc::new(RefCell::new(0));
+        let counted = Rc::clone(&sounds);
+
+        emulator.on_sound(move |_| *counted.borrow_mut() += 1);
+
+        for _ in 0..60 {
+            emulator.run_frame().unwrap();
+        }
+
+        // Both frame callbacks every frame, one draw, two frames still
+        // beeping at their end, and the start and the stop of the beep.
+        assert_eq!(*counts.borrow(), [60, 60, 1, 2]);
+        assert_eq!(*sounds.borrow(), 2);
+        assert_eq!(emulator.frame_number, 60);
+    }
+
+    #[test]
+    fn sound_events_follow_the_sound_timer() {
+        let mut emulator = Emulator::new(EmulatorOptions {
+            cycles_per_frame: 100,
+            ..EmulatorOptions::default()
+        });
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let recorded = Rc::clone(&events);
+
+        // ST=3, extended to 5 while sounding, runs out. After ten frames ST=0
+        // while silent, ST=2 and cut short by ST=0.
+        emulator
+            .load_rom(&[
+                0x60, 0x03, 0xF0, 0x18, 0x61, 0x05, 0xF1, 0x18, 0x62, 0x0A, 0xF2, 0x15, 0xF3, 0x07,
+                0x33, 0x00, 0x12, 0x0C, 0x64, 0x00, 0xF4, 0x18, 0x65, 0x02, 0xF5, 0x18, 0xF4, 0x18,
+                0x12, 0x1C,
+            ])
+            .unwrap();
+        emulator.set_frame_timers(true);
+        emulator.on_sound(move |event| recorded.borrow_mut().push(*event));
+
+        let mut timeline = Vec::new();
+
+        for _ in 0..20 {
+            emulator.run_frame().unwrap();
+
+            for event in emulator.sound_events() {
+                timeline.push((emulator.frame_number, *event));
+            }
+        }
+
+        assert_eq!(
+            timeline,
+            [
+                (1, SoundEvent::Started { frames: 3 }),
+                (1, SoundEvent::Started { frames: 5 }),
+                (5, SoundEvent::Stopped),
+                (11, SoundEvent::Started { frames: 2 }),
+                (11, SoundEvent::Stopped),
+            ]
+        );
+        assert_eq!(
+            *events.borrow(),
+            timeline.iter().map(|(_, event)| *event).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn reset_keeps_the_settings() {
+        let mut emulator = emulator();
+
+        emulator.set_frame_timers(true);
+        emulator.set_max_cycles(Some(12));
+        emulator.add_opcode_breakpoint(OpcodeBreakpoint::parse("Dxyn").unwrap());
+        emulator.enable_memory_audit(16);
+        emulator.enable_input_latency();
+        emulator.enable_profiler();
+        emulator.run_frame().unwrap();
+        emulator.reset();
+
+        assert!(emulator.memory_audit().is_some());
+        assert!(emulator.input_latency().is_some());
+        assert!(emulator.profiler().is_some());
+
+        emulator.run_frame().unwrap();
+
+        assert_eq!(emulator.break_reason(), Some(BreakReason::Opcode(0)));
+        assert_eq!(emulator.pc(), 0x204);
+        assert_eq!(emulator.snapshot().delay_timer, 0x2A);
+
+        emulator.continue_from_break();
+        emulator.run_frame().unwrap();
+
+        assert_eq!(emulator.snapshot().delay_timer, 0x29);
+
+        emulator.run_frame().unwrap();
+
+        assert!(emulator.watchdog_expired());
+        assert_eq!(emulator.cycles(), 12);
+
+        // A random number, an unknown opcode, an FX01 and a write over 0x200.
+        let mut emulator = Emulator::new(EmulatorOptions::default());
+
+        emulator
+            .load_rom(&[0xC1, 0xFF, 0x5A, 0xB1, 0xF1, 0x01, 0xA2, 0x00, 0xF0, 0x55])
+            .unwrap();
+        emulator.seed_random(7);
+        emulator.set_unknown_opcode_policy(UnknownOpcodePolicy::WarnAndSkip);
+        emulator.set_debug_opcodes(true);
+        emulator.set_strict_code_writes(true);
+
+        let runs = [(); 2].map(|()| {
+            let error = emulator.run_frame();
+            let run = (
+                error,
+                emulator.snapshot().registers[1],
+                emulator.take_messages(),
+            );
+
+            emulator.reset();
+            run
+        });
+
+        assert_eq!(runs[0], runs[1]);
+        assert_eq!(runs[0].0, Err(CpuError::CodeWrite { address: 0x200 }));
+        assert_eq!(runs[0].2.len(), 2);
+    }
+}
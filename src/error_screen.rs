@@ -0,0 +1,141 @@
+use chip8_emu::font::{self, FONTSET};
+
+const TEXT: [u8; 4] = [0xff, 0x40, 0x40, 0xff];
+// Glyphs are laid out on the 64x32 grid and scaled up to the frame.
+const GRID_WIDTH: usize = 64;
+
+pub struct Fault {
+    pub message: String,
+    pub pc: u16,
+    pub opcode: u16,
+}
+
+// Dims the last frame and writes the PC and the opcode of the faulting
+// instruction over it, one hex number per line. Only reads `frame`, so it
+// doesn't need the CPU to do anything.
+pub fn render(frame: &mut [u8], width: usize, fault: &Fault) {
+    let scale = (width / GRID_WIDTH).max(1);
+
+    for pixel in frame.chunks_exact_mut(4) {
+        for channel in &mut pixel[..3] {
+            *channel /= 3;
+        }
+    }
+
+    let lines = [format!("{:03X}", fault.pc), format!("{:04X}", fault.opcode)];
+
+    for (line, text) in lines.iter().enumerate() {
+        for (column, digit) in text.chars().enumerate() {
+            let digit = digit.to_digit(16).unwrap() as u8;
+            let glyph_x = 2 + column * 5;
+            let glyph_y = 2 + line * (font::GLYPH_HEIGHT + 2);
+
+            for (row, bits) in font::glyph(&FONTSET, digit).iter().enumerate() {
+                for bit in 0..4 {
+                    if bits & (0x80 >> bit) != 0 {
+                        fill(frame, width, scale, glyph_x + bit, glyph_y + row);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn fill(frame: &mut [u8], width: usize, scale: usize, x: usize, y: usize) {
+    for dy in 0..scale {
+        for dx in 0..scale {
+            let i = ((y * scale + dy) * width + x * scale + dx) * 4;
+
+            if let Some(pixel) = frame.get_mut(i..i + 4) {
+                pixel.copy_from_slice(&TEXT);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BACKGROUND: [u8; 4] = [0x90, 0x60, 0x30, 0xff];
+
+    fn composed(width: usize, height: usize) -> Vec<u8> {
+        let mut frame = BACKGROUND.repeat(width * height);
+        let fault = Fault {
+            message: "unknown opcode".to_string(),
+            pc: 0x2A4,
+            opcode: 0x5AB1,
+        };
+
+        render(&mut frame, width, &fault);
+        frame
+    }
+
+    // The top left of the frame, `#` for text and `.` for the dimmed picture.
+    fn screenshot(frame: &[u8], width: usize, rows: usize, columns: usize) -> String {
+        (0..rows)
+            .map(|y| {
+                let row: String = (0..columns)
+                    .map(|x| {
+                        let i = (y * width + x) * 4;
+
+                        match &frame[i..i + 4] {
+                            pixel if pixel == TEXT => '#',
+                            pixel if pixel == [0x30, 0x20, 0x10, 0xff] => '.',
+                            _ => '?',
+                        }
+                    })
+                    .collect();
+
+                row + "\n"
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_error_frame_shows_the_pc_and_the_opcode_over_the_dimmed_picture() {
+        let frame = composed(64, 32);
+
+        assert_eq!(
+            screenshot(&frame, 64, 16, 24),
+            "........................\n\
+             ........................\n\
+             ..####.####.#..#........\n\
+             .....#.#..#.#..#........\n\
+             ..####.####.####........\n\
+             ..#....#..#....#........\n\
+             ..####.#..#....#........\n\
+             ........................\n\
+             ........................\n\
+             ..####.####.###....#....\n\
+             ..#....#..#.#..#..##....\n\
+             ..####.####.###....#....\n\
+             .....#.#..#.#..#...#....\n\
+             ..####.#..#.###...###...\n\
+             ........................\n\
+             ........................\n"
+        );
+        // Nothing else is drawn on.
+        assert_eq!(
+            screenshot(&frame, 64, 32, 64).matches('#').count(),
+            screenshot(&frame, 64, 16, 24).matches('#').count()
+        );
+    }
+
+    #[test]
+    fn a_bigger_frame_gets_bigger_glyphs() {
+        let small = composed(64, 32);
+        let big = composed(256, 128);
+        let text = |frame: &[u8]| frame.chunks_exact(4).filter(|pixel| *pixel == TEXT).count();
+
+        assert_eq!(text(&big), 16 * text(&small));
+
+        // The first lit pixel of the 2, at (2, 2) on the grid, is 4x4 now.
+        let at = |x: usize, y: usize| &big[(y * 256 + x) * 4..(y * 256 + x) * 4 + 4];
+
+        assert_eq!(at(8, 8), TEXT);
+        assert_eq!(at(11, 11), TEXT);
+        assert_ne!(at(7, 8), TEXT);
+        assert_ne!(at(8, 7), TEXT);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::display::DisplayMode;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    None,
+    Scale2x,
+    Scale3x,
+}
+
+impl Filter {
+    pub fn parse(name: &str) -> Option<Filter> {
+        match name {
+            "none" => Some(Filter::None),
+            "scale2x" => Some(Filter::Scale2x),
+            "scale3x" => Some(Filter::Scale3x),
+            _ => None,
+        }
+    }
+
+    pub fn factor(&self) -> usize {
+        match self {
+            Filter::None => 1,
+            Filter::Scale2x => 2,
+            Filter::Scale3x => 3,
+        }
+    }
+
+    // Writes `display` scaled up by `factor()` into `output`, one byte per pixel.
+    pub fn apply(&self, display: &[u8], mode: DisplayMode, output: &mut [u8]) {
+        let factor = self.factor();
+        let out_width = mode.width * factor;
+
+        // Neighbours past the edge repeat the edge pixel.
+        let at = |x: isize, y: isize| {
+            let x = x.clamp(0, mode.width as isize - 1) as usize;
+            let y = y.clamp(0, mode.height as isize - 1) as usize;
+
+            display[mode.index(x, y)]
+        };
+
+        let mut put = |x: usize, y: usize, block: &[u8]| {
+            for (i, pixel) in block.iter().enumerate() {
+                output[(y * factor + i / factor) * out_width + x * factor + i % factor] = *pixel;
+            }
+        };
+
+        for y in 0..mode.height {
+            for x in 0..mode.width {
+                let (xi, yi) = (x as isize, y as isize);
+
+                match self {
+                    Filter::None => put(x, y, &[at(xi, yi)]),
+                    Filter::Scale2x => put(
+                        x,
+                        y,
+                        &scale2x([
+                            at(xi, yi - 1),
+                            at(xi - 1, yi),
+                            at(xi, yi),
+                            at(xi + 1, yi),
+                            at(xi, yi + 1),
+                        ]),
+                    ),
+                    Filter::Scale3x => {
+                        let mut neighbours = [0; 9];
+
+                        for (i, pixel) in neighbours.iter_mut().enumerate() {
+                            *pixel = at(xi + i as isize % 3 - 1, yi + i as isize / 3 - 1);
+                        }
+
+                        put(x, y, &scale3x(neighbours));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn scale2x([b, d, e, f, h]: [u8; 5]) -> [u8; 4] {
+    if b != h && d != f {
+        [
+            if d == b { d } else { e },
+            if b == f { f } else { e },
+            if d == h { d } else { e },
+            if h == f { f } else { e },
+        ]
+    } else {
+        [e; 4]
+    }
+}
+
+fn scale3x([a, b, c, d, e, f, g, h, i]: [u8; 9]) -> [u8; 9] {
+    if b != h && d != f {
+        [
+            if d == b { d } else { e },
+            if (d == b && e != c) || (b == f && e != a) {
+                b
+            } else {
+                e
+            },
+            if b == f { f } else { e },
+            if (d == b && e != g) || (d == h && e != a) {
+                d
+            } else {
+                e
+            },
+            e,
+            if (b == f && e != i) || (h == f && e != c) {
+                f
+            } else {
+                e
+            },
+            if d == h { d } else { e },
+            if (d == h && e != i) || (h == f && e != g) {
+                h
+            } else {
+                e
+            },
+            if h == f { f } else { e },
+        ]
+    } else {
+        [e; 9]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODE: DisplayMode = DisplayMode {
+        width: 3,
+        height: 3,
+    };
+
+    // The scaled picture as `#`/`.` lines.
+    fn scale(filter: Filter, rows: [&str; 3]) -> Vec<String> {
+        let display: Vec<u8> = rows
+            .iter()
+            .flat_map(|row| row.chars().map(|c| (c == '#') as u8))
+            .collect();
+        let factor = filter.factor();
+        let mut output = vec![0; display.len() * factor * factor];
+
+        filter.apply(&display, MODE, &mut output);
+
+        output
+            .chunks(MODE.width * factor)
+            .map(|row| {
+                row.iter()
+                    .map(|pixel| ['.', '#'][*pixel as usize])
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn names_and_factors() {
+        assert_eq!(Filter::parse("none"), Some(Filter::None));
+        assert_eq!(Filter::parse("scale3x"), Some(Filter::Scale3x));
+        assert_eq!(Filter::parse("hq2x"), None);
+        assert_eq!(Filter::Scale2x.factor(), 2);
+    }
+
+    #[test]
+    fn none_copies_the_display() {
+        assert_eq!(
+            scale(Filter::None, ["#..", ".#.", "..#"]),
+            ["#..", ".#.", "..#"]
+        );
+    }
+
+    #[test]
+    fn scale2x_joins_up_a_diagonal() {
+        assert_eq!(
+            scale(Filter::Scale2x, ["#..", ".#.", "..#"]),
+            ["##....", "#.#...", ".###..", "..###.", "...#.#", "....##"]
+        );
+        // A lone pixel and a full screen just get bigger.
+        assert_eq!(
+            scale(Filter::Scale2x, ["...", ".#.", "..."]),
+            ["......", "......", "..##..", "..##..", "......", "......"]
+        );
+        assert_eq!(scale(Filter::Scale2x, ["###", "###", "###"]), ["######"; 6]);
+    }
+
+    #[test]
+    fn scale3x_joins_up_a_diagonal() {
+        assert_eq!(
+            scale(Filter::Scale3x, ["#..", ".#.", "..#"]),
+            [
+                "###......",
+                "##.#.....",
+                "#..#.....",
+                ".#####...",
+                "...###...",
+                "...#####.",
+                ".....#..#",
+                ".....#.##",
+                "......###",
+            ]
+        );
+    }
+}
@@ -0,0 +1,109 @@
+// The flat part of TOML the sidecar and config files are written in:
+// `field = value` lines under optional `[section]` headers, with strings,
+// whole numbers, booleans and one-line arrays of strings.
+pub enum Value {
+    String(String),
+    Number(u32),
+    Bool(bool),
+    Array(Vec<String>),
+}
+
+// Hands every assignment to `set` along with its section, and puts the line
+// and the field in front of the errors.
+pub fn parse(
+    source: &str,
+    mut set: impl FnMut(Option<&str>, &str, Value) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut section = None;
+
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            section = Some(name.trim());
+            continue;
+        }
+
+        let (field, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected `field = value`", i + 1))?;
+        let field = field.trim();
+
+        parse_value(value.trim())
+            .and_then(|value| set(section, field, value))
+            .map_err(|err| format!("line {}: {}: {}", i + 1, field, err))?;
+    }
+
+    Ok(())
+}
+
+fn parse_value(text: &str) -> Result<Value, String> {
+    match text {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        _ => {}
+    }
+
+    if let Some(items) = text
+        .strip_prefix('[')
+        .and_then(|text| text.strip_suffix(']'))
+    {
+        let mut strings = Vec::new();
+        let mut rest = items.trim();
+
+        while !rest.is_empty() {
+            let (string, after) = parse_string(rest)?;
+
+            strings.push(string);
+            rest = after.trim_start();
+            rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+        }
+
+        return Ok(Value::Array(strings));
+    }
+
+    if text.starts_with('"') {
+        return match parse_string(text)? {
+            (string, "") => Ok(Value::String(string)),
+            (_, rest) => Err(format!("unexpected `{}` after the string", rest)),
+        };
+    }
+
+    text.parse()
+        .map(Value::Number)
+        .map_err(|_| format!("invalid value `{}`", text))
+}
+
+// A double-quoted string at the start of `text`, and what follows it.
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+    let body = text
+        .strip_prefix('"')
+        .ok_or_else(|| format!("expected a string, got `{}`", text))?;
+    let mut string = String::new();
+    let mut chars = body.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((string, body[i + 1..].trim_end())),
+            '\\' => match chars.next() {
+                Some((_, '"')) => string.push('"'),
+                Some((_, '\\')) => string.push('\\'),
+                _ => return Err("only \\\" and \\\\ escapes are supported".to_string()),
+            },
+            c => string.push(c),
+        }
+    }
+
+    Err("unterminated string".to_string())
+}
+
+pub fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
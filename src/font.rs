@@ -0,0 +1,248 @@
+// The interpreter area below 0x200, where FX29 looks for the glyphs.
+pub const FONT_ADDRESS: usize = 0x000;
+pub const GLYPH_HEIGHT: usize = 5;
+pub const FONT_SIZE: usize = 16 * GLYPH_HEIGHT;
+
+pub type Font = [u8; FONT_SIZE];
+
+pub const FONTSET: Font = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const VIP: Font = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const DREAM_6800: Font = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+const ETI_660: Font = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+// SUPER-CHIP's 8x10 digits for FX30, right after the small font. SCHIP only
+// had 0-9, the letters are Octo's.
+pub const BIG_FONT_ADDRESS: usize = FONT_ADDRESS + FONT_SIZE;
+pub const BIG_GLYPH_HEIGHT: usize = 10;
+pub const BIG_FONT_SIZE: usize = 16 * BIG_GLYPH_HEIGHT;
+
+pub const BIG_FONT: [u8; BIG_FONT_SIZE] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+pub const FONTS: &[(&str, &Font)] = &[
+    ("default", &FONTSET),
+    ("vip", &VIP),
+    ("dream6800", &DREAM_6800),
+    ("eti660", &ETI_660),
+];
+
+pub fn by_name(name: &str) -> Option<&'static Font> {
+    FONTS
+        .iter()
+        .find(|(font_name, _)| *font_name == name)
+        .map(|(_, font)| *font)
+}
+
+// A font file is the 80 raw glyph bytes, 4 pixels wide like the built-in ones.
+pub fn parse(data: &[u8]) -> Result<Font, String> {
+    let font: Font = data
+        .try_into()
+        .map_err(|_| format!("expected {} bytes, got {}", FONT_SIZE, data.len()))?;
+
+    if let Some(i) = font.iter().position(|row| row & 0x0F != 0) {
+        return Err(format!(
+            "row {} of glyph {:X} is wider than 4 pixels",
+            i % GLYPH_HEIGHT,
+            i / GLYPH_HEIGHT
+        ));
+    }
+
+    Ok(font)
+}
+
+pub fn glyph(font: &Font, digit: u8) -> &[u8] {
+    let start = (digit & 0xF) as usize * GLYPH_HEIGHT;
+
+    &font[start..start + GLYPH_HEIGHT]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The 16 glyphs side by side, one `#`/`.` line per row.
+    fn render(font: &Font) -> String {
+        let mut text = String::new();
+
+        for row in 0..GLYPH_HEIGHT {
+            let glyphs: Vec<String> = (0..16)
+                .map(|digit| {
+                    (0..4)
+                        .map(|x| match glyph(font, digit)[row] & 0x80 >> x {
+                            0 => '.',
+                            _ => '#',
+                        })
+                        .collect()
+                })
+                .collect();
+
+            text += &glyphs.join(" ");
+            text.push('\n');
+        }
+
+        text
+    }
+
+    #[test]
+    fn the_default_font_looks_like_its_snapshot() {
+        assert_eq!(
+            render(by_name("default").unwrap()),
+            "\
+#### ..#. #### #### #..# #### #### #### #### #### #### ###. #### ###. #### ####
+#..# .##. ...# ...# #..# #... #... ...# #..# #..# #..# #..# #... #..# #... #...
+#..# ..#. #### #### #### #### #### ..#. #### #### #### ###. #... #..# #### ####
+#..# ..#. #... ...# ...# ...# #..# .#.. #..# ...# #..# #..# #... #..# #... #...
+#### .### #### #### ...# #### #### .#.. #### #### #..# ###. #### ###. #### #...
+"
+        );
+    }
+
+    #[test]
+    fn the_vip_font_looks_like_its_snapshot() {
+        assert_eq!(
+            render(by_name("vip").unwrap()),
+            "\
+#### .##. #### #### #.#. #### #### #### #### #### #### #### #### #### #### ####
+#..# ..#. ...# ...# #.#. #... #... ...# #..# #..# #..# .#.# #... .#.# #... #...
+#..# ..#. #### #### #### #### #### ...# #### #### #### .### #... .#.# #### ####
+#..# ..#. #... ...# ..#. ...# #..# ...# #..# ...# #..# .#.# #... .#.# #... #...
+#### .### #### #### ..#. #### #### ...# #### #### #..# #### #### #### #### #...
+"
+        );
+    }
+
+    #[test]
+    fn the_dream6800_font_looks_like_its_snapshot() {
+        assert_eq!(
+            render(by_name("dream6800").unwrap()),
+            "\
+###. .#.. ###. ###. #... ###. ###. ###. ###. ###. ###. ##.. ###. ##.. ###. ###.
+#.#. .#.. ..#. ..#. #.#. #... #... ..#. #.#. #.#. #.#. #.#. #... #.#. #... #...
+#.#. .#.. ###. ###. #.#. ###. ###. ..#. ###. ###. ###. ###. #... #.#. ###. ##..
+#.#. .#.. #... ..#. ###. ..#. #.#. ..#. #.#. ..#. #.#. #.#. #... #.#. #... #...
+###. .#.. ###. ###. ..#. ###. ###. ..#. ###. ###. #.#. ##.. ###. ##.. ###. #...
+"
+        );
+    }
+
+    #[test]
+    fn the_eti660_font_looks_like_its_snapshot() {
+        assert_eq!(
+            render(by_name("eti660").unwrap()),
+            "\
+###. ..#. ###. ###. #.#. ###. ###. ###. ###. ###. ###. #... ###. ..#. ###. ###.
+#.#. ..#. ..#. ..#. #.#. #... #... ..#. #.#. #.#. #.#. #... #... ..#. #... #...
+#.#. ..#. ###. ###. ###. ###. ###. ..#. ###. ###. ###. ###. #... ###. ###. ##..
+#.#. ..#. #... ..#. ..#. ..#. #.#. ..#. #.#. ..#. #.#. #.#. #... #.#. #... #...
+###. ..#. ###. ###. ..#. ###. ###. ..#. ###. ###. #.#. ###. ###. ###. ###. #...
+"
+        );
+    }
+
+    #[test]
+    fn font_files_are_80_bytes_4_pixels_wide() {
+        let mut data = FONTSET.to_vec();
+
+        assert_eq!(parse(&data), Ok(FONTSET));
+        assert_eq!(
+            parse(&data[1..]).err(),
+            Some("expected 80 bytes, got 79".to_string())
+        );
+
+        data[7] = 0x18;
+
+        assert_eq!(
+            parse(&data).err(),
+            Some("row 2 of glyph 1 is wider than 4 pixels".to_string())
+        );
+        assert_eq!(glyph(&FONTSET, 0x1F), glyph(&FONTSET, 0xF));
+        assert!(by_name("comic-sans").is_none());
+    }
+}
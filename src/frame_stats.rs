@@ -0,0 +1,230 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+const WINDOW: usize = 120;
+// A frame counts as late when it starts this much after its slot.
+const LATE_FACTOR: f32 = 1.5;
+// Warn once per streak of late frames this long, a single hiccup is fine.
+const LATE_STREAK_WARNING: u32 = 30;
+
+// The last WINDOW samples, oldest overwritten first.
+pub struct Ring {
+    values: [f32; WINDOW],
+    next: usize,
+    len: usize,
+}
+
+impl Ring {
+    pub fn new() -> Self {
+        Ring {
+            values: [0.0; WINDOW],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, value: f32) {
+        self.values[self.next] = value;
+        self.next = (self.next + 1) % WINDOW;
+        self.len = (self.len + 1).min(WINDOW);
+    }
+
+    // Nearest-rank percentile, `p` between 0 and 100.
+    pub fn percentile(&self, p: f32) -> f32 {
+        if self.len == 0 {
+            return 0.0;
+        }
+
+        let mut sorted = self.values;
+        let sorted = &mut sorted[..self.len];
+
+        sorted.sort_by(f32::total_cmp);
+
+        let rank = (p / 100.0 * self.len as f32).ceil() as usize;
+
+        sorted[rank.clamp(1, self.len) - 1]
+    }
+
+    pub fn max(&self) -> f32 {
+        self.values[..self.len].iter().copied().fold(0.0, f32::max)
+    }
+}
+
+pub struct FrameStats {
+    intervals: Ring,
+    cycle_times: Ring,
+    render_times: Ring,
+    instructions: Ring,
+    last_frame: Option<Instant>,
+    frames: u64,
+    late_frames: u64,
+    late_streak: u32,
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        FrameStats {
+            intervals: Ring::new(),
+            cycle_times: Ring::new(),
+            render_times: Ring::new(),
+            instructions: Ring::new(),
+            last_frame: None,
+            frames: 0,
+            late_frames: 0,
+            late_streak: 0,
+        }
+    }
+
+    // `started` is when the frame began running, `budget` how long a frame lasts.
+    pub fn record_frame(
+        &mut self,
+        started: Instant,
+        budget: Duration,
+        cycle_time: Duration,
+        instructions: u64,
+    ) {
+        if let Some(last_frame) = self.last_frame {
+            let interval = started.duration_since(last_frame);
+
+            self.intervals.push(millis(interval));
+
+            if interval.as_secs_f32() > budget.as_secs_f32() * LATE_FACTOR {
+                self.late_frames += 1;
+                self.late_streak += 1;
+
+                if self.late_streak == LATE_STREAK_WARNING {
+                    eprintln!(
+                        "warning: {} frames in a row ran late, the last one by {:.1} ms",
+                        LATE_STREAK_WARNING,
+                        millis(interval) - millis(budget)
+                    );
+                }
+            } else {
+                self.late_streak = 0;
+            }
+        }
+
+        self.last_frame = Some(started);
+        self.cycle_times.push(millis(cycle_time));
+        self.instructions.push(instructions as f32);
+        self.frames += 1;
+    }
+
+    pub fn record_render(&mut self, render_time: Duration) {
+        self.render_times.push(millis(render_time));
+    }
+
+    // Pausing must not show up as one huge late frame.
+    pub fn restart(&mut self) {
+        self.last_frame = None;
+        self.late_streak = 0;
+    }
+}
+
+fn millis(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
+
+fn write_row(f: &mut fmt::Formatter, name: &str, ring: &Ring, unit: &str) -> fmt::Result {
+    writeln!(
+        f,
+        "  {:<13} p50 {:>7.2}{unit}  p95 {:>7.2}{unit}  max {:>7.2}{unit}",
+        name,
+        ring.percentile(50.0),
+        ring.percentile(95.0),
+        ring.max(),
+    )
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Frame stats over the last {} frames, {} of {} frames late:",
+            self.intervals.len.max(self.cycle_times.len),
+            self.late_frames,
+            self.frames
+        )?;
+        write_row(f, "frame time", &self.intervals, " ms")?;
+        write_row(f, "cycles", &self.cycle_times, " ms")?;
+        write_row(f, "render", &self.render_times, " ms")?;
+        write_row(f, "instructions", &self.instructions, "   ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let mut ring = Ring::new();
+
+        assert_eq!((ring.percentile(50.0), ring.max()), (0.0, 0.0));
+
+        // Pushed out of order, 1 to 100.
+        for i in 0..100 {
+            ring.push(((i * 37) % 100 + 1) as f32);
+        }
+
+        assert_eq!(ring.percentile(50.0), 50.0);
+        assert_eq!(ring.percentile(95.0), 95.0);
+        assert_eq!(ring.percentile(0.0), 1.0);
+        assert_eq!(ring.percentile(100.0), 100.0);
+        assert_eq!(ring.max(), 100.0);
+    }
+
+    #[test]
+    fn only_the_last_window_of_samples_counts() {
+        let mut ring = Ring::new();
+
+        ring.push(1000.0);
+
+        for _ in 0..WINDOW {
+            ring.push(2.0);
+        }
+
+        assert_eq!(ring.len, WINDOW);
+        assert_eq!(ring.max(), 2.0);
+        assert_eq!(ring.percentile(100.0), 2.0);
+    }
+
+    #[test]
+    fn late_frames_are_counted_but_not_a_pause() {
+        let budget = Duration::from_millis(20);
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut stats = FrameStats::new();
+
+        // On time, 29 ms late is within 1.5 budgets, then 31 ms is not.
+        for (i, started) in [0, 20, 40, 69, 100].into_iter().enumerate() {
+            stats.record_frame(at(started), budget, Duration::from_millis(4), 10 * i as u64);
+        }
+
+        assert_eq!(
+            (stats.frames, stats.late_frames, stats.late_streak),
+            (5, 1, 1)
+        );
+        assert_eq!(stats.intervals.max(), 31.0);
+        assert_eq!(stats.intervals.percentile(50.0), 20.0);
+        assert_eq!(stats.instructions.max(), 40.0);
+
+        // A pause of a second, then back on time.
+        stats.restart();
+        stats.record_frame(at(1100), budget, Duration::from_millis(4), 10);
+        stats.record_frame(at(1120), budget, Duration::from_millis(4), 10);
+        stats.record_render(Duration::from_millis(3));
+
+        assert_eq!(
+            (stats.frames, stats.late_frames, stats.late_streak),
+            (7, 1, 0)
+        );
+        assert_eq!(stats.intervals.max(), 31.0);
+        assert_eq!(stats.cycle_times.percentile(50.0), 4.0);
+        assert!(stats
+            .to_string()
+            .starts_with("Frame stats over the last 7 frames, 1 of 7 frames late:\n"));
+    }
+}
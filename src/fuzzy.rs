@@ -0,0 +1,109 @@
+// Case-insensitive subsequence matching, scored so that tight runs of
+// characters and matches at the start of words come first.
+const MATCH: i32 = 16;
+const CONSECUTIVE: i32 = 24;
+const WORD_START: i32 = 32;
+const GAP: i32 = 1;
+
+pub fn score(pattern: &str, candidate: &str) -> Option<i32> {
+    let candidate: Vec<char> = candidate.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous: Option<usize> = None;
+
+    for wanted in pattern.chars().flat_map(char::to_lowercase) {
+        let found = next + candidate[next..].iter().position(|c| *c == wanted)?;
+
+        score += MATCH;
+
+        if found == 0 || !candidate[found - 1].is_alphanumeric() {
+            score += WORD_START;
+        }
+
+        match previous {
+            Some(previous) if found == previous + 1 => score += CONSECUTIVE,
+            Some(previous) => score -= GAP * (found - previous - 1) as i32,
+            None => score -= GAP * found as i32,
+        }
+
+        previous = Some(found);
+        next = found + 1;
+    }
+
+    // Among equal matches the shorter name is the closer one.
+    Some(score - (candidate.len() - next) as i32 * GAP)
+}
+
+// Best match first, ties broken by name.
+pub fn rank<'a>(pattern: &str, candidates: &[&'a str]) -> Vec<(i32, &'a str)> {
+    let mut ranked: Vec<(i32, &str)> = candidates
+        .iter()
+        .filter_map(|candidate| Some((score(pattern, candidate)?, *candidate)))
+        .collect();
+
+    ranked.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_pattern_character_has_to_appear_in_order() {
+        assert!(score("brx", "Brix.ch8").is_some());
+        assert!(score("bxr", "Brix.ch8").is_none());
+        assert!(score("brixx", "Brix.ch8").is_none());
+        assert!(score("z", "").is_none());
+    }
+
+    #[test]
+    fn matching_ignores_case() {
+        assert_eq!(score("BRIX", "brix.ch8"), score("brix", "Brix.ch8"));
+        assert_eq!(score("ÉCRAN", "écran.ch8"), score("écran", "Écran.ch8"));
+    }
+
+    #[test]
+    fn scores_add_up_per_character() {
+        // A word start, three in a row, and the rest of the name.
+        assert_eq!(score("brix", "Brix.ch8"), Some(48 + 3 * 40 - 4));
+        // One letter skipped after the word start, one left over.
+        assert_eq!(score("bi", "Brix"), Some(48 + 16 - 1 - 1));
+        // Everything matches the empty pattern, shorter names first.
+        assert_eq!(score("", "pong.ch8"), Some(-8));
+    }
+
+    #[test]
+    fn runs_and_word_starts_beat_scattered_letters() {
+        assert!(score("inv", "Space Invaders.ch8") > score("inv", "Tank Driving.ch8"));
+        assert!(score("tet", "Tetris.ch8") > score("tet", "Tic-Tac-Toe.ch8"));
+        assert!(score("si", "Space Invaders.ch8") > score("si", "Missile.ch8"));
+    }
+
+    #[test]
+    fn ranking_puts_the_best_match_first_and_breaks_ties_by_name() {
+        let roms = [
+            "Pong 2.ch8",
+            "Tetris.ch8",
+            "Pong.ch8",
+            "Paddles.ch8",
+            "Blinky.ch8",
+            "Pong 1.ch8",
+        ];
+
+        let names: Vec<&str> = rank("pong", &roms)
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect();
+
+        assert_eq!(names, ["Pong.ch8", "Pong 1.ch8", "Pong 2.ch8"]);
+
+        let names: Vec<&str> = rank("p", &roms).into_iter().map(|(_, name)| name).collect();
+
+        assert_eq!(
+            names,
+            ["Pong.ch8", "Pong 1.ch8", "Pong 2.ch8", "Paddles.ch8"]
+        );
+        assert_eq!(rank("zzz", &roms), []);
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+
+// Two colors need the smallest code size GIF allows.
+const MIN_CODE_SIZE: u8 = 2;
+const MAX_CODE_SIZE: u8 = 12;
+const MAX_CODES: u16 = 1 << MAX_CODE_SIZE;
+const SUB_BLOCK_SIZE: usize = 255;
+
+// A looping animated GIF with a two-color palette. Each frame holds one
+// palette index per pixel, row by row, and how long it shows in hundredths
+// of a second.
+pub fn encode(
+    width: usize,
+    height: usize,
+    palette: [[u8; 3]; 2],
+    frames: &[(Vec<u8>, u16)],
+) -> Vec<u8> {
+    let mut gif = Vec::new();
+
+    gif.extend_from_slice(b"GIF89a");
+    gif.extend_from_slice(&(width as u16).to_le_bytes());
+    gif.extend_from_slice(&(height as u16).to_le_bytes());
+    // A global color table of 2 entries, background color 0, square pixels.
+    gif.extend_from_slice(&[0x80, 0, 0]);
+
+    for color in palette {
+        gif.extend_from_slice(&color);
+    }
+
+    // Loops forever.
+    gif.extend_from_slice(&[0x21, 0xff, 0x0b]);
+    gif.extend_from_slice(b"NETSCAPE2.0");
+    gif.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+
+    for (pixels, delay) in frames {
+        assert_eq!(pixels.len(), width * height);
+
+        gif.extend_from_slice(&[0x21, 0xf9, 0x04, 0x04]);
+        gif.extend_from_slice(&delay.to_le_bytes());
+        gif.extend_from_slice(&[0x00, 0x00]);
+
+        gif.push(0x2c);
+        gif.extend_from_slice(&[0, 0, 0, 0]);
+        gif.extend_from_slice(&(width as u16).to_le_bytes());
+        gif.extend_from_slice(&(height as u16).to_le_bytes());
+        gif.push(0);
+
+        gif.push(MIN_CODE_SIZE);
+
+        for block in compress(pixels).chunks(SUB_BLOCK_SIZE) {
+            gif.push(block.len() as u8);
+            gif.extend_from_slice(block);
+        }
+
+        gif.push(0);
+    }
+
+    gif.push(0x3b);
+    gif
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    // GIF packs codes starting from the least significant bit.
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+
+        self.bytes
+    }
+}
+
+// Variable-length LZW as GIF decoders expect it: the code size grows once
+// the next free code no longer fits, and a full table starts over with a
+// clear code.
+fn compress(pixels: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << MIN_CODE_SIZE;
+    let end = clear + 1;
+    let mut writer = BitWriter {
+        bytes: Vec::new(),
+        buffer: 0,
+        bits: 0,
+    };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut size = MIN_CODE_SIZE + 1;
+    let mut next = end + 1;
+
+    writer.write(clear, size);
+
+    let Some((first, rest)) = pixels.split_first() else {
+        writer.write(end, size);
+        return writer.finish();
+    };
+
+    let mut prefix = *first as u16;
+
+    for pixel in rest {
+        if let Some(code) = table.get(&(prefix, *pixel)) {
+            prefix = *code;
+            continue;
+        }
+
+        write_code(&mut writer, prefix, &mut size, next);
+
+        if next < MAX_CODES {
+            table.insert((prefix, *pixel), next);
+            next += 1;
+        } else {
+            writer.write(clear, size);
+            table.clear();
+            size = MIN_CODE_SIZE + 1;
+            next = end + 1;
+        }
+
+        prefix = *pixel as u16;
+    }
+
+    write_code(&mut writer, prefix, &mut size, next);
+    writer.write(end, size);
+    writer.finish()
+}
+
+// The decoder adds a table entry for every code it reads, so the code size
+// grows right after the code that makes `next` overflow it.
+fn write_code(writer: &mut BitWriter, code: u16, size: &mut u8, next: u16) {
+    writer.write(code, *size);
+
+    if next > (1 << *size) - 1 && *size < MAX_CODE_SIZE {
+        *size += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Frame {
+        delay: u16,
+        pixels: Vec<u8>,
+    }
+
+    // Just enough of a GIF reader for what `encode` writes.
+    fn read(gif: &[u8]) -> (usize, usize, Vec<Frame>) {
+        assert_eq!(&gif[..6], b"GIF89a");
+
+        let width = u16::from_le_bytes([gif[6], gif[7]]) as usize;
+        let height = u16::from_le_bytes([gif[8], gif[9]]) as usize;
+        // The header, the color table and the loop extension.
+        let mut at = 13 + 6 + 19;
+        let mut frames = Vec::new();
+        let mut delay = 0;
+
+        loop {
+            match gif[at] {
+                0x21 => {
+                    assert_eq!(gif[at + 1], 0xf9);
+                    delay = u16::from_le_bytes([gif[at + 4], gif[at + 5]]);
+                    at += 8;
+                }
+                0x2c => {
+                    let code_size = gif[at + 10];
+                    let mut data = Vec::new();
+
+                    at += 11;
+
+                    while gif[at] != 0 {
+                        let len = gif[at] as usize;
+
+                        data.extend_from_slice(&gif[at + 1..at + 1 + len]);
+                        at += 1 + len;
+                    }
+
+                    at += 1;
+                    frames.push(Frame {
+                        delay,
+                        pixels: decompress(&data, code_size),
+                    });
+                }
+                0x3b => {
+                    assert_eq!(at, gif.len() - 1);
+                    return (width, height, frames);
+                }
+                byte => panic!("unexpected block 0x{:02X} at {}", byte, at),
+            }
+        }
+    }
+
+    fn decompress(data: &[u8], code_size: u8) -> Vec<u8> {
+        let clear = 1usize << code_size;
+        let end = clear + 1;
+        let fresh = || (0..=end).map(|code| vec![code as u8]).collect::<Vec<_>>();
+        let mut table = fresh();
+        let mut size = code_size + 1;
+        let mut previous: Option<Vec<u8>> = None;
+        let mut pixels = Vec::new();
+        let mut bit = 0;
+
+        loop {
+            let code = (0..size as usize).fold(0, |code, i| {
+                let at = bit + i;
+
+                code | ((data[at / 8] >> (at % 8) & 1) as usize) << i
+            });
+
+            bit += size as usize;
+
+            if code == clear {
+                table = fresh();
+                size = code_size + 1;
+                previous = None;
+                continue;
+            }
+
+            if code == end {
+                return pixels;
+            }
+
+            let entry = match (table.get(code), &previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) => [previous.clone(), vec![previous[0]]].concat(),
+                (None, None) => panic!("code {} before any other", code),
+            };
+
+            pixels.extend_from_slice(&entry);
+
+            if let Some(previous) = previous {
+                if table.len() < MAX_CODES as usize {
+                    table.push([previous, vec![entry[0]]].concat());
+                }
+            }
+
+            if table.len() == 1 << size && size < MAX_CODE_SIZE {
+                size += 1;
+            }
+
+            previous = Some(entry);
+        }
+    }
+
+    #[test]
+    fn every_frame_is_written_with_its_delay() {
+        let frames = [
+            (vec![0, 1, 1, 0, 0, 0], 10),
+            (vec![1, 1, 1, 1, 1, 1], 2),
+            (vec![0, 0, 0, 0, 0, 1], 300),
+        ];
+        let gif = encode(3, 2, [[0, 0, 0], [255, 255, 255]], &frames);
+        let (width, height, read) = read(&gif);
+
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(read.len(), 3);
+
+        for (frame, (pixels, delay)) in read.iter().zip(&frames) {
+            assert_eq!(frame.delay, *delay);
+            assert_eq!(&frame.pixels, pixels);
+        }
+
+        // The palette goes in the global color table.
+        assert_eq!(&gif[13..19], &[0, 0, 0, 255, 255, 255]);
+    }
+
+    #[test]
+    fn a_noisy_frame_fills_the_table_and_starts_over() {
+        // Enough different runs to need 12 bit codes and a clear code.
+        let mut state = 1u32;
+        let pixels: Vec<u8> = (0..256 * 128)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8 & 1
+            })
+            .collect();
+        let gif = encode(256, 128, [[0; 3], [255; 3]], &[(pixels.clone(), 5)]);
+        let (_, _, read) = read(&gif);
+
+        assert_eq!(read.len(), 1);
+        assert!(read[0].pixels == pixels);
+    }
+
+    #[test]
+    fn no_frames_is_still_a_gif() {
+        let gif = encode(64, 32, [[0; 3], [255; 3]], &[]);
+
+        assert!(read(&gif).2.is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::{breakpoint::BreakReason, cheats::Cheats, display::DisplayMode, emulator::Emulator};
+
+// How a run that didn't fail came to an end.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ending {
+    Finished,
+    // An FX0A was waiting for a key, after this many cycles. With no input it
+    // would wait forever.
+    WaitingForKey(u64),
+    // A breakpoint or a break event stopped the run before the end.
+    Stopped(BreakReason),
+    // The max cycles or the timeout ran out, after this many cycles.
+    Watchdog(u64),
+}
+
+// Runs `cycles` instructions with no window, audio or input. The timers
+// follow the frames and the random numbers come from a fixed seed, as in
+// smoke tests, so the same ROM always ends on the same screen. The frozen
+// cheats are written back after every frame, as in the window.
+pub fn run(
+    emulator: &mut Emulator,
+    cycles: u64,
+    cheats: &Cheats,
+    timeout: Option<Duration>,
+) -> Result<Ending, String> {
+    let started = Instant::now();
+
+    emulator.set_frame_timers(true);
+    emulator.seed_random(0);
+    emulator.run_for(cycles);
+
+    loop {
+        match emulator.break_reason() {
+            Some(BreakReason::RunFor) => return Ok(Ending::Finished),
+            Some(reason) => return Ok(Ending::Stopped(reason)),
+            None => {}
+        }
+
+        if emulator.is_waiting_for_key() {
+            return Ok(Ending::WaitingForKey(emulator.cycles()));
+        }
+
+        emulator.run_frame().map_err(|error| error.to_string())?;
+        cheats.apply(emulator);
+
+        let timed_out = timeout.is_some_and(|timeout| started.elapsed() >= timeout);
+
+        if emulator.watchdog_expired() || timed_out {
+            return Ok(Ending::Watchdog(emulator.cycles()));
+        }
+    }
+}
+
+// The format comes from the extension: a grayscale PNG in black and white,
+// or a binary PGM. Either way one image pixel per CHIP-8 pixel.
+pub fn dump(emulator: &Emulator, path: &Path) -> Result<(), String> {
+    let (display, mode) = (emulator.display(), emulator.display_mode());
+    let data = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("png") => encode_png(display, mode),
+        Some("pgm") => encode_pgm(display, mode),
+        _ => return Err("expected a .png or .pgm file".to_string()),
+    };
+
+    fs::write(path, data).map_err(|err| err.to_string())
+}
+
+fn gray(display: &[u8], mode: DisplayMode) -> impl Iterator<Item = u8> + '_ {
+    display[..mode.pixel_count()]
+        .iter()
+        .map(|pixel| pixel * 0xff)
+}
+
+fn encode_png(display: &[u8], mode: DisplayMode) -> Vec<u8> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, mode.width as u32, mode.height as u32);
+
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    // Writing into a Vec can't fail.
+    let mut writer = encoder.write_header().unwrap();
+    writer
+        .write_image_data(&gray(display, mode).collect::<Vec<_>>())
+        .unwrap();
+    writer.finish().unwrap();
+
+    png
+}
+
+fn encode_pgm(display: &[u8], mode: DisplayMode) -> Vec<u8> {
+    let mut pgm = format!("P5\n{} {}\n255\n", mode.width, mode.height).into_bytes();
+
+    pgm.extend(gray(display, mode));
+    pgm
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{breakpoint::OpcodeBreakpoint, display::TextStyle, emulator::EmulatorOptions};
+
+    fn run_rom(rom: &[u8], cycles: u64) -> (Emulator, Result<Ending, String>) {
+        let mut emulator = Emulator::new(EmulatorOptions::default());
+
+        emulator.load_rom(rom).unwrap();
+
+        let ending = run(&mut emulator, cycles, &Cheats::default(), None);
+
+        (emulator, ending)
+    }
+
+    #[test]
+    fn the_ibm_logo_matches_its_golden_file() {
+        let (emulator, ending) = run_rom(include_bytes!("../roms/ibm_logo.ch8"), 1000);
+
+        assert_eq!(ending, Ok(Ending::Finished));
+        assert_eq!(emulator.cycles(), 1000);
+        assert_eq!(
+            emulator.display_text(TextStyle::Ascii),
+            include_str!("../roms/ibm_logo.txt")
+        );
+    }
+
+    #[test]
+    fn runs_stop_at_a_key_wait_or_a_fault() {
+        let (_, ending) = run_rom(&[0x60, 0x01, 0xF0, 0x0A], 1000);
+
+        assert_eq!(ending, Ok(Ending::WaitingForKey(2)));
+
+        let (_, ending) = run_rom(&[0x00, 0xEE], 1000);
+
+        assert_eq!(ending, Err("Return with an empty stack".to_string()));
+    }
+
+    #[test]
+    fn the_watchdog_and_the_breakpoints_end_the_run_early() {
+        // A jump to itself never ends on its own.
+        let rom = [0x12, 0x00];
+        let mut emulator = Emulator::new(EmulatorOptions::default());
+
+        emulator.load_rom(&rom).unwrap();
+        emulator.set_max_cycles(Some(50));
+
+        let ending = run(&mut emulator, 1000, &Cheats::default(), None);
+
+        assert_eq!(ending, Ok(Ending::Watchdog(50)));
+
+        let mut emulator = Emulator::new(EmulatorOptions::default());
+
+        emulator.load_rom(&rom).unwrap();
+
+        let ending = run(
+            &mut emulator,
+            1000,
+            &Cheats::default(),
+            Some(Duration::ZERO),
+        );
+
+        assert_eq!(ending, Ok(Ending::Watchdog(emulator.cycles())));
+        assert!(emulator.cycles() < 1000);
+
+        let mut emulator = Emulator::new(EmulatorOptions::default());
+
+        emulator.load_rom(&rom).unwrap();
+        emulator.add_opcode_breakpoint(OpcodeBreakpoint::parse("1NNN").unwrap());
+
+        let ending = run(&mut emulator, 1000, &Cheats::default(), None);
+
+        assert_eq!(ending, Ok(Ending::Stopped(BreakReason::Opcode(0))));
+        assert_eq!(emulator.cycles(), 0);
+    }
+
+    #[test]
+    fn frozen_cheats_hold_through_the_run() {
+        // Counts V0 up and stores it at 0x300 forever.
+        let rom = [0xA3, 0x00, 0x70, 0x01, 0xF0, 0x55, 0x12, 0x02];
+        let cheats = Cheats::parse("freeze 0x300=0x42 lives").unwrap();
+        let mut emulator = Emulator::new(EmulatorOptions::default());
+
+        emulator.load_rom(&rom).unwrap();
+
+        let ending = run(&mut emulator, 95, &cheats, None);
+
+        assert_eq!(ending, Ok(Ending::Finished));
+        assert_eq!(emulator.read_memory(0x300), 0x42);
+    }
+
+    #[test]
+    fn a_pgm_has_one_byte_per_pixel() {
+        let mut display = vec![0; DisplayMode::LORES.pixel_count()];
+
+        display[1] = 1;
+
+        let pgm = encode_pgm(&display, DisplayMode::LORES);
+        let header = b"P5\n64 32\n255\n";
+
+        assert_eq!(&pgm[..header.len()], header);
+        assert_eq!(pgm[header.len()..header.len() + 3], [0x00, 0xff, 0x00]);
+        assert_eq!(pgm.len(), header.len() + 64 * 32);
+    }
+}
@@ -0,0 +1,369 @@
+use std::io::{self, Read, Write};
+
+use chip8_emu::patch::{PatchBuffer, ROW_SIZE};
+
+const VISIBLE_ROWS: usize = 16;
+const PAGE: isize = (VISIBLE_ROWS * ROW_SIZE) as isize;
+
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Char(u8),
+}
+
+// Puts the terminal back the way it was, however the editor exits.
+#[cfg(unix)]
+struct RawMode {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable() -> Result<Self, String> {
+        unsafe {
+            let mut original: libc::termios = std::mem::zeroed();
+
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err("stdin is not a terminal".to_string());
+            }
+
+            let mut raw = original;
+
+            libc::cfmakeraw(&mut raw);
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw);
+
+            Ok(RawMode { original })
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+fn read_key(stdin: &mut impl Read) -> Option<Key> {
+    let mut byte = [0];
+
+    stdin.read_exact(&mut byte).ok()?;
+
+    if byte[0] != 0x1b {
+        return Some(Key::Char(byte[0]));
+    }
+
+    let mut sequence = [0; 2];
+
+    stdin.read_exact(&mut sequence).ok()?;
+
+    match sequence {
+        [b'[', b'A'] => Some(Key::Up),
+        [b'[', b'B'] => Some(Key::Down),
+        [b'[', b'C'] => Some(Key::Right),
+        [b'[', b'D'] => Some(Key::Left),
+        [b'[', b'5'] | [b'[', b'6'] => {
+            stdin.read_exact(&mut byte).ok()?;
+
+            if sequence[1] == b'5' {
+                Some(Key::PageUp)
+            } else {
+                Some(Key::PageDown)
+            }
+        }
+        _ => Some(Key::Char(0x1b)),
+    }
+}
+
+// The cursor byte in reverse video, changed bytes underlined. Raw mode needs
+// \r\n line ends.
+fn render(buffer: &PatchBuffer, top: usize, status: &str) -> String {
+    let mut screen = String::from("\x1b[2J\x1b[H");
+
+    for row in top..(top + VISIBLE_ROWS).min(buffer.row_count()) {
+        let line = buffer.format_row(row, |address, text| {
+            let address = address as u16;
+
+            if address == buffer.cursor() {
+                format!("\x1b[7m{}\x1b[0m", text)
+            } else if buffer.is_changed(address) {
+                format!("\x1b[4m{}\x1b[0m", text)
+            } else {
+                text
+            }
+        });
+
+        screen.push_str(&line);
+        screen.push_str("\r\n");
+    }
+
+    screen.push_str("\r\n");
+
+    for line in buffer.disassemble_row(buffer.cursor_row()) {
+        screen.push_str(&line);
+        screen.push_str("\r\n");
+    }
+
+    screen.push_str(&format!(
+        "\r\n0x{:03X}  {} changed  {}\r\n",
+        buffer.cursor(),
+        buffer.changes().len(),
+        status
+    ));
+    screen.push_str("arrows/PgUp/PgDn move, 0-9 a-f edit, u undo byte, w write, q quit");
+    screen
+}
+
+// What the keys have done so far, apart from the bytes themselves.
+#[derive(Default)]
+struct Session {
+    top: usize,
+    status: String,
+    quitting: bool,
+}
+
+impl Session {
+    // Keeps the cursor's row on screen.
+    fn scroll(&mut self, buffer: &PatchBuffer) {
+        let row = buffer.cursor_row();
+
+        if row < self.top {
+            self.top = row;
+        } else if row >= self.top + VISIBLE_ROWS {
+            self.top = row + 1 - VISIBLE_ROWS;
+        }
+    }
+
+    // Returns false once the editor should close.
+    fn press(
+        &mut self,
+        buffer: &mut PatchBuffer,
+        key: Key,
+        save: &mut impl FnMut(&[u8]) -> Result<String, String>,
+    ) -> bool {
+        self.status.clear();
+
+        match key {
+            Key::Up => buffer.move_by(-(ROW_SIZE as isize)),
+            Key::Down => buffer.move_by(ROW_SIZE as isize),
+            Key::Left => buffer.move_by(-1),
+            Key::Right => buffer.move_by(1),
+            Key::PageUp => buffer.move_by(-PAGE),
+            Key::PageDown => buffer.move_by(PAGE),
+            // Ctrl+C doesn't raise a signal in raw mode.
+            Key::Char(0x03) => return false,
+            Key::Char(b'q') if buffer.changes().is_empty() || self.quitting => return false,
+            Key::Char(b'q') => {
+                self.quitting = true;
+                self.status = "unsaved changes, q again to quit anyway".to_string();
+                return true;
+            }
+            Key::Char(b'w') => match save(buffer.bytes()) {
+                Ok(message) => {
+                    buffer.mark_saved();
+                    self.status = message;
+                }
+                Err(err) => self.status = err,
+            },
+            Key::Char(b'u') => buffer.revert(),
+            Key::Char(c) if c.is_ascii_hexdigit() => {
+                buffer.type_digit((c as char).to_digit(16).unwrap() as u8)
+            }
+            Key::Char(_) => {}
+        }
+
+        self.quitting = false;
+        self.scroll(buffer);
+        true
+    }
+}
+
+// Runs until `q`, calling `save` on `w`. Returns whether anything was
+// left unsaved.
+#[cfg(unix)]
+pub fn run(
+    buffer: &mut PatchBuffer,
+    mut save: impl FnMut(&[u8]) -> Result<String, String>,
+) -> Result<bool, String> {
+    let _raw = RawMode::enable()?;
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout();
+    let mut session = Session::default();
+
+    loop {
+        let _ = write!(stdout, "{}", render(buffer, session.top, &session.status));
+        let _ = stdout.flush();
+
+        let Some(key) = read_key(&mut stdin) else {
+            break;
+        };
+
+        if !session.press(buffer, key, &mut save) {
+            break;
+        }
+    }
+
+    let _ = write!(stdout, "\x1b[2J\x1b[H");
+    let _ = stdout.flush();
+
+    Ok(!buffer.changes().is_empty())
+}
+
+#[cfg(not(unix))]
+pub fn run(
+    _buffer: &mut PatchBuffer,
+    _save: impl FnMut(&[u8]) -> Result<String, String>,
+) -> Result<bool, String> {
+    Err("the interactive editor needs a Unix terminal, use --set instead".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    // Feeds `typed` through the key reader, as the terminal would.
+    fn type_keys(
+        buffer: &mut PatchBuffer,
+        session: &mut Session,
+        typed: &[u8],
+        save: &mut impl FnMut(&[u8]) -> Result<String, String>,
+    ) -> bool {
+        let mut input = typed;
+
+        while let Some(key) = read_key(&mut input) {
+            if !session.press(buffer, key, save) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn no_save(_: &[u8]) -> Result<String, String> {
+        Err("not saving".to_string())
+    }
+
+    #[test]
+    fn the_arrows_move_the_cursor_by_a_byte_or_a_row() {
+        let mut buffer = PatchBuffer::new(vec![0; 0x300]);
+        let mut session = Session::default();
+
+        type_keys(
+            &mut buffer,
+            &mut session,
+            b"\x1b[C\x1b[C\x1b[B",
+            &mut no_save,
+        );
+        assert_eq!(buffer.cursor(), 0x212);
+
+        type_keys(&mut buffer, &mut session, b"\x1b[D\x1b[A", &mut no_save);
+        assert_eq!(buffer.cursor(), 0x201);
+
+        // A page down scrolls so the cursor stays on screen.
+        type_keys(&mut buffer, &mut session, b"\x1b[6~\x1b[6~", &mut no_save);
+        assert_eq!(buffer.cursor(), 0x401);
+        assert_eq!(session.top, 17);
+
+        type_keys(&mut buffer, &mut session, b"\x1b[5~", &mut no_save);
+        assert_eq!((buffer.cursor(), session.top), (0x301, 16));
+    }
+
+    #[test]
+    fn two_digits_make_a_byte_and_u_undoes_it() {
+        let mut buffer = PatchBuffer::new(vec![0x12, 0x34, 0x56]);
+        let mut session = Session::default();
+
+        // One nibble changes the high half and waits for the second.
+        type_keys(&mut buffer, &mut session, b"a", &mut no_save);
+        assert_eq!(buffer.bytes(), [0xA2, 0x34, 0x56]);
+        assert_eq!(buffer.cursor(), 0x200);
+
+        type_keys(&mut buffer, &mut session, b"Bc", &mut no_save);
+        assert_eq!(buffer.bytes(), [0xAB, 0xC4, 0x56]);
+        assert_eq!(buffer.changes(), [0x200, 0x201]);
+
+        // The half-typed byte goes back the way it was loaded.
+        type_keys(&mut buffer, &mut session, b"u", &mut no_save);
+        assert_eq!(buffer.bytes(), [0xAB, 0x34, 0x56]);
+        assert_eq!(buffer.changes(), [0x200]);
+
+        // Other keys are ignored.
+        type_keys(&mut buffer, &mut session, b"xz!", &mut no_save);
+        assert_eq!(buffer.bytes(), [0xAB, 0x34, 0x56]);
+    }
+
+    #[test]
+    fn q_asks_twice_while_there_are_unsaved_changes() {
+        let mut buffer = PatchBuffer::new(vec![0x00, 0x00]);
+        let mut session = Session::default();
+
+        assert!(type_keys(&mut buffer, &mut session, b"ff", &mut no_save));
+        assert!(type_keys(&mut buffer, &mut session, b"q", &mut no_save));
+        assert_eq!(session.status, "unsaved changes, q again to quit anyway");
+
+        // Anything else in between asks again.
+        assert!(type_keys(
+            &mut buffer,
+            &mut session,
+            b"\x1b[Dq",
+            &mut no_save
+        ));
+        assert!(!type_keys(&mut buffer, &mut session, b"q", &mut no_save));
+
+        let mut buffer = PatchBuffer::new(vec![0x00, 0x00]);
+
+        assert!(!type_keys(
+            &mut buffer,
+            &mut Session::default(),
+            b"q",
+            &mut no_save
+        ));
+    }
+
+    #[test]
+    fn w_writes_the_patched_rom() {
+        let path = env::temp_dir().join(format!("chip8-emu-hex-editor-{}.ch8", process::id()));
+        let mut buffer = PatchBuffer::new(vec![0x00, 0xE0, 0x12, 0x00]);
+        let mut session = Session::default();
+        let mut save = |bytes: &[u8]| {
+            fs::write(&path, bytes)
+                .map(|_| "wrote it".to_string())
+                .map_err(|err| err.to_string())
+        };
+
+        type_keys(
+            &mut buffer,
+            &mut session,
+            b"\x1b[C\x1b[C\x1b[C02w",
+            &mut save,
+        );
+
+        assert_eq!(session.status, "wrote it");
+        assert_eq!(fs::read(&path).unwrap(), [0x00, 0xE0, 0x12, 0x02]);
+
+        // Saved, so q quits at once.
+        assert!(buffer.changes().is_empty());
+        assert!(!type_keys(&mut buffer, &mut session, b"q", &mut save));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_failed_write_keeps_the_changes() {
+        let mut buffer = PatchBuffer::new(vec![0x00]);
+        let mut session = Session::default();
+
+        type_keys(&mut buffer, &mut session, b"11w", &mut no_save);
+
+        assert_eq!(session.status, "not saving");
+        assert_eq!(buffer.changes(), [0x200]);
+    }
+}
@@ -0,0 +1,180 @@
+use chip8_emu::{
+    cpu::{MAX_ROM_SIZE, PROGRAM_START},
+    database::{self, RomInfo},
+    disassembler::disassemble,
+    hash::{sha256, to_hex},
+    scan::{self, OpcodeCounts},
+};
+
+const LISTED_INSTRUCTIONS: usize = 12;
+
+pub fn print(name: &str, rom: &[u8], json: bool) {
+    print!("{}", describe(name, rom, json));
+}
+
+fn describe(name: &str, rom: &[u8], json: bool) -> String {
+    let info = database::lookup(rom);
+    let counts = scan::scan(rom);
+    let instructions: Vec<(u16, u16)> = rom
+        .chunks_exact(2)
+        .take(LISTED_INSTRUCTIONS)
+        .enumerate()
+        .map(|(i, pair)| {
+            let address = PROGRAM_START + 2 * i as u16;
+
+            (address, (pair[0] as u16) << 8 | pair[1] as u16)
+        })
+        .collect();
+
+    if json {
+        to_json(name, rom, info, &counts, &instructions) + "\n"
+    } else {
+        to_text(name, rom, info, &counts, &instructions)
+    }
+}
+
+fn fits(rom: &[u8]) -> bool {
+    !rom.is_empty() && rom.len() <= MAX_ROM_SIZE
+}
+
+fn to_text(
+    name: &str,
+    rom: &[u8],
+    info: Option<&RomInfo>,
+    counts: &OpcodeCounts,
+    instructions: &[(u16, u16)],
+) -> String {
+    let size = if rom.is_empty() {
+        "empty".to_string()
+    } else if fits(rom) {
+        format!("{} bytes, fits in memory", rom.len())
+    } else {
+        format!(
+            "{} bytes, {} bytes too large",
+            rom.len(),
+            rom.len() - MAX_ROM_SIZE
+        )
+    };
+
+    let mut text = format!(
+        "File:     {}\nSize:     {}\nSHA-256:  {}\nTitle:    {}\nVariant:  {}\n",
+        name,
+        size,
+        to_hex(&sha256(rom)),
+        info.map_or("not in the database".to_string(), |info| info.to_string()),
+        counts.variant()
+    );
+
+    text.push_str(&format!(
+        "Opcodes:  {} DXYN, {} FX0A, {} CXNN, {} SUPER-CHIP, {} XO-CHIP\n\n",
+        counts.draws, counts.key_waits, counts.randoms, counts.superchip, counts.xochip
+    ));
+
+    for (address, opcode) in instructions {
+        text.push_str(&format!(
+            "{:03X}  {:04X}  {}\n",
+            address,
+            opcode,
+            disassemble(*opcode)
+        ));
+    }
+
+    text
+}
+
+// Hand-rolled so the binary doesn't need a JSON dependency for one command.
+fn to_json(
+    name: &str,
+    rom: &[u8],
+    info: Option<&RomInfo>,
+    counts: &OpcodeCounts,
+    instructions: &[(u16, u16)],
+) -> String {
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+    let instructions: Vec<String> = instructions
+        .iter()
+        .map(|(address, opcode)| {
+            format!(
+                "{{\"address\":{},\"opcode\":\"{:04X}\",\"text\":{}}}",
+                address,
+                opcode,
+                json_string(&disassemble(*opcode))
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\"file\":{},\"size\":{},\"sha256\":\"{}\",\"fits\":{},\"title\":{},\"author\":{},\"year\":{},\"variant\":\"{}\",\"opcodes\":{{\"draw\":{},\"key_wait\":{},\"random\":{},\"superchip\":{},\"xochip\":{}}},\"instructions\":[{}]}}",
+        json_string(name),
+        rom.len(),
+        to_hex(&sha256(rom)),
+        fits(rom),
+        optional(info.map(|info| json_string(info.title))),
+        optional(info.and_then(|info| info.author).map(json_string)),
+        optional(info.and_then(|info| info.year).map(|year| year.to_string())),
+        counts.variant(),
+        counts.draws,
+        counts.key_waits,
+        counts.randoms,
+        counts.superchip,
+        counts.xochip,
+        instructions.join(",")
+    )
+}
+
+pub fn json_string(text: &str) -> String {
+    let mut json = String::from("\"");
+
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: &[u8] = include_bytes!("../roms/ibm_logo.ch8");
+
+    #[test]
+    fn the_ibm_logo_matches_its_golden_info() {
+        assert_eq!(
+            describe("ibm_logo", ROM, false),
+            include_str!("../roms/ibm_logo.info.txt")
+        );
+        assert_eq!(
+            describe("ibm_logo", ROM, true),
+            include_str!("../roms/ibm_logo.info.json")
+        );
+    }
+
+    #[test]
+    fn unknown_and_oversized_roms_say_so() {
+        let rom = vec![0x12; MAX_ROM_SIZE + 2];
+        let text = describe("big \"one\"", &rom, false);
+        let json = describe("big \"one\"", &rom, true);
+
+        assert!(text.contains("Size:     3586 bytes, 2 bytes too large\n"));
+        assert!(text.contains("Title:    not in the database\n"));
+        assert_eq!(
+            text.lines()
+                .filter(|line| line.ends_with("JP #212"))
+                .count(),
+            12
+        );
+        assert!(json.starts_with("{\"file\":\"big \\\"one\\\"\",\"size\":3586,"));
+        assert!(json.contains("\"fits\":false,\"title\":null,\"author\":null,\"year\":null,"));
+        assert_eq!(
+            describe("empty", &[], false).lines().nth(1),
+            Some("Size:     empty")
+        );
+    }
+}
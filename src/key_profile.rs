@@ -0,0 +1,145 @@
+use std::{collections::BTreeMap, fmt};
+
+// A host key, by name, bound to a keypad key.
+pub type KeyBinding = (String, u8);
+
+// `<host key>=<keypad key in hex>`, e.g. `Up=2`.
+pub fn parse_binding(text: &str) -> Result<KeyBinding, String> {
+    let (host, key) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected `<host key>=<keypad key>`, got `{}`", text))?;
+
+    match u8::from_str_radix(key, 16) {
+        Ok(key) if key < 0x10 && !host.is_empty() => Ok((host.to_string(), key)),
+        _ => Err(format!("invalid binding `{}`", text)),
+    }
+}
+
+// Later layers win for the host keys they mention.
+pub fn merge(layers: &[&[KeyBinding]]) -> Vec<KeyBinding> {
+    let mut merged: Vec<KeyBinding> = Vec::new();
+
+    for binding in layers.iter().flat_map(|layer| layer.iter()) {
+        match merged.iter_mut().find(|(host, _)| *host == binding.0) {
+            Some(existing) => existing.1 = binding.1,
+            None => merged.push(binding.clone()),
+        }
+    }
+
+    merged
+}
+
+// Saved key profiles, one `<rom sha1> <binding> <binding>...` line per ROM.
+#[derive(Default)]
+pub struct KeyOverrides {
+    entries: BTreeMap<String, Vec<KeyBinding>>,
+}
+
+impl KeyOverrides {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut entries = BTreeMap::new();
+
+        for (i, line) in source.lines().enumerate() {
+            let mut words = line.split_whitespace();
+
+            let hash = match words.next() {
+                Some(hash) => hash,
+                None => continue,
+            };
+
+            let bindings = words
+                .map(parse_binding)
+                .collect::<Result<_, _>>()
+                .map_err(|err| format!("line {}: {}", i + 1, err))?;
+
+            entries.insert(hash.to_string(), bindings);
+        }
+
+        Ok(KeyOverrides { entries })
+    }
+
+    pub fn get(&self, hash: &str) -> &[KeyBinding] {
+        self.entries.get(hash).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn set(&mut self, hash: &str, bindings: Vec<KeyBinding>) {
+        self.entries.insert(hash.to_string(), bindings);
+    }
+}
+
+impl fmt::Display for KeyOverrides {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (hash, bindings) in &self.entries {
+            write!(f, "{}", hash)?;
+
+            for (host, key) in bindings {
+                write!(f, " {}={:X}", host, key)?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bindings(texts: &[&str]) -> Vec<KeyBinding> {
+        texts
+            .iter()
+            .map(|text| parse_binding(text).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn later_layers_win_for_the_keys_they_mention() {
+        let database = bindings(&["Up=2", "Down=8", "Left=4"]);
+        let sidecar = bindings(&["Left=7"]);
+        let saved = bindings(&["Down=9", "Space=5"]);
+        let map = bindings(&["Up=C"]);
+
+        assert_eq!(
+            merge(&[&database, &sidecar, &saved, &map]),
+            bindings(&["Up=C", "Down=9", "Left=7", "Space=5"])
+        );
+        assert_eq!(
+            merge(&[&map, &database]),
+            bindings(&["Up=2", "Down=8", "Left=4"])
+        );
+        assert_eq!(merge(&[&[], &sidecar, &[]]), sidecar);
+    }
+
+    #[test]
+    fn bindings_need_a_host_key_and_a_hex_keypad_key() {
+        assert_eq!(parse_binding("Up=a"), Ok(("Up".to_string(), 0xA)));
+
+        for text in ["Up", "=2", "Up=10", "Up=G", "Up="] {
+            assert!(parse_binding(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn saved_profiles_read_back_what_was_written() {
+        let mut overrides = KeyOverrides::default();
+
+        overrides.set("bbb", bindings(&["Up=2", "Down=8"]));
+        overrides.set("aaa", bindings(&["Space=F"]));
+        overrides.set("bbb", bindings(&["Left=4"]));
+
+        let written = overrides.to_string();
+        let read = KeyOverrides::parse(&written).unwrap();
+
+        assert_eq!(written, "aaa Space=F\nbbb Left=4\n");
+        assert_eq!(read.get("bbb"), bindings(&["Left=4"]));
+        assert_eq!(read.get("aaa"), bindings(&["Space=F"]));
+        assert_eq!(read.get("ccc"), []);
+        assert_eq!(read.to_string(), written);
+        assert_eq!(
+            KeyOverrides::parse("aaa Up=2\nbbb Up\n").err().as_deref(),
+            Some("line 2: expected `<host key>=<keypad key>`, got `Up`")
+        );
+    }
+}
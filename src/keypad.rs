@@ -1,38 +1,292 @@
 use winit::event::VirtualKeyCode;
 use winit_input_helper::WinitInputHelper;
 
-use crate::bus::Bus;
+// The COSMAC VIP hex keypad, row by row.
+pub const LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+// The usual keyboard block for it, laid out the same way.
+const HOST_LAYOUT: [[VirtualKeyCode; 4]; 4] = [
+    [
+        VirtualKeyCode::Key1,
+        VirtualKeyCode::Key2,
+        VirtualKeyCode::Key3,
+        VirtualKeyCode::Key4,
+    ],
+    [
+        VirtualKeyCode::Q,
+        VirtualKeyCode::W,
+        VirtualKeyCode::E,
+        VirtualKeyCode::R,
+    ],
+    [
+        VirtualKeyCode::A,
+        VirtualKeyCode::S,
+        VirtualKeyCode::D,
+        VirtualKeyCode::F,
+    ],
+    [
+        VirtualKeyCode::Z,
+        VirtualKeyCode::X,
+        VirtualKeyCode::C,
+        VirtualKeyCode::V,
+    ],
+];
+
+struct Turbo {
+    value: u8,
+    rate: u32,
+    frames: u32,
+    pressed: bool,
+}
 
 pub struct Keypad {
-    mapping: [(VirtualKeyCode, u8); 0x9],
+    mapping: Vec<(VirtualKeyCode, u8)>,
+    held: Vec<bool>,
+    turbo: Option<Turbo>,
+}
+
+// The host key for each keypad key, 0 to F.
+pub type HostKeys = [VirtualKeyCode; 0x10];
+
+pub fn default_host_keys() -> HostKeys {
+    let mut keys = [VirtualKeyCode::Key0; 0x10];
+
+    for (host, value) in HOST_LAYOUT.iter().flatten().zip(LAYOUT.iter().flatten()) {
+        keys[*value as usize] = *host;
+    }
+
+    keys
 }
 
 impl Keypad {
     pub fn new() -> Self {
+        Self::with_host_keys(&default_host_keys())
+    }
+
+    pub fn with_host_keys(keys: &HostKeys) -> Self {
+        let mapping: Vec<(VirtualKeyCode, u8)> = keys.iter().copied().zip(0..).collect();
+
         Keypad {
-            mapping: [
-                (VirtualKeyCode::Key1, 0x1),
-                (VirtualKeyCode::Key2, 0x2),
-                (VirtualKeyCode::Key3, 0x3),
-                (VirtualKeyCode::Key4, 0x4),
-                (VirtualKeyCode::Key5, 0x5),
-                (VirtualKeyCode::Key6, 0x6),
-                (VirtualKeyCode::Key7, 0x7),
-                (VirtualKeyCode::Key8, 0x8),
-                (VirtualKeyCode::Key9, 0x9),
-            ],
+            held: vec![false; mapping.len()],
+            mapping,
+            turbo: None,
+        }
+    }
+
+    // While its host key is held, `value` flips between pressed and released
+    // every `rate` frames.
+    pub fn set_turbo(&mut self, value: u8, rate: u32) {
+        self.turbo = Some(Turbo {
+            value,
+            rate,
+            frames: 0,
+            pressed: false,
+        });
+    }
+
+    // Rebinds `host` if it already drives a keypad key, adds it otherwise.
+    pub fn bind(&mut self, host: VirtualKeyCode, value: u8) {
+        match self.mapping.iter_mut().find(|(key, _)| *key == host) {
+            Some(binding) => binding.1 = value,
+            None => {
+                self.mapping.push((host, value));
+                self.held.push(false);
+            }
         }
     }
 
-    pub fn read(&self, input: &WinitInputHelper, bus: &mut Bus) {
-        for (key, value) in self.mapping {
-            if input.key_pressed(key) {
-                bus.send(value, 0x1)
+    pub fn is_mapped(&self, value: u8) -> bool {
+        self.mapping.iter().any(|(_, key)| *key == value)
+    }
+
+    pub fn is_held(&self, value: u8) -> bool {
+        self.mapping
+            .iter()
+            .zip(&self.held)
+            .any(|((_, key), held)| *key == value && *held)
+    }
+
+    // Only state changes are reported, the CPU keeps the last state it saw for each key.
+    // Keys pressed with Ctrl or Alt are shortcuts (Ctrl+C, Alt+Enter) and
+    // don't reach the keypad, a key already down stays down.
+    pub fn read(&mut self, input: &WinitInputHelper) -> Vec<(u8, bool)> {
+        let turbo = self.turbo.as_ref().map(|turbo| turbo.value);
+        let chorded = input.held_control() || input.held_alt();
+        let mut changes = Vec::new();
+
+        for ((key, value), held) in self.mapping.iter().zip(self.held.iter_mut()) {
+            let is_held = input.key_held(*key) && (*held || !chorded);
+
+            // The turbo key is sent by `frame`, on emulated frames.
+            if Some(*value) == turbo {
+                *held = is_held;
+                continue;
             }
 
-            if input.key_released(key) {
-                bus.send(value, 0x0);
+            // A tap shorter than one input update still has to reach the CPU.
+            if !*held && !is_held && !chorded && input.key_pressed(*key) && input.key_released(*key)
+            {
+                changes.push((*value, true));
+                changes.push((*value, false));
+                continue;
+            }
+
+            if is_held != *held {
+                changes.push((*value, is_held));
+                *held = is_held;
             }
         }
+
+        changes
+    }
+
+    pub fn frame(&mut self) -> Option<(u8, bool)> {
+        let held = self
+            .turbo
+            .as_ref()
+            .is_some_and(|turbo| self.is_held(turbo.value));
+        let turbo = self.turbo.as_mut()?;
+
+        if held {
+            let toggle = turbo.frames % turbo.rate == 0;
+
+            turbo.frames += 1;
+
+            if toggle {
+                turbo.pressed = !turbo.pressed;
+                return Some((turbo.value, turbo.pressed));
+            }
+        } else {
+            turbo.frames = 0;
+
+            if turbo.pressed {
+                turbo.pressed = false;
+                return Some((turbo.value, false));
+            }
+        }
+
+        None
+    }
+}
+
+// The host keys that can be named in bindings and the config file.
+pub const HOST_KEYS: &[(&str, VirtualKeyCode)] = &[
+    ("0", VirtualKeyCode::Key0),
+    ("1", VirtualKeyCode::Key1),
+    ("2", VirtualKeyCode::Key2),
+    ("3", VirtualKeyCode::Key3),
+    ("4", VirtualKeyCode::Key4),
+    ("5", VirtualKeyCode::Key5),
+    ("6", VirtualKeyCode::Key6),
+    ("7", VirtualKeyCode::Key7),
+    ("8", VirtualKeyCode::Key8),
+    ("9", VirtualKeyCode::Key9),
+    ("A", VirtualKeyCode::A),
+    ("B", VirtualKeyCode::B),
+    ("C", VirtualKeyCode::C),
+    ("D", VirtualKeyCode::D),
+    ("E", VirtualKeyCode::E),
+    ("F", VirtualKeyCode::F),
+    ("G", VirtualKeyCode::G),
+    ("H", VirtualKeyCode::H),
+    ("I", VirtualKeyCode::I),
+    ("J", VirtualKeyCode::J),
+    ("K", VirtualKeyCode::K),
+    ("L", VirtualKeyCode::L),
+    ("M", VirtualKeyCode::M),
+    ("N", VirtualKeyCode::N),
+    ("O", VirtualKeyCode::O),
+    ("P", VirtualKeyCode::P),
+    ("Q", VirtualKeyCode::Q),
+    ("R", VirtualKeyCode::R),
+    ("S", VirtualKeyCode::S),
+    ("T", VirtualKeyCode::T),
+    ("U", VirtualKeyCode::U),
+    ("V", VirtualKeyCode::V),
+    ("W", VirtualKeyCode::W),
+    ("X", VirtualKeyCode::X),
+    ("Y", VirtualKeyCode::Y),
+    ("Z", VirtualKeyCode::Z),
+    ("Up", VirtualKeyCode::Up),
+    ("Down", VirtualKeyCode::Down),
+    ("Left", VirtualKeyCode::Left),
+    ("Right", VirtualKeyCode::Right),
+    ("Space", VirtualKeyCode::Space),
+    ("Return", VirtualKeyCode::Return),
+    ("F10", VirtualKeyCode::F10),
+    ("F11", VirtualKeyCode::F11),
+    ("F12", VirtualKeyCode::F12),
+];
+
+pub fn host_key(name: &str) -> Option<VirtualKeyCode> {
+    HOST_KEYS
+        .iter()
+        .find(|(key_name, _)| *key_name == name)
+        .map(|(_, key)| *key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs `frames` emulated frames with the host key for `value` held or
+    // not, and keeps the frames that sent something, and what.
+    fn frames(keypad: &mut Keypad, value: u8, held: bool, frames: u32) -> Vec<(u32, u8, bool)> {
+        let index = keypad
+            .mapping
+            .iter()
+            .position(|(_, key)| *key == value)
+            .unwrap();
+
+        keypad.held[index] = held;
+
+        (0..frames)
+            .filter_map(|frame| keypad.frame().map(|(sent, pressed)| (frame, sent, pressed)))
+            .collect()
+    }
+
+    #[test]
+    fn a_held_turbo_key_flips_every_rate_frames() {
+        let mut keypad = Keypad::new();
+
+        keypad.set_turbo(5, 3);
+
+        assert_eq!(
+            frames(&mut keypad, 5, true, 20),
+            [
+                (0, 5, true),
+                (3, 5, false),
+                (6, 5, true),
+                (9, 5, false),
+                (12, 5, true),
+                (15, 5, false),
+                (18, 5, true)
+            ]
+        );
+        // Letting go releases it at once, and then nothing more is sent.
+        assert_eq!(frames(&mut keypad, 5, false, 20), [(0, 5, false)]);
+        // Holding it again starts over with a press.
+        assert_eq!(
+            frames(&mut keypad, 5, true, 4),
+            [(0, 5, true), (3, 5, false)]
+        );
+        assert_eq!(frames(&mut keypad, 5, false, 4), []);
+    }
+
+    #[test]
+    fn only_the_turbo_key_is_sent_on_frames() {
+        let mut keypad = Keypad::new();
+
+        assert_eq!(frames(&mut keypad, 5, true, 20), []);
+
+        frames(&mut keypad, 5, false, 1);
+        keypad.set_turbo(5, 1);
+
+        assert_eq!(frames(&mut keypad, 6, true, 20), []);
     }
 }
@@ -0,0 +1,126 @@
+use std::{fmt, time::Duration};
+
+// Upper bounds of the histogram buckets in milliseconds, the last bucket
+// takes everything slower.
+const BUCKETS: [u64; 7] = [1, 2, 4, 8, 16, 32, 64];
+const BAR_WIDTH: usize = 40;
+
+// Time from a host key press reaching the bus to the ROM first looking at
+// that key with EX9E, EXA1 or FX0A.
+#[derive(Clone, Debug, Default)]
+pub struct InputLatency {
+    counts: [u32; BUCKETS.len() + 1],
+    total: Duration,
+    max: Duration,
+}
+
+impl InputLatency {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let millis = latency.as_millis() as u64;
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| millis < *bound)
+            .unwrap_or(BUCKETS.len());
+
+        self.counts[bucket] += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.counts.iter().sum()
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.samples() {
+            0 => Duration::ZERO,
+            samples => self.total / samples,
+        }
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+}
+
+impl fmt::Display for InputLatency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let samples = self.samples();
+
+        writeln!(
+            f,
+            "Input latency: {} key presses, mean {:.1} ms, max {:.1} ms",
+            samples,
+            self.mean().as_secs_f32() * 1000.0,
+            self.max.as_secs_f32() * 1000.0
+        )?;
+
+        let largest = self.counts.iter().copied().max().unwrap_or(0).max(1);
+
+        for (i, count) in self.counts.iter().enumerate() {
+            let label = match BUCKETS.get(i) {
+                Some(bound) => format!("< {} ms", bound),
+                None => format!(">= {} ms", BUCKETS[BUCKETS.len() - 1]),
+            };
+            let bar = "#".repeat(*count as usize * BAR_WIDTH / largest as usize);
+
+            let line = format!("  {:>8} {:>5} {}", label, count, bar);
+
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(millis: &[u64]) -> InputLatency {
+        let mut latency = InputLatency::new();
+
+        for millis in millis {
+            latency.record(Duration::from_millis(*millis));
+        }
+
+        latency
+    }
+
+    #[test]
+    fn samples_land_in_the_bucket_below_their_bound() {
+        assert_eq!(millis(&[0]).counts, [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(millis(&[1, 3, 3]).counts, [0, 1, 2, 0, 0, 0, 0, 0]);
+        assert_eq!(millis(&[63, 64, 500]).counts, [0, 0, 0, 0, 0, 0, 1, 2]);
+    }
+
+    #[test]
+    fn the_mean_and_max_cover_every_sample() {
+        let latency = millis(&[2, 4, 12]);
+
+        assert_eq!(latency.samples(), 3);
+        assert_eq!(latency.mean(), Duration::from_millis(6));
+        assert_eq!(latency.max(), Duration::from_millis(12));
+        assert_eq!(InputLatency::new().mean(), Duration::ZERO);
+    }
+
+    #[test]
+    fn the_histogram_scales_to_the_fullest_bucket() {
+        assert_eq!(
+            millis(&[0, 0, 5, 5, 5, 5, 100]).to_string(),
+            "Input latency: 7 key presses, mean 17.1 ms, max 100.0 ms\n\
+             \x20   < 1 ms     2 ####################\n\
+             \x20   < 2 ms     0\n\
+             \x20   < 4 ms     0\n\
+             \x20   < 8 ms     4 ########################################\n\
+             \x20  < 16 ms     0\n\
+             \x20  < 32 ms     0\n\
+             \x20  < 64 ms     0\n\
+             \x20 >= 64 ms     1 ##########\n"
+        );
+    }
+}
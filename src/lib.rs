@@ -0,0 +1,38 @@
+pub mod assembler;
+pub mod audit;
+pub mod breakpoint;
+pub mod bus;
+pub mod callgraph;
+pub mod cheats;
+pub mod clock;
+pub mod cpu;
+pub mod database;
+pub mod debugger;
+pub mod disassembler;
+pub mod display;
+pub mod emulator;
+pub mod filter;
+pub mod flat_toml;
+pub mod font;
+pub mod gif;
+pub mod hash;
+pub mod headless;
+pub mod key_profile;
+pub mod latency;
+pub mod memory_bus;
+mod parse;
+pub mod patch;
+pub mod poke;
+pub mod profiler;
+pub mod quirks;
+pub mod savestate;
+pub mod scan;
+pub mod sidecar;
+pub mod snapshot;
+pub mod speed;
+pub mod timing;
+pub mod trace;
+pub mod zip;
+
+#[cfg(feature = "capi")]
+pub mod capi;
@@ -0,0 +1,250 @@
+use winit::event::VirtualKeyCode;
+use winit_input_helper::WinitInputHelper;
+
+use crate::keypad::host_key;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+    Press(u8),
+    Release(u8),
+    Wait(u32),
+}
+
+struct Macro {
+    trigger: VirtualKeyCode,
+    steps: Vec<Step>,
+}
+
+struct Playback {
+    index: usize,
+    step: usize,
+    wait: u32,
+    pressed: u16,
+}
+
+#[derive(Default)]
+pub struct Macros {
+    macros: Vec<Macro>,
+    playing: Option<Playback>,
+}
+
+impl Macros {
+    // One macro per line: `<host key> <step>, <step>...` where a step is
+    // `press K`, `release K` or `wait FRAMES`. `#` starts a comment.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut macros = Vec::new();
+
+        for (i, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let parsed =
+                Self::parse_line(line).map_err(|err| format!("line {}: {}", i + 1, err))?;
+
+            macros.push(parsed);
+        }
+
+        Ok(Macros {
+            macros,
+            playing: None,
+        })
+    }
+
+    fn parse_line(line: &str) -> Result<Macro, String> {
+        let (trigger, steps) = line
+            .split_once(char::is_whitespace)
+            .ok_or("expected `<host key> <steps>`")?;
+        let trigger = host_key(trigger).ok_or_else(|| format!("unknown host key `{}`", trigger))?;
+
+        let steps = steps
+            .split(',')
+            .map(|step| Self::parse_step(step.trim()))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Macro { trigger, steps })
+    }
+
+    fn parse_step(step: &str) -> Result<Step, String> {
+        let (action, argument) = step
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("invalid step `{}`", step))?;
+        let argument = argument.trim();
+
+        let key = || match u8::from_str_radix(argument, 16) {
+            Ok(key) if key < 0x10 => Ok(key),
+            _ => Err(format!("invalid keypad key `{}`", argument)),
+        };
+
+        match action {
+            "press" => Ok(Step::Press(key()?)),
+            "release" => Ok(Step::Release(key()?)),
+            "wait" => argument
+                .parse()
+                .map(Step::Wait)
+                .map_err(|_| format!("invalid frame count `{}`", argument)),
+            _ => Err(format!("unknown step `{}`", action)),
+        }
+    }
+
+    // Pressing the trigger of the macro being played cancels it.
+    pub fn check_triggers(&mut self, input: &WinitInputHelper) -> Vec<(u8, bool)> {
+        let triggered = self
+            .macros
+            .iter()
+            .position(|played| input.key_pressed(played.trigger));
+
+        let index = match triggered {
+            Some(index) => index,
+            None => return Vec::new(),
+        };
+
+        self.trigger(index)
+    }
+
+    fn trigger(&mut self, index: usize) -> Vec<(u8, bool)> {
+        let cancelled = self.playing.as_ref().map(|playback| playback.index) == Some(index);
+        let releases = self.stop();
+
+        if !cancelled {
+            self.playing = Some(Playback {
+                index,
+                step: 0,
+                wait: 0,
+                pressed: 0,
+            });
+        }
+
+        releases
+    }
+
+    // Keys still held by a stopped macro are released.
+    fn stop(&mut self) -> Vec<(u8, bool)> {
+        match self.playing.take() {
+            Some(playback) => (0..0x10)
+                .filter(|key| playback.pressed & (1 << key) != 0)
+                .map(|key| (key, false))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn frame(&mut self) -> Vec<(u8, bool)> {
+        let playback = match &mut self.playing {
+            Some(playback) => playback,
+            None => return Vec::new(),
+        };
+
+        if playback.wait > 0 {
+            playback.wait -= 1;
+            return Vec::new();
+        }
+
+        let steps = &self.macros[playback.index].steps;
+        let mut changes = Vec::new();
+
+        while let Some(step) = steps.get(playback.step) {
+            playback.step += 1;
+
+            match *step {
+                Step::Press(key) => {
+                    changes.push((key, true));
+                    playback.pressed |= 1 << key;
+                }
+                Step::Release(key) => {
+                    changes.push((key, false));
+                    playback.pressed &= !(1 << key);
+                }
+                Step::Wait(frames) => {
+                    playback.wait = frames.saturating_sub(1);
+                    return changes;
+                }
+            }
+        }
+
+        changes.extend(self.stop());
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_emu::bus::{Bus, Signal};
+
+    // Sends the keys each frame changes onto a keypad bus the way the front
+    // end does, and reads back what reached it, frame by frame.
+    fn play(macros: &mut Macros, started: Vec<(u8, bool)>, frames: usize) -> Vec<(usize, Signal)> {
+        let mut bus = Bus::new();
+        let mut traffic = Vec::new();
+
+        for (frame, changes) in std::iter::once(started)
+            .chain((0..frames).map(|_| macros.frame()))
+            .enumerate()
+        {
+            for (key, pressed) in changes {
+                bus.send(key, pressed as u8);
+            }
+
+            traffic.extend(std::iter::from_fn(|| bus.try_read()).map(|signal| (frame, signal)));
+        }
+
+        traffic
+    }
+
+    #[test]
+    fn a_macro_sends_exactly_its_script_on_the_frame_timeline() {
+        let mut macros =
+            Macros::parse("F12 press 5, wait 10, press 7, wait 5, release 5, release 7").unwrap();
+        let started = macros.trigger(0);
+
+        // Frame 0 is the trigger itself, the steps start on the next one.
+        assert_eq!(
+            play(&mut macros, started, 30),
+            [(1, (5, 1)), (11, (7, 1)), (16, (5, 0)), (16, (7, 0))]
+        );
+        assert!(macros.playing.is_none());
+    }
+
+    #[test]
+    fn triggering_a_playing_macro_cancels_it_and_releases_its_keys() {
+        let mut macros = Macros::parse("F12 press 5, press 7, wait 10, release 7").unwrap();
+        let started = macros.trigger(0);
+
+        assert_eq!(play(&mut macros, started, 3), [(1, (5, 1)), (1, (7, 1))]);
+
+        let cancelled = macros.trigger(0);
+
+        assert_eq!(play(&mut macros, cancelled, 20), [(0, (5, 0)), (0, (7, 0))]);
+    }
+
+    #[test]
+    fn keys_left_held_at_the_end_are_released() {
+        let mut macros = Macros::parse("F12 press A").unwrap();
+        let started = macros.trigger(0);
+
+        assert_eq!(
+            play(&mut macros, started, 5),
+            [(1, (0xA, 1)), (1, (0xA, 0))]
+        );
+    }
+
+    #[test]
+    fn bad_lines_are_reported_with_their_number() {
+        let errors = [
+            ("F12 press 5\nF11 press G", "line 2: invalid keypad key `G`"),
+            ("F12", "line 1: expected `<host key> <steps>`"),
+            ("Nope press 5", "line 1: unknown host key `Nope`"),
+            ("F12 hold 5", "line 1: unknown step `hold`"),
+            ("F12 wait soon", "line 1: invalid frame count `soon`"),
+        ];
+
+        for (source, error) in errors {
+            assert_eq!(Macros::parse(source).err().as_deref(), Some(error));
+        }
+
+        assert_eq!(Macros::parse("# nothing\n\n").unwrap().macros.len(), 0);
+    }
+}
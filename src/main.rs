@@ -1,124 +1,1790 @@
-use std::{fs::File, io::Read};
+use std::{
+    cell::RefCell,
+    env, fs,
+    fs::File,
+    io::{self, LineWriter, Write},
+    path::{Path, PathBuf},
+    process,
+    rc::Rc,
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
-use buzzer::Buzzer;
-use cpu::CPU;
-use keypad::Keypad;
+use buzzer::{BeepSound, Buzzer};
+use chip8_emu::{
+    breakpoint::BreakReason,
+    callgraph::Symbols,
+    cheats::Cheats,
+    clock::Clock,
+    cpu::{CpuError, CpuMessage, UnknownOpcodePolicy},
+    database,
+    debugger::{self, Command},
+    disassembler::{self, disassemble},
+    display::{DisplayMode, SpriteDraw, TextStyle, MAX_PIXELS},
+    emulator::{Emulator, EmulatorOptions},
+    filter::Filter,
+    font::{self, Font},
+    headless::{self, Ending},
+    key_profile::{self, KeyBinding, KeyOverrides},
+    poke::Poke,
+    sidecar::Sidecar,
+    speed::{resolve_speed, SpeedOverrides},
+    timing::{Timing, DEFAULT_REFRESH_RATE},
+    trace::TraceLine,
+};
+use cli::Options;
+use config::Config;
+use error_screen::Fault;
+use frame_stats::FrameStats;
+use keypad::{host_key, Keypad};
+use macros::Macros;
+use palette::Palette;
 use pixels::{Pixels, SurfaceTexture};
-use rodio::OutputStream;
+use replay::Replay;
+use rom_file::{check_rom_size, pick_rom, read_rom};
+use window_geometry::{Monitor, WindowGeometry};
 use winit::{
     dpi::PhysicalSize,
-    event::{Event, VirtualKeyCode},
+    event::{Event, StartCause, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    monitor::MonitorHandle,
+    window::{Fullscreen, Window, WindowBuilder},
 };
 use winit_input_helper::WinitInputHelper;
 
-mod bus;
+mod audio;
 mod buzzer;
-mod cpu;
+mod cli;
+mod compare;
+mod config;
+mod error_screen;
+mod frame_stats;
+mod fuzzy;
+mod hex_editor;
+mod info;
 mod keypad;
+mod macros;
+mod palette;
+mod poll_overlay;
+mod replay;
+mod rom_file;
+mod screenshot;
+mod smoke;
+mod state_dump;
+mod subcommands;
+mod test_pattern;
+mod thumbnails;
+mod window_geometry;
 
-const WIDTH: u32 = 64;
-const HEIGHT: u32 = 32;
+const SCALE: f64 = 10.0;
+const AUDIT_CAPACITY: usize = 4096;
+const HIGHLIGHT_FRAMES: u32 = 8;
+const WATCHDOG_EXIT_CODE: i32 = 3;
+const CONTINUE_KEY: VirtualKeyCode = VirtualKeyCode::F10;
+const RESET_KEY: VirtualKeyCode = VirtualKeyCode::F2;
+// R is keypad D while the game runs, so it only resets from the pause menu.
+const PAUSED_RESET_KEY: VirtualKeyCode = VirtualKeyCode::R;
+const PAUSE_KEY: VirtualKeyCode = VirtualKeyCode::P;
+const STEP_KEY: VirtualKeyCode = VirtualKeyCode::N;
+const SAVE_STATE_KEY: VirtualKeyCode = VirtualKeyCode::F5;
+const LOAD_STATE_KEY: VirtualKeyCode = VirtualKeyCode::F9;
+const DUMP_KEY: VirtualKeyCode = VirtualKeyCode::D;
+const POLL_OVERLAY_KEY: VirtualKeyCode = VirtualKeyCode::Tab;
+const REPLAY_KEY: VirtualKeyCode = VirtualKeyCode::F11;
+// With Alt, F11 being the replay.
+const FULLSCREEN_KEY: VirtualKeyCode = VirtualKeyCode::Return;
+
+// In the pause menu, where the keypad isn't read.
+const CHEAT_TOGGLE_KEYS: [VirtualKeyCode; 9] = [
+    VirtualKeyCode::Key1,
+    VirtualKeyCode::Key2,
+    VirtualKeyCode::Key3,
+    VirtualKeyCode::Key4,
+    VirtualKeyCode::Key5,
+    VirtualKeyCode::Key6,
+    VirtualKeyCode::Key7,
+    VirtualKeyCode::Key8,
+    VirtualKeyCode::Key9,
+];
 
 struct Chip8 {
-    cpu: CPU,
+    emulator: Emulator,
     keypad: Keypad,
+    cheats: Cheats,
+    pokes: Vec<Poke>,
+    macros: Macros,
+    paused: bool,
+    stopped: bool,
+    clock: Clock,
+    palette: Palette,
+    inverted: bool,
+    filter: Filter,
+    scaled: Vec<u8>,
+    highlight_draws: bool,
+    highlight: Option<(SpriteDraw, u32)>,
+    frame_stats: FrameStats,
+    fault: Option<Fault>,
+    poll_overlay: bool,
+    // Filled by the emulator's frame callback.
+    replay: Rc<RefCell<Replay>>,
+    // The colors the frame buffer was last drawn in, none when it has to be
+    // drawn again from scratch.
+    drawn_palette: Option<Palette>,
+    overlaid: bool,
+    dropped_keys: u64,
+    // Names for the profiler's report.
+    symbols: Symbols,
+    // The resolution the pixels buffer was last sized for.
+    buffer_mode: DisplayMode,
 }
 
 impl Chip8 {
-    fn new() -> Self {
+    fn new(options: EmulatorOptions, cheats: Cheats, replay_seconds: u32) -> Self {
+        let mut emulator = Emulator::new(options);
+        let replay = Rc::new(RefCell::new(Replay::new(
+            replay_seconds,
+            options.refresh_rate,
+        )));
+        let recorder = Rc::clone(&replay);
+
+        emulator.on_frame(move |_, emulator| {
+            recorder
+                .borrow_mut()
+                .push(emulator.display(), emulator.display_mode());
+        });
+
         Chip8 {
-            cpu: CPU::new(),
+            emulator,
             keypad: Keypad::new(),
+            cheats,
+            pokes: Vec::new(),
+            macros: Macros::default(),
+            paused: false,
+            stopped: false,
+            clock: Clock::new(Instant::now()),
+            palette: Palette::DEFAULT,
+            inverted: false,
+            filter: Filter::None,
+            scaled: Vec::new(),
+            highlight_draws: false,
+            highlight: None,
+            frame_stats: FrameStats::new(),
+            fault: None,
+            poll_overlay: false,
+            replay,
+            drawn_palette: None,
+            overlaid: false,
+            dropped_keys: 0,
+            symbols: Symbols::default(),
+            buffer_mode: DisplayMode::LORES,
+        }
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.update_clock();
+    }
+
+    fn set_stopped(&mut self, stopped: bool) {
+        self.stopped = stopped;
+        self.update_clock();
+    }
+
+    // Losing the focus and sitting on a breakpoint both freeze the timers.
+    fn update_clock(&mut self) {
+        if self.paused || self.stopped {
+            self.emulator.pause_clock();
+        } else {
+            self.emulator.resume_clock();
+            self.clock.restart(Instant::now());
+            self.frame_stats.restart();
+        }
+    }
+
+    fn print_messages(&mut self) {
+        for message in self.emulator.take_messages() {
+            match message {
+                CpuMessage::Debug { .. } => println!("{}", message),
+                _ => eprintln!("warning: {}", message),
+            }
+        }
+    }
+
+    // Returns false once the debugger asked to quit.
+    fn run_command(&mut self, command: &Command) -> bool {
+        if let Some(output) = self.emulator.debug(command) {
+            print!("{}", output);
+        }
+
+        self.print_messages();
+
+        if let (None, Some(error)) = (&self.fault, self.emulator.fault()) {
+            self.report_fault(error);
+        }
+
+        match command {
+            Command::Quit => return false,
+            Command::Step | Command::Continue if self.fault.is_some() => {
+                println!("The CPU faulted, press F2 to reset");
+            }
+            Command::Step => self.set_stopped(true),
+            Command::Continue => self.set_stopped(false),
+            _ => {}
+        }
+
+        true
+    }
+
+    fn report_break(&self, reason: BreakReason) {
+        let emulator = &self.emulator;
+        let (pc, cause) = match reason {
+            BreakReason::Opcode(index) => (
+                emulator.pc(),
+                format!("--break-op {}", emulator.opcode_breakpoints()[index]),
+            ),
+            BreakReason::Address(address) => (address, format!("break 0x{:03X}", address)),
+            BreakReason::Event(event, address) => (address, event.to_string()),
+            BreakReason::Watch { address, pc } => (pc, format!("watch 0x{:03X}", address)),
+            BreakReason::RunFor => (emulator.pc(), "run-for".to_string()),
+        };
+        let opcode = (emulator.read_memory(pc) as u16) << 8 | emulator.read_memory(pc + 1) as u16;
+
+        println!(
+            "Stopped at 0x{:03X} on {:04X} {} ({}), press F10 to continue",
+            pc,
+            opcode,
+            disassemble(opcode),
+            cause
+        );
+    }
+
+    // The emulation stays stopped until F2 resets it.
+    fn report_fault(&mut self, error: CpuError) {
+        let (pc, opcode) = self
+            .emulator
+            .history()
+            .last()
+            .map_or((self.emulator.pc(), 0), |line| (line.pc, line.opcode));
+        let fault = Fault {
+            message: error.to_string(),
+            pc,
+            opcode,
+        };
+
+        eprintln!(
+            "CPU error at 0x{:03X} on {:04X}: {}",
+            fault.pc, fault.opcode, fault.message
+        );
+        eprint!("{}", self.emulator.disassemble(fault.pc, 8));
+        eprintln!("Press F2 to reset, D to dump the state, Esc to quit");
+
+        self.fault = Some(fault);
+        self.set_stopped(true);
+    }
+
+    // The pokes and cheat patches go back in, the ROM was loaded afresh.
+    fn reset(&mut self) {
+        self.emulator.reset();
+        self.apply_pokes();
+        self.fault = None;
+        self.set_stopped(false);
+    }
+
+    fn read_fault_input(&mut self, input: &WinitInputHelper) {
+        if input.key_pressed(RESET_KEY) {
+            self.reset();
+        } else if input.key_pressed(DUMP_KEY) {
+            match state_dump::write(&self.emulator) {
+                Ok(path) => eprintln!("wrote the machine state to {}", path),
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+    }
+
+    fn start(&mut self, rom_data: &[u8], pokes: &[Poke]) {
+        self.emulator.load_rom(rom_data).unwrap_or_else(|err| {
+            eprintln!("Could not load the ROM: {}", err);
+            process::exit(1);
+        });
+
+        self.pokes = pokes.to_vec();
+        self.apply_pokes();
+    }
+
+    fn apply_pokes(&mut self) {
+        for poke in &self.pokes {
+            poke.apply(&mut self.emulator);
+        }
+
+        self.cheats.apply_patches(&mut self.emulator);
+    }
+
+    fn send_keys(&mut self, changes: impl IntoIterator<Item = (u8, bool)>) {
+        for (key, pressed) in changes {
+            if pressed {
+                self.emulator.press_key(key);
+            } else {
+                self.emulator.release_key(key);
+            }
+        }
+
+        let dropped = self.emulator.dropped_key_signals();
+
+        if dropped > self.dropped_keys {
+            eprintln!(
+                "warning: keypad bus full, dropped {} key signals",
+                dropped - self.dropped_keys
+            );
+        }
+        self.dropped_keys = dropped;
+    }
+
+    fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+        self.scaled = vec![0; MAX_PIXELS * filter.factor() * filter.factor()];
+    }
+
+    // The filter upscales before pixels does the rest of the scaling.
+    fn buffer_size(&self) -> (u32, u32) {
+        let mode = self.emulator.display_mode();
+        let factor = self.filter.factor();
+
+        ((mode.width * factor) as u32, (mode.height * factor) as u32)
+    }
+
+    // 00FE/00FF switch resolution at runtime, the pixels buffer follows.
+    // Returns its new size when the mode changed since the last call.
+    fn take_buffer_resize(&mut self) -> Option<(u32, u32)> {
+        let mode = self.emulator.display_mode();
+
+        if mode == self.buffer_mode {
+            return None;
+        }
+
+        self.buffer_mode = mode;
+        self.drawn_palette = None;
+        Some(self.buffer_size())
+    }
+
+    fn active_palette(&self) -> Palette {
+        if self.inverted {
+            self.palette.inverted()
+        } else {
+            self.palette
+        }
+    }
+
+    fn copy_screenshot(&self) {
+        let png = screenshot::encode_png(
+            self.emulator.display(),
+            self.emulator.display_mode(),
+            self.active_palette(),
+            screenshot::SCALE,
+        );
+
+        match screenshot::copy_to_clipboard(&png) {
+            Ok(message) => println!("{}", message),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+
+    fn save_state(&self, path: &Path) {
+        match fs::write(path, self.emulator.save_state()) {
+            Ok(()) => println!("Saved the state to {}", path.display()),
+            Err(err) => eprintln!("Could not save the state to {}: {}", path.display(), err),
+        }
+    }
+
+    // A state saved before a fault brings the game back from it.
+    fn load_state(&mut self, path: &Path) {
+        let loaded = fs::read(path)
+            .map_err(|err| err.to_string())
+            .and_then(|state| self.emulator.load_state(&state));
+
+        match loaded {
+            Ok(()) => {
+                println!("Loaded the state from {}", path.display());
+
+                if self.fault.take().is_some() {
+                    self.set_stopped(false);
+                }
+            }
+            Err(err) => eprintln!("Could not load {}: {}", path.display(), err),
         }
     }
 
-    fn start(&mut self, rom_data: Vec<u8>) {
-        self.cpu.load_rom(rom_data);
+    // Encoding a few hundred frames takes a moment, the game keeps running
+    // meanwhile.
+    fn save_replay(&self) {
+        let clip = self
+            .replay
+            .borrow()
+            .clip(self.active_palette(), self.emulator.refresh_rate());
+
+        thread::spawn(move || {
+            let seconds = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs());
+            let path = format!("chip8-replay-{}.gif", seconds);
+
+            match fs::write(&path, clip.encode()) {
+                Ok(()) => println!("saved {} frames to {}", clip.frame_count(), path),
+                Err(err) => eprintln!("failed to write {}: {}", path, err),
+            }
+        });
     }
 
-    fn draw(&self, frame: &mut [u8]) {
-        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-            let x = (i % WIDTH as usize) as i16;
-            let y = (i / WIDTH as usize) as i16;
+    // Only the rows the CPU touched are converted again, unless something is
+    // drawn over the picture now or was last time, or the colors changed.
+    fn draw(&mut self, frame: &mut [u8]) {
+        let palette = self.active_palette();
+
+        let mode = self.emulator.display_mode();
+        let mut dirty = self.emulator.take_dirty_rows();
+        let overlaid = self.highlight.is_some() || self.poll_overlay || self.fault.is_some();
+
+        if overlaid || self.overlaid || self.drawn_palette != Some(palette) {
+            dirty = u64::MAX;
+        }
+
+        self.overlaid = overlaid;
+        self.drawn_palette = Some(palette);
 
-            let chip8_pixel = self.cpu.display[(y * WIDTH as i16 + x) as usize];
+        // The scaling filters look at the rows above and below.
+        if self.filter != Filter::None {
+            dirty |= dirty << 1 | dirty >> 1;
+        }
+
+        self.filter
+            .apply(self.emulator.display(), mode, &mut self.scaled);
+
+        let factor = self.filter.factor();
+        let width = mode.width * factor;
+
+        for row in (0..mode.height).filter(|row| dirty & 1 << row != 0) {
+            let span = row * factor * width..(row + 1) * factor * width;
+            let pixels = frame[span.start * 4..span.end * 4].chunks_exact_mut(4);
 
-            let color = if chip8_pixel == 1 {
-                [0x5e, 0x48, 0xe8, 0xff]
+            for ((i, pixel), chip8_pixel) in span.clone().zip(pixels).zip(&self.scaled[span]) {
+                let mut color = palette.color(*chip8_pixel);
+
+                if let Some((draw, frames)) = self.highlight {
+                    if draw.contains(i % width / factor, i / width / factor) {
+                        let amount = 0.6 * frames as f32 / HIGHLIGHT_FRAMES as f32;
+
+                        color = Palette::highlight(color, amount);
+                    }
+                }
+
+                pixel.copy_from_slice(&color);
+            }
+        }
+
+        if self.poll_overlay {
+            poll_overlay::render(
+                frame,
+                width,
+                self.emulator.recently_polled_keys(),
+                self.emulator.is_waiting_for_key(),
+                |key| self.keypad.is_held(key),
+            );
+        }
+
+        if let Some(fault) = &self.fault {
+            error_screen::render(frame, width, fault);
+        }
+    }
+
+    fn show_test_pattern(&mut self, input: &WinitInputHelper) {
+        // Nothing runs on the CPU, the keys only light up the pattern.
+        self.keypad.read(input);
+
+        let mode = self.emulator.display_mode();
+        let font = self.emulator.font();
+
+        test_pattern::render(self.emulator.display_mut(), mode, &font, &self.keypad);
+    }
+
+    // A new draw restarts the highlight, otherwise the old one fades out.
+    fn update_highlight(&mut self) {
+        match self.emulator.take_last_draw() {
+            Some(draw) if self.highlight_draws => self.highlight = Some((draw, HIGHLIGHT_FRAMES)),
+            _ => {
+                self.highlight = self
+                    .highlight
+                    .filter(|(_, frames)| *frames > 1)
+                    .map(|(draw, frames)| (draw, frames - 1));
+            }
+        }
+    }
+
+    // Called once per input update, key presses would repeat otherwise.
+    fn read_input(&mut self, input: &WinitInputHelper) {
+        if self.paused {
+            return;
+        }
+
+        if self.fault.is_some() {
+            self.read_fault_input(input);
+            return;
+        }
+
+        if input.key_pressed(RESET_KEY) {
+            self.reset();
+            return;
+        }
+
+        if self.stopped {
+            if input.key_pressed(CONTINUE_KEY) || input.key_pressed(PAUSE_KEY) {
+                self.emulator.continue_from_break();
+                self.set_stopped(false);
+            } else if input.key_pressed(STEP_KEY) {
+                self.step();
+            } else if input.key_pressed(PAUSED_RESET_KEY) {
+                self.reset();
             } else {
-                [0x48, 0xb2, 0xe8, 0xff]
+                self.toggle_cheats(input);
+            }
+
+            return;
+        }
+
+        if input.key_pressed(PAUSE_KEY) {
+            self.print_pause_menu();
+            self.set_stopped(true);
+            return;
+        }
+
+        let changes = self.keypad.read(input);
+
+        self.send_keys(changes);
+
+        let changes = self.macros.check_triggers(input);
+
+        self.send_keys(changes);
+    }
+
+    fn print_pause_menu(&self) {
+        println!("Paused, press P to resume, N to run one instruction or R to reset");
+
+        let cheats = self.cheats.entries().iter().take(CHEAT_TOGGLE_KEYS.len());
+
+        for (i, cheat) in cheats.enumerate() {
+            let state = if cheat.enabled { "on" } else { "off" };
+
+            println!("  {} toggles \"{}\" ({})", i + 1, cheat.name, state);
+        }
+    }
+
+    fn toggle_cheats(&mut self, input: &WinitInputHelper) {
+        for (i, key) in CHEAT_TOGGLE_KEYS.iter().enumerate() {
+            if input.key_pressed(*key) {
+                if let Some(cheat) = self.cheats.toggle(i, &mut self.emulator) {
+                    let state = if cheat.enabled { "on" } else { "off" };
+
+                    println!("Cheat \"{}\" {}", cheat.name, state);
+                }
+            }
+        }
+    }
+
+    // One instruction, breakpoints or not. The timers stay where they are.
+    fn step(&mut self) {
+        let result = self.emulator.step();
+
+        self.print_messages();
+
+        match result {
+            Ok(()) => print!("{}", self.emulator.disassemble(self.emulator.pc(), 1)),
+            Err(error) => self.report_fault(error),
+        }
+    }
+
+    fn tick(&mut self, buzzer: &mut Buzzer) {
+        if self.paused || self.stopped {
+            buzzer.update(true);
+            return;
+        }
+
+        let frame_duration = self.emulator.frame_duration();
+
+        for _ in 0..self.clock.tick(Instant::now(), frame_duration) {
+            let turbo = self.keypad.frame();
+            let macro_keys = self.macros.frame();
+
+            self.send_keys(turbo.into_iter().chain(macro_keys));
+
+            let started = Instant::now();
+            let cycles = self.emulator.cycles();
+
+            let result = self.emulator.run_frame();
+
+            self.print_messages();
+
+            if let Err(error) = result {
+                self.report_fault(error);
+                break;
+            }
+
+            for event in self.emulator.sound_events() {
+                buzzer.handle(event);
+            }
+
+            self.frame_stats.record_frame(
+                started,
+                frame_duration,
+                started.elapsed(),
+                self.emulator.cycles() - cycles,
+            );
+            self.cheats.apply(&mut self.emulator);
+            self.update_highlight();
+
+            if let Some(reason) = self.emulator.break_reason() {
+                self.report_break(reason);
+                self.set_stopped(true);
+                break;
+            }
+        }
+
+        buzzer.update(false);
+    }
+}
+
+fn check_pokes(pokes: &[Poke], rom_len: usize, force: bool) {
+    for poke in pokes {
+        if !force {
+            if let Err(err) = poke.check_rom_area(rom_len) {
+                eprintln!("Refusing --poke: {} (use --force to allow)", err);
+                process::exit(2);
+            }
+        }
+
+        println!(
+            "Poke 0x{:03X}..0x{:03X} = 0x{:02X} ({} bytes)",
+            poke.addresses.start,
+            poke.addresses.end,
+            poke.value,
+            poke.addresses.len()
+        );
+    }
+}
+
+fn data_dir() -> Option<PathBuf> {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .map(|dir| dir.join("chip8-emu"))
+}
+
+fn speed_overrides_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("speeds.txt"))
+}
+
+fn load_speed_overrides() -> SpeedOverrides {
+    let source = match speed_overrides_path().and_then(|path| fs::read_to_string(path).ok()) {
+        Some(source) => source,
+        None => return SpeedOverrides::default(),
+    };
+
+    SpeedOverrides::parse(&source).unwrap_or_else(|err| {
+        eprintln!("warning: ignoring saved speeds: {}", err);
+        SpeedOverrides::default()
+    })
+}
+
+fn save_speed_overrides(overrides: &SpeedOverrides) {
+    let path = match speed_overrides_path() {
+        Some(path) => path,
+        None => {
+            eprintln!("warning: no data directory to save the speed in");
+            return;
+        }
+    };
+
+    let result = fs::create_dir_all(path.parent().unwrap())
+        .and_then(|_| fs::write(&path, overrides.to_string()));
+
+    match result {
+        Ok(()) => println!("Saved speed to {}", path.display()),
+        Err(err) => eprintln!(
+            "warning: could not save speed to {}: {}",
+            path.display(),
+            err
+        ),
+    }
+}
+
+fn window_geometry_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("window.txt"))
+}
+
+fn load_window_geometry() -> WindowGeometry {
+    let source = match window_geometry_path().and_then(|path| fs::read_to_string(path).ok()) {
+        Some(source) => source,
+        None => return WindowGeometry::default(),
+    };
+
+    WindowGeometry::parse(&source).unwrap_or_else(|err| {
+        eprintln!("warning: ignoring saved window position: {}", err);
+        WindowGeometry::default()
+    })
+}
+
+// A fullscreen window sits at the corner of its monitor, that's no place to
+// come back to. It keeps the position and size of the window it left instead.
+fn window_geometry(window: &Window, windowed: &WindowGeometry) -> WindowGeometry {
+    let monitor = window.current_monitor().and_then(|monitor| monitor.name());
+
+    if window.fullscreen().is_some() {
+        return WindowGeometry {
+            monitor,
+            fullscreen: true,
+            ..windowed.clone()
+        };
+    }
+
+    let size = window.inner_size();
+
+    WindowGeometry {
+        // Wayland doesn't tell windows where they are.
+        position: window
+            .outer_position()
+            .ok()
+            .map(|position| (position.x, position.y)),
+        monitor,
+        size: Some((size.width, size.height)),
+        fullscreen: false,
+    }
+}
+
+// Saved on every exit without a message, unlike the opt-in speed and keys.
+fn save_window_geometry(window: &Window, windowed: &WindowGeometry) {
+    let Some(path) = window_geometry_path() else {
+        return;
+    };
+    let geometry = window_geometry(window, windowed);
+    let result = fs::create_dir_all(path.parent().unwrap())
+        .and_then(|_| fs::write(&path, geometry.to_string()));
+
+    if let Err(err) = result {
+        eprintln!(
+            "warning: could not save the window position to {}: {}",
+            path.display(),
+            err
+        );
+    }
+}
+
+// Line buffered, so the trace is complete up to a crash or exit.
+fn open_trace(path: &str) -> LineWriter<File> {
+    File::create(path)
+        .map(LineWriter::new)
+        .unwrap_or_else(|err| {
+            eprintln!("Could not create {}: {}", path, err);
+            process::exit(1);
+        })
+}
+
+fn state_path(rom: &str) -> Option<PathBuf> {
+    (rom != "-").then(|| PathBuf::from(format!("{}.state", rom)))
+}
+
+// Without a config file everything keeps its default, a broken one is fatal
+// like a broken sidecar.
+fn load_config() -> Config {
+    let path = match Config::path() {
+        Some(path) if path.exists() => path,
+        _ => return Config::default(),
+    };
+
+    let config = fs::read_to_string(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|source| Config::parse(&source))
+        .unwrap_or_else(|err| {
+            eprintln!("Invalid config file {}: {}", path.display(), err);
+            process::exit(1);
+        });
+
+    println!("Settings from {}", path.display());
+
+    config
+}
+
+// Headless runs keep stdout for the display.
+fn print_setting(options: &Options, message: String) {
+    if options.headless {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+}
+
+fn sidecar_path(rom: &str) -> Option<PathBuf> {
+    (rom != "-").then(|| PathBuf::from(format!("{}.toml", rom)))
+}
+
+// A broken sidecar is fatal, it was written for this ROM on purpose.
+fn load_sidecar(options: &Options) -> Sidecar {
+    let path = match sidecar_path(&options.rom) {
+        Some(path) if path.exists() => path,
+        _ => return Sidecar::default(),
+    };
+
+    let sidecar = fs::read_to_string(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|source| Sidecar::parse(&source))
+        .unwrap_or_else(|err| {
+            eprintln!("Invalid sidecar file {}: {}", path.display(), err);
+            process::exit(1);
+        });
+
+    print_setting(options, format!("Settings from {}", path.display()));
+
+    sidecar
+}
+
+fn save_sidecar(rom: &str, sidecar: &Sidecar) {
+    let path = sidecar_path(rom).unwrap();
+
+    match fs::write(&path, sidecar.to_string()) {
+        Ok(()) => println!("Saved settings to {}", path.display()),
+        Err(err) => eprintln!(
+            "warning: could not save settings to {}: {}",
+            path.display(),
+            err
+        ),
+    }
+}
+
+fn pick_speed(options: &Options, rom_data: &[u8], sidecar: &Sidecar) -> u32 {
+    let hash = database::rom_hash(rom_data);
+    let mut overrides = load_speed_overrides();
+    let database_speed = database::lookup(rom_data).and_then(|info| info.cycles_per_frame);
+
+    let (speed, source) = resolve_speed(
+        options.speed,
+        overrides.get(&hash),
+        sidecar.speed,
+        database_speed,
+    );
+
+    print_setting(
+        options,
+        format!("Running at {} cycles per frame ({})", speed, source),
+    );
+
+    if options.remember_speed {
+        overrides.set(&hash, speed);
+        save_speed_overrides(&overrides);
+    }
+
+    speed
+}
+
+fn key_overrides_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("keys.txt"))
+}
+
+fn load_key_overrides() -> KeyOverrides {
+    let source = match key_overrides_path().and_then(|path| fs::read_to_string(path).ok()) {
+        Some(source) => source,
+        None => return KeyOverrides::default(),
+    };
+
+    KeyOverrides::parse(&source).unwrap_or_else(|err| {
+        eprintln!("warning: ignoring saved keys: {}", err);
+        KeyOverrides::default()
+    })
+}
+
+fn save_key_overrides(overrides: &KeyOverrides) {
+    let path = match key_overrides_path() {
+        Some(path) => path,
+        None => {
+            eprintln!("warning: no data directory to save the keys in");
+            return;
+        }
+    };
+
+    let result = fs::create_dir_all(path.parent().unwrap())
+        .and_then(|_| fs::write(&path, overrides.to_string()));
+
+    match result {
+        Ok(()) => println!("Saved keys to {}", path.display()),
+        Err(err) => eprintln!(
+            "warning: could not save keys to {}: {}",
+            path.display(),
+            err
+        ),
+    }
+}
+
+// The ROM database remaps the default keys, then the sidecar file, the saved
+// keys and --map, which has the last word. Returns the bindings it applied.
+fn apply_key_profile(
+    keypad: &mut Keypad,
+    options: &Options,
+    rom_data: &[u8],
+    sidecar: &Sidecar,
+) -> Vec<KeyBinding> {
+    let hash = database::rom_hash(rom_data);
+    let mut overrides = load_key_overrides();
+    let database_keys: Vec<KeyBinding> = database::lookup(rom_data)
+        .map(|info| info.keys)
+        .unwrap_or_default()
+        .iter()
+        .map(|(host, key)| (host.to_string(), *key))
+        .collect();
+    let saved_keys = overrides.get(&hash).to_vec();
+
+    let mut sources = Vec::new();
+
+    for (bindings, source) in [
+        (&database_keys, "ROM database"),
+        (&sidecar.keys, "sidecar file"),
+        (&saved_keys, "saved keys"),
+        (&options.key_bindings, "--map"),
+    ] {
+        if !bindings.is_empty() {
+            sources.push(source);
+        }
+    }
+
+    let bindings = key_profile::merge(&[
+        &database_keys,
+        &sidecar.keys,
+        &saved_keys,
+        &options.key_bindings,
+    ]);
+
+    for (host, key) in &bindings {
+        match host_key(host) {
+            Some(host) => keypad.bind(host, *key),
+            None => eprintln!("warning: ignoring unknown host key `{}`", host),
+        }
+    }
+
+    if !sources.is_empty() {
+        println!("Key profile: {}", sources.join(" + "));
+    }
+
+    if options.remember_keys {
+        overrides.set(
+            &hash,
+            key_profile::merge(&[&saved_keys, &options.key_bindings]),
+        );
+        save_key_overrides(&overrides);
+    }
+
+    bindings
+}
+
+fn pick_refresh_rate(options: &Options, rom_data: &[u8], sidecar: &Sidecar) -> u32 {
+    let refresh_rate = options
+        .refresh_rate
+        .or(sidecar.refresh_rate)
+        .or_else(|| database::lookup(rom_data).and_then(|info| info.refresh_rate))
+        .unwrap_or(DEFAULT_REFRESH_RATE);
+
+    if refresh_rate != DEFAULT_REFRESH_RATE {
+        print_setting(options, format!("Running at {} Hz", refresh_rate));
+    }
+
+    refresh_rate
+}
+
+fn emulator_options(
+    options: &Options,
+    rom_data: Option<&[u8]>,
+    sidecar: &Sidecar,
+) -> EmulatorOptions {
+    let mut emulator_options = EmulatorOptions {
+        timing: options.timing.or(sidecar.timing).unwrap_or(Timing::Fixed),
+        quirks: options.quirks.or(sidecar.quirks).unwrap_or_default(),
+        ..Default::default()
+    };
+
+    if let Some(rom_data) = rom_data {
+        emulator_options.cycles_per_frame = pick_speed(options, rom_data, sidecar);
+        emulator_options.refresh_rate = pick_refresh_rate(options, rom_data, sidecar);
+    }
+
+    if let Some(font) = options.font {
+        emulator_options.font = font;
+    }
+
+    emulator_options
+}
+
+// The settings the window and --headless share, applied once the ROM and the
+// pokes are in.
+fn configure_emulator(emulator: &mut Emulator, options: &Options) {
+    emulator.set_strict_code_writes(options.strict_smc);
+    emulator.set_max_cycles(options.max_cycles);
+    emulator.set_debug_opcodes(options.debug_opcodes);
+
+    if options.skip_unknown_opcodes {
+        emulator.set_unknown_opcode_policy(UnknownOpcodePolicy::WarnAndSkip);
+    }
+
+    for event in &options.break_events {
+        emulator.add_break_event(*event);
+    }
+
+    for breakpoint in &options.break_ops {
+        emulator.add_opcode_breakpoint(breakpoint.clone());
+    }
+
+    if options.audit_memory {
+        emulator.enable_memory_audit(AUDIT_CAPACITY);
+    }
+
+    if options.measure_input_latency {
+        emulator.enable_input_latency();
+    }
+
+    if options.profile || options.profile_folded.is_some() {
+        emulator.enable_profiler();
+    }
+
+    if let Some(mut trace) = options.trace.as_deref().map(open_trace) {
+        emulator.set_tracer(Some(Box::new(move |line: &TraceLine| {
+            let _ = writeln!(trace, "{}", line.format());
+        })));
+    }
+}
+
+// Nothing from winit or rodio is touched, so this runs on machines with no
+// display or sound card.
+fn run_headless(
+    options: &Options,
+    cycles: u64,
+    rom_data: &[u8],
+    sidecar: &Sidecar,
+    cheats: &mut Cheats,
+) -> ! {
+    let mut emulator = Emulator::new(emulator_options(options, Some(rom_data), sidecar));
+
+    emulator.load_rom(rom_data).unwrap_or_else(|err| {
+        eprintln!("Could not load the ROM: {}", err);
+        process::exit(1);
+    });
+
+    for poke in &options.pokes {
+        poke.apply(&mut emulator);
+    }
+
+    cheats.apply_patches(&mut emulator);
+    configure_emulator(&mut emulator, options);
+
+    let ending = headless::run(&mut emulator, cycles, cheats, options.timeout);
+
+    // Debug output too goes to stderr, stdout is for the display.
+    for message in emulator.take_messages() {
+        match message {
+            CpuMessage::Debug { .. } => eprintln!("{}", message),
+            _ => eprintln!("warning: {}", message),
+        }
+    }
+
+    let code = match ending {
+        Ok(Ending::Finished) => 0,
+        Ok(Ending::WaitingForKey(cycles)) => {
+            eprintln!(
+                "warning: stopped after {} cycles, waiting for a key",
+                cycles
+            );
+            0
+        }
+        Ok(Ending::Stopped(reason)) => {
+            eprintln!(
+                "warning: stopped after {} cycles at 0x{:03X} ({:?})",
+                emulator.cycles(),
+                emulator.pc(),
+                reason
+            );
+            0
+        }
+        Ok(Ending::Watchdog(cycles)) => {
+            let reason = match options.max_cycles {
+                Some(max_cycles) if cycles >= max_cycles => "--max-cycles",
+                _ => "--timeout",
             };
 
-            pixel.copy_from_slice(&color);
+            eprintln!("Watchdog expired after {} cycles ({})", cycles, reason);
+            WATCHDOG_EXIT_CODE
+        }
+        Err(err) => {
+            eprintln!("Stopped after {} cycles: {}", emulator.cycles(), err);
+            process::exit(1);
+        }
+    };
+
+    match &options.dump {
+        Some(path) => headless::dump(&emulator, path).unwrap_or_else(|err| {
+            eprintln!("Could not write {}: {}", path.display(), err);
+            process::exit(1);
+        }),
+        None => print!("{}", emulator.display_text(TextStyle::Ascii)),
+    }
+
+    process::exit(code);
+}
+
+fn print_exit_reports(chip8: &Chip8, options: &Options) {
+    let emulator = &chip8.emulator;
+
+    if let Some(audit) = emulator.memory_audit() {
+        for access in audit.records() {
+            println!("{}", access);
+        }
+    }
+
+    if options.print_keys_on_exit {
+        print_used_keys(emulator, &chip8.keypad);
+    }
+
+    if emulator.skipped_opcodes() > 0 {
+        println!(
+            "Skipped {} unknown instructions",
+            emulator.skipped_opcodes()
+        );
+    }
+
+    if options.dump_display {
+        print!("{}", emulator.display_text(TextStyle::Ascii));
+    }
+
+    if options.frame_stats {
+        print!("{}", chip8.frame_stats);
+    }
+
+    if let Some(latency) = emulator.input_latency() {
+        print!("{}", latency);
+    }
+
+    if let Some(profiler) = emulator.profiler() {
+        if options.profile {
+            print!("{}", profiler.report(&chip8.symbols));
+        }
+
+        if let Some(path) = &options.profile_folded {
+            match fs::write(path, profiler.folded_stacks(&chip8.symbols)) {
+                Ok(()) => println!("Wrote folded call stacks to {}", path),
+                Err(err) => eprintln!("warning: could not write {}: {}", path, err),
+            }
         }
     }
+}
+
+fn print_used_keys(emulator: &Emulator, keypad: &Keypad) {
+    let keys = emulator.queried_keys();
+
+    if keys.is_empty() {
+        println!("The ROM never tested a key");
+    } else {
+        let names: Vec<String> = keys.iter().map(|key| format!("{:X}", key)).collect();
+
+        println!("Keys tested by the ROM: {}", names.join(" "));
+    }
 
-    fn tick(&mut self, input: &WinitInputHelper, buzzer: &mut Buzzer) {
-        self.keypad.read(input, self.cpu.get_keypad_bus());
-        buzzer.update(self.cpu.get_sound_timer() > 0);
+    if emulator.waited_for_key() {
+        println!("The ROM also waited for any key with FX0A");
+    }
 
-        self.cpu.cycle();
+    for key in keys.iter().filter(|key| !keypad.is_mapped(**key)) {
+        eprintln!("warning: key {:X} has no host key mapped to it", key);
     }
 }
 
-fn read_file(path: &str) -> Vec<u8> {
-    let mut file = File::open(path).unwrap();
-    let mut buffer = Vec::new();
+fn load_font(path: &str) -> Font {
+    let data = fs::read(path).unwrap_or_else(|err| {
+        eprintln!("Could not read font file {}: {}", path, err);
+        process::exit(1);
+    });
+
+    font::parse(&data).unwrap_or_else(|err| {
+        eprintln!("Invalid font file {}: {}", path, err);
+        process::exit(1);
+    })
+}
+
+fn load_cheats(path: &str) -> Cheats {
+    let source = fs::read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("Could not read cheat file {}: {}", path, err);
+        process::exit(1);
+    });
+
+    Cheats::parse(&source).unwrap_or_else(|err| {
+        eprintln!("Invalid cheat file {}: {}", path, err);
+        process::exit(1);
+    })
+}
 
-    file.read_to_end(&mut buffer).unwrap();
+fn load_symbols(path: &str) -> Symbols {
+    let source = fs::read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("Could not read symbol file {}: {}", path, err);
+        process::exit(1);
+    });
 
-    buffer
+    Symbols::parse(&source).unwrap_or_else(|err| {
+        eprintln!("Invalid symbol file {}: {}", path, err);
+        process::exit(1);
+    })
+}
+
+// Bad lines are skipped with a warning, or abort the run in strict mode.
+fn load_debug_script(path: &str, strict: bool) -> Vec<Command> {
+    let source = fs::read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("Could not read debug script {}: {}", path, err);
+        process::exit(1);
+    });
+    let (commands, warnings) = debugger::parse_script(&source, strict).unwrap_or_else(|err| {
+        eprintln!("Invalid debug script {}: {}", path, err);
+        process::exit(1);
+    });
+
+    for warning in warnings {
+        eprintln!("warning: {}: {}", path, warning);
+    }
+
+    commands
+}
+
+// The window owns the main thread, so stdin is read on its own and handed over.
+fn spawn_debugger_input() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        for line in io::stdin().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+fn load_macros(path: &str) -> Macros {
+    let source = fs::read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("Could not read macro file {}: {}", path, err);
+        process::exit(1);
+    });
+
+    Macros::parse(&source).unwrap_or_else(|err| {
+        eprintln!("Invalid macro file {}: {}", path, err);
+        process::exit(1);
+    })
+}
+
+fn load_rom(options: &Options) -> (String, Vec<u8>) {
+    let (name, rom_data) = read_rom(&options.rom, options.entry.as_deref(), options.assemble);
+
+    check_rom_size(&name, &rom_data);
+
+    let title = match database::lookup(&rom_data) {
+        Some(info) => info.to_string(),
+        None => name,
+    };
+
+    check_pokes(&options.pokes, rom_data.len(), options.force);
+
+    (title, rom_data)
 }
 
 fn main() {
-    let rom_data = read_file("roms/airplane.ch8");
+    if let Some(name) = env::args().nth(1) {
+        subcommands::run(&name, env::args().skip(2));
+    }
+
+    let mut options = cli::parse(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        eprintln!("{}", cli::USAGE);
+        process::exit(2);
+    });
+
+    if options.list_audio_devices {
+        let names = audio::device_names();
+
+        if names.is_empty() {
+            eprintln!("No audio output devices found");
+        }
+
+        for name in names {
+            println!("{}", name);
+        }
+
+        process::exit(0);
+    }
+
+    if let Some(name) = &options.pick {
+        options.rom = pick_rom(options.rom_dir.as_deref().unwrap_or("roms"), name);
+    }
 
+    if let Some(path) = &options.font_file {
+        options.font = Some(load_font(path));
+    }
+
+    let mut cheats = options
+        .cheats
+        .as_deref()
+        .map(load_cheats)
+        .unwrap_or_default();
+    let macros = options
+        .macros
+        .as_deref()
+        .map(load_macros)
+        .unwrap_or_default();
+    let symbols = options
+        .symbols
+        .as_deref()
+        .map(load_symbols)
+        .unwrap_or_default();
+    let debug_commands = options
+        .debug_script
+        .as_deref()
+        .map(|path| load_debug_script(path, options.debug_script_strict))
+        .unwrap_or_default();
+
+    let (title, rom_data, sidecar) = if options.test_pattern {
+        ("test pattern".to_string(), None, Sidecar::default())
+    } else {
+        let (title, rom_data) = load_rom(&options);
+
+        if options.disassemble {
+            print!("{}", disassembler::listing(&rom_data));
+            process::exit(0);
+        }
+
+        let sidecar = load_sidecar(&options);
+
+        if let (true, Some(cycles)) = (options.headless, options.cycles) {
+            run_headless(&options, cycles, &rom_data, &sidecar, &mut cheats);
+        }
+
+        (
+            sidecar.title.clone().unwrap_or(title),
+            Some(rom_data),
+            sidecar,
+        )
+    };
+
+    let config = load_config();
     let mut input = WinitInputHelper::new();
-    let (_stream, stream_handle) = OutputStream::try_default().unwrap();
+    let audio = audio::open(options.audio_device.as_deref());
+
+    if let (Some(audio), Some(_)) = (&audio, &options.audio_device) {
+        println!("Audio device: {}", audio.name);
+    }
+
+    let beep_sound = options
+        .beep_sound
+        .as_deref()
+        .and_then(|path| BeepSound::load_or_tone(path, options.beep_mode));
 
+    let window_title = format!("chip-8 by ganitzsh - {}", title);
     let event_loop = EventLoop::new();
+    // What the window was left at, updated whenever it goes fullscreen.
+    let mut windowed = load_window_geometry();
     let window = {
-        let size = PhysicalSize::new(WIDTH as f64 * 10.0, HEIGHT as f64 * 10.0);
+        let mode = DisplayMode::LORES;
+        let size = match windowed.size {
+            Some((width, height)) => PhysicalSize::new(width as f64, height as f64),
+            None => PhysicalSize::new(mode.width as f64 * SCALE, mode.height as f64 * SCALE),
+        };
+        let handles: Vec<MonitorHandle> = event_loop
+            .primary_monitor()
+            .into_iter()
+            .chain(event_loop.available_monitors())
+            .collect();
+        let monitors: Vec<Monitor> = handles.iter().map(Monitor::from_handle).collect();
 
-        WindowBuilder::new()
-            .with_title("chip-8 by ganitzsh")
+        // Small enough for hi-res at one window pixel per pixel.
+        let min_size = PhysicalSize::new(
+            DisplayMode::HIRES.width as u32,
+            DisplayMode::HIRES.height as u32,
+        );
+        let mut builder = WindowBuilder::new()
+            .with_title(&window_title)
             .with_inner_size(size)
-            .with_min_inner_size(size)
-            .with_max_inner_size(size)
-            .with_resizable(false)
-            .build(&event_loop)
-            .unwrap()
+            .with_min_inner_size(min_size);
+
+        if let Some(position) = windowed.placement(&monitors, size.cast()) {
+            builder = builder.with_position(position);
+        }
+
+        if windowed.fullscreen {
+            let monitor = handles
+                .into_iter()
+                .find(|handle| handle.name() == windowed.monitor);
+
+            builder = builder.with_fullscreen(Some(Fullscreen::Borderless(monitor)));
+        }
+
+        builder.build(&event_loop).unwrap()
+    };
+
+    let emulator_options = emulator_options(&options, rom_data.as_deref(), &sidecar);
+    let mut chip8 = Chip8::new(emulator_options, cheats, options.replay_seconds);
+    let high_contrast = options.high_contrast || sidecar.high_contrast == Some(true);
+
+    chip8.inverted = options.invert || sidecar.invert == Some(true);
+    chip8.highlight_draws = options.highlight_draws;
+    chip8.poll_overlay = options.show_polling;
+    chip8.macros = macros;
+    chip8.symbols = symbols;
+    chip8.set_filter(options.filter);
+    chip8.keypad = Keypad::with_host_keys(&config.host_keys);
+    chip8.palette = if high_contrast {
+        Palette::HIGH_CONTRAST
+    } else {
+        config.palette
     };
 
     let mut pixels = {
         let window_size = window.inner_size();
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        Pixels::new(WIDTH as u32, HEIGHT as u32, surface_texture).unwrap()
+        let (width, height) = chip8.buffer_size();
+
+        Pixels::new(width, height, surface_texture).unwrap()
     };
 
-    let mut chip8 = Chip8::new();
-    let mut buzzer = Buzzer::new(&stream_handle);
+    let mut buzzer = Buzzer::new(audio.as_ref().map(|audio| &audio.handle), beep_sound);
 
-    chip8.start(rom_data);
+    if let Some(rom_data) = rom_data {
+        let keys = apply_key_profile(&mut chip8.keypad, &options, &rom_data, &sidecar);
+
+        if options.write_sidecar {
+            let effective = Sidecar {
+                title: Some(title.clone()),
+                speed: Some(emulator_options.cycles_per_frame),
+                refresh_rate: Some(emulator_options.refresh_rate),
+                timing: Some(emulator_options.timing),
+                quirks: Some(emulator_options.quirks),
+                high_contrast: Some(high_contrast),
+                invert: Some(chip8.inverted),
+                keys,
+            };
+
+            save_sidecar(&options.rom, &effective);
+        }
+
+        chip8.start(&rom_data, &options.pokes);
+    }
+
+    configure_emulator(&mut chip8.emulator, &options);
+
+    for command in &debug_commands {
+        if !chip8.run_command(command) {
+            process::exit(0);
+        }
+    }
+
+    if options.start_paused && !options.test_pattern {
+        chip8.set_stopped(true);
+
+        if options.debugger {
+            print!("{}", chip8.emulator.disassemble(chip8.emulator.pc(), 1));
+        } else {
+            println!("Paused before the first instruction, press F10 to continue");
+        }
+    }
+
+    let debugger = options.debugger.then(spawn_debugger_input);
+    let mut title_state = (false, false);
+
+    if let Some((key, rate)) = options.turbo {
+        if !chip8.keypad.is_mapped(key) {
+            eprintln!("Invalid --turbo: keypad key {:X} has no host key", key);
+            process::exit(2);
+        }
+
+        chip8.keypad.set_turbo(key, rate);
+    }
+
+    state_dump::install();
+
+    let started = Instant::now();
 
     event_loop.run(move |event, _, control_flow| {
         if let Event::RedrawRequested(_) = event {
+            if let Some((width, height)) = chip8.take_buffer_resize() {
+                pixels.resize_buffer(width, height).unwrap();
+            }
+
+            let started = Instant::now();
+
             chip8.draw(pixels.get_frame_mut());
 
             pixels.render().unwrap();
+            chip8.frame_stats.record_render(started.elapsed());
+        }
+
+        if let Event::WindowEvent {
+            event: WindowEvent::Focused(focused),
+            ..
+        } = event
+        {
+            chip8.set_paused(!focused);
+        }
+
+        // The buffer keeps its size, pixels scales it by the largest whole
+        // factor that fits and letterboxes the rest. A minimized window
+        // reports 0x0, which the surface can't take.
+        if let Event::WindowEvent {
+            event: WindowEvent::Resized(size),
+            ..
+        } = event
+        {
+            if size.width > 0 && size.height > 0 {
+                pixels.resize_surface(size.width, size.height).unwrap();
+                window.request_redraw();
+            }
+        }
+
+        let input_updated = input.update(&event);
+
+        if state_dump::take_request() {
+            match state_dump::write(&chip8.emulator) {
+                Ok(path) => eprintln!("wrote the machine state to {}", path),
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+
+        for line in debugger.iter().flat_map(|debugger| debugger.try_iter()) {
+            match Command::parse(&line) {
+                Ok(Some(command)) if !chip8.run_command(&command) => {
+                    print_exit_reports(&chip8, &options);
+                    save_window_geometry(&window, &windowed);
+
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+                Ok(_) => {}
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+
+        if input_updated && (input.key_pressed(VirtualKeyCode::Escape) || input.quit()) {
+            print_exit_reports(&chip8, &options);
+            save_window_geometry(&window, &windowed);
+
+            *control_flow = ControlFlow::Exit;
+            return;
+        }
+
+        if (chip8.stopped, chip8.fault.is_some()) != title_state {
+            title_state = (chip8.stopped, chip8.fault.is_some());
+
+            if let Some(fault) = &chip8.fault {
+                window.set_title(&format!("{} [error: {}]", window_title, fault.message));
+                window.request_redraw();
+            } else if chip8.stopped {
+                window.set_title(&format!("{} [stopped]", window_title));
+            } else {
+                window.set_title(&window_title);
+            }
+        }
+
+        if input_updated && input.key_pressed(VirtualKeyCode::I) {
+            chip8.inverted = !chip8.inverted;
+            window.request_redraw();
+        }
+
+        if input_updated && input.key_pressed(POLL_OVERLAY_KEY) {
+            chip8.poll_overlay = !chip8.poll_overlay;
+            window.request_redraw();
+        }
+
+        if input_updated && input.held_control() && input.key_pressed(VirtualKeyCode::C) {
+            chip8.copy_screenshot();
+        }
+
+        if input_updated {
+            if let Some(path) = state_path(&options.rom) {
+                if input.key_pressed(SAVE_STATE_KEY) {
+                    chip8.save_state(&path);
+                } else if input.key_pressed(LOAD_STATE_KEY) {
+                    chip8.load_state(&path);
+                    window.request_redraw();
+                }
+            }
+        }
+
+        if input_updated && input.held_alt() && input.key_pressed(FULLSCREEN_KEY) {
+            let fullscreen = match window.fullscreen() {
+                Some(_) => None,
+                None => {
+                    windowed = window_geometry(&window, &windowed);
+                    Some(Fullscreen::Borderless(None))
+                }
+            };
+
+            window.set_fullscreen(fullscreen);
+        }
+
+        if input_updated && input.key_pressed(REPLAY_KEY) {
+            chip8.save_replay();
         }
 
-        if input.update(&event) {
-            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
-                *control_flow = ControlFlow::Exit;
-                return;
+        if options.test_pattern {
+            if input_updated {
+                chip8.show_test_pattern(&input);
+            }
+        } else {
+            if input_updated {
+                chip8.read_input(&input);
             }
+
+            chip8.tick(&mut buzzer);
+        }
+
+        let timed_out = options
+            .timeout
+            .is_some_and(|timeout| started.elapsed() >= timeout);
+
+        if chip8.emulator.watchdog_expired() || timed_out {
+            let reason = if timed_out {
+                "--timeout"
+            } else {
+                "--max-cycles"
+            };
+
+            eprintln!(
+                "Watchdog expired after {} cycles ({})",
+                chip8.emulator.cycles(),
+                reason
+            );
+            print_exit_reports(&chip8, &options);
+            save_window_geometry(&window, &windowed);
+
+            *control_flow = ControlFlow::ExitWithCode(WATCHDOG_EXIT_CODE);
+            return;
         }
 
-        chip8.tick(&input, &mut buzzer);
+        if chip8.paused || chip8.stopped || chip8.emulator.is_waiting_for_key() {
+            // The screen can't change until a key arrives or the emulator
+            // resumes, only wake up for the timers and the buzzer.
+            *control_flow =
+                ControlFlow::WaitUntil(Instant::now() + chip8.emulator.frame_duration());
 
-        window.request_redraw();
+            if let Event::NewEvents(StartCause::ResumeTimeReached { .. }) = event {
+                window.request_redraw();
+            }
+        } else {
+            *control_flow = ControlFlow::Poll;
+            window.request_redraw();
+        }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use chip8_emu::quirks::Quirks;
+
+    use super::*;
+
+    // Not in the ROM database.
+    const ROM: [u8; 2] = [0x12, 0x00];
+
+    #[test]
+    fn the_command_line_beats_the_sidecar_which_beats_the_defaults() {
+        let sidecar = Sidecar {
+            refresh_rate: Some(50),
+            timing: Some(Timing::Vip),
+            quirks: Some(Quirks::SCHIP),
+            ..Sidecar::default()
+        };
+        let mut options = Options::default();
+
+        let from_sidecar = emulator_options(&options, None, &sidecar);
+
+        assert_eq!(from_sidecar.timing, Timing::Vip);
+        assert_eq!(from_sidecar.quirks, Quirks::SCHIP);
+        assert_eq!(pick_refresh_rate(&options, &ROM, &sidecar), 50);
+
+        options.refresh_rate = Some(60);
+        options.timing = Some(Timing::Fixed);
+        options.quirks = Some(Quirks::XOCHIP);
+
+        let from_options = emulator_options(&options, None, &sidecar);
+
+        assert_eq!(from_options.timing, Timing::Fixed);
+        assert_eq!(from_options.quirks, Quirks::XOCHIP);
+        assert_eq!(pick_refresh_rate(&options, &ROM, &sidecar), 60);
+
+        let defaults = emulator_options(&Options::default(), None, &Sidecar::default());
+
+        assert_eq!(defaults.timing, Timing::Fixed);
+        assert_eq!(defaults.quirks, Quirks::default());
+        assert_eq!(
+            pick_refresh_rate(&Options::default(), &ROM, &Sidecar::default()),
+            DEFAULT_REFRESH_RATE
+        );
+    }
+
+    // The lit pixels of an RGBA frame `width` wide.
+    fn lit(frame: &[u8], width: usize, palette: Palette) -> Vec<(usize, usize)> {
+        frame
+            .chunks_exact(4)
+            .enumerate()
+            .filter(|(_, color)| *color == palette.color(1))
+            .map(|(i, _)| (i % width, i / width))
+            .collect()
+    }
+
+    #[test]
+    fn the_buffer_follows_the_cpu_into_hires_and_back() {
+        let mut chip8 = Chip8::new(EmulatorOptions::default(), Cheats::default(), 1);
+
+        // Hi-res, a pixel at 100, 50, then lo-res and a pixel at 10, 5.
+        chip8
+            .emulator
+            .load_rom(&[
+                0x00, 0xFF, 0xA2, 0x14, 0x60, 0x64, 0x61, 0x32, 0xD0, 0x11, 0x00, 0xFE, 0x60, 0x0A,
+                0x61, 0x05, 0xD0, 0x11, 0x12, 0x12, 0x80,
+            ])
+            .unwrap();
+        chip8.set_filter(Filter::Scale2x);
+
+        assert_eq!(chip8.take_buffer_resize(), None);
+        assert_eq!(chip8.buffer_size(), (128, 64));
+
+        // Stops before 00FE.
+        for _ in 0..5 {
+            chip8.emulator.step().unwrap();
+        }
+
+        assert_eq!(chip8.emulator.display_mode(), DisplayMode::HIRES);
+        assert_eq!(chip8.take_buffer_resize(), Some((256, 128)));
+        assert_eq!(chip8.take_buffer_resize(), None);
+
+        let mut frame = vec![0; 256 * 128 * 4];
+
+        chip8.draw(&mut frame);
+
+        assert_eq!(
+            lit(&frame, 256, chip8.palette),
+            [(200, 100), (201, 100), (200, 101), (201, 101)]
+        );
+
+        for _ in 0..4 {
+            chip8.emulator.step().unwrap();
+        }
+
+        assert_eq!(chip8.emulator.display_mode(), DisplayMode::LORES);
+        assert_eq!(chip8.take_buffer_resize(), Some((128, 64)));
+
+        let mut frame = vec![0; 128 * 64 * 4];
+
+        chip8.draw(&mut frame);
+
+        assert_eq!(
+            lit(&frame, 128, chip8.palette),
+            [(20, 10), (21, 10), (20, 11), (21, 11)]
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use std::ops::Range;
+
+use crate::cpu::MEMORY_SIZE;
+
+pub const FRAME_COUNTER_ADDRESS: u16 = 0x1F0;
+
+// What the instructions that read and write data (FX33, FX55, FX65 and the
+// sprite fetch of DXYN) see at an address. Instructions are always fetched
+// straight from RAM, and so is the font.
+pub trait MemoryBus {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+
+    // Called at the start of every CPU frame.
+    fn frame(&mut self) {}
+}
+
+impl MemoryBus for [u8; MEMORY_SIZE] {
+    fn read(&mut self, address: u16) -> u8 {
+        self[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self[address as usize] = value;
+    }
+}
+
+// A device answering for `range` instead of RAM. It gets the full address,
+// not the offset into the range.
+pub struct MappedRange {
+    pub range: Range<u16>,
+    pub device: Box<dyn MemoryBus>,
+}
+
+// One byte counting the frames since it was last written, wrapping at 256.
+// Below 0x200, so it can't overlap a ROM.
+#[derive(Default)]
+pub struct FrameCounter {
+    frames: u8,
+}
+
+impl FrameCounter {
+    pub fn range() -> Range<u16> {
+        FRAME_COUNTER_ADDRESS..FRAME_COUNTER_ADDRESS + 1
+    }
+}
+
+impl MemoryBus for FrameCounter {
+    fn read(&mut self, _address: u16) -> u8 {
+        self.frames
+    }
+
+    fn write(&mut self, _address: u16, value: u8) {
+        self.frames = value;
+    }
+
+    fn frame(&mut self) {
+        self.frames = self.frames.wrapping_add(1);
+    }
+}
@@ -0,0 +1,105 @@
+const HIGHLIGHT: [u8; 4] = [0xff, 0xc8, 0x30, 0xff];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    on: [u8; 4],
+    off: [u8; 4],
+}
+
+impl Palette {
+    pub const DEFAULT: Palette = Palette {
+        on: [0x5e, 0x48, 0xe8, 0xff],
+        off: [0x48, 0xb2, 0xe8, 0xff],
+    };
+
+    pub const HIGH_CONTRAST: Palette = Palette {
+        on: [0xff, 0xff, 0xff, 0xff],
+        off: [0x00, 0x00, 0x00, 0xff],
+    };
+
+    pub fn new(on: [u8; 4], off: [u8; 4]) -> Palette {
+        Palette { on, off }
+    }
+
+    pub fn inverted(self) -> Palette {
+        Palette {
+            on: self.off,
+            off: self.on,
+        }
+    }
+
+    // Mixes `amount` (0 to 1) of the highlight color into `color`.
+    pub fn highlight(color: [u8; 4], amount: f32) -> [u8; 4] {
+        let mut mixed = color;
+
+        for (channel, highlight) in mixed.iter_mut().zip(HIGHLIGHT).take(3) {
+            *channel = (*channel as f32 + (highlight as f32 - *channel as f32) * amount) as u8;
+        }
+
+        mixed
+    }
+
+    pub fn color(&self, pixel: u8) -> [u8; 4] {
+        if pixel == 1 {
+            self.on
+        } else {
+            self.off
+        }
+    }
+}
+
+// `#RRGGBB`, opaque.
+pub fn parse_color(text: &str) -> Result<[u8; 4], String> {
+    let invalid = || format!("invalid color `{}`, expected one like \"#5E48E8\"", text);
+    let hex = text
+        .strip_prefix('#')
+        .filter(|hex| hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(invalid)?;
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+
+    Ok([channel(0), channel(2), channel(4), 0xff])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inverting_swaps_the_colors_of_lit_and_dark_pixels() {
+        let pixels = [1, 0, 0, 1];
+
+        for palette in [Palette::DEFAULT, Palette::HIGH_CONTRAST] {
+            let inverted = palette.inverted();
+
+            for pixel in pixels {
+                assert_eq!(inverted.color(pixel), palette.color(1 - pixel));
+            }
+
+            assert!(inverted != palette);
+            assert!(inverted.inverted() == palette);
+        }
+
+        assert_eq!(Palette::HIGH_CONTRAST.color(1), [0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(
+            Palette::HIGH_CONTRAST.inverted().color(1),
+            [0x00, 0x00, 0x00, 0xff]
+        );
+    }
+
+    #[test]
+    fn colors_are_hex_triplets() {
+        assert_eq!(parse_color("#5E48e8"), Ok([0x5e, 0x48, 0xe8, 0xff]));
+
+        for text in ["5E48E8", "#5E48E", "#5E48E80", "#5G48E8"] {
+            assert!(parse_color(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn a_full_highlight_replaces_the_color_but_not_the_alpha() {
+        let color = [0x10, 0x20, 0x30, 0x80];
+
+        assert_eq!(Palette::highlight(color, 0.0), color);
+        assert_eq!(Palette::highlight(color, 1.0), [0xff, 0xc8, 0x30, 0x80]);
+    }
+}
@@ -0,0 +1,12 @@
+pub fn parse_number(text: &str) -> Option<u16> {
+    let text = text.trim();
+
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+pub fn parse_byte(text: &str) -> Option<u8> {
+    parse_number(text).and_then(|value| u8::try_from(value).ok())
+}
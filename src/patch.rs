@@ -0,0 +1,224 @@
+use crate::{cpu::PROGRAM_START, debugger::format_dump_row, disassembler::disassemble, poke::Poke};
+
+pub const ROW_SIZE: usize = 16;
+
+// A ROM being edited, addressed like memory, so 0x200 is its first byte and
+// `--set` takes the same addresses as `--poke`.
+pub struct PatchBuffer {
+    bytes: Vec<u8>,
+    original: Vec<u8>,
+    cursor: usize,
+    // The high nibble typed so far at the cursor.
+    pending: Option<u8>,
+}
+
+impl PatchBuffer {
+    pub fn new(rom: Vec<u8>) -> Self {
+        PatchBuffer {
+            original: rom.clone(),
+            bytes: rom,
+            cursor: 0,
+            pending: None,
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn cursor(&self) -> u16 {
+        PROGRAM_START + self.cursor as u16
+    }
+
+    // Addresses whose byte differs from the file as loaded.
+    pub fn changes(&self) -> Vec<u16> {
+        (0..self.bytes.len())
+            .filter(|i| self.bytes[*i] != self.original[*i])
+            .map(|i| PROGRAM_START + i as u16)
+            .collect()
+    }
+
+    pub fn is_changed(&self, address: u16) -> bool {
+        let i = (address - PROGRAM_START) as usize;
+
+        self.bytes.get(i) != self.original.get(i)
+    }
+
+    // Patches only ever change bytes, never grow or shrink the ROM.
+    pub fn apply(&mut self, poke: &Poke) -> Result<(), String> {
+        poke.check_rom_area(self.bytes.len())?;
+
+        for address in poke.addresses.clone() {
+            self.bytes[(address - PROGRAM_START) as usize] = poke.value;
+        }
+
+        Ok(())
+    }
+
+    // Moving drops a half-typed byte. The cursor stops at either end.
+    pub fn move_by(&mut self, delta: isize) {
+        let last = self.bytes.len().saturating_sub(1) as isize;
+
+        self.cursor = (self.cursor as isize + delta).clamp(0, last) as usize;
+        self.pending = None;
+    }
+
+    // Two hex digits make a byte, then the cursor moves on.
+    pub fn type_digit(&mut self, digit: u8) {
+        if self.bytes.is_empty() {
+            return;
+        }
+
+        match self.pending.take() {
+            None => {
+                self.bytes[self.cursor] = digit << 4 | self.bytes[self.cursor] & 0x0F;
+                self.pending = Some(digit);
+            }
+            Some(high) => {
+                self.bytes[self.cursor] = high << 4 | digit;
+                self.move_by(1);
+            }
+        }
+    }
+
+    // Puts the byte under the cursor back the way it was loaded.
+    pub fn revert(&mut self) {
+        if let Some(original) = self.original.get(self.cursor) {
+            self.bytes[self.cursor] = *original;
+        }
+
+        self.pending = None;
+    }
+
+    // The file as it is now counts as unchanged from here on.
+    pub fn mark_saved(&mut self) {
+        self.original = self.bytes.clone();
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.bytes.len().div_ceil(ROW_SIZE)
+    }
+
+    pub fn cursor_row(&self) -> usize {
+        self.cursor / ROW_SIZE
+    }
+
+    pub fn format_row(&self, row: usize, style: impl FnMut(usize, String) -> String) -> String {
+        let start = row * ROW_SIZE;
+        let end = (start + ROW_SIZE).min(self.bytes.len());
+
+        format_dump_row(
+            PROGRAM_START as usize + start,
+            &self.bytes[start..end],
+            style,
+        )
+    }
+
+    // The instructions in the cursor's row, taken from even addresses.
+    pub fn disassemble_row(&self, row: usize) -> Vec<String> {
+        let start = row * ROW_SIZE;
+        let end = (start + ROW_SIZE).min(self.bytes.len());
+
+        (start..end)
+            .step_by(2)
+            .map(|i| {
+                let opcode = (self.bytes[i] as u16) << 8
+                    | self.bytes.get(i + 1).copied().unwrap_or(0) as u16;
+
+                format!(
+                    "0x{:03X}  {:04X}  {}",
+                    PROGRAM_START as usize + i,
+                    opcode,
+                    disassemble(opcode)
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_cursor_stops_at_either_end() {
+        let mut buffer = PatchBuffer::new(vec![0; 20]);
+
+        buffer.move_by(-1);
+        assert_eq!(buffer.cursor(), 0x200);
+
+        buffer.move_by(ROW_SIZE as isize);
+        assert_eq!((buffer.cursor(), buffer.cursor_row()), (0x210, 1));
+
+        buffer.move_by(100);
+        assert_eq!(buffer.cursor(), 0x213);
+        assert_eq!(buffer.row_count(), 2);
+    }
+
+    #[test]
+    fn moving_drops_a_half_typed_byte() {
+        let mut buffer = PatchBuffer::new(vec![0x11, 0x22]);
+
+        buffer.type_digit(0xA);
+        buffer.move_by(1);
+        buffer.type_digit(0xB);
+        buffer.type_digit(0xC);
+
+        assert_eq!(buffer.bytes(), [0xA1, 0xBC]);
+
+        // The last byte keeps the cursor, and a third digit starts over.
+        assert_eq!(buffer.cursor(), 0x201);
+
+        buffer.type_digit(0xD);
+        assert_eq!(buffer.bytes(), [0xA1, 0xDC]);
+    }
+
+    #[test]
+    fn saving_makes_the_current_bytes_the_original() {
+        let mut buffer = PatchBuffer::new(vec![0x11, 0x22]);
+
+        buffer.type_digit(0x3);
+        buffer.type_digit(0x3);
+
+        assert!(buffer.is_changed(0x200));
+        assert!(!buffer.is_changed(0x201));
+
+        buffer.mark_saved();
+        buffer.move_by(-1);
+        buffer.revert();
+
+        assert_eq!(buffer.bytes(), [0x33, 0x22]);
+        assert!(buffer.changes().is_empty());
+    }
+
+    #[test]
+    fn patches_stay_inside_the_rom() {
+        let mut buffer = PatchBuffer::new(vec![0; 4]);
+
+        buffer
+            .apply(&Poke::parse("0x201..0x203=0xEE").unwrap())
+            .unwrap();
+
+        assert_eq!(buffer.bytes(), [0x00, 0xEE, 0xEE, 0x00]);
+        assert_eq!(buffer.changes(), [0x201, 0x202]);
+
+        assert!(buffer
+            .apply(&Poke::parse("0x203..0x205=1").unwrap())
+            .is_err());
+        assert!(buffer.apply(&Poke::parse("0x1FF=1").unwrap()).is_err());
+        assert_eq!(buffer.bytes(), [0x00, 0xEE, 0xEE, 0x00]);
+    }
+
+    #[test]
+    fn the_cursor_row_is_disassembled() {
+        let buffer = PatchBuffer::new(vec![0x00, 0xE0, 0x12]);
+
+        assert_eq!(
+            buffer.disassemble_row(0),
+            [
+                format!("0x200  00E0  {}", disassemble(0x00E0)),
+                format!("0x202  1200  {}", disassemble(0x1200)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use std::ops::Range;
+
+use crate::{
+    cpu::{MEMORY_SIZE, PROGRAM_START},
+    emulator::Emulator,
+    parse::{parse_byte, parse_number},
+};
+
+#[derive(Clone, Debug)]
+pub struct Poke {
+    pub addresses: Range<u16>,
+    pub value: u8,
+}
+
+impl Poke {
+    // Accepts `0x3A2=0xFF`, `0x3A2..0x3A6=0x00` and `0x3A2..=0x3A5=0x00`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (target, value) = text
+            .rsplit_once('=')
+            .ok_or_else(|| format!("expected `address=value`, got `{}`", text))?;
+
+        let addresses = if let Some((start, end)) = target.split_once("..=") {
+            let start = parse_address(start)?;
+            let end = parse_address(end)?;
+
+            start..end + 1
+        } else if let Some((start, end)) = target.split_once("..") {
+            parse_address(start)?..parse_address(end)?
+        } else {
+            let address = parse_address(target)?;
+
+            address..address + 1
+        };
+
+        if addresses.is_empty() || addresses.end as usize > MEMORY_SIZE {
+            return Err(format!("invalid address range in `{}`", text));
+        }
+
+        let value = parse_byte(value).ok_or_else(|| format!("invalid value `{}`", value))?;
+
+        Ok(Poke { addresses, value })
+    }
+
+    pub fn check_rom_area(&self, rom_len: usize) -> Result<(), String> {
+        let rom_end = PROGRAM_START as usize + rom_len;
+
+        if self.addresses.start < PROGRAM_START || self.addresses.end as usize > rom_end {
+            return Err(format!(
+                "0x{:03X}..0x{:03X} is outside the ROM area 0x{:03X}..0x{:03X}",
+                self.addresses.start, self.addresses.end, PROGRAM_START, rom_end
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn apply(&self, emulator: &mut Emulator) {
+        for address in self.addresses.clone() {
+            emulator.write_memory(address, self.value);
+        }
+    }
+}
+
+fn parse_address(text: &str) -> Result<u16, String> {
+    parse_number(text)
+        .filter(|address| (*address as usize) < MEMORY_SIZE)
+        .ok_or_else(|| format!("invalid address `{}`", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::EmulatorOptions;
+
+    #[test]
+    fn single_addresses_and_both_kinds_of_range() {
+        let poke = Poke::parse("0x3A2=0xFF").unwrap();
+
+        assert_eq!((poke.addresses, poke.value), (0x3A2..0x3A3, 0xFF));
+        assert_eq!(
+            Poke::parse("0x3A2..0x3A6=0").unwrap().addresses,
+            0x3A2..0x3A6
+        );
+        assert_eq!(
+            Poke::parse("0x3A2..=0x3A5=0").unwrap().addresses,
+            0x3A2..0x3A6
+        );
+        assert_eq!(Poke::parse("930=7").unwrap().addresses, 930..931);
+        assert_eq!(
+            Poke::parse("0xFFF..=0xFFF=1").unwrap().addresses,
+            0xFFF..0x1000
+        );
+    }
+
+    #[test]
+    fn bad_addresses_values_and_ranges_are_refused() {
+        for (text, error) in [
+            ("0x3A2", "expected `address=value`, got `0x3A2`"),
+            ("0x1000=1", "invalid address `0x1000`"),
+            ("lives=1", "invalid address `lives`"),
+            ("0x3A2=0x100", "invalid value `0x100`"),
+            (
+                "0x3A6..0x3A2=0",
+                "invalid address range in `0x3A6..0x3A2=0`",
+            ),
+            (
+                "0x3A2..0x3A2=0",
+                "invalid address range in `0x3A2..0x3A2=0`",
+            ),
+        ] {
+            assert_eq!(Poke::parse(text).err().as_deref(), Some(error), "{}", text);
+        }
+    }
+
+    #[test]
+    fn only_the_rom_area_is_allowed_without_force() {
+        let rom_len = 0x10;
+
+        assert!(Poke::parse("0x200=1")
+            .unwrap()
+            .check_rom_area(rom_len)
+            .is_ok());
+        assert!(Poke::parse("0x20C..0x210=1")
+            .unwrap()
+            .check_rom_area(rom_len)
+            .is_ok());
+        assert_eq!(
+            Poke::parse("0x20C..=0x210=1")
+                .unwrap()
+                .check_rom_area(rom_len)
+                .err(),
+            Some("0x20C..0x211 is outside the ROM area 0x200..0x210".to_string())
+        );
+        assert!(Poke::parse("0x1FF=1")
+            .unwrap()
+            .check_rom_area(rom_len)
+            .is_err());
+    }
+
+    #[test]
+    fn apply_writes_every_address_in_the_range() {
+        let mut emulator = Emulator::new(EmulatorOptions::default());
+
+        emulator.load_rom(&[0x12, 0x00]).unwrap();
+        Poke::parse("0x300..=0x302=0xAB")
+            .unwrap()
+            .apply(&mut emulator);
+
+        assert_eq!(emulator.read_memory(0x2FF), 0);
+        assert_eq!(emulator.read_memory(0x300), 0xAB);
+        assert_eq!(emulator.read_memory(0x302), 0xAB);
+        assert_eq!(emulator.read_memory(0x303), 0);
+    }
+}
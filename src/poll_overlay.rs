@@ -0,0 +1,62 @@
+use crate::keypad::LAYOUT;
+
+const FRAME: [u8; 4] = [0x60, 0x60, 0x60, 0xff];
+const POLLED: [u8; 4] = [0xff, 0xc8, 0x30, 0xff];
+const PRESSED: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+// Laid out on the 64x32 grid and scaled up to the frame, in the top right.
+const GRID_WIDTH: usize = 64;
+const CELL: usize = 3;
+const ORIGIN_X: usize = GRID_WIDTH - 4 * (CELL + 1);
+const ORIGIN_Y: usize = 1;
+
+// One cell per keypad key: outlined in yellow when the ROM tested it
+// recently (every cell while an FX0A waits), filled when the key is held.
+pub fn render(
+    frame: &mut [u8],
+    width: usize,
+    polled: u16,
+    waiting: bool,
+    held: impl Fn(u8) -> bool,
+) {
+    let scale = (width / GRID_WIDTH).max(1);
+
+    for (row, keys) in LAYOUT.iter().enumerate() {
+        for (column, key) in keys.iter().enumerate() {
+            let outline = if waiting || polled & (1 << key) != 0 {
+                POLLED
+            } else {
+                FRAME
+            };
+            let cell_x = ORIGIN_X + column * (CELL + 1);
+            let cell_y = ORIGIN_Y + row * (CELL + 1);
+
+            for dy in 0..CELL {
+                for dx in 0..CELL {
+                    let color = if dx == 1 && dy == 1 {
+                        if held(*key) {
+                            PRESSED
+                        } else {
+                            continue;
+                        }
+                    } else {
+                        outline
+                    };
+
+                    fill(frame, width, scale, cell_x + dx, cell_y + dy, color);
+                }
+            }
+        }
+    }
+}
+
+fn fill(frame: &mut [u8], width: usize, scale: usize, x: usize, y: usize, color: [u8; 4]) {
+    for dy in 0..scale {
+        for dx in 0..scale {
+            let i = ((y * scale + dy) * width + x * scale + dx) * 4;
+
+            if let Some(pixel) = frame.get_mut(i..i + 4) {
+                pixel.copy_from_slice(&color);
+            }
+        }
+    }
+}
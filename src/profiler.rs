@@ -0,0 +1,271 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{callgraph::Symbols, cpu::PROGRAM_START};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RoutineStats {
+    pub calls: u64,
+    // Instructions executed in the routine itself, and including its callees.
+    pub self_count: u64,
+    pub total_count: u64,
+}
+
+struct Frame {
+    entry: u16,
+    started_at: u64,
+}
+
+// Instruction counts per subroutine, following 2NNN and 00EE. Everything
+// outside of a call is counted against the entry point.
+pub struct Profiler {
+    frames: Vec<Frame>,
+    // The entry addresses of `frames`, kept apart to key `stacks` with.
+    path: Vec<u16>,
+    instructions: u64,
+    routines: HashMap<u16, RoutineStats>,
+    stacks: HashMap<Vec<u16>, u64>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        let mut routines = HashMap::new();
+
+        routines.insert(PROGRAM_START, RoutineStats::default());
+
+        Profiler {
+            frames: vec![Frame {
+                entry: PROGRAM_START,
+                started_at: 0,
+            }],
+            path: vec![PROGRAM_START],
+            instructions: 0,
+            routines,
+            stacks: HashMap::new(),
+        }
+    }
+
+    pub fn instruction(&mut self) {
+        self.instructions += 1;
+
+        let entry = *self.path.last().unwrap();
+
+        self.routines.get_mut(&entry).unwrap().self_count += 1;
+
+        match self.stacks.get_mut(self.path.as_slice()) {
+            Some(count) => *count += 1,
+            None => {
+                self.stacks.insert(self.path.clone(), 1);
+            }
+        }
+    }
+
+    pub fn call(&mut self, entry: u16) {
+        self.routines.entry(entry).or_default().calls += 1;
+        self.frames.push(Frame {
+            entry,
+            started_at: self.instructions,
+        });
+        self.path.push(entry);
+    }
+
+    // A stray 00EE at the top level leaves the entry frame alone.
+    pub fn ret(&mut self) {
+        if self.frames.len() > 1 {
+            let frame = self.frames.pop().unwrap();
+
+            self.path.pop();
+            self.close(&frame);
+        }
+    }
+
+    fn close(&mut self, frame: &Frame) {
+        let stats = self.routines.get_mut(&frame.entry).unwrap();
+
+        stats.total_count += self.instructions - frame.started_at;
+    }
+
+    // Frames still open count up to now.
+    pub fn routines(&self) -> Vec<(u16, RoutineStats)> {
+        let mut routines = self.routines.clone();
+
+        for frame in &self.frames {
+            routines.get_mut(&frame.entry).unwrap().total_count +=
+                self.instructions - frame.started_at;
+        }
+
+        let mut routines: Vec<(u16, RoutineStats)> = routines.into_iter().collect();
+
+        routines.sort_by(|a, b| b.1.self_count.cmp(&a.1.self_count).then(a.0.cmp(&b.0)));
+        routines
+    }
+
+    // One `main;sub_2A4;sub_300 <count>` line per call stack, the folded
+    // format flamegraph tools read.
+    pub fn folded_stacks(&self, symbols: &Symbols) -> String {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .map(|(path, count)| {
+                let names: Vec<String> = path.iter().map(|entry| symbols.name(*entry)).collect();
+
+                format!("{} {}\n", names.join(";"), count)
+            })
+            .collect();
+
+        lines.sort();
+        lines.concat()
+    }
+
+    pub fn report<'a>(&'a self, symbols: &'a Symbols) -> Report<'a> {
+        Report {
+            profiler: self,
+            symbols,
+        }
+    }
+}
+
+// The table printed on exit, sorted by the instructions run in each routine.
+pub struct Report<'a> {
+    profiler: &'a Profiler,
+    symbols: &'a Symbols,
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let instructions = self.profiler.instructions;
+        let percent = |count: u64| count as f64 * 100.0 / instructions.max(1) as f64;
+        let routines = self.profiler.routines();
+        // Symbols can be longer than the generated names.
+        let width = routines
+            .iter()
+            .map(|(entry, _)| self.symbols.name(*entry).len())
+            .fold(10, usize::max);
+
+        writeln!(
+            f,
+            "{:<width$} {:>8} {:>12} {:>7} {:>12} {:>7}",
+            "Routine", "Calls", "Self", "Self %", "Total", "Total %"
+        )?;
+
+        for (entry, stats) in routines {
+            writeln!(
+                f,
+                "{:<width$} {:>8} {:>12} {:>6.1}% {:>12} {:>6.1}%",
+                self.symbols.name(entry),
+                stats.calls,
+                stats.self_count,
+                percent(stats.self_count),
+                stats.total_count,
+                percent(stats.total_count)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+
+    // main calls a, which runs an instruction, calls b and runs two more.
+    // b runs three. Then main spins.
+    const NESTED: [u16; 10] = [
+        0x2206, 0x1202, 0x0000, 0x6001, 0x220E, 0x6102, 0x00EE, 0x6203, 0x6304, 0x00EE,
+    ];
+
+    fn profiled(program: &[u16], instructions: usize) -> CPU {
+        let mut cpu = CPU::new();
+        let rom = program.iter().flat_map(|opcode| opcode.to_be_bytes());
+
+        cpu.load_rom(rom.collect()).unwrap();
+        cpu.enable_profiler();
+
+        for _ in 0..instructions {
+            cpu.cycle().unwrap();
+        }
+
+        cpu
+    }
+
+    #[test]
+    fn nested_calls_count_against_the_innermost_routine() {
+        let cpu = profiled(&NESTED, 10);
+        let stats = |calls, self_count, total_count| RoutineStats {
+            calls,
+            self_count,
+            total_count,
+        };
+
+        // The call counts for the caller, the return for the callee. main's
+        // frame is still open, so its total is everything so far.
+        assert_eq!(
+            cpu.profiler().unwrap().routines(),
+            [
+                (0x206, stats(1, 4, 7)),
+                (0x200, stats(0, 3, 10)),
+                (0x20E, stats(1, 3, 3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_routine_called_twice_adds_up() {
+        // main calls b twice.
+        let cpu = profiled(&[0x2206, 0x2206, 0x1204, 0x6001, 0x00EE], 7);
+        let routines = cpu.profiler().unwrap().routines();
+
+        assert_eq!(
+            routines[0],
+            (
+                0x206,
+                RoutineStats {
+                    calls: 2,
+                    self_count: 4,
+                    total_count: 4,
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn the_report_and_the_stacks_use_the_symbols() {
+        let cpu = profiled(&NESTED, 10);
+        let profiler = cpu.profiler().unwrap();
+        let symbols = Symbols::parse("# from the listing\n0x206 draw_player\n").unwrap();
+
+        assert_eq!(
+            profiler.folded_stacks(&symbols),
+            "main 3\nmain;draw_player 4\nmain;draw_player;sub_20E 3\n"
+        );
+        assert_eq!(
+            profiler.report(&symbols).to_string(),
+            "\
+Routine        Calls         Self  Self %        Total Total %
+draw_player        1            4   40.0%            7   70.0%
+main               0            3   30.0%           10  100.0%
+sub_20E            1            3   30.0%            3   30.0%
+"
+        );
+    }
+
+    #[test]
+    fn bad_symbol_lines_are_errors() {
+        assert_eq!(
+            Symbols::parse("0x206").err().as_deref(),
+            Some("line 1: expected `ADDRESS NAME`")
+        );
+        assert_eq!(
+            Symbols::parse("\n0x1000 far").err().as_deref(),
+            Some("line 2: invalid address 0x1000")
+        );
+        assert_eq!(Symbols::parse("2A4 loop").unwrap().name(0x2A4), "loop");
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::VecDeque;
+
+use chip8_emu::{display::DisplayMode, gif};
+
+use crate::palette::Palette;
+
+pub const DEFAULT_SECONDS: u32 = 10;
+pub const SCALE: usize = 4;
+// Browsers slow down frames shorter than this, in hundredths of a second.
+const MIN_DELAY: u64 = 2;
+
+// One bit per pixel, and how many frames in a row showed it.
+struct Entry {
+    mode: DisplayMode,
+    bits: Vec<u8>,
+    frames: u32,
+}
+
+// The last few seconds of emulated frames, to be turned into a GIF after the
+// fact. Only frames the CPU actually ran get pushed, so a pause doesn't eat
+// into the buffer, and a display that didn't change only bumps a counter.
+pub struct Replay {
+    entries: VecDeque<Entry>,
+    frames: u32,
+    capacity: u32,
+}
+
+impl Replay {
+    pub fn new(seconds: u32, refresh_rate: u32) -> Self {
+        Replay {
+            entries: VecDeque::new(),
+            frames: 0,
+            capacity: seconds * refresh_rate,
+        }
+    }
+
+    pub fn push(&mut self, display: &[u8], mode: DisplayMode) {
+        let bits = pack(display);
+
+        match self.entries.back_mut() {
+            Some(last) if last.mode == mode && last.bits == bits => last.frames += 1,
+            _ => self.entries.push_back(Entry {
+                mode,
+                bits,
+                frames: 1,
+            }),
+        }
+
+        self.frames += 1;
+
+        while self.frames > self.capacity {
+            let first = self.entries.front_mut().unwrap();
+
+            first.frames -= 1;
+            self.frames -= 1;
+
+            if first.frames == 0 {
+                self.entries.pop_front();
+            }
+        }
+    }
+
+    // Everything the encoder needs, so it can run on another thread.
+    pub fn clip(&self, palette: Palette, refresh_rate: u32) -> Clip {
+        let canvas = if self
+            .entries
+            .iter()
+            .any(|entry| entry.mode == DisplayMode::HIRES)
+        {
+            DisplayMode::HIRES
+        } else {
+            DisplayMode::LORES
+        };
+        let mut frames = Vec::new();
+        let mut elapsed = 0;
+        let mut shown = 0;
+        let mut skipped = false;
+
+        // Delays are rounded on the running total so they don't drift. A
+        // frame too short to show is replaced by the one after it, but the
+        // last one always makes it in.
+        for entry in &self.entries {
+            elapsed += entry.frames as u64;
+
+            let end = elapsed * 100 / refresh_rate as u64;
+
+            skipped = end - shown < MIN_DELAY;
+
+            if !skipped {
+                let delay = (end - shown).min(u16::MAX as u64) as u16;
+
+                frames.push((unpack(entry, canvas), delay));
+                shown = end;
+            }
+        }
+
+        if let Some(entry) = self.entries.back().filter(|_| skipped) {
+            frames.push((unpack(entry, canvas), MIN_DELAY as u16));
+        }
+
+        Clip {
+            canvas,
+            frames,
+            palette,
+        }
+    }
+}
+
+pub struct Clip {
+    canvas: DisplayMode,
+    frames: Vec<(Vec<u8>, u16)>,
+    palette: Palette,
+}
+
+impl Clip {
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let (width, height) = (self.canvas.width * SCALE, self.canvas.height * SCALE);
+        let color = |pixel| {
+            let [r, g, b, _] = self.palette.color(pixel);
+
+            [r, g, b]
+        };
+        let frames: Vec<(Vec<u8>, u16)> = self
+            .frames
+            .iter()
+            .map(|(pixels, delay)| {
+                let mut scaled = Vec::with_capacity(width * height);
+
+                for y in 0..height {
+                    for x in 0..width {
+                        scaled.push(pixels[self.canvas.index(x / SCALE, y / SCALE)]);
+                    }
+                }
+
+                (scaled, *delay)
+            })
+            .collect();
+
+        gif::encode(width, height, [color(0), color(1)], &frames)
+    }
+}
+
+fn pack(display: &[u8]) -> Vec<u8> {
+    display
+        .chunks(8)
+        .map(|pixels| {
+            pixels
+                .iter()
+                .enumerate()
+                .fold(0, |byte, (i, pixel)| byte | (pixel & 1) << (7 - i))
+        })
+        .collect()
+}
+
+// Lo-res frames in a hi-res clip are drawn 2x2.
+fn unpack(entry: &Entry, canvas: DisplayMode) -> Vec<u8> {
+    let factor = canvas.width / entry.mode.width;
+    let mut pixels = Vec::with_capacity(canvas.pixel_count());
+
+    for y in 0..canvas.height {
+        for x in 0..canvas.width {
+            let i = entry.mode.index(x / factor, y / factor);
+
+            pixels.push(entry.bits[i / 8] >> (7 - i % 8) & 1);
+        }
+    }
+
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A lo-res display with only pixel `lit` on.
+    fn display(lit: usize) -> Vec<u8> {
+        let mut display = vec![0; DisplayMode::LORES.pixel_count()];
+
+        display[lit] = 1;
+        display
+    }
+
+    fn lit_pixels(clip: &Clip) -> Vec<(usize, u16)> {
+        clip.frames
+            .iter()
+            .map(|(pixels, delay)| (pixels.iter().position(|pixel| *pixel == 1).unwrap(), *delay))
+            .collect()
+    }
+
+    // Image descriptors, skipping the sub-blocks after each.
+    fn gif_frames(gif: &[u8]) -> usize {
+        let mut at = 13 + 6 + 19;
+        let mut frames = 0;
+
+        while gif[at] != 0x3b {
+            if gif[at] == 0x21 {
+                at += 8;
+                continue;
+            }
+
+            frames += 1;
+            at += 11;
+
+            while gif[at] != 0 {
+                at += 1 + gif[at] as usize;
+            }
+
+            at += 1;
+        }
+
+        frames
+    }
+
+    #[test]
+    fn only_the_last_seconds_are_kept_oldest_first() {
+        // One second at 10 frames a second.
+        let mut replay = Replay::new(1, 10);
+
+        for frame in 0..15 {
+            replay.push(&display(frame), DisplayMode::LORES);
+        }
+
+        let clip = replay.clip(Palette::DEFAULT, 10);
+        let expected: Vec<(usize, u16)> = (5..15).map(|frame| (frame, 10)).collect();
+
+        assert_eq!(replay.frames, 10);
+        assert_eq!(lit_pixels(&clip), expected);
+    }
+
+    #[test]
+    fn a_still_display_only_makes_the_frame_longer() {
+        let mut replay = Replay::new(1, 10);
+
+        for frame in [0, 0, 0, 1, 2, 2] {
+            replay.push(&display(frame), DisplayMode::LORES);
+        }
+
+        assert_eq!(replay.entries.len(), 3);
+
+        // Dropping the oldest frame shortens the first entry first.
+        for _ in 0..5 {
+            replay.push(&display(2), DisplayMode::LORES);
+        }
+
+        assert_eq!(
+            lit_pixels(&replay.clip(Palette::DEFAULT, 10)),
+            [(0, 20), (1, 10), (2, 70)]
+        );
+    }
+
+    #[test]
+    fn frames_too_short_for_a_gif_are_merged() {
+        // 60 frames a second are under 2 hundredths each. The last one still
+        // shows, for the shortest delay.
+        let mut replay = Replay::new(1, 60);
+
+        for frame in 0..4 {
+            replay.push(&display(frame), DisplayMode::LORES);
+        }
+
+        assert_eq!(
+            lit_pixels(&replay.clip(Palette::DEFAULT, 60)),
+            [(1, 3), (2, 2), (3, 2)]
+        );
+    }
+
+    #[test]
+    fn the_gif_holds_every_frame_of_the_clip() {
+        let mut replay = Replay::new(2, 10);
+
+        for frame in 0..12 {
+            replay.push(&display(frame / 3), DisplayMode::LORES);
+        }
+
+        replay.push(&[0; DisplayMode::HIRES.pixel_count()], DisplayMode::HIRES);
+
+        let clip = replay.clip(Palette::DEFAULT, 10);
+        let gif = clip.encode();
+
+        assert_eq!(clip.frame_count(), 5);
+        assert_eq!(gif_frames(&gif), 5);
+
+        // Mixed modes give a hi-res canvas.
+        let size = |at: usize| u16::from_le_bytes([gif[at], gif[at + 1]]) as usize;
+
+        assert_eq!(size(6), DisplayMode::HIRES.width * SCALE);
+        assert_eq!(size(8), DisplayMode::HIRES.height * SCALE);
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    fs::{self, File},
+    io::{self, IsTerminal, Read},
+    path::{Path, PathBuf},
+    process,
+};
+
+use chip8_emu::{
+    assembler,
+    cpu::MAX_ROM_SIZE,
+    zip::{self, ZipArchive},
+};
+
+use crate::fuzzy;
+
+const PICK_CANDIDATES: usize = 5;
+
+// An exact file stem wins outright, otherwise the best fuzzy match if there
+// is a single one.
+pub fn pick_rom(dir: &str, name: &str) -> String {
+    let entries = fs::read_dir(dir).unwrap_or_else(|err| {
+        eprintln!("Could not read {}: {}", dir, err);
+        process::exit(1);
+    });
+    let mut files: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+
+    files.sort();
+
+    let exact = files.iter().find(|file| {
+        Path::new(file)
+            .file_stem()
+            .is_some_and(|stem| stem.to_string_lossy().eq_ignore_ascii_case(name))
+    });
+    let names: Vec<&str> = files.iter().map(String::as_str).collect();
+    let ranked = fuzzy::rank(name, &names);
+
+    let picked = match (exact, ranked.as_slice()) {
+        (Some(file), _) => file.as_str(),
+        (None, []) => {
+            eprintln!("No ROM in {} matches `{}`", dir, name);
+            process::exit(1);
+        }
+        (None, [(best, _), (second, _), ..]) if best == second => {
+            eprintln!("`{}` matches several ROMs in {}:", name, dir);
+
+            for (_, file) in ranked.iter().take(PICK_CANDIDATES) {
+                eprintln!("  {}", file);
+            }
+
+            process::exit(2);
+        }
+        (None, [(_, file), ..]) => file,
+    };
+
+    println!("Picked {}", picked);
+
+    Path::new(dir).join(picked).to_string_lossy().into_owned()
+}
+
+pub fn read_file(path: &str) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    let read = File::open(path).and_then(|mut file| file.read_to_end(&mut buffer));
+
+    if let Err(err) = read {
+        eprintln!("Could not read {}: {}", path, err);
+        process::exit(1);
+    }
+
+    buffer
+}
+
+fn read_stdin() -> Vec<u8> {
+    let mut stdin = io::stdin().lock();
+
+    if stdin.is_terminal() {
+        eprintln!("Refusing to read a ROM from a terminal, pipe it in instead");
+        process::exit(2);
+    }
+
+    let mut buffer = Vec::new();
+
+    stdin.read_to_end(&mut buffer).unwrap_or_else(|err| {
+        eprintln!("Could not read ROM from stdin: {}", err);
+        process::exit(1);
+    });
+
+    buffer
+}
+
+pub fn rom_name(path: &str) -> String {
+    if path == "-" {
+        return "<stdin>".to_string();
+    }
+
+    Path::new(path)
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
+
+pub fn check_rom_size(name: &str, rom_data: &[u8]) {
+    if rom_data.is_empty() {
+        eprintln!("{} is empty", name);
+        process::exit(1);
+    }
+
+    if rom_data.len() > MAX_ROM_SIZE {
+        eprintln!(
+            "{} is {} bytes, larger than the {} bytes available for ROMs",
+            name,
+            rom_data.len(),
+            MAX_ROM_SIZE
+        );
+        process::exit(1);
+    }
+}
+
+fn extract_rom(path: &str, data: &[u8], entry: Option<&str>) -> Vec<u8> {
+    let archive = ZipArchive::parse(data).unwrap_or_else(|err| {
+        eprintln!("Could not open {}: {}", path, err);
+        process::exit(1);
+    });
+
+    let entry = match entry {
+        Some(name) => archive.find(name).unwrap_or_else(|| {
+            eprintln!("{} has no entry named {}", path, name);
+            process::exit(1);
+        }),
+        None => {
+            let roms: Vec<_> = archive
+                .entries()
+                .iter()
+                .filter(|entry| entry.name.to_lowercase().ends_with(".ch8"))
+                .collect();
+
+            match roms.as_slice() {
+                [rom] => *rom,
+                [] => {
+                    eprintln!("{} does not contain any .ch8 file", path);
+                    process::exit(1);
+                }
+                _ => {
+                    eprintln!("{} contains several ROMs, pick one with --entry:", path);
+
+                    for rom in roms {
+                        eprintln!("  {}", rom.name);
+                    }
+
+                    process::exit(1);
+                }
+            }
+        }
+    };
+
+    archive.extract(entry, MAX_ROM_SIZE).unwrap_or_else(|err| {
+        eprintln!("Could not extract from {}: {}", path, err);
+        process::exit(1);
+    })
+}
+
+fn assemble_source(path: &str, source: &[u8]) -> Vec<u8> {
+    let source = String::from_utf8_lossy(source);
+
+    assembler::assemble(&source).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+    })
+}
+
+// Octo sources (`.8o` or --assemble) are assembled on the way in.
+pub fn read_rom(path: &str, entry: Option<&str>, assemble: bool) -> (String, Vec<u8>) {
+    let name = rom_name(path);
+    let rom_data = if path == "-" {
+        read_stdin()
+    } else {
+        read_file(path)
+    };
+    let rom_data = if assemble || path.ends_with(".8o") {
+        assemble_source(path, &rom_data)
+    } else if zip::is_zip(&rom_data) {
+        extract_rom(&name, &rom_data, entry)
+    } else {
+        rom_data
+    };
+
+    (name, rom_data)
+}
+
+// Every .ch8 file in `dir`, sorted by name.
+pub fn read_rom_dir(dir: &str) -> Vec<(String, Vec<u8>)> {
+    let entries = fs::read_dir(dir).unwrap_or_else(|err| {
+        eprintln!("Could not read {}: {}", dir, err);
+        process::exit(1);
+    });
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("ch8"))
+        })
+        .collect();
+
+    paths.sort();
+
+    if paths.is_empty() {
+        eprintln!("No .ch8 files in {}", dir);
+        process::exit(1);
+    }
+
+    paths
+        .iter()
+        .map(|path| {
+            let path = path.to_string_lossy();
+
+            (rom_name(&path), read_file(&path))
+        })
+        .collect()
+}
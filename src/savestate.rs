@@ -0,0 +1,179 @@
+// A save state is `C8ST`, a version byte, then the fields in a fixed order,
+// little endian. A version bump means older files are refused.
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 2;
+
+pub struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        let mut bytes = MAGIC.to_vec();
+
+        bytes.push(VERSION);
+        StateWriter { bytes }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend(bytes);
+    }
+
+    // Pixels are 0 or 1, eight of them go in a byte.
+    pub fn bits(&mut self, pixels: &[u8]) {
+        for chunk in pixels.chunks(8) {
+            let byte = chunk
+                .iter()
+                .enumerate()
+                .fold(0, |byte, (i, pixel)| byte | (pixel & 1) << (7 - i));
+
+            self.bytes.push(byte);
+        }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<Self, String> {
+        let mut reader = StateReader { bytes };
+
+        if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err("not a save state".to_string());
+        }
+
+        match reader.u8()? {
+            VERSION => Ok(reader),
+            version => Err(format!(
+                "save state version {} is not supported, expected {}",
+                version, VERSION
+            )),
+        }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < count {
+            return Err("the save state is truncated".to_string());
+        }
+
+        let (taken, rest) = self.bytes.split_at(count);
+
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn bytes<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    pub fn bits(&mut self, pixels: &mut [u8]) -> Result<(), String> {
+        let bytes = self.take(pixels.len().div_ceil(8))?;
+
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            *pixel = bytes[i / 8] >> (7 - i % 8) & 1;
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} unexpected bytes at the end of the save state",
+                self.bytes.len()
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let mut state = StateWriter::new();
+
+        state.u8(0xAB);
+        state.u16(0x1234);
+        state.u64(u64::MAX - 1);
+        state.bytes(&[1, 2, 3]);
+        state.bits(&[1, 0, 1, 1, 0, 0, 0, 0, 1, 1]);
+        state.finish()
+    }
+
+    #[test]
+    fn fields_read_back_in_the_order_they_were_written() {
+        let bytes = sample();
+        let mut state = StateReader::new(&bytes).unwrap();
+        let mut pixels = [0; 10];
+
+        assert_eq!(state.u8(), Ok(0xAB));
+        assert_eq!(state.u16(), Ok(0x1234));
+        assert_eq!(state.u64(), Ok(u64::MAX - 1));
+        assert_eq!(state.bytes(), Ok([1, 2, 3]));
+        assert_eq!(state.bits(&mut pixels), Ok(()));
+        assert_eq!(pixels, [1, 0, 1, 1, 0, 0, 0, 0, 1, 1]);
+        assert_eq!(state.finish(), Ok(()));
+    }
+
+    #[test]
+    fn bad_states_are_refused() {
+        let bytes = sample();
+
+        assert!(StateReader::new(b"C8SX\x01").is_err());
+        assert!(StateReader::new(b"C8").is_err());
+
+        let mut newer = bytes.clone();
+
+        newer[MAGIC.len()] = VERSION + 1;
+        assert!(StateReader::new(&newer).is_err());
+
+        let mut state = StateReader::new(&bytes[..8]).unwrap();
+
+        assert_eq!(state.u8(), Ok(0xAB));
+        assert_eq!(state.u64(), Err("the save state is truncated".to_string()));
+
+        let mut state = StateReader::new(&bytes).unwrap();
+
+        state.u8().unwrap();
+        assert!(state.finish().is_err());
+    }
+}
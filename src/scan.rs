@@ -0,0 +1,68 @@
+use std::fmt;
+
+use crate::cpu::decompose_opcode;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OpcodeCounts {
+    pub draws: u32,
+    pub key_waits: u32,
+    pub randoms: u32,
+    pub superchip: u32,
+    pub xochip: u32,
+}
+
+// Reads the ROM as aligned instructions, so sprite and text data get counted too
+// whenever it happens to look like an opcode.
+pub fn scan(rom: &[u8]) -> OpcodeCounts {
+    let mut counts = OpcodeCounts::default();
+
+    for pair in rom.chunks_exact(2) {
+        let opcode = (pair[0] as u16) << 8 | pair[1] as u16;
+
+        match decompose_opcode(opcode) {
+            (0, 0, 0xC, _) | (0, 0, 0xF, 0xB..=0xF) | (0xD, _, _, 0) => counts.superchip += 1,
+            (0xF, _, 3, 0) | (0xF, _, 7, 5) | (0xF, _, 8, 5) => counts.superchip += 1,
+            (5, _, _, 2) | (5, _, _, 3) | (0xF, 0, 0, 0) | (0xF, _, 0, 1) | (0xF, 0, 0, 2) => {
+                counts.xochip += 1
+            }
+            (0xF, _, 3, 0xA) => counts.xochip += 1,
+            (0xD, _, _, _) => counts.draws += 1,
+            (0xF, _, 0, 0xA) => counts.key_waits += 1,
+            (0xC, _, _, _) => counts.randoms += 1,
+            _ => {}
+        }
+    }
+
+    counts
+}
+
+impl OpcodeCounts {
+    pub fn variant(&self) -> Variant {
+        if self.xochip > 0 {
+            Variant::XoChip
+        } else if self.superchip > 0 {
+            Variant::SuperChip
+        } else {
+            Variant::Chip8
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Variant::Chip8 => "CHIP-8",
+            Variant::SuperChip => "SUPER-CHIP",
+            Variant::XoChip => "XO-CHIP",
+        };
+
+        write!(f, "{}", name)
+    }
+}
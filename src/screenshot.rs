@@ -0,0 +1,97 @@
+use std::{
+    fs,
+    io::Write,
+    process::{Command, Stdio},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chip8_emu::display::DisplayMode;
+
+use crate::palette::Palette;
+
+pub const SCALE: usize = 8;
+
+// Clipboard helpers that take the data on stdin, tried in order.
+const IMAGE_COPIERS: &[&[&str]] = &[
+    &["wl-copy", "--type", "image/png"],
+    &["xclip", "-selection", "clipboard", "-t", "image/png", "-i"],
+];
+
+const TEXT_COPIERS: &[&[&str]] = &[
+    &["wl-copy"],
+    &["xclip", "-selection", "clipboard", "-i"],
+    &["pbcopy"],
+    &["clip"],
+];
+
+pub fn encode_png(display: &[u8], mode: DisplayMode, palette: Palette, scale: usize) -> Vec<u8> {
+    let (width, height) = (mode.width * scale, mode.height * scale);
+    let mut rgba = Vec::with_capacity(width * height * 4);
+
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = display[mode.index(x / scale, y / scale)];
+
+            rgba.extend_from_slice(&palette.color(pixel));
+        }
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width as u32, height as u32);
+
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    // Writing into a Vec can't fail.
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&rgba).unwrap();
+    writer.finish().unwrap();
+
+    png
+}
+
+fn pipe_to(command: &[&str], data: &[u8]) -> bool {
+    let child = Command::new(command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+
+    let Ok(mut child) = child else {
+        return false;
+    };
+
+    let written = child.stdin.take().unwrap().write_all(data).is_ok();
+
+    child.wait().is_ok_and(|status| status.success()) && written
+}
+
+fn copy_with(copiers: &[&[&str]], data: &[u8]) -> bool {
+    copiers.iter().any(|command| pipe_to(command, data))
+}
+
+// Puts the PNG on the clipboard, or saves it and copies its path when no
+// clipboard tool takes images. Returns a message for the user.
+pub fn copy_to_clipboard(png: &[u8]) -> Result<String, String> {
+    if copy_with(IMAGE_COPIERS, png) {
+        return Ok("copied the screen to the clipboard".to_string());
+    }
+
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let path = format!("chip8-screenshot-{}.png", seconds);
+
+    fs::write(&path, png).map_err(|err| format!("failed to write {}: {}", path, err))?;
+
+    let path = fs::canonicalize(&path)
+        .map(|path| path.display().to_string())
+        .unwrap_or(path);
+
+    if copy_with(TEXT_COPIERS, path.as_bytes()) {
+        Ok(format!("saved the screen to {} and copied its path", path))
+    } else {
+        Ok(format!("saved the screen to {}", path))
+    }
+}
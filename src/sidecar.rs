@@ -0,0 +1,219 @@
+use std::fmt;
+
+use crate::{
+    flat_toml::{self, quote, Value},
+    key_profile::{parse_binding, KeyBinding},
+    quirks::{self, Quirks},
+    timing::{self, Timing},
+};
+
+// Per-ROM settings shipped next to the ROM as `<rom>.toml`, in the flat
+// subset of TOML that `flat_toml` reads.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Sidecar {
+    pub title: Option<String>,
+    pub speed: Option<u32>,
+    pub refresh_rate: Option<u32>,
+    pub timing: Option<Timing>,
+    pub quirks: Option<Quirks>,
+    pub high_contrast: Option<bool>,
+    pub invert: Option<bool>,
+    pub keys: Vec<KeyBinding>,
+}
+
+impl Sidecar {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut sidecar = Sidecar::default();
+
+        flat_toml::parse(source, |section, field, value| match section {
+            None => sidecar.set(field, value),
+            Some(_) => Err("unknown field".to_string()),
+        })?;
+
+        Ok(sidecar)
+    }
+
+    fn set(&mut self, field: &str, value: Value) -> Result<(), String> {
+        match (field, value) {
+            ("title", Value::String(title)) => self.title = Some(title),
+            ("title", _) => return Err("expected a string".to_string()),
+            ("speed", Value::Number(speed)) if speed > 0 => self.speed = Some(speed),
+            ("speed", _) => return Err("expected a number of cycles per frame".to_string()),
+            ("refresh", Value::Number(hz)) if timing::is_valid_refresh_rate(hz) => {
+                self.refresh_rate = Some(hz)
+            }
+            ("refresh", _) => return Err("expected 50 or 60".to_string()),
+            ("timing", Value::String(timing)) if timing == "fixed" => {
+                self.timing = Some(Timing::Fixed)
+            }
+            ("timing", Value::String(timing)) if timing == "vip" => self.timing = Some(Timing::Vip),
+            ("timing", _) => return Err("expected \"fixed\" or \"vip\"".to_string()),
+            ("quirks", Value::String(name)) if Quirks::preset(&name).is_some() => {
+                self.quirks = Quirks::preset(&name)
+            }
+            ("quirks", _) => {
+                return Err(format!(
+                    "expected one of {}",
+                    quirks::PRESET_NAMES.map(quote).join(", ")
+                ))
+            }
+            ("palette", Value::String(palette)) if palette == "default" => {
+                self.high_contrast = Some(false)
+            }
+            ("palette", Value::String(palette)) if palette == "high-contrast" => {
+                self.high_contrast = Some(true)
+            }
+            ("palette", _) => return Err("expected \"default\" or \"high-contrast\"".to_string()),
+            ("invert", Value::Bool(invert)) => self.invert = Some(invert),
+            ("invert", _) => return Err("expected true or false".to_string()),
+            ("keys", Value::Array(keys)) => {
+                self.keys = keys
+                    .iter()
+                    .map(|binding| parse_binding(binding))
+                    .collect::<Result<_, _>>()?
+            }
+            ("keys", _) => {
+                return Err("expected an array of `<host key>=<keypad key>` strings".to_string())
+            }
+            _ => return Err("unknown field".to_string()),
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Sidecar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(title) = &self.title {
+            writeln!(f, "title = {}", quote(title))?;
+        }
+
+        if let Some(speed) = self.speed {
+            writeln!(f, "speed = {}", speed)?;
+        }
+
+        if let Some(hz) = self.refresh_rate {
+            writeln!(f, "refresh = {}", hz)?;
+        }
+
+        if let Some(timing) = self.timing {
+            let timing = match timing {
+                Timing::Fixed => "fixed",
+                Timing::Vip => "vip",
+            };
+
+            writeln!(f, "timing = {}", quote(timing))?;
+        }
+
+        if let Some(name) = self.quirks.and_then(|quirks| quirks.name()) {
+            writeln!(f, "quirks = {}", quote(name))?;
+        }
+
+        if let Some(high_contrast) = self.high_contrast {
+            let palette = if high_contrast {
+                "high-contrast"
+            } else {
+                "default"
+            };
+
+            writeln!(f, "palette = {}", quote(palette))?;
+        }
+
+        if let Some(invert) = self.invert {
+            writeln!(f, "invert = {}", invert)?;
+        }
+
+        if !self.keys.is_empty() {
+            let keys: Vec<String> = self
+                .keys
+                .iter()
+                .map(|(host, key)| quote(&format!("{}={:X}", host, key)))
+                .collect();
+
+            writeln!(f, "keys = [{}]", keys.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full() -> Sidecar {
+        Sidecar {
+            title: Some("Maze \"deluxe\"".to_string()),
+            speed: Some(30),
+            refresh_rate: Some(50),
+            timing: Some(Timing::Vip),
+            quirks: Some(Quirks::SCHIP),
+            high_contrast: Some(true),
+            invert: Some(false),
+            keys: vec![("Up".to_string(), 2), ("Space".to_string(), 0xA)],
+        }
+    }
+
+    #[test]
+    fn a_written_sidecar_reads_back_the_same() {
+        let written = full().to_string();
+
+        assert_eq!(
+            written,
+            "title = \"Maze \\\"deluxe\\\"\"\n\
+             speed = 30\n\
+             refresh = 50\n\
+             timing = \"vip\"\n\
+             quirks = \"schip\"\n\
+             palette = \"high-contrast\"\n\
+             invert = false\n\
+             keys = [\"Up=2\", \"Space=A\"]\n"
+        );
+        assert_eq!(Sidecar::parse(&written), Ok(full()));
+        assert_eq!(Sidecar::default().to_string(), "");
+        assert_eq!(Sidecar::parse(""), Ok(Sidecar::default()));
+    }
+
+    #[test]
+    fn quirks_that_match_no_preset_are_left_out() {
+        let mut sidecar = full();
+        let mut quirks = Quirks::SCHIP;
+
+        quirks.display_wait = !quirks.display_wait;
+        sidecar.quirks = Some(quirks);
+
+        assert_eq!(Sidecar::parse(&sidecar.to_string()).unwrap().quirks, None);
+    }
+
+    #[test]
+    fn errors_name_the_offending_field() {
+        for (source, error) in [
+            (
+                "speed = 0",
+                "line 1: speed: expected a number of cycles per frame",
+            ),
+            ("title = 3", "line 1: title: expected a string"),
+            ("\nrefresh = 59", "line 2: refresh: expected 50 or 60"),
+            (
+                "timing = \"fast\"",
+                "line 1: timing: expected \"fixed\" or \"vip\"",
+            ),
+            (
+                "quirks = \"vip\"",
+                "line 1: quirks: expected one of \"chip8\", \"schip\", \"xo-chip\"",
+            ),
+            (
+                "palette = \"neon\"",
+                "line 1: palette: expected \"default\" or \"high-contrast\"",
+            ),
+            ("invert = 1", "line 1: invert: expected true or false"),
+            (
+                "keys = [\"Up\"]",
+                "line 1: keys: expected `<host key>=<keypad key>`, got `Up`",
+            ),
+            ("colour = \"red\"", "line 1: colour: unknown field"),
+        ] {
+            assert_eq!(Sidecar::parse(source), Err(error.to_string()), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use std::{collections::VecDeque, fmt, sync::Mutex, thread};
+
+use chip8_emu::{
+    cpu::{CpuError, UnknownOpcodePolicy},
+    display::DisplayMode,
+    emulator::{Emulator, EmulatorOptions},
+    hash::{sha1, to_hex},
+};
+
+use crate::info::json_string;
+
+pub const DEFAULT_FRAMES: u32 = 600;
+
+#[derive(Clone, Copy)]
+pub struct SmokeOptions {
+    pub frames: u32,
+    pub max_cycles: Option<u64>,
+    pub seed: u64,
+    pub emulator: EmulatorOptions,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Completed,
+    UnknownOpcode(String),
+    Fault(String),
+    Watchdog,
+    // The ROM didn't even load.
+    Rejected(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct SmokeResult {
+    pub name: String,
+    pub outcome: Outcome,
+    pub frames: u32,
+    pub frames_drawn: u32,
+    pub cycles: u64,
+    pub display_hash: String,
+    // The final frame.
+    pub display: Vec<u8>,
+    pub display_mode: DisplayMode,
+}
+
+impl Outcome {
+    fn label(&self) -> &'static str {
+        match self {
+            Outcome::Completed => "completed",
+            Outcome::UnknownOpcode(_) => "unknown-opcode",
+            Outcome::Fault(_) => "fault",
+            Outcome::Watchdog => "watchdog",
+            Outcome::Rejected(_) => "rejected",
+        }
+    }
+
+    fn message(&self) -> Option<&str> {
+        match self {
+            Outcome::UnknownOpcode(message)
+            | Outcome::Fault(message)
+            | Outcome::Rejected(message) => Some(message),
+            Outcome::Completed | Outcome::Watchdog => None,
+        }
+    }
+}
+
+// Runs `options.frames` frames without a window, audio or input. The timers
+// follow the frames and CXNN is seeded, so the same ROM always ends up with
+// the same display. A CPU fault ends the run with its message.
+pub fn run(name: &str, rom: &[u8], options: &SmokeOptions) -> SmokeResult {
+    let mut emulator = Emulator::new(options.emulator);
+    let mut result = SmokeResult {
+        name: name.to_string(),
+        outcome: Outcome::Completed,
+        frames: 0,
+        frames_drawn: 0,
+        cycles: 0,
+        display_hash: String::new(),
+        display: Vec::new(),
+        display_mode: DisplayMode::LORES,
+    };
+
+    if let Err(err) = emulator.load_rom(rom) {
+        result.outcome = Outcome::Rejected(err);
+        result.finish(&emulator);
+        return result;
+    }
+
+    emulator.set_frame_timers(true);
+    emulator.seed_random(options.seed);
+    emulator.set_max_cycles(options.max_cycles);
+    emulator.set_unknown_opcode_policy(UnknownOpcodePolicy::Error);
+
+    while result.frames < options.frames {
+        match emulator.run_frame() {
+            Ok(event) => {
+                result.frames += 1;
+
+                if event.display_dirty {
+                    result.frames_drawn += 1;
+                }
+            }
+            Err(CpuError::UnknownOpcode { opcode, pc }) => {
+                result.outcome = Outcome::UnknownOpcode(format!("{:04X} at 0x{:03X}", opcode, pc));
+                break;
+            }
+            Err(error) => {
+                result.outcome = Outcome::Fault(error.to_string());
+                break;
+            }
+        }
+
+        if emulator.watchdog_expired() {
+            result.outcome = Outcome::Watchdog;
+            break;
+        }
+    }
+
+    result.finish(&emulator);
+    result
+}
+
+impl SmokeResult {
+    fn finish(&mut self, emulator: &Emulator) {
+        self.cycles = emulator.cycles();
+        self.display_hash = to_hex(&sha1(emulator.display()));
+        self.display = emulator.display().to_vec();
+        self.display_mode = emulator.display_mode();
+    }
+}
+
+// Every ROM gets its own emulator, spread over `jobs` threads. The results
+// come back in the order of `roms`.
+pub fn run_all(
+    roms: &[(String, Vec<u8>)],
+    options: &SmokeOptions,
+    jobs: usize,
+) -> Vec<SmokeResult> {
+    let queue = Mutex::new((0..roms.len()).collect::<VecDeque<usize>>());
+    let results = Mutex::new(vec![None; roms.len()]);
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, roms.len().max(1)) {
+            scope.spawn(|| loop {
+                let Some(index) = queue.lock().unwrap().pop_front() else {
+                    break;
+                };
+                let (name, rom) = &roms[index];
+                let result = run(name, rom, options);
+
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+pub struct Summary<'a>(pub &'a [SmokeResult]);
+
+impl fmt::Display for Summary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self
+            .0
+            .iter()
+            .map(|result| result.name.len())
+            .max()
+            .unwrap_or(0)
+            .max("ROM".len());
+
+        writeln!(
+            f,
+            "{:<width$} {:<14} {:>7} {:>7} {:>10}  {:<8}  Details",
+            "ROM",
+            "Outcome",
+            "Frames",
+            "Drawn",
+            "Cycles",
+            "Display",
+            width = width
+        )?;
+
+        for result in self.0 {
+            let line = format!(
+                "{:<width$} {:<14} {:>7} {:>7} {:>10}  {:<8}  {}",
+                result.name,
+                result.outcome.label(),
+                result.frames,
+                result.frames_drawn,
+                result.cycles,
+                &result.display_hash[..8],
+                result.outcome.message().unwrap_or(""),
+                width = width
+            );
+
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        let completed = self
+            .0
+            .iter()
+            .filter(|result| result.outcome == Outcome::Completed)
+            .count();
+
+        write!(f, "{} of {} ROMs completed", completed, self.0.len())
+    }
+}
+
+pub fn to_json(results: &[SmokeResult], options: &SmokeOptions) -> String {
+    let roms: Vec<String> = results
+        .iter()
+        .map(|result| {
+            format!(
+                "{{\"name\":{},\"outcome\":\"{}\",\"message\":{},\"frames\":{},\"frames_drawn\":{},\"cycles\":{},\"display_sha1\":\"{}\"}}",
+                json_string(&result.name),
+                result.outcome.label(),
+                result
+                    .outcome
+                    .message()
+                    .map(json_string)
+                    .unwrap_or_else(|| "null".to_string()),
+                result.frames,
+                result.frames_drawn,
+                result.cycles,
+                result.display_hash
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\"frames\":{},\"seed\":{},\"roms\":[{}]}}\n",
+        options.frames,
+        options.seed,
+        roms.join(",")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom_file::read_rom_dir;
+
+    fn fixture_roms() -> Vec<(String, Vec<u8>)> {
+        read_rom_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/smoke"))
+    }
+
+    fn options() -> SmokeOptions {
+        SmokeOptions {
+            frames: 60,
+            max_cycles: Some(300),
+            seed: 0,
+            emulator: EmulatorOptions::default(),
+        }
+    }
+
+    #[test]
+    fn each_fixture_rom_ends_the_way_it_should() {
+        let results = run_all(&fixture_roms(), &options(), 3);
+        let outcomes: Vec<(&str, &Outcome)> = results
+            .iter()
+            .map(|result| (result.name.as_str(), &result.outcome))
+            .collect();
+
+        assert_eq!(
+            outcomes,
+            [
+                ("fail", &Outcome::UnknownOpcode("5AB1 at 0x202".to_string())),
+                ("hang", &Outcome::Watchdog),
+                ("pass", &Outcome::Completed),
+            ]
+        );
+
+        // The failing ROM stops on its second instruction, the hanging one
+        // on the limit, and the passing one runs every frame waiting for a key.
+        assert_eq!((results[0].frames, results[0].cycles), (0, 2));
+        assert_eq!(results[1].cycles, 300);
+        assert_eq!(results[2].frames, 60);
+        assert!(results[2].cycles < 300);
+
+        // Only the passing ROM drew anything.
+        assert_eq!(results[2].frames_drawn, 1);
+        assert!(results[2].display.iter().any(|&pixel| pixel != 0));
+        assert!(results[1].display.iter().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    fn the_summary_and_the_report_agree() {
+        let options = options();
+        let results = run_all(&fixture_roms(), &options, 1);
+        let summary = Summary(&results).to_string();
+
+        assert!(summary.ends_with("1 of 3 ROMs completed"));
+        assert!(summary.contains("unknown-opcode"));
+
+        let json = to_json(&results, &options);
+
+        assert!(json.starts_with("{\"frames\":60,\"seed\":0,\"roms\":["));
+        assert!(json.contains(
+            "\"name\":\"fail\",\"outcome\":\"unknown-opcode\",\"message\":\"5AB1 at 0x202\""
+        ));
+        assert!(json.contains("\"name\":\"hang\",\"outcome\":\"watchdog\",\"message\":null"));
+        assert!(json.contains("\"name\":\"pass\",\"outcome\":\"completed\",\"message\":null"));
+    }
+}
@@ -0,0 +1,228 @@
+use std::fmt;
+
+use crate::cpu::CPU;
+
+// What an instruction can change, cheap enough to take every step.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CpuSnapshot {
+    pub registers: [u8; 0x10],
+    pub pc: u16,
+    pub pointer: u16,
+    pub stack: Vec<u16>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub display_hash: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StateChange {
+    Register { index: u8, old: u8, new: u8 },
+    Pc { old: u16, new: u16 },
+    Pointer { old: u16, new: u16 },
+    Stack { old: Vec<u16>, new: Vec<u16> },
+    DelayTimer { old: u8, new: u8 },
+    SoundTimer { old: u8, new: u8 },
+    Display,
+}
+
+impl CpuSnapshot {
+    pub fn capture(cpu: &CPU) -> Self {
+        CpuSnapshot {
+            registers: *cpu.registers(),
+            pc: cpu.pc(),
+            pointer: cpu.pointer(),
+            stack: cpu.stack().to_vec(),
+            delay_timer: cpu.delay_timer(),
+            sound_timer: cpu.get_sound_timer(),
+            display_hash: hash_display(cpu.display()),
+        }
+    }
+
+    // Everything that differs in `other`, registers first.
+    pub fn diff(&self, other: &CpuSnapshot) -> Vec<StateChange> {
+        let mut changes: Vec<StateChange> = self
+            .registers
+            .iter()
+            .zip(other.registers)
+            .enumerate()
+            .filter(|(_, (old, new))| **old != *new)
+            .map(|(index, (old, new))| StateChange::Register {
+                index: index as u8,
+                old: *old,
+                new,
+            })
+            .collect();
+
+        if self.pc != other.pc {
+            changes.push(StateChange::Pc {
+                old: self.pc,
+                new: other.pc,
+            });
+        }
+
+        if self.pointer != other.pointer {
+            changes.push(StateChange::Pointer {
+                old: self.pointer,
+                new: other.pointer,
+            });
+        }
+
+        if self.stack != other.stack {
+            changes.push(StateChange::Stack {
+                old: self.stack.clone(),
+                new: other.stack.clone(),
+            });
+        }
+
+        if self.delay_timer != other.delay_timer {
+            changes.push(StateChange::DelayTimer {
+                old: self.delay_timer,
+                new: other.delay_timer,
+            });
+        }
+
+        if self.sound_timer != other.sound_timer {
+            changes.push(StateChange::SoundTimer {
+                old: self.sound_timer,
+                new: other.sound_timer,
+            });
+        }
+
+        if self.display_hash != other.display_hash {
+            changes.push(StateChange::Display);
+        }
+
+        changes
+    }
+}
+
+// FNV-1a, only used to tell whether two displays differ.
+fn hash_display(display: &[u8]) -> u64 {
+    display.iter().fold(0xcbf29ce484222325, |hash, pixel| {
+        (hash ^ *pixel as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn format_stack(stack: &[u16]) -> String {
+    let addresses: Vec<String> = stack
+        .iter()
+        .map(|address| format!("0x{:03X}", address))
+        .collect();
+
+    format!("[{}]", addresses.join(", "))
+}
+
+impl fmt::Display for StateChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateChange::Register { index, old, new } => {
+                write!(f, "V{:X}: 0x{:02X} -> 0x{:02X}", index, old, new)
+            }
+            StateChange::Pc { old, new } => write!(f, "PC: 0x{:03X} -> 0x{:03X}", old, new),
+            StateChange::Pointer { old, new } => write!(f, "I: 0x{:03X} -> 0x{:03X}", old, new),
+            StateChange::Stack { old, new } => {
+                write!(f, "stack: {} -> {}", format_stack(old), format_stack(new))
+            }
+            StateChange::DelayTimer { old, new } => write!(f, "DT: {} -> {}", old, new),
+            StateChange::SoundTimer { old, new } => write!(f, "ST: {} -> {}", old, new),
+            StateChange::Display => write!(f, "display changed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> CpuSnapshot {
+        CpuSnapshot {
+            registers: [0; 0x10],
+            pc: 0x204,
+            pointer: 0x300,
+            stack: vec![0x202],
+            delay_timer: 10,
+            sound_timer: 0,
+            display_hash: hash_display(&[0; 8]),
+        }
+    }
+
+    #[test]
+    fn an_unchanged_state_has_no_differences() {
+        assert_eq!(snapshot().diff(&snapshot()), []);
+    }
+
+    #[test]
+    fn every_difference_is_listed_registers_first() {
+        let old = snapshot();
+        let mut new = snapshot();
+
+        new.registers[0xF] = 1;
+        new.registers[3] = 0x20;
+        new.pc = 0x208;
+        new.pointer = 0x302;
+        new.stack.push(0x206);
+        new.delay_timer = 9;
+        new.sound_timer = 4;
+        new.display_hash = hash_display(&[0, 0, 1, 0, 0, 0, 0, 0]);
+
+        let changes: Vec<String> = old
+            .diff(&new)
+            .iter()
+            .map(|change| change.to_string())
+            .collect();
+
+        assert_eq!(
+            changes,
+            [
+                "V3: 0x00 -> 0x20",
+                "VF: 0x00 -> 0x01",
+                "PC: 0x204 -> 0x208",
+                "I: 0x300 -> 0x302",
+                "stack: [0x202] -> [0x202, 0x206]",
+                "DT: 10 -> 9",
+                "ST: 0 -> 4",
+                "display changed",
+            ]
+        );
+        assert_eq!(
+            new.diff(&old)[0],
+            StateChange::Register {
+                index: 3,
+                old: 0x20,
+                new: 0
+            }
+        );
+    }
+
+    #[test]
+    fn a_snapshot_is_taken_from_the_cpu() {
+        let mut cpu = CPU::new();
+
+        // Draws the first byte of the ROM itself.
+        cpu.load_rom(vec![0x63, 0x1F, 0xA2, 0x00, 0xD0, 0x01])
+            .unwrap();
+
+        let before = cpu.snapshot();
+
+        for _ in 0..3 {
+            cpu.cycle().unwrap();
+        }
+
+        assert_eq!(
+            before.diff(&cpu.snapshot()),
+            [
+                StateChange::Register {
+                    index: 3,
+                    old: 0,
+                    new: 0x1F
+                },
+                StateChange::Pc {
+                    old: 0x200,
+                    new: 0x206
+                },
+                StateChange::Pointer { old: 0, new: 0x200 },
+                StateChange::Display,
+            ]
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::cpu::DEFAULT_CYCLES_PER_FRAME;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpeedSource {
+    CommandLine,
+    LocalOverride,
+    Sidecar,
+    Database,
+    Default,
+}
+
+// An explicit --speed wins over the user's saved speed, then the ROM's
+// sidecar file, then the database.
+pub fn resolve_speed(
+    command_line: Option<u32>,
+    local_override: Option<u32>,
+    sidecar: Option<u32>,
+    database: Option<u32>,
+) -> (u32, SpeedSource) {
+    [
+        (command_line, SpeedSource::CommandLine),
+        (local_override, SpeedSource::LocalOverride),
+        (sidecar, SpeedSource::Sidecar),
+        (database, SpeedSource::Database),
+    ]
+    .into_iter()
+    .find_map(|(speed, source)| speed.map(|speed| (speed, source)))
+    .unwrap_or((DEFAULT_CYCLES_PER_FRAME, SpeedSource::Default))
+}
+
+// Saved speeds, one `<rom sha1> <cycles per frame>` pair per line.
+#[derive(Default)]
+pub struct SpeedOverrides {
+    entries: BTreeMap<String, u32>,
+}
+
+impl SpeedOverrides {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut entries = BTreeMap::new();
+
+        for (i, line) in source.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let (hash, speed) = line
+                .split_once(char::is_whitespace)
+                .and_then(|(hash, speed)| Some((hash, speed.trim().parse().ok()?)))
+                .ok_or_else(|| format!("line {}: expected `<hash> <cycles per frame>`", i + 1))?;
+
+            entries.insert(hash.to_string(), speed);
+        }
+
+        Ok(SpeedOverrides { entries })
+    }
+
+    pub fn get(&self, hash: &str) -> Option<u32> {
+        self.entries.get(hash).copied()
+    }
+
+    pub fn set(&mut self, hash: &str, speed: u32) {
+        self.entries.insert(hash.to_string(), speed);
+    }
+}
+
+impl fmt::Display for SpeedOverrides {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (hash, speed) in &self.entries {
+            writeln!(f, "{} {}", hash, speed)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for SpeedSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SpeedSource::CommandLine => "--speed",
+            SpeedSource::LocalOverride => "saved speed",
+            SpeedSource::Sidecar => "sidecar file",
+            SpeedSource::Database => "ROM database",
+            SpeedSource::Default => "default",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_source_wins_over_the_ones_after_it() {
+        assert_eq!(
+            resolve_speed(Some(1), Some(2), Some(3), Some(4)),
+            (1, SpeedSource::CommandLine)
+        );
+        assert_eq!(
+            resolve_speed(None, Some(2), Some(3), Some(4)),
+            (2, SpeedSource::LocalOverride)
+        );
+        assert_eq!(
+            resolve_speed(None, None, Some(3), Some(4)),
+            (3, SpeedSource::Sidecar)
+        );
+        assert_eq!(
+            resolve_speed(None, None, None, Some(4)),
+            (4, SpeedSource::Database)
+        );
+        assert_eq!(
+            resolve_speed(None, None, None, None),
+            (DEFAULT_CYCLES_PER_FRAME, SpeedSource::Default)
+        );
+        // A gap in the middle doesn't stop the search.
+        assert_eq!(
+            resolve_speed(None, Some(2), None, Some(4)),
+            (2, SpeedSource::LocalOverride)
+        );
+    }
+
+    #[test]
+    fn saved_speeds_round_trip() {
+        let mut overrides = SpeedOverrides::parse("abc 12\n\n  def   30\n").unwrap();
+
+        assert_eq!(overrides.get("abc"), Some(12));
+        assert_eq!(overrides.get("def"), Some(30));
+        assert_eq!(overrides.get("ghi"), None);
+
+        overrides.set("abc", 15);
+
+        let saved = overrides.to_string();
+
+        assert_eq!(saved, "abc 15\ndef 30\n");
+        assert_eq!(SpeedOverrides::parse(&saved).unwrap().get("abc"), Some(15));
+        assert_eq!(
+            SpeedOverrides::parse("abc 12\nabc fast").err(),
+            Some("line 2: expected `<hash> <cycles per frame>`".to_string())
+        );
+    }
+}
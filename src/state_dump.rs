@@ -0,0 +1,108 @@
+use std::{
+    fs,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chip8_emu::emulator::Emulator;
+
+use crate::info::json_string;
+
+static DUMP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn request_dump(_: libc::c_int) {
+    // Only the flag is touched here, the main loop does the writing.
+    DUMP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+// SIGUSR1 asks for a dump at the next trip through the event loop.
+#[cfg(unix)]
+pub fn install() {
+    let handler = request_dump as extern "C" fn(libc::c_int);
+
+    unsafe {
+        libc::signal(libc::SIGUSR1, handler as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install() {}
+
+pub fn take_request() -> bool {
+    DUMP_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+fn to_json(emulator: &Emulator) -> String {
+    let snapshot = emulator.snapshot();
+    let list = |values: Vec<String>| values.join(",");
+    let mode = emulator.display_mode();
+
+    format!(
+        "{{\"cycles\":{},\"pc\":{},\"i\":{},\"registers\":[{}],\"stack\":[{}],\"delay_timer\":{},\"sound_timer\":{},\"waiting_for_key\":{},\"display\":{{\"width\":{},\"height\":{}}},\"recent\":[{}]}}",
+        emulator.cycles(),
+        snapshot.pc,
+        snapshot.pointer,
+        list(snapshot.registers.iter().map(u8::to_string).collect()),
+        list(snapshot.stack.iter().map(u16::to_string).collect()),
+        snapshot.delay_timer,
+        snapshot.sound_timer,
+        emulator.is_waiting_for_key(),
+        mode.width,
+        mode.height,
+        list(emulator.history().map(|line| json_string(&line.format())).collect())
+    )
+}
+
+// Writes the dump next to where the emulator was started and returns its path.
+pub fn write(emulator: &Emulator) -> Result<String, String> {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let path = format!("chip8-state-{}.json", seconds);
+
+    fs::write(&path, to_json(emulator))
+        .map_err(|err| format!("failed to write {}: {}", path, err))?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use chip8_emu::emulator::EmulatorOptions;
+
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn sigusr1_requests_a_dump_that_lands_in_a_file() {
+        let mut emulator = Emulator::new(EmulatorOptions::default());
+
+        emulator.load_rom(&[0x63, 0x1F, 0x12, 0x02]).unwrap();
+        emulator.run_frame().unwrap();
+        install();
+
+        assert!(!take_request());
+
+        unsafe {
+            libc::raise(libc::SIGUSR1);
+        }
+
+        assert!(take_request());
+        // Only once per signal.
+        assert!(!take_request());
+
+        let path = write(&emulator).unwrap();
+        let dump = fs::read_to_string(&path).unwrap();
+
+        fs::remove_file(&path).unwrap();
+
+        assert!(path.starts_with("chip8-state-") && path.ends_with(".json"));
+        assert!(dump.starts_with(&format!(
+            "{{\"cycles\":{},\"pc\":514,\"i\":0,\"registers\":[0,0,0,31,",
+            emulator.cycles()
+        )));
+        assert!(dump.contains("\"waiting_for_key\":false,\"display\":{\"width\":64,\"height\":32}"));
+        assert!(dump.contains("\"recent\":[\"200 631F LD V3, #1F"));
+    }
+}
@@ -0,0 +1,666 @@
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::Path,
+    process, thread,
+};
+
+use chip8_emu::{
+    callgraph,
+    emulator::EmulatorOptions,
+    patch::PatchBuffer,
+    poke::Poke,
+    trace::{self, TraceDiff, TraceLine},
+};
+
+use crate::{
+    cli::parse_limit,
+    compare::{self, Side},
+    data_dir, hex_editor, info, load_config,
+    rom_file::{check_rom_size, read_file, read_rom, read_rom_dir, rom_name},
+    smoke::{self, Outcome, SmokeOptions, Summary},
+    thumbnails::Thumbnails,
+};
+
+const SMOKE_REPORT: &str = "smoke.json";
+const TRACE_DIFF_CONTEXT: usize = 3;
+
+const INFO_USAGE: &str = "usage: chip8-emu info ROM|- [--entry NAME] [--json]";
+const CALLGRAPH_USAGE: &str = "usage: chip8-emu callgraph ROM|- [--entry NAME] [--dot FILE]";
+const TRACE_DIFF_USAGE: &str = "usage: chip8-emu trace-diff OLD NEW";
+const COMPARE_USAGE: &str = "\
+usage: chip8-emu compare ROM --left SPEC --right SPEC [--seed N]
+SPEC is fixed, vip, chip8, schip, xo-chip, or a comma list of timing=fixed|vip, quirks=NAME and speed=N";
+const PATCH_USAGE: &str =
+    "usage: chip8-emu patch ROM [--set ADDR[..END]=VALUE]... [-o FILE | --in-place]";
+const SMOKE_USAGE: &str =
+    "usage: chip8-emu smoke DIR [--frames N] [--max-cycles N] [--seed N] [--jobs N] [--report FILE]";
+const THUMBNAILS_USAGE: &str = "usage: chip8-emu thumbnails DIR";
+
+// Runs the subcommand called `name` and exits, or returns when there is no
+// such subcommand and the arguments are for the emulator itself.
+pub fn run(name: &str, args: impl Iterator<Item = String>) {
+    let (result, usage) = match name {
+        "info" => (parse_info(args).map(run_info), INFO_USAGE),
+        "callgraph" => (parse_callgraph(args).map(run_callgraph), CALLGRAPH_USAGE),
+        "trace-diff" => (parse_trace_diff(args).map(run_trace_diff), TRACE_DIFF_USAGE),
+        "compare" => (parse_compare(args).map(run_compare), COMPARE_USAGE),
+        "patch" => (parse_patch(args).map(run_patch), PATCH_USAGE),
+        "smoke" => (parse_smoke(args).map(run_smoke), SMOKE_USAGE),
+        "thumbnails" => (parse_thumbnails(args).map(run_thumbnails), THUMBNAILS_USAGE),
+        _ => return,
+    };
+
+    match result {
+        Ok(code) => process::exit(code),
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("{}", usage);
+            process::exit(2);
+        }
+    }
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("{} needs a value", flag))
+}
+
+// A ROM path or `-` for stdin where the subcommand reads one.
+fn is_rom(arg: &str) -> bool {
+    arg == "-" || !arg.starts_with('-')
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct InfoArgs {
+    rom: String,
+    entry: Option<String>,
+    json: bool,
+}
+
+fn parse_info(mut args: impl Iterator<Item = String>) -> Result<InfoArgs, String> {
+    let mut rom = None;
+    let mut entry = None;
+    let mut json = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--entry" => entry = Some(next_value(&mut args, &arg)?),
+            _ if rom.is_none() && is_rom(&arg) => rom = Some(arg),
+            _ => return Err(format!("Unexpected {}", arg)),
+        }
+    }
+
+    Ok(InfoArgs {
+        rom: rom.ok_or("Missing the ROM")?,
+        entry,
+        json,
+    })
+}
+
+// `chip8-emu info ROM [--entry NAME] [--json]`, handled before any window opens.
+fn run_info(args: InfoArgs) -> i32 {
+    let (name, rom_data) = read_rom(&args.rom, args.entry.as_deref(), false);
+
+    info::print(&name, &rom_data, args.json);
+    0
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct CallgraphArgs {
+    rom: String,
+    entry: Option<String>,
+    dot: Option<String>,
+}
+
+fn parse_callgraph(mut args: impl Iterator<Item = String>) -> Result<CallgraphArgs, String> {
+    let mut rom = None;
+    let mut entry = None;
+    let mut dot = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dot" => dot = Some(next_value(&mut args, &arg)?),
+            "--entry" => entry = Some(next_value(&mut args, &arg)?),
+            _ if rom.is_none() && is_rom(&arg) => rom = Some(arg),
+            _ => return Err(format!("Unexpected {}", arg)),
+        }
+    }
+
+    Ok(CallgraphArgs {
+        rom: rom.ok_or("Missing the ROM")?,
+        entry,
+        dot,
+    })
+}
+
+// `chip8-emu callgraph ROM [--entry NAME] [--dot FILE]`, the listing always goes to stdout.
+fn run_callgraph(args: CallgraphArgs) -> i32 {
+    let (name, rom_data) = read_rom(&args.rom, args.entry.as_deref(), false);
+
+    check_rom_size(&name, &rom_data);
+
+    let graph = callgraph::build(&rom_data);
+
+    print!("{}", graph);
+
+    if let Some(path) = args.dot {
+        if let Err(err) = fs::write(&path, graph.to_dot()) {
+            eprintln!("Could not write {}: {}", path, err);
+            return 1;
+        }
+    }
+
+    0
+}
+
+fn parse_trace_diff(mut args: impl Iterator<Item = String>) -> Result<(String, String), String> {
+    match (args.next(), args.next(), args.next()) {
+        (Some(old), Some(new), None) => Ok((old, new)),
+        (_, _, Some(extra)) => Err(format!("Unexpected {}", extra)),
+        _ => Err("Missing a trace".to_string()),
+    }
+}
+
+// `chip8-emu trace-diff OLD NEW`, exits with 1 when the traces differ.
+fn run_trace_diff((old_path, new_path): (String, String)) -> i32 {
+    let open = |path: &str| {
+        File::open(path).map(BufReader::new).unwrap_or_else(|err| {
+            eprintln!("Could not open {}: {}", path, err);
+            process::exit(1);
+        })
+    };
+
+    let result = match trace::diff(open(&old_path), open(&new_path), TRACE_DIFF_CONTEXT) {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("Could not read the traces: {}", err);
+            return 1;
+        }
+    };
+
+    match result {
+        TraceDiff::Identical { lines } => {
+            println!("The traces are identical ({} lines)", lines);
+            return 0;
+        }
+        TraceDiff::Diverged {
+            line,
+            context,
+            old,
+            new,
+        } => {
+            println!("The traces diverge at line {}:", line);
+
+            for (i, text) in context.iter().enumerate() {
+                println!("  {:>8}  {}", line - (context.len() - i) as u64, text);
+            }
+
+            println!("- {:>8}  {}", line, old);
+            println!("+ {:>8}  {}", line, new);
+
+            if let (Some(old), Some(new)) = (TraceLine::parse(&old), TraceLine::parse(&new)) {
+                println!("Delta: {}", trace::describe_delta(&old, &new));
+            }
+        }
+        TraceDiff::Prefix {
+            line,
+            old_ended,
+            next,
+        } => {
+            let (ended, longer) = if old_ended {
+                (&old_path, &new_path)
+            } else {
+                (&new_path, &old_path)
+            };
+
+            println!(
+                "{} is a prefix of {}, which goes on at line {}:",
+                ended, longer, line
+            );
+            println!("  {:>8}  {}", line, next);
+        }
+    }
+
+    1
+}
+
+struct CompareArgs {
+    rom: String,
+    left: Side,
+    right: Side,
+    seed: u64,
+}
+
+fn parse_compare(mut args: impl Iterator<Item = String>) -> Result<CompareArgs, String> {
+    let mut rom = None;
+    let mut sides = [None, None];
+    let mut seed = 0;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--left" | "--right" => {
+                let side = compare::parse_side(&next_value(&mut args, &arg)?)
+                    .map_err(|err| format!("Invalid {}: {}", arg, err))?;
+
+                sides[(arg == "--right") as usize] = Some(side);
+            }
+            "--seed" => {
+                let text = next_value(&mut args, &arg)?;
+
+                seed = text
+                    .parse()
+                    .map_err(|_| format!("Invalid --seed: {}", text))?;
+            }
+            _ if rom.is_none() && !arg.starts_with('-') => rom = Some(arg),
+            _ => return Err(format!("Unexpected {}", arg)),
+        }
+    }
+
+    let [left, right] = sides;
+
+    Ok(CompareArgs {
+        rom: rom.ok_or("Missing the ROM")?,
+        left: left.ok_or("Missing --left")?,
+        right: right.ok_or("Missing --right")?,
+        seed,
+    })
+}
+
+// `chip8-emu compare ROM --left SPEC --right SPEC`, two instances side by side.
+fn run_compare(args: CompareArgs) -> i32 {
+    let (name, rom_data) = read_rom(&args.rom, None, false);
+
+    check_rom_size(&name, &rom_data);
+    compare::run(
+        &name,
+        &rom_data,
+        args.left,
+        args.right,
+        args.seed,
+        &load_config(),
+    );
+}
+
+struct PatchArgs {
+    rom: String,
+    pokes: Vec<Poke>,
+    output: String,
+    in_place: bool,
+}
+
+fn parse_patch(mut args: impl Iterator<Item = String>) -> Result<PatchArgs, String> {
+    let mut rom = None;
+    let mut pokes = Vec::new();
+    let mut output = None;
+    let mut in_place = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--set" => {
+                let poke = Poke::parse(&next_value(&mut args, &arg)?)
+                    .map_err(|err| format!("Invalid --set: {}", err))?;
+
+                pokes.push(poke);
+            }
+            "-o" | "--output" => output = Some(next_value(&mut args, &arg)?),
+            "--in-place" => in_place = true,
+            _ if rom.is_none() && !arg.starts_with('-') => rom = Some(arg),
+            _ => return Err(format!("Unexpected {}", arg)),
+        }
+    }
+
+    let rom = rom.ok_or("Missing the ROM")?;
+    let output = match (output, in_place) {
+        (Some(_), true) => return Err("-o and --in-place don't go together".to_string()),
+        (Some(output), false) => output,
+        (None, true) => rom.clone(),
+        (None, false) => patched_path(&rom),
+    };
+
+    Ok(PatchArgs {
+        rom,
+        pokes,
+        output,
+        in_place,
+    })
+}
+
+// `game.ch8` becomes `game.patched.ch8` next to it.
+fn patched_path(rom: &str) -> String {
+    let path = Path::new(rom);
+    let name = match path.extension() {
+        Some(extension) => format!("{}.patched.{}", rom_name(rom), extension.to_string_lossy()),
+        None => format!("{}.patched", rom_name(rom)),
+    };
+
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+// `chip8-emu patch ROM`, the hex editor unless there are --set patches to
+// apply. The original is only overwritten with --in-place.
+fn run_patch(args: PatchArgs) -> i32 {
+    let PatchArgs {
+        rom,
+        pokes,
+        output,
+        in_place,
+    } = args;
+
+    if !in_place && fs::canonicalize(&output).ok() == fs::canonicalize(&rom).ok() {
+        eprintln!(
+            "{} is the ROM itself, use --in-place to overwrite it",
+            output
+        );
+        return 2;
+    }
+
+    let rom_data = read_file(&rom);
+
+    check_rom_size(&rom_name(&rom), &rom_data);
+
+    let mut buffer = PatchBuffer::new(rom_data);
+
+    for poke in &pokes {
+        if let Err(err) = buffer.apply(poke) {
+            eprintln!("Refusing --set: {}", err);
+            return 2;
+        }
+    }
+
+    let save = |bytes: &[u8]| {
+        fs::write(&output, bytes)
+            .map(|_| format!("wrote {}", output))
+            .map_err(|err| format!("Could not write {}: {}", output, err))
+    };
+
+    if !pokes.is_empty() {
+        let changes = buffer.changes().len();
+
+        return match save(buffer.bytes()) {
+            Ok(_) => {
+                println!("Changed {} bytes, wrote {}", changes, output);
+                0
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                1
+            }
+        };
+    }
+
+    match hex_editor::run(&mut buffer, save) {
+        Ok(true) => println!("Quit without writing the last changes"),
+        Ok(false) => {}
+        Err(err) => {
+            eprintln!("{}", err);
+            return 1;
+        }
+    }
+
+    0
+}
+
+struct SmokeArgs {
+    dir: String,
+    report: String,
+    jobs: usize,
+    options: SmokeOptions,
+}
+
+fn parse_smoke(mut args: impl Iterator<Item = String>) -> Result<SmokeArgs, String> {
+    let mut dir = None;
+    let mut report = SMOKE_REPORT.to_string();
+    let mut jobs = thread::available_parallelism().map_or(1, |jobs| jobs.get());
+    let mut options = SmokeOptions {
+        frames: smoke::DEFAULT_FRAMES,
+        max_cycles: None,
+        seed: 0,
+        emulator: EmulatorOptions::default(),
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" | "--max-cycles" | "--jobs" => {
+                let value = parse_limit(&arg, &next_value(&mut args, &arg)?)?;
+
+                match arg.as_str() {
+                    "--frames" => options.frames = value.try_into().unwrap_or(u32::MAX),
+                    "--max-cycles" => options.max_cycles = Some(value),
+                    _ => jobs = value as usize,
+                }
+            }
+            "--seed" => {
+                let text = next_value(&mut args, &arg)?;
+
+                options.seed = text
+                    .parse()
+                    .map_err(|_| format!("Invalid --seed: {}", text))?;
+            }
+            "--report" => report = next_value(&mut args, &arg)?,
+            _ if dir.is_none() && !arg.starts_with('-') => dir = Some(arg),
+            _ => return Err(format!("Unexpected {}", arg)),
+        }
+    }
+
+    Ok(SmokeArgs {
+        dir: dir.ok_or("Missing the ROM directory")?,
+        report,
+        jobs,
+        options,
+    })
+}
+
+// `chip8-emu smoke DIR`, runs every .ch8 in DIR headlessly and exits with 1
+// unless all of them completed.
+fn run_smoke(args: SmokeArgs) -> i32 {
+    let roms = read_rom_dir(&args.dir);
+
+    let results = smoke::run_all(&roms, &args.options, args.jobs);
+
+    println!("{}", Summary(&results));
+
+    if let Err(err) = fs::write(&args.report, smoke::to_json(&results, &args.options)) {
+        eprintln!("Could not write {}: {}", args.report, err);
+        return 1;
+    }
+
+    let completed = results
+        .iter()
+        .all(|result| result.outcome == Outcome::Completed);
+
+    (!completed) as i32
+}
+
+fn parse_thumbnails(mut args: impl Iterator<Item = String>) -> Result<String, String> {
+    match (args.next(), args.next()) {
+        (Some(dir), None) if !dir.starts_with('-') => Ok(dir),
+        (None, _) => Err("Missing the ROM directory".to_string()),
+        (Some(dir), None) => Err(format!("Unexpected {}", dir)),
+        (_, Some(extra)) => Err(format!("Unexpected {}", extra)),
+    }
+}
+
+// `chip8-emu thumbnails DIR`, lists the thumbnail of every ROM in DIR and
+// makes the missing ones.
+fn run_thumbnails(dir: String) -> i32 {
+    let Some(cache) = data_dir().map(|dir| dir.join("thumbnails")) else {
+        eprintln!("No data directory to keep the thumbnails in");
+        return 1;
+    };
+    let roms = read_rom_dir(&dir);
+    let thumbnails = Thumbnails::new(cache);
+    let mut failed = false;
+
+    for (name, rom) in &roms {
+        if let Some(path) = thumbnails.cached(rom) {
+            println!("{}  {}", name, path.display());
+        }
+    }
+
+    for (i, written) in thumbnails.fill_in(roms.clone()) {
+        match written {
+            Ok(path) => println!("{}  {} (new)", roms[i].0, path.display()),
+            Err(err) => {
+                eprintln!("Could not write the thumbnail of {}: {}", roms[i].0, err);
+                failed = true;
+            }
+        }
+    }
+
+    failed as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> impl Iterator<Item = String> + '_ {
+        line.split_whitespace().map(String::from)
+    }
+
+    #[test]
+    fn info_and_callgraph_take_a_rom_or_stdin() {
+        assert_eq!(
+            parse_info(args("- --json --entry pong.ch8")),
+            Ok(InfoArgs {
+                rom: "-".to_string(),
+                entry: Some("pong.ch8".to_string()),
+                json: true,
+            })
+        );
+        assert_eq!(
+            parse_callgraph(args("game.ch8 --dot calls.dot")),
+            Ok(CallgraphArgs {
+                rom: "game.ch8".to_string(),
+                entry: None,
+                dot: Some("calls.dot".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_info(args("--json")),
+            Err("Missing the ROM".to_string())
+        );
+        assert_eq!(
+            parse_info(args("a.ch8 b.ch8")),
+            Err("Unexpected b.ch8".to_string())
+        );
+        assert_eq!(
+            parse_callgraph(args("game.ch8 --dot")),
+            Err("--dot needs a value".to_string())
+        );
+    }
+
+    #[test]
+    fn trace_diff_takes_exactly_two_traces() {
+        assert_eq!(
+            parse_trace_diff(args("old.txt new.txt")),
+            Ok(("old.txt".to_string(), "new.txt".to_string()))
+        );
+        assert!(parse_trace_diff(args("old.txt")).is_err());
+        assert!(parse_trace_diff(args("a b c")).is_err());
+    }
+
+    #[test]
+    fn compare_needs_both_sides() {
+        let compare = parse_compare(args("game.ch8 --left vip --right speed=20 --seed 7")).unwrap();
+
+        assert_eq!(compare.rom, "game.ch8");
+        assert_eq!(compare.right.options.cycles_per_frame, 20);
+        assert_eq!(compare.seed, 7);
+
+        assert_eq!(
+            parse_compare(args("game.ch8 --left vip")).err(),
+            Some("Missing --right".to_string())
+        );
+        assert!(parse_compare(args("game.ch8 --left bogus --right vip")).is_err());
+        assert!(parse_compare(args("game.ch8 --left vip --right vip --seed x")).is_err());
+    }
+
+    #[test]
+    fn patch_writes_next_to_the_rom_unless_told_otherwise() {
+        let patch = parse_patch(args("roms/game.ch8 --set 0x200=0")).unwrap();
+
+        assert_eq!(patch.output, "roms/game.patched.ch8");
+        assert_eq!(patch.pokes.len(), 1);
+
+        let patch = parse_patch(args("roms/game.ch8 --in-place")).unwrap();
+
+        assert_eq!(patch.output, "roms/game.ch8");
+        assert!(patch.in_place);
+
+        let patch = parse_patch(args("game -o out.bin")).unwrap();
+
+        assert_eq!(patch.output, "out.bin");
+        assert_eq!(patched_path("game"), "game.patched");
+
+        assert!(parse_patch(args("game.ch8 -o out.ch8 --in-place")).is_err());
+        assert!(parse_patch(args("game.ch8 --set nonsense")).is_err());
+    }
+
+    #[test]
+    fn patch_writes_the_patched_rom_and_leaves_the_original() {
+        let dir = std::env::temp_dir().join(format!("chip8-emu-patch-{}", std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+
+        let rom = dir.join("game.ch8").to_string_lossy().into_owned();
+
+        fs::write(&rom, [0x00, 0xE0, 0x12, 0x00]).unwrap();
+
+        let line = format!("{} --set 0x202..=0x203=0x13 --set 0x201=0xEE", rom);
+
+        assert_eq!(run_patch(parse_patch(args(&line)).unwrap()), 0);
+        assert_eq!(
+            fs::read(dir.join("game.patched.ch8")).unwrap(),
+            [0x00, 0xEE, 0x13, 0x13]
+        );
+        assert_eq!(fs::read(&rom).unwrap(), [0x00, 0xE0, 0x12, 0x00]);
+
+        // Past the end of the ROM nothing is written.
+        let line = format!("{} --set 0x204=0 --in-place", rom);
+
+        assert_eq!(run_patch(parse_patch(args(&line)).unwrap()), 2);
+        assert_eq!(fs::read(&rom).unwrap(), [0x00, 0xE0, 0x12, 0x00]);
+
+        let line = format!("{} --set 0x200=0x60 --in-place", rom);
+
+        assert_eq!(run_patch(parse_patch(args(&line)).unwrap()), 0);
+        assert_eq!(fs::read(&rom).unwrap(), [0x60, 0xE0, 0x12, 0x00]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn smoke_limits_must_be_positive() {
+        let smoke = parse_smoke(args(
+            "roms --frames 30 --max-cycles 900 --jobs 2 --report r.json",
+        ))
+        .unwrap();
+
+        assert_eq!(smoke.dir, "roms");
+        assert_eq!(smoke.options.frames, 30);
+        assert_eq!(smoke.options.max_cycles, Some(900));
+        assert_eq!(smoke.jobs, 2);
+        assert_eq!(smoke.report, "r.json");
+
+        let smoke = parse_smoke(args("roms")).unwrap();
+
+        assert_eq!(smoke.options.frames, smoke::DEFAULT_FRAMES);
+        assert_eq!(smoke.report, SMOKE_REPORT);
+
+        assert_eq!(
+            parse_smoke(args("roms --jobs 0")).err(),
+            Some("Invalid --jobs: 0".to_string())
+        );
+        assert!(parse_smoke(args("--frames 10")).is_err());
+    }
+
+    #[test]
+    fn thumbnails_take_one_directory() {
+        assert_eq!(parse_thumbnails(args("roms")), Ok("roms".to_string()));
+        assert!(parse_thumbnails(args("")).is_err());
+        assert!(parse_thumbnails(args("roms more")).is_err());
+        assert!(parse_thumbnails(args("--all")).is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use chip8_emu::{
+    display::DisplayMode,
+    font::{self, Font},
+};
+
+use crate::keypad::{Keypad, LAYOUT};
+
+pub fn render(display: &mut [u8], mode: DisplayMode, glyphs: &Font, keypad: &Keypad) {
+    let (width, height) = (mode.width, mode.height);
+
+    display.fill(0);
+
+    let mut set = |x: usize, y: usize| display[mode.index(x, y)] = 1;
+
+    for x in 0..width {
+        set(x, 0);
+        set(x, height - 1);
+    }
+
+    for y in 0..height {
+        set(0, y);
+        set(width - 1, y);
+    }
+
+    for digit in 0..0x10u8 {
+        let glyph_x = 2 + (digit as usize % 8) * 5;
+        let glyph_y = 2 + (digit as usize / 8) * (font::GLYPH_HEIGHT + 2);
+
+        for (row, bits) in font::glyph(glyphs, digit).iter().enumerate() {
+            for column in 0..4 {
+                if bits & (0x80 >> column) != 0 {
+                    set(glyph_x + column, glyph_y + row);
+                }
+            }
+        }
+    }
+
+    for y in 17..height - 2 {
+        for x in 2..42 {
+            if (x + y) % 2 == 0 {
+                set(x, y);
+            }
+        }
+    }
+
+    // Held keys are drawn filled, the others as hollow cells.
+    for (row, keys) in LAYOUT.iter().enumerate() {
+        for (column, key) in keys.iter().enumerate() {
+            let cell_x = 45 + column * 4;
+            let cell_y = 2 + row * 4;
+            let held = keypad.is_held(*key);
+
+            for dy in 0..3 {
+                for dx in 0..3 {
+                    if held || dx != 1 || dy != 1 {
+                        set(cell_x + dx, cell_y + dy);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use chip8_emu::{
+    display::DisplayMode,
+    emulator::EmulatorOptions,
+    hash::{sha1, to_hex},
+};
+
+use crate::{
+    palette::Palette,
+    screenshot,
+    smoke::{self, Outcome, SmokeOptions},
+};
+
+// Three seconds, enough for most games to get past a title fade-in.
+pub const FRAMES: u32 = 180;
+const MAX_CYCLES: u64 = 1_000_000;
+
+// A cache of 128x64 PNGs named after the SHA-1 of the ROM, so a renamed ROM
+// keeps its thumbnail and an edited one gets a new one. Only the `thumbnails`
+// subcommand fills it: `--pick` chooses by name in the terminal and there is
+// no window listing ROMs to show them in, so they are left for file managers
+// and launchers.
+#[derive(Clone)]
+pub struct Thumbnails {
+    dir: PathBuf,
+}
+
+impl Thumbnails {
+    pub fn new(dir: PathBuf) -> Self {
+        Thumbnails { dir }
+    }
+
+    pub fn path(&self, rom: &[u8]) -> PathBuf {
+        self.dir.join(format!("{}.png", to_hex(&sha1(rom))))
+    }
+
+    pub fn cached(&self, rom: &[u8]) -> Option<PathBuf> {
+        let path = self.path(rom);
+
+        path.is_file().then_some(path)
+    }
+
+    // Makes the missing thumbnails one after the other on a worker thread.
+    // Each one comes back with its index in `roms` once it is written, and
+    // the worker stops early when the receiver is dropped.
+    pub fn fill_in(
+        &self,
+        roms: Vec<(String, Vec<u8>)>,
+    ) -> Receiver<(usize, Result<PathBuf, String>)> {
+        let (sender, receiver) = mpsc::channel();
+        let thumbnails = self.clone();
+
+        thread::spawn(move || {
+            for (i, (name, rom)) in roms.iter().enumerate() {
+                if thumbnails.cached(rom).is_some() {
+                    continue;
+                }
+
+                let path = thumbnails.path(rom);
+                let written = fs::create_dir_all(&thumbnails.dir)
+                    .and_then(|_| fs::write(&path, render(name, rom)))
+                    .map(|_| path)
+                    .map_err(|err| err.to_string());
+
+                if sender.send((i, written)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        receiver
+    }
+}
+
+// Runs the ROM like `smoke` does, so the same ROM always gives the same
+// picture. A ROM that faults or doesn't load gets the placeholder; one still
+// busy when the cycle budget runs out is shown as it was.
+pub fn render(name: &str, rom: &[u8]) -> Vec<u8> {
+    let options = SmokeOptions {
+        frames: FRAMES,
+        max_cycles: Some(MAX_CYCLES),
+        seed: 0,
+        emulator: EmulatorOptions::default(),
+    };
+    let result = smoke::run(name, rom, &options);
+
+    match result.outcome {
+        Outcome::Completed | Outcome::Watchdog => encode(&result.display, result.display_mode),
+        _ => encode(&placeholder(), DisplayMode::LORES),
+    }
+}
+
+fn encode(display: &[u8], mode: DisplayMode) -> Vec<u8> {
+    let scale = DisplayMode::HIRES.width / mode.width;
+
+    screenshot::encode_png(display, mode, Palette::DEFAULT, scale)
+}
+
+// A cross over the whole screen.
+fn placeholder() -> Vec<u8> {
+    let mode = DisplayMode::LORES;
+    let mut display = vec![0; mode.pixel_count()];
+
+    for x in 0..mode.width {
+        let y = x * mode.height / mode.width;
+
+        display[mode.index(x, y)] = 1;
+        display[mode.index(x, mode.height - 1 - y)] = 1;
+    }
+
+    display
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+    // Draws a zero and waits for a key.
+    const ZERO: [u8; 15] = [
+        0x00, 0xE0, 0xA2, 0x0A, 0xD0, 0x05, 0xF0, 0x0A, 0x12, 0x06, 0xF0, 0x90, 0x90, 0x90, 0xF0,
+    ];
+
+    fn cache(name: &str) -> Thumbnails {
+        let dir = env::temp_dir().join(format!("chip8-emu-{}-{}", name, process::id()));
+
+        let _ = fs::remove_dir_all(&dir);
+        Thumbnails::new(dir)
+    }
+
+    #[test]
+    fn thumbnails_are_found_by_the_rom_not_its_name() {
+        let thumbnails = cache("thumbnails-keys");
+        let mut edited = ZERO.to_vec();
+
+        edited[12] = 0x60;
+
+        assert_ne!(thumbnails.path(&ZERO), thumbnails.path(&edited));
+        assert_eq!(
+            thumbnails.path(&[]).file_name().unwrap(),
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709.png"
+        );
+        assert_eq!(thumbnails.cached(&ZERO), None);
+    }
+
+    #[test]
+    fn the_worker_makes_only_the_missing_ones() {
+        let thumbnails = cache("thumbnails-fill");
+        let roms = vec![("zero".to_string(), ZERO.to_vec())];
+
+        let made: Vec<_> = thumbnails.fill_in(roms.clone()).into_iter().collect();
+
+        assert_eq!(made, [(0, Ok(thumbnails.path(&ZERO)))]);
+
+        let png = fs::read(thumbnails.cached(&ZERO).unwrap()).unwrap();
+
+        assert!(png.starts_with(&PNG_SIGNATURE));
+        assert_eq!(png, render("zero", &ZERO));
+
+        // Renamed it is already there, edited it needs a new one.
+        let mut edited = ZERO.to_vec();
+
+        edited[12] = 0x60;
+
+        let roms = vec![
+            ("renamed".to_string(), ZERO.to_vec()),
+            ("edited".to_string(), edited.clone()),
+        ];
+        let made: Vec<_> = thumbnails.fill_in(roms).into_iter().collect();
+
+        assert_eq!(made, [(1, Ok(thumbnails.path(&edited)))]);
+        assert_ne!(fs::read(thumbnails.path(&edited)).unwrap(), png);
+        assert!(thumbnails.cached(&ZERO).is_some());
+
+        fs::remove_dir_all(&thumbnails.dir).unwrap();
+    }
+
+    #[test]
+    fn a_broken_rom_gets_the_cross() {
+        let cross = render("bad", &[0x5A, 0xB1]);
+
+        assert_eq!(cross, encode(&placeholder(), DisplayMode::LORES));
+        assert_eq!(render("empty", &[]), cross);
+        assert_ne!(render("zero", &ZERO), cross);
+    }
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timing {
+    Fixed,
+    Vip,
+}
+
+pub const DEFAULT_REFRESH_RATE: u32 = 60;
+
+// The VIP's 1802 runs at 1.7609 MHz with 8 clocks per machine cycle.
+const VIP_MACHINE_CYCLES_PER_SECOND: u32 = 1_760_900 / 8;
+
+pub fn is_valid_refresh_rate(hz: u32) -> bool {
+    hz == 50 || hz == 60
+}
+
+// Timers, frames and the VIP cycle budget all advance at the refresh rate.
+pub fn frame_duration(refresh_rate: u32) -> Duration {
+    Duration::from_secs(1) / refresh_rate
+}
+
+pub fn vip_cycles_per_frame(refresh_rate: u32) -> i32 {
+    (VIP_MACHINE_CYCLES_PER_SECOND / refresh_rate) as i32
+}
+
+const FETCH_CYCLES: u32 = 40;
+
+// Approximate cost in machine cycles of one instruction in the VIP
+// interpreter, including the common fetch and decode. Skips cost a little
+// more when taken, sprites more when they straddle a byte boundary.
+pub fn vip_cycles(opcode: u16, registers: &[u8; 0x10]) -> u32 {
+    let x = ((opcode >> 8) & 0xF) as usize;
+    let y = ((opcode >> 4) & 0xF) as usize;
+    let n = (opcode & 0xF) as u32;
+    let nn = (opcode & 0xFF) as u8;
+
+    let skip = |taken: bool| if taken { 14 } else { 10 };
+
+    let cost = match opcode >> 12 {
+        0x0 if opcode == 0x00E0 => 3078,
+        0x0 if opcode == 0x00EE => 10,
+        0x1 => 12,
+        0x2 => 26,
+        0x3 => skip(registers[x] == nn),
+        0x4 => skip(registers[x] != nn),
+        0x5 => skip(registers[x] == registers[y]),
+        0x6 => 6,
+        0x7 => 10,
+        0x8 if n == 0 => 12,
+        0x8 => 44,
+        0x9 => skip(registers[x] != registers[y]),
+        0xA => 12,
+        0xB => 22,
+        0xC => 36,
+        0xD if registers[x].is_multiple_of(8) => 26 + n * 34,
+        0xD => 26 + n * 54,
+        0xE => 14,
+        0xF => match nn {
+            0x1E | 0x29 => 16,
+            0x33 => {
+                let value = registers[x] as u32;
+
+                72 + 8 * (value / 100 + value / 10 % 10 + value % 10)
+            }
+            0x55 | 0x65 => 14 + 14 * (x as u32 + 1),
+            _ => 10,
+        },
+        _ => 0,
+    };
+
+    FETCH_CYCLES + cost
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_vip_frame_holds_its_share_of_the_machine_cycles() {
+        assert_eq!(vip_cycles_per_frame(60), 3668);
+        assert_eq!(vip_cycles_per_frame(50), 4402);
+        assert_eq!(frame_duration(50), Duration::from_millis(20));
+    }
+
+    #[test]
+    fn instructions_cost_the_fetch_plus_their_own_work() {
+        let mut registers = [0; 0x10];
+
+        assert_eq!(vip_cycles(0x00E0, &registers), 40 + 3078);
+        assert_eq!(vip_cycles(0x6012, &registers), 40 + 6);
+        assert_eq!(vip_cycles(0x8120, &registers), 40 + 12);
+        assert_eq!(vip_cycles(0x8124, &registers), 40 + 44);
+        // Skips cost more when taken.
+        assert_eq!(vip_cycles(0x3000, &registers), 40 + 14);
+        assert_eq!(vip_cycles(0x3001, &registers), 40 + 10);
+
+        // Sprites cost more off a byte boundary.
+        assert_eq!(vip_cycles(0xD015, &registers), 40 + 26 + 5 * 34);
+        registers[0] = 3;
+        assert_eq!(vip_cycles(0xD015, &registers), 40 + 26 + 5 * 54);
+
+        // BCD goes up with the digits, stores and loads with the registers.
+        registers[0] = 255;
+        assert_eq!(vip_cycles(0xF033, &registers), 40 + 72 + 8 * (2 + 5 + 5));
+        assert_eq!(vip_cycles(0xF355, &registers), 40 + 14 + 14 * 4);
+        assert_eq!(vip_cycles(0xF065, &registers), 40 + 14 + 14);
+    }
+}
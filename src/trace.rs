@@ -0,0 +1,278 @@
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead},
+};
+
+use crate::disassembler::disassemble;
+
+// One executed instruction: `<pc> <opcode> <mnemonic>  V0=.. .. VF=.. I=...`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceLine {
+    pub pc: u16,
+    pub opcode: u16,
+    pub registers: [u8; 0x10],
+    pub pointer: u16,
+}
+
+impl TraceLine {
+    pub fn format(&self) -> String {
+        let registers: Vec<String> = self
+            .registers
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!("V{:X}={:02X}", i, value))
+            .collect();
+
+        format!(
+            "{:03X} {:04X} {:<16} {} I={:03X}",
+            self.pc,
+            self.opcode,
+            disassemble(self.opcode),
+            registers.join(" "),
+            self.pointer
+        )
+    }
+
+    pub fn parse(line: &str) -> Option<TraceLine> {
+        let mut tokens = line.split_whitespace();
+        let pc = u16::from_str_radix(tokens.next()?, 16).ok()?;
+        let opcode = u16::from_str_radix(tokens.next()?, 16).ok()?;
+        let mut registers = [0; 0x10];
+        let mut pointer = None;
+        let mut seen = 0u16;
+
+        for (name, value) in tokens.filter_map(|token| token.split_once('=')) {
+            if name == "I" {
+                pointer = Some(u16::from_str_radix(value, 16).ok()?);
+            } else if let Some(index) = name.strip_prefix('V') {
+                let index = usize::from_str_radix(index, 16)
+                    .ok()
+                    .filter(|i| *i < 0x10)?;
+
+                registers[index] = u8::from_str_radix(value, 16).ok()?;
+                seen |= 1 << index;
+            }
+        }
+
+        if seen != 0xFFFF {
+            return None;
+        }
+
+        Some(TraceLine {
+            pc,
+            opcode,
+            registers,
+            pointer: pointer?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TraceDiff {
+    Identical {
+        lines: u64,
+    },
+    // `line` is 1-based, `context` holds the shared lines right before it.
+    Diverged {
+        line: u64,
+        context: Vec<String>,
+        old: String,
+        new: String,
+    },
+    // One trace ended while the other went on, `line` is the first extra one.
+    Prefix {
+        line: u64,
+        old_ended: bool,
+        next: String,
+    },
+}
+
+// Streams both traces line by line so their size doesn't matter.
+pub fn diff(old: impl BufRead, new: impl BufRead, context: usize) -> io::Result<TraceDiff> {
+    let mut old = old.lines();
+    let mut new = new.lines();
+    let mut previous = VecDeque::with_capacity(context + 1);
+    let mut line = 0;
+
+    loop {
+        line += 1;
+
+        match (old.next().transpose()?, new.next().transpose()?) {
+            (None, None) => return Ok(TraceDiff::Identical { lines: line - 1 }),
+            // A trailing blank line is not an extra instruction.
+            (Some(next), None) | (None, Some(next)) if next.is_empty() => line -= 1,
+            (Some(next), None) => {
+                return Ok(TraceDiff::Prefix {
+                    line,
+                    old_ended: false,
+                    next,
+                })
+            }
+            (None, Some(next)) => {
+                return Ok(TraceDiff::Prefix {
+                    line,
+                    old_ended: true,
+                    next,
+                })
+            }
+            (Some(old), Some(new)) if old != new => {
+                return Ok(TraceDiff::Diverged {
+                    line,
+                    context: previous.into(),
+                    old,
+                    new,
+                })
+            }
+            (Some(same), Some(_)) => {
+                if previous.len() == context {
+                    previous.pop_front();
+                }
+
+                if context > 0 {
+                    previous.push_back(same);
+                }
+            }
+        }
+    }
+}
+
+// What changed between two parsed lines, e.g. `pc 204 -> 206, V3 1F -> 20`.
+pub fn describe_delta(old: &TraceLine, new: &TraceLine) -> String {
+    let mut changes = Vec::new();
+
+    if old.pc != new.pc {
+        changes.push(format!("pc {:03X} -> {:03X}", old.pc, new.pc));
+    }
+
+    if old.opcode != new.opcode {
+        changes.push(format!(
+            "opcode {:04X} ({}) -> {:04X} ({})",
+            old.opcode,
+            disassemble(old.opcode),
+            new.opcode,
+            disassemble(new.opcode)
+        ));
+    }
+
+    for (i, (old, new)) in old.registers.iter().zip(new.registers).enumerate() {
+        if *old != new {
+            changes.push(format!("V{:X} {:02X} -> {:02X}", i, old, new));
+        }
+    }
+
+    if old.pointer != new.pointer {
+        changes.push(format!("I {:03X} -> {:03X}", old.pointer, new.pointer));
+    }
+
+    changes.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(pc: u16, opcode: u16, v3: u8) -> TraceLine {
+        let mut registers = [0; 0x10];
+
+        registers[3] = v3;
+
+        TraceLine {
+            pc,
+            opcode,
+            registers,
+            pointer: 0x300,
+        }
+    }
+
+    // A trace of `count` lines, V3 counting up.
+    fn trace(count: u8) -> String {
+        (0..count)
+            .map(|i| line(0x200 + 2 * i as u16, 0x7301, i).format() + "\n")
+            .collect()
+    }
+
+    fn diff_traces(old: &str, new: &str, context: usize) -> TraceDiff {
+        diff(old.as_bytes(), new.as_bytes(), context).unwrap()
+    }
+
+    #[test]
+    fn lines_read_back_what_was_written() {
+        let written = line(0x20A, 0xD01F, 0x1F);
+        let text = written.format();
+
+        assert!(text.starts_with("20A D01F DRW V0, V1, 15   V0=00"));
+        assert!(text.ends_with(
+            "V3=1F V4=00 V5=00 V6=00 V7=00 V8=00 V9=00 VA=00 VB=00 VC=00 VD=00 VE=00 VF=00 I=300"
+        ));
+        assert_eq!(TraceLine::parse(&text), Some(written));
+        assert_eq!(TraceLine::parse(&text.replace(" VF=00", "")), None);
+        assert_eq!(TraceLine::parse(&text.replace(" I=300", "")), None);
+        assert_eq!(TraceLine::parse(&text.replace("V3=1F", "V3=XY")), None);
+        assert_eq!(TraceLine::parse("garbage"), None);
+    }
+
+    #[test]
+    fn the_first_differing_line_comes_with_its_context() {
+        let old = trace(10);
+        let new = old.replace("V3=06", "V3=07");
+
+        assert_eq!(
+            diff_traces(&old, &old, 3),
+            TraceDiff::Identical { lines: 10 }
+        );
+        assert_eq!(
+            diff_traces(&old, &new, 2),
+            TraceDiff::Diverged {
+                line: 7,
+                context: old.lines().skip(4).take(2).map(String::from).collect(),
+                old: old.lines().nth(6).unwrap().to_string(),
+                new: new.lines().nth(6).unwrap().to_string(),
+            }
+        );
+
+        match diff_traces(&trace(2), &trace(2).replace("V3=00", "V3=01"), 5) {
+            TraceDiff::Diverged { line, context, .. } => assert_eq!((line, context), (1, vec![])),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn a_trace_that_stops_early_is_a_prefix() {
+        let (short, long) = (trace(4), trace(6));
+
+        assert_eq!(
+            diff_traces(&short, &long, 1),
+            TraceDiff::Prefix {
+                line: 5,
+                old_ended: true,
+                next: long.lines().nth(4).unwrap().to_string(),
+            }
+        );
+        assert_eq!(
+            diff_traces(&long, &short, 1),
+            TraceDiff::Prefix {
+                line: 5,
+                old_ended: false,
+                next: long.lines().nth(4).unwrap().to_string(),
+            }
+        );
+        assert_eq!(
+            diff_traces(&(short.clone() + "\n"), &short, 1),
+            TraceDiff::Identical { lines: 4 }
+        );
+    }
+
+    #[test]
+    fn the_delta_lists_only_what_changed() {
+        let old = line(0x204, 0x7301, 0x1F);
+        let mut new = line(0x206, 0x6301, 0x20);
+
+        new.pointer = 0x302;
+
+        assert_eq!(describe_delta(&old, &old), "");
+        assert_eq!(
+            describe_delta(&old, &new),
+            "pc 204 -> 206, opcode 7301 (ADD V3, #01) -> 6301 (LD V3, #01), V3 1F -> 20, I 300 -> 302"
+        );
+    }
+}
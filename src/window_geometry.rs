@@ -0,0 +1,285 @@
+use std::{fmt, str::FromStr};
+
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    monitor::MonitorHandle,
+};
+
+// Where the window was when the emulator last quit, one `<key> <value>` per line.
+// In fullscreen, the position and size are the ones of the window it left.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WindowGeometry {
+    pub position: Option<(i32, i32)>,
+    pub monitor: Option<String>,
+    pub size: Option<(u32, u32)>,
+    pub fullscreen: bool,
+}
+
+// Just the parts of a MonitorHandle the placement looks at.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Monitor {
+    pub name: Option<String>,
+    pub position: (i32, i32),
+    pub size: (u32, u32),
+}
+
+impl Monitor {
+    pub fn from_handle(handle: &MonitorHandle) -> Self {
+        let position = handle.position();
+        let size = handle.size();
+
+        Monitor {
+            name: handle.name(),
+            position: (position.x, position.y),
+            size: (size.width, size.height),
+        }
+    }
+
+    fn contains(&self, (x, y): (i32, i32)) -> bool {
+        let (left, top) = self.position;
+
+        x >= left
+            && y >= top
+            && ((x - left) as u32) < self.size.0
+            && ((y - top) as u32) < self.size.1
+    }
+
+    fn center(&self, window: PhysicalSize<u32>) -> PhysicalPosition<i32> {
+        let offset = |monitor: u32, window: u32| monitor.saturating_sub(window) as i32 / 2;
+
+        PhysicalPosition::new(
+            self.position.0 + offset(self.size.0, window.width),
+            self.position.1 + offset(self.size.1, window.height),
+        )
+    }
+}
+
+impl WindowGeometry {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut geometry = WindowGeometry::default();
+
+        for (i, line) in source.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+
+            match key {
+                "position" => {
+                    let position = pair(value)
+                        .ok_or_else(|| format!("line {}: expected `position <x> <y>`", i + 1))?;
+
+                    geometry.position = Some(position);
+                }
+                "size" => {
+                    let size = pair(value)
+                        .filter(|&(width, height)| width > 0 && height > 0)
+                        .ok_or_else(|| {
+                            format!("line {}: expected `size <width> <height>`", i + 1)
+                        })?;
+
+                    geometry.size = Some(size);
+                }
+                "monitor" => geometry.monitor = Some(value.to_string()),
+                "fullscreen" if value.is_empty() => geometry.fullscreen = true,
+                _ => return Err(format!("line {}: unknown key `{}`", i + 1, key)),
+            }
+        }
+
+        Ok(geometry)
+    }
+
+    // The saved spot if the monitor it was on is still connected and still
+    // covers it, otherwise the window goes in the middle of the first monitor.
+    pub fn placement(
+        &self,
+        monitors: &[Monitor],
+        window: PhysicalSize<u32>,
+    ) -> Option<PhysicalPosition<i32>> {
+        let saved = self.position.filter(|position| {
+            monitors
+                .iter()
+                .any(|monitor| monitor.name == self.monitor && monitor.contains(*position))
+        });
+
+        match saved {
+            Some((x, y)) => Some(PhysicalPosition::new(x, y)),
+            None => monitors.first().map(|monitor| monitor.center(window)),
+        }
+    }
+}
+
+fn pair<T: FromStr>(value: &str) -> Option<(T, T)> {
+    let (a, b) = value.split_once(' ')?;
+
+    Some((a.parse().ok()?, b.trim().parse().ok()?))
+}
+
+impl fmt::Display for WindowGeometry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((x, y)) = self.position {
+            writeln!(f, "position {} {}", x, y)?;
+        }
+
+        if let Some((width, height)) = self.size {
+            writeln!(f, "size {} {}", width, height)?;
+        }
+
+        if let Some(monitor) = &self.monitor {
+            writeln!(f, "monitor {}", monitor)?;
+        }
+
+        if self.fullscreen {
+            writeln!(f, "fullscreen")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: PhysicalSize<u32> = PhysicalSize::new(640, 320);
+
+    // A laptop panel, and a bigger screen to its left.
+    fn monitors() -> Vec<Monitor> {
+        vec![
+            Monitor {
+                name: Some("eDP-1".to_string()),
+                position: (0, 0),
+                size: (1920, 1080),
+            },
+            Monitor {
+                name: Some("HDMI-1".to_string()),
+                position: (-2560, -200),
+                size: (2560, 1440),
+            },
+        ]
+    }
+
+    fn saved(x: i32, y: i32, monitor: &str) -> WindowGeometry {
+        WindowGeometry {
+            position: Some((x, y)),
+            monitor: Some(monitor.to_string()),
+            ..WindowGeometry::default()
+        }
+    }
+
+    #[test]
+    fn a_position_on_its_connected_monitor_is_kept() {
+        assert_eq!(
+            saved(100, 50, "eDP-1").placement(&monitors(), WINDOW),
+            Some(PhysicalPosition::new(100, 50))
+        );
+        assert_eq!(
+            saved(-2000, -100, "HDMI-1").placement(&monitors(), WINDOW),
+            Some(PhysicalPosition::new(-2000, -100))
+        );
+    }
+
+    #[test]
+    fn otherwise_the_window_is_centered_on_the_first_monitor() {
+        let centered = Some(PhysicalPosition::new(640, 380));
+
+        // The monitor was unplugged.
+        assert_eq!(
+            saved(-2000, -100, "HDMI-1").placement(&monitors()[..1], WINDOW),
+            centered
+        );
+        // Still there, but it no longer covers the spot.
+        assert_eq!(
+            saved(1920, 50, "eDP-1").placement(&monitors(), WINDOW),
+            centered
+        );
+        assert_eq!(
+            saved(-1, 50, "eDP-1").placement(&monitors(), WINDOW),
+            centered
+        );
+        // On another monitor than the one saved.
+        assert_eq!(
+            saved(100, 50, "HDMI-1").placement(&monitors(), WINDOW),
+            centered
+        );
+        assert_eq!(
+            WindowGeometry::default().placement(&monitors(), WINDOW),
+            centered
+        );
+        assert_eq!(saved(100, 50, "eDP-1").placement(&[], WINDOW), None);
+    }
+
+    #[test]
+    fn a_window_bigger_than_the_monitor_goes_in_its_corner() {
+        let small = Monitor {
+            name: None,
+            position: (10, 20),
+            size: (320, 240),
+        };
+
+        assert_eq!(
+            WindowGeometry::default().placement(&[small], WINDOW),
+            Some(PhysicalPosition::new(10, 20))
+        );
+    }
+
+    #[test]
+    fn the_saved_geometry_reads_back_what_was_written() {
+        let geometry = saved(-2000, -100, "HDMI-1 (DELL U2719D)");
+        let written = geometry.to_string();
+
+        assert_eq!(
+            written,
+            "position -2000 -100\nmonitor HDMI-1 (DELL U2719D)\n"
+        );
+        assert_eq!(WindowGeometry::parse(&written), Ok(geometry));
+        assert_eq!(WindowGeometry::parse(""), Ok(WindowGeometry::default()));
+        assert_eq!(
+            WindowGeometry::parse("\nposition 10").err().as_deref(),
+            Some("line 2: expected `position <x> <y>`")
+        );
+        assert_eq!(
+            WindowGeometry::parse("width 640").err().as_deref(),
+            Some("line 1: unknown key `width`")
+        );
+    }
+
+    #[test]
+    fn the_size_and_fullscreen_read_back_too() {
+        let geometry = WindowGeometry {
+            size: Some((1280, 720)),
+            fullscreen: true,
+            ..saved(100, 50, "eDP-1")
+        };
+        let written = geometry.to_string();
+
+        assert_eq!(
+            written,
+            "position 100 50\nsize 1280 720\nmonitor eDP-1\nfullscreen\n"
+        );
+        assert_eq!(WindowGeometry::parse(&written), Ok(geometry));
+
+        // Fullscreen on a monitor, without a window to come back to.
+        let fullscreen = WindowGeometry {
+            monitor: Some("HDMI-1".to_string()),
+            fullscreen: true,
+            ..WindowGeometry::default()
+        };
+
+        assert_eq!(
+            WindowGeometry::parse(&fullscreen.to_string()),
+            Ok(fullscreen)
+        );
+
+        for (source, error) in [
+            ("size 0 320", "line 1: expected `size <width> <height>`"),
+            ("size 640", "line 1: expected `size <width> <height>`"),
+            ("fullscreen yes", "line 1: unknown key `fullscreen`"),
+        ] {
+            assert_eq!(WindowGeometry::parse(source).err().as_deref(), Some(error));
+        }
+    }
+}
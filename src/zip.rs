@@ -0,0 +1,317 @@
+use std::io::Read;
+
+use flate2::{read::DeflateDecoder, Crc};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const END_OF_DIRECTORY_SIZE: usize = 22;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+
+pub struct ZipEntry {
+    pub name: String,
+    pub size: usize,
+    method: u16,
+    compressed_size: usize,
+    crc32: u32,
+    header_offset: usize,
+}
+
+pub struct ZipArchive<'a> {
+    data: &'a [u8],
+    entries: Vec<ZipEntry>,
+}
+
+pub fn is_zip(data: &[u8]) -> bool {
+    read_u32(data, 0) == Some(LOCAL_HEADER_SIGNATURE)
+        || read_u32(data, 0) == Some(END_OF_DIRECTORY_SIGNATURE)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+impl<'a> ZipArchive<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, String> {
+        let corrupt = || "corrupt zip archive".to_string();
+
+        // The end-of-directory record sits at the very end, before a comment of up to 64K.
+        let search_start = data.len().saturating_sub(END_OF_DIRECTORY_SIZE + 0xFFFF);
+        let end = (search_start..=data.len().saturating_sub(END_OF_DIRECTORY_SIZE))
+            .rev()
+            .find(|offset| read_u32(data, *offset) == Some(END_OF_DIRECTORY_SIGNATURE))
+            .ok_or_else(corrupt)?;
+
+        let count = read_u16(data, end + 10).ok_or_else(corrupt)? as usize;
+        let mut offset = read_u32(data, end + 16).ok_or_else(corrupt)? as usize;
+        let mut entries = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            if read_u32(data, offset) != Some(CENTRAL_HEADER_SIGNATURE) {
+                return Err(corrupt());
+            }
+
+            let name_len = read_u16(data, offset + 28).ok_or_else(corrupt)? as usize;
+            let extra_len = read_u16(data, offset + 30).ok_or_else(corrupt)? as usize;
+            let comment_len = read_u16(data, offset + 32).ok_or_else(corrupt)? as usize;
+            let name = data
+                .get(offset + 46..offset + 46 + name_len)
+                .ok_or_else(corrupt)?;
+
+            entries.push(ZipEntry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                method: read_u16(data, offset + 10).ok_or_else(corrupt)?,
+                crc32: read_u32(data, offset + 16).ok_or_else(corrupt)?,
+                compressed_size: read_u32(data, offset + 20).ok_or_else(corrupt)? as usize,
+                size: read_u32(data, offset + 24).ok_or_else(corrupt)? as usize,
+                header_offset: read_u32(data, offset + 42).ok_or_else(corrupt)? as usize,
+            });
+
+            offset += 46 + name_len + extra_len + comment_len;
+        }
+
+        Ok(ZipArchive { data, entries })
+    }
+
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    pub fn find(&self, name: &str) -> Option<&ZipEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    pub fn extract(&self, entry: &ZipEntry, max_size: usize) -> Result<Vec<u8>, String> {
+        let corrupt = || format!("corrupt zip entry {}", entry.name);
+
+        if entry.size > max_size {
+            return Err(format!(
+                "{} is {} bytes, larger than the {} bytes allowed",
+                entry.name, entry.size, max_size
+            ));
+        }
+
+        let header = entry.header_offset;
+
+        if read_u32(self.data, header) != Some(LOCAL_HEADER_SIGNATURE) {
+            return Err(corrupt());
+        }
+
+        let name_len = read_u16(self.data, header + 26).ok_or_else(corrupt)? as usize;
+        let extra_len = read_u16(self.data, header + 28).ok_or_else(corrupt)? as usize;
+        let start = header + 30 + name_len + extra_len;
+        let compressed = self
+            .data
+            .get(start..start + entry.compressed_size)
+            .ok_or_else(corrupt)?;
+
+        let contents = match entry.method {
+            METHOD_STORED => compressed.to_vec(),
+            METHOD_DEFLATE => {
+                let mut contents = Vec::with_capacity(entry.size);
+
+                DeflateDecoder::new(compressed)
+                    .take(max_size as u64 + 1)
+                    .read_to_end(&mut contents)
+                    .map_err(|_| corrupt())?;
+
+                contents
+            }
+            method => {
+                return Err(format!(
+                    "{} uses unsupported compression method {}",
+                    entry.name, method
+                ))
+            }
+        };
+
+        let mut crc = Crc::new();
+        crc.update(&contents);
+
+        if contents.len() != entry.size || crc.sum() != entry.crc32 {
+            return Err(corrupt());
+        }
+
+        Ok(contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::DeflateEncoder, Compression};
+
+    use super::*;
+
+    const ROM: &[u8] = &[
+        0x00, 0xE0, 0xA2, 0x2A, 0x60, 0x0C, 0x61, 0x08, 0xD0, 0x1F, 0x12, 0x0A,
+    ];
+
+    // A zip with one entry per (name, method, contents), laid out like the
+    // ones zip tools write: local headers and data, then the central
+    // directory and the end record.
+    fn build(files: &[(&str, u16, &[u8])]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut central = Vec::new();
+
+        for (name, method, contents) in files {
+            let mut crc = Crc::new();
+            crc.update(contents);
+
+            let stored = match *method {
+                METHOD_DEFLATE => {
+                    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+
+                    encoder.write_all(contents).unwrap();
+                    encoder.finish().unwrap()
+                }
+                _ => contents.to_vec(),
+            };
+            let offset = data.len() as u32;
+
+            data.extend(LOCAL_HEADER_SIGNATURE.to_le_bytes());
+            data.extend([20, 0, 0, 0]);
+            data.extend(method.to_le_bytes());
+            data.extend([0; 4]);
+            data.extend(crc.sum().to_le_bytes());
+            data.extend((stored.len() as u32).to_le_bytes());
+            data.extend((contents.len() as u32).to_le_bytes());
+            data.extend((name.len() as u16).to_le_bytes());
+            data.extend([0, 0]);
+            data.extend(name.as_bytes());
+            data.extend(&stored);
+
+            central.extend(CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            central.extend([20, 0, 20, 0, 0, 0]);
+            central.extend(method.to_le_bytes());
+            central.extend([0; 4]);
+            central.extend(crc.sum().to_le_bytes());
+            central.extend((stored.len() as u32).to_le_bytes());
+            central.extend((contents.len() as u32).to_le_bytes());
+            central.extend((name.len() as u16).to_le_bytes());
+            central.extend([0; 12]);
+            central.extend(offset.to_le_bytes());
+            central.extend(name.as_bytes());
+        }
+
+        let directory_offset = data.len() as u32;
+
+        data.extend(&central);
+        data.extend(END_OF_DIRECTORY_SIGNATURE.to_le_bytes());
+        data.extend([0; 4]);
+        data.extend((files.len() as u16).to_le_bytes());
+        data.extend((files.len() as u16).to_le_bytes());
+        data.extend((central.len() as u32).to_le_bytes());
+        data.extend(directory_offset.to_le_bytes());
+        data.extend([0, 0]);
+
+        data
+    }
+
+    fn extract(data: &[u8], name: &str, max_size: usize) -> Result<Vec<u8>, String> {
+        let archive = ZipArchive::parse(data)?;
+        let entry = archive.find(name).unwrap();
+
+        archive.extract(entry, max_size)
+    }
+
+    #[test]
+    fn stored_and_deflated_entries_come_out_whole() {
+        let text = b"freeze 0x1F0=3 lives\n".repeat(20);
+        let data = build(&[
+            ("pong.ch8", METHOD_STORED, ROM),
+            ("cheats.txt", METHOD_DEFLATE, &text),
+        ]);
+
+        assert!(is_zip(&data));
+
+        let archive = ZipArchive::parse(&data).unwrap();
+        let names: Vec<&str> = archive
+            .entries()
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect();
+
+        assert_eq!(names, ["pong.ch8", "cheats.txt"]);
+        assert_eq!(archive.find("cheats.txt").unwrap().size, text.len());
+        assert!(archive.find("tetris.ch8").is_none());
+
+        assert_eq!(extract(&data, "pong.ch8", 4096), Ok(ROM.to_vec()));
+        assert_eq!(extract(&data, "cheats.txt", 4096), Ok(text));
+    }
+
+    #[test]
+    fn a_truncated_central_directory_is_rejected() {
+        let data = build(&[("pong.ch8", METHOD_STORED, ROM)]);
+
+        assert_eq!(
+            ZipArchive::parse(&data[..data.len() - 4]).err(),
+            Some("corrupt zip archive".to_string())
+        );
+
+        // The end record counts two entries, the directory holds one.
+        let mut data = data;
+        let count = data.len() - END_OF_DIRECTORY_SIZE + 10;
+
+        data[count] = 2;
+
+        assert_eq!(
+            ZipArchive::parse(&data).err(),
+            Some("corrupt zip archive".to_string())
+        );
+    }
+
+    #[test]
+    fn a_crc_mismatch_is_rejected() {
+        let mut data = build(&[("pong.ch8", METHOD_STORED, ROM)]);
+        let contents = 30 + "pong.ch8".len();
+
+        data[contents] ^= 0xFF;
+
+        assert_eq!(
+            extract(&data, "pong.ch8", 4096),
+            Err("corrupt zip entry pong.ch8".to_string())
+        );
+
+        let mut data = build(&[("pong.ch8", METHOD_DEFLATE, ROM)]);
+
+        // The CRC in the central directory.
+        let crc = data.len() - END_OF_DIRECTORY_SIZE - 46 - "pong.ch8".len() + 16;
+
+        data[crc] ^= 0xFF;
+
+        assert_eq!(
+            extract(&data, "pong.ch8", 4096),
+            Err("corrupt zip entry pong.ch8".to_string())
+        );
+    }
+
+    #[test]
+    fn oversize_entries_and_unknown_methods_are_rejected() {
+        let data = build(&[("big.ch8", METHOD_DEFLATE, &[0; 5000])]);
+
+        assert_eq!(
+            extract(&data, "big.ch8", 3584),
+            Err("big.ch8 is 5000 bytes, larger than the 3584 bytes allowed".to_string())
+        );
+
+        // Bzip2, the data doesn't matter.
+        let data = build(&[("pong.ch8", 12, ROM)]);
+
+        assert_eq!(
+            extract(&data, "pong.ch8", 4096),
+            Err("pong.ch8 uses unsupported compression method 12".to_string())
+        );
+    }
+}
@@ -0,0 +1,61 @@
+#include "chip8.h"
+
+/* V0 = 5, draws the 5 glyph at (5, 5) and spins. */
+static const uint8_t DRAW_ROM[] = {0x60, 0x05, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06};
+/* Returns with an empty stack. */
+static const uint8_t FAULT_ROM[] = {0x00, 0xEE};
+static uint8_t too_large[CHIP8_MAX_ROM_SIZE + 1];
+
+static int run(Chip8 *chip8)
+{
+    uint8_t display[CHIP8_DISPLAY_MAX_WIDTH * CHIP8_DISPLAY_MAX_HEIGHT];
+    size_t width, height, lit = 0;
+
+    if (chip8_load_rom(chip8, DRAW_ROM, sizeof DRAW_ROM) != CHIP8_OK)
+        return 2;
+    if (chip8_run_frame(chip8) != CHIP8_OK)
+        return 3;
+    if (chip8_display_size(chip8, &width, &height) != CHIP8_OK || width != 64 || height != 32)
+        return 4;
+    if (chip8_display(chip8, display) != CHIP8_OK)
+        return 5;
+
+    for (size_t i = 0; i < width * height; i++)
+        lit += display[i];
+
+    /* The 5 glyph has 14 pixels on. */
+    if (lit != 14)
+        return 6;
+    if (chip8_key(chip8, 0xF, true) != CHIP8_OK || chip8_key(chip8, 16, true) != CHIP8_ERR_INVALID_KEY)
+        return 7;
+    if (chip8_load_rom(chip8, too_large, sizeof too_large) != CHIP8_ERR_ROM_TOO_LARGE)
+        return 8;
+    if (chip8_run_frame(NULL) != CHIP8_ERR_NULL_POINTER || chip8_sound_active(NULL))
+        return 9;
+
+    /* A fault sticks until the next ROM is loaded. */
+    if (chip8_load_rom(chip8, FAULT_ROM, sizeof FAULT_ROM) != CHIP8_OK)
+        return 10;
+    if (chip8_run_frame(chip8) != CHIP8_ERR_FAULT || chip8_run_frame(chip8) != CHIP8_ERR_FAULT)
+        return 11;
+    if (chip8_load_rom(chip8, DRAW_ROM, sizeof DRAW_ROM) != CHIP8_OK || chip8_run_frame(chip8) != CHIP8_OK)
+        return 12;
+
+    return 0;
+}
+
+/* 0 when every check passes, otherwise the number of the one that failed. */
+int chip8_capi_smoke(void)
+{
+    Chip8 *chip8 = chip8_new();
+    int failed;
+
+    if (chip8 == NULL)
+        return 1;
+
+    failed = run(chip8);
+    chip8_free(chip8);
+    chip8_free(NULL);
+
+    return failed;
+}
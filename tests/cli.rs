@@ -0,0 +1,19 @@
+#![cfg(feature = "gui")]
+
+use std::process::Command;
+
+#[test]
+fn no_rom_prints_the_usage_and_exits_with_2() {
+    let output = Command::new(env!("CARGO_BIN_EXE_chip8-emu"))
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        stderr.starts_with("No ROM given\nusage: chip8-emu "),
+        "{}",
+        stderr
+    );
+    assert!(output.stdout.is_empty());
+}
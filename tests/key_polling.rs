@@ -0,0 +1,107 @@
+use chip8_emu::emulator::{Emulator, EmulatorOptions};
+
+// Tests keys 4, 5 and 6 with EX9E once each, then waits for any key.
+const KEYS_456: &[u8] = include_bytes!("fixtures/keys_456.ch8");
+
+fn emulator() -> Emulator {
+    let mut emulator = Emulator::new(EmulatorOptions::default());
+
+    emulator.load_rom(KEYS_456).unwrap();
+    emulator
+}
+
+#[test]
+fn only_the_tested_keys_are_reported() {
+    let mut emulator = emulator();
+
+    assert!(emulator.queried_keys().is_empty());
+
+    for _ in 0..10 {
+        emulator.run_frame().unwrap();
+    }
+
+    assert_eq!(emulator.queried_keys(), [4, 5, 6]);
+    assert!(emulator.waited_for_key());
+
+    // A key taken by FX0A isn't one the ROM tested.
+    emulator.press_key(0x7);
+    emulator.run_frame().unwrap();
+    emulator.release_key(0x7);
+
+    for _ in 0..10 {
+        emulator.run_frame().unwrap();
+    }
+
+    assert_eq!(emulator.queried_keys(), [4, 5, 6]);
+}
+
+#[test]
+fn a_polled_key_is_recent_for_a_second_of_frames() {
+    let mut emulator = emulator();
+
+    // At 8 instructions a frame, 4 and 5 are tested in the first frame and
+    // 6 in the second.
+    emulator.run_frame().unwrap();
+    assert_eq!(emulator.recently_polled_keys(), 0b0011_0000);
+
+    for _ in 1..60 {
+        emulator.run_frame().unwrap();
+    }
+
+    assert_eq!(emulator.recently_polled_keys(), 0b0111_0000);
+
+    // Each key expires a second after it was last tested.
+    emulator.run_frame().unwrap();
+    assert_eq!(emulator.recently_polled_keys(), 0b0100_0000);
+
+    emulator.run_frame().unwrap();
+    assert_eq!(emulator.recently_polled_keys(), 0);
+
+    // The keys tested at all are still known.
+    assert_eq!(emulator.queried_keys(), [4, 5, 6]);
+}
+
+#[test]
+fn a_second_is_fifty_frames_at_50_hz() {
+    let mut emulator = Emulator::new(EmulatorOptions {
+        refresh_rate: 50,
+        ..EmulatorOptions::default()
+    });
+
+    emulator.load_rom(KEYS_456).unwrap();
+
+    for _ in 0..51 {
+        emulator.run_frame().unwrap();
+    }
+
+    assert_eq!(emulator.recently_polled_keys(), 0b0100_0000);
+
+    emulator.run_frame().unwrap();
+    assert_eq!(emulator.recently_polled_keys(), 0);
+}
+
+#[test]
+fn polling_again_keeps_a_key_recent() {
+    // Tests key 3 until it is pressed, then spins.
+    let mut emulator = Emulator::new(EmulatorOptions::default());
+
+    emulator
+        .load_rom(&[0x60, 0x03, 0xE0, 0xA1, 0x12, 0x08, 0x12, 0x02, 0x12, 0x08])
+        .unwrap();
+
+    for _ in 0..200 {
+        emulator.run_frame().unwrap();
+        assert_eq!(emulator.recently_polled_keys(), 1 << 3);
+    }
+
+    emulator.press_key(0x3);
+
+    for _ in 0..60 {
+        emulator.run_frame().unwrap();
+    }
+
+    assert_eq!(emulator.recently_polled_keys(), 1 << 3);
+
+    emulator.run_frame().unwrap();
+    assert_eq!(emulator.recently_polled_keys(), 0);
+}
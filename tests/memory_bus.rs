@@ -0,0 +1,62 @@
+use chip8_emu::{
+    emulator::{Emulator, EmulatorOptions},
+    memory_bus::{FrameCounter, FRAME_COUNTER_ADDRESS},
+};
+
+fn emulator_with_counter(rom: &[u8]) -> Emulator {
+    let mut emulator = Emulator::new(EmulatorOptions::default());
+
+    emulator.load_rom(rom).unwrap();
+    emulator.map_memory(FrameCounter::range(), Box::new(FrameCounter::default()));
+
+    emulator
+}
+
+#[test]
+fn a_rom_reads_the_frame_counter_with_fx65() {
+    // I = 0x1F0, V0 = [I], over and over.
+    let mut emulator = emulator_with_counter(&[0xA1, 0xF0, 0xF0, 0x65, 0x12, 0x00]);
+
+    for frame in 1..=5 {
+        emulator.run_frame().unwrap();
+
+        assert_eq!(emulator.snapshot().registers[0], frame);
+    }
+
+    // RAM under the device is never touched.
+    assert_eq!(emulator.read_memory(FRAME_COUNTER_ADDRESS), 0);
+}
+
+#[test]
+fn writing_the_frame_counter_sets_it_and_it_wraps() {
+    // Stores 0xFE there once, then keeps reading it back into V0.
+    let mut emulator = emulator_with_counter(&[
+        0x60, 0xFE, 0xA1, 0xF0, 0xF0, 0x55, 0xA1, 0xF0, 0xF0, 0x65, 0x12, 0x06,
+    ]);
+
+    let mut seen = Vec::new();
+
+    for _ in 0..3 {
+        emulator.run_frame().unwrap();
+        seen.push(emulator.snapshot().registers[0]);
+    }
+
+    assert_eq!(seen, [0xFE, 0xFF, 0x00]);
+    assert_eq!(emulator.read_memory(FRAME_COUNTER_ADDRESS), 0);
+}
+
+#[test]
+fn the_counter_runs_only_while_mapped() {
+    // Unmapped, 0x1F0 is plain RAM.
+    let mut emulator = Emulator::new(EmulatorOptions::default());
+
+    emulator
+        .load_rom(&[0xA1, 0xF0, 0xF0, 0x65, 0x12, 0x00])
+        .unwrap();
+
+    for _ in 0..3 {
+        emulator.run_frame().unwrap();
+    }
+
+    assert_eq!(emulator.snapshot().registers[0], 0);
+}